@@ -35,13 +35,19 @@ else
 fi
 
 cd ../rust
-if cargo build --release; then
+# The library must also pass its tests with only the pure core, and with
+# the optional bindings, the REPL must build without a C compiler, and the
+# browser bindings must build for wasm
+if cargo build --release && cargo test --no-default-features && cargo test --all-features \
+    && cargo build --no-default-features --features fallback-repl \
+    && cargo build --lib --target wasm32-unknown-unknown --no-default-features --features io,regex,wasm; then
     echo "[RUST] SUCCESS"
     rust_status="SUCCESS"
+    echo "rust interpreter available at ./rust/target/release/tcl"
 else
     echo "[RUST] FAIL"
     rust_status="FAIL"
-    echo "rust interpreter available at ./rust/target/release/tcl"
+fi
 
 cd ..
-echo "Build summary: CPP=$cpp_status PICOL=$picol_status ZIG=$zig_status"
+echo "Build summary: CPP=$cpp_status PICOL=$picol_status ZIG=$zig_status RUST=$rust_status"
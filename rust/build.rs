@@ -1,5 +1,14 @@
 fn main() {
-    cc::Build::new()
-        .file("../vendor/linenoise.c")
-        .compile("linenoise");
+    // linenoise is only used by the REPL, so link it into the binary alone
+    // and keep the library free of C dependencies
+    #[cfg(feature = "linenoise")]
+    {
+        cc::Build::new()
+            .file("../vendor/linenoise.c")
+            .cargo_metadata(false)
+            .compile("linenoise");
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        println!("cargo:rustc-link-arg-bins={out_dir}/liblinenoise.a");
+    }
+    println!("cargo:rerun-if-changed=../vendor/linenoise.c");
 }
@@ -0,0 +1,29 @@
+//! Evaluates arbitrary scripts. Run with `cargo fuzz run eval`, from the
+//! directory above this one. Inputs that once crashed are kept in
+//! fuzz/regressions, and tests/fuzz_regressions.rs replays them.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tcl3::{EvalLimits, InterpBuilder};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(script) = std::str::from_utf8(data) else {
+        return;
+    };
+    // No io or process commands: the fuzzer mustn't write files or sleep.
+    // Values are capped too, or a loop that doubles a string runs out of
+    // memory within its step limit.
+    let mut interp = InterpBuilder::safe()
+        .with_test_commands(true)
+        .max_value_len(Some(1 << 20))
+        .build();
+    let limits = EvalLimits {
+        max_nesting_depth: 200,
+        eval_limit: 10_000,
+    };
+    // libfuzzer-sys aborts on any panic, even one that eval_safe would
+    // catch, so errors themselves are fine. Their messages can't be
+    // checked for "panicked": scripts can put any words in them.
+    let _ = interp.eval_safe(script, limits);
+});
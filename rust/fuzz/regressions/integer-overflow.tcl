@@ -0,0 +1,12 @@
+catch {/ -9223372036854775808 -1}
+catch {* -9223372036854775808 -1}
+catch {abs -9223372036854775808}
+catch {- 0 -9223372036854775808}
+catch {** 3 9223372036854775807}
+catch {expr {-9223372036854775808 % -1}}
+catch {expr {2 ** 64}}
+catch {expr {1 << 64}}
+catch {expr {int(1e400)}}
+catch {return -level 9223372036854775807}
+catch {upvar #9223372036854775807 a b}
+catch {info level -9223372036854775808}
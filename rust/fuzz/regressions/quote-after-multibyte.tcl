@@ -0,0 +1 @@
+é""
\ No newline at end of file
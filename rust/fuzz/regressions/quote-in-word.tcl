@@ -0,0 +1,2 @@
+set x €"a"
+puts é"
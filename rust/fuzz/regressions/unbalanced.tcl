@@ -0,0 +1,5 @@
+${é
+$é(
+set a(é
+[é
+"é\
@@ -0,0 +1,5 @@
+set i 0
+while {$i < 30} {
+    set "v [info vars] [info vars]" $i
+    set i [+ $i 1]
+}
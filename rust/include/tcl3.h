@@ -0,0 +1,40 @@
+/* C interface to the tcl3 interpreter, built with the `ffi` cargo feature.
+ * Strings are NUL-terminated UTF-8; invalid UTF-8 is replaced. */
+#ifndef TCL3_H
+#define TCL3_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define TCL3_OK 0
+#define TCL3_ERROR 1
+#define TCL3_RETURN 2
+#define TCL3_BREAK 3
+#define TCL3_CONTINUE 4
+/* A Rust panic was caught at the boundary */
+#define TCL3_PANIC (-1)
+/* A null pointer was passed where a value was needed */
+#define TCL3_INVALID (-2)
+
+typedef struct tcl3_interp tcl3_interp;
+
+/* Sets its result (or error message) with tcl3_set_result */
+typedef int (*tcl3_cmd_func)(void *clientdata, tcl3_interp *interp, int argc,
+                             const char *const *argv);
+
+tcl3_interp *tcl3_interp_new(void);
+void tcl3_interp_free(tcl3_interp *interp);
+int tcl3_eval(tcl3_interp *interp, const char *script);
+/* Valid until the next call into the interpreter */
+const char *tcl3_get_result(tcl3_interp *interp);
+int tcl3_set_result(tcl3_interp *interp, const char *value);
+int tcl3_set_var(tcl3_interp *interp, const char *name, const char *value);
+int tcl3_register_command(tcl3_interp *interp, const char *name,
+                          tcl3_cmd_func func, void *clientdata);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
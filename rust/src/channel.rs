@@ -0,0 +1,825 @@
+//! Channels opened by scripts, such as files from `open`. Each keeps its own
+//! read and write buffers, so that `gets` doesn't go to the OS for every
+//! line. Seeking drops what was read ahead and writes out what is pending,
+//! so the position `tell` reports is always the script's view of it.
+//!
+//! `fconfigure` sets, for each channel, when written output goes out, how
+//! line endings are translated and which encoding text is in.
+//!
+//! A channel opened with `open |command` talks to a [`Pipeline`] of child
+//! processes instead of a file, and closing it waits for them. Embedders
+//! can add channels of their own with [`crate::Interp::register_channel`].
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+use crate::encoding::{self, Encoding};
+use crate::{TclError, TclErrorKind, list_from_iter};
+
+/// How much is read from the OS at a time, and how much written output is
+/// held before it goes out
+const BUFFER_SIZE: usize = 4096;
+
+/// What a channel reads from and writes to: a file, a socket, a pipeline,
+/// or anything an embedder registers with
+/// [`Interp::register_channel`](crate::Interp::register_channel). The
+/// channel buffers around it and handles encodings and line endings, so a
+/// stream only moves bytes. Reading 0 bytes is the end of the stream.
+pub trait Stream: Send {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>;
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Moves to a new position, failing for streams that can't, as by
+    /// default
+    fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid argument",
+        ))
+    }
+
+    #[cfg(feature = "process")]
+    /// The processes behind the stream, for `pid`
+    fn pids(&self) -> Vec<u32> {
+        Vec::new()
+    }
+
+    /// Finishes with the stream once `close` has written out what was
+    /// pending, or once the interpreter is dropped with the channel still
+    /// open. It is called exactly once, and an error fails the `close`.
+    fn close(&mut self) -> Result<(), TclError> {
+        Ok(())
+    }
+}
+
+impl Stream for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Read::read(self, buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Write::write(self, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Write::flush(self)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        Seek::seek(self, pos)
+    }
+}
+
+impl Stream for TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Read::read(self, buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Write::write(self, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Write::flush(self)
+    }
+}
+
+/// The commands of an `open |command` channel, each one's stdout feeding
+/// the next one's stdin. The channel writes to the first command and reads
+/// from the last; what either end isn't opened for is left connected to
+/// the interpreter's own stdin or stdout. Everything the commands write
+/// to stderr is collected for `close` to report.
+pub(crate) struct Pipeline {
+    children: Vec<Child>,
+    stdin: Option<ChildStdin>,
+    stdout: Option<ChildStdout>,
+    // Each reads one child's stderr until it ends, so that a child can't
+    // block on a full pipe nobody reads
+    stderr: Vec<JoinHandle<Vec<u8>>>,
+}
+
+impl Pipeline {
+    /// Starts each command, given as its words, connecting them in order
+    pub fn spawn(
+        commands: &[Vec<String>],
+        readable: bool,
+        writable: bool,
+    ) -> Result<Pipeline, TclError> {
+        let mut pipeline = Pipeline {
+            children: Vec::new(),
+            stdin: None,
+            stdout: None,
+            stderr: Vec::new(),
+        };
+        let last = commands.len() - 1;
+        for (i, words) in commands.iter().enumerate() {
+            let stdin = match pipeline.stdout.take() {
+                Some(previous) => Stdio::from(previous),
+                None if writable => Stdio::piped(),
+                None => Stdio::inherit(),
+            };
+            let stdout = if i < last || readable {
+                Stdio::piped()
+            } else {
+                Stdio::inherit()
+            };
+            let spawned = Command::new(&words[0])
+                .args(&words[1..])
+                .stdin(stdin)
+                .stdout(stdout)
+                .stderr(Stdio::piped())
+                .spawn();
+            let mut child = match spawned {
+                Ok(child) => child,
+                Err(e) => {
+                    pipeline.kill();
+                    return Err(io_error(format!("couldn't execute \"{}\"", words[0]), &e));
+                }
+            };
+            if i == 0 {
+                pipeline.stdin = child.stdin.take();
+            }
+            pipeline.stdout = child.stdout.take();
+            let mut stderr = child.stderr.take().unwrap();
+            pipeline.stderr.push(thread::spawn(move || {
+                let mut collected = Vec::new();
+                let _ = stderr.read_to_end(&mut collected);
+                collected
+            }));
+            pipeline.children.push(child);
+        }
+        Ok(pipeline)
+    }
+
+    /// Stops the commands started so far, after one of them couldn't be
+    fn kill(&mut self) {
+        self.stdin = None;
+        self.stdout = None;
+        for child in &mut self.children {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Stream for Pipeline {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.stdout {
+            Some(stdout) => stdout.read(buf),
+            None => Ok(0),
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.stdin {
+            Some(stdin) => stdin.write(buf),
+            None => Err(io::Error::from(io::ErrorKind::BrokenPipe)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.stdin {
+            Some(stdin) => stdin.flush(),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "process")]
+    fn pids(&self) -> Vec<u32> {
+        self.children.iter().map(Child::id).collect()
+    }
+
+    /// Closes the pipes, so the first command sees the end of its input,
+    /// and waits for every command. The first that fails makes the error,
+    /// with what they wrote to stderr as its message.
+    fn close(&mut self) -> Result<(), TclError> {
+        self.stdin = None;
+        self.stdout = None;
+        let mut failure = None;
+        for child in &mut self.children {
+            let status = child
+                .wait()
+                .map_err(|e| io_error("error waiting for process to exit", &e))?;
+            if failure.is_none() && !status.success() {
+                failure = Some((child.id(), status));
+            }
+        }
+        let mut stderr = Vec::new();
+        for reader in self.stderr.drain(..) {
+            stderr.extend(reader.join().unwrap_or_default());
+        }
+
+        let Some((pid, status)) = failure else {
+            return Ok(());
+        };
+        let stderr = String::from_utf8_lossy(&stderr);
+        let code = match status.code() {
+            Some(code) => list_from_iter(["CHILDSTATUS", &pid.to_string(), &code.to_string()]),
+            None => {
+                #[cfg(unix)]
+                let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+                #[cfg(not(unix))]
+                let signal: Option<i32> = None;
+                let signal = signal.map_or_else(String::new, |s| s.to_string());
+                list_from_iter(["CHILDKILLED", &pid.to_string(), &signal])
+            }
+        };
+        let message = match stderr.strip_suffix('\n').unwrap_or(&stderr) {
+            "" => "child process exited abnormally",
+            text => text,
+        };
+        Err(TclError::new(TclErrorKind::General, message).with_code(code))
+    }
+}
+
+/// When output written to a channel is passed on to the stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Buffering {
+    /// Once the buffer fills up
+    Full,
+    /// At the end of every line
+    Line,
+    /// Straight away
+    None,
+}
+
+impl Buffering {
+    pub const NAMES: &[&str] = &["full", "line", "none"];
+
+    pub fn from_name(name: &str) -> Buffering {
+        match name {
+            "line" => Buffering::Line,
+            "none" => Buffering::None,
+            _ => Buffering::Full,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Buffering::Full => "full",
+            Buffering::Line => "line",
+            Buffering::None => "none",
+        }
+    }
+}
+
+/// How line endings are translated between the script's `\n` and the
+/// stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Translation {
+    /// `\r\n` or `\n` on input, `\n` on output
+    Auto,
+    /// `\n` both ways, leaving `\r` alone
+    Lf,
+    /// `\r\n` both ways
+    Crlf,
+    /// Bytes as they are, one character each, with no encoding
+    Binary,
+}
+
+impl Translation {
+    pub const NAMES: &[&str] = &["auto", "lf", "crlf", "binary"];
+
+    pub fn from_name(name: &str) -> Translation {
+        match name {
+            "lf" => Translation::Lf,
+            "crlf" => Translation::Crlf,
+            "binary" => Translation::Binary,
+            _ => Translation::Auto,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Translation::Auto => "auto",
+            Translation::Lf => "lf",
+            Translation::Crlf => "crlf",
+            Translation::Binary => "binary",
+        }
+    }
+
+    /// Whether a `\r` before a `\n` read in is part of the line ending
+    fn strips_cr(self) -> bool {
+        matches!(self, Translation::Auto | Translation::Crlf)
+    }
+}
+
+pub(crate) struct Channel {
+    stream: Box<dyn Stream>,
+    readable: bool,
+    writable: bool,
+    // Read ahead of the script; rpos is how much of it has been used
+    rbuf: Vec<u8>,
+    rpos: usize,
+    // Written by the script but not yet passed to the stream
+    wbuf: Vec<u8>,
+    // Whether the last read reached the end of the stream
+    eof: bool,
+    // How text is turned into bytes and back
+    encoding: &'static dyn Encoding,
+    buffering: Buffering,
+    translation: Translation,
+    // Whether the stream's close has been called, so that dropping the
+    // channel doesn't call it again
+    closed: bool,
+}
+
+impl Channel {
+    pub fn new(stream: Box<dyn Stream>, readable: bool, writable: bool) -> Channel {
+        Channel {
+            stream,
+            readable,
+            writable,
+            rbuf: Vec::new(),
+            rpos: 0,
+            wbuf: Vec::new(),
+            eof: false,
+            encoding: encoding::UTF8,
+            buffering: Buffering::Full,
+            translation: Translation::Auto,
+            closed: false,
+        }
+    }
+
+    pub fn encoding(&self) -> &'static dyn Encoding {
+        self.encoding
+    }
+
+    pub fn set_encoding(&mut self, encoding: &'static dyn Encoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn buffering(&self) -> Buffering {
+        self.buffering
+    }
+
+    /// Changes when output goes out, writing out what is pending if it
+    /// would no longer be held
+    pub fn set_buffering(&mut self, buffering: Buffering) -> io::Result<()> {
+        self.buffering = buffering;
+        match buffering {
+            Buffering::Full => Ok(()),
+            _ => self.flush(),
+        }
+    }
+
+    pub fn translation(&self) -> Translation {
+        self.translation
+    }
+
+    /// Changes how line endings are translated. Binary also reads and
+    /// writes bytes as they are, as the iso8859-1 encoding does.
+    pub fn set_translation(&mut self, translation: Translation) {
+        self.translation = translation;
+        if translation == Translation::Binary {
+            self.encoding = encoding::find("iso8859-1").unwrap();
+        }
+    }
+
+    pub fn readable(&self) -> bool {
+        self.readable
+    }
+
+    pub fn writable(&self) -> bool {
+        self.writable
+    }
+
+    /// Whether the last read reached the end of the stream
+    pub fn eof(&self) -> bool {
+        self.eof
+    }
+
+    /// Bytes read from the stream that the script hasn't seen yet
+    fn unread(&self) -> usize {
+        self.rbuf.len() - self.rpos
+    }
+
+    /// Reads the next line without its line ending, or None at the end of
+    /// the stream. A last line without a newline is still returned.
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        self.flush()?;
+        loop {
+            let pending = &self.rbuf[self.rpos..];
+            if let Some(n) = pending.iter().position(|&b| b == b'\n') {
+                let mut line = &pending[..n];
+                if self.translation.strips_cr() && line.last() == Some(&b'\r') {
+                    line = &line[..line.len() - 1];
+                }
+                let line = self.decode(line);
+                self.rpos += n + 1;
+                return Ok(Some(line));
+            }
+            if self.fill()? == 0 {
+                self.eof = true;
+                if self.unread() == 0 {
+                    return Ok(None);
+                }
+                let line = self.decode(&self.rbuf[self.rpos..]);
+                self.rpos = self.rbuf.len();
+                return Ok(Some(line));
+            }
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> String {
+        self.encoding.decode(bytes, false).unwrap_or_default()
+    }
+
+    /// Reads more of the stream onto the end of the read buffer, returning
+    /// how much was read
+    fn fill(&mut self) -> io::Result<usize> {
+        self.rbuf.drain(..self.rpos);
+        self.rpos = 0;
+        let start = self.rbuf.len();
+        self.rbuf.resize(start + BUFFER_SIZE, 0);
+        let res = self.stream.read(&mut self.rbuf[start..]);
+        let n = *res.as_ref().unwrap_or(&0);
+        self.rbuf.truncate(start + n);
+        res
+    }
+
+    /// Drops what was read ahead, moving the stream back to where the script
+    /// has read up to. On streams that can't seek, like sockets, reading
+    /// and writing are independent and what was read ahead is kept.
+    fn discard_read_ahead(&mut self) {
+        let unread = self.unread() as i64;
+        if self.stream.seek(SeekFrom::Current(-unread)).is_ok() {
+            self.rbuf.clear();
+            self.rpos = 0;
+        }
+    }
+
+    /// Reads `count` characters, or everything to the end of the stream
+    /// without a count, as with [`read_text`]
+    pub fn read_text(&mut self, count: Option<usize>) -> io::Result<String> {
+        self.flush()?;
+        let text = read_text(self, self.encoding, count)?;
+        match self.translation.strips_cr() && text.contains("\r\n") {
+            true => Ok(text.replace("\r\n", "\n")),
+            false => Ok(text),
+        }
+    }
+
+    /// Writes text in the channel's encoding, with `?` for characters it
+    /// doesn't have, and its line endings
+    pub fn write_text(&mut self, text: &str) -> io::Result<()> {
+        let text = match self.translation {
+            Translation::Crlf => Cow::Owned(text.replace('\n', "\r\n")),
+            _ => Cow::Borrowed(text),
+        };
+        let bytes = self.encoding.encode(&text, false).unwrap_or_default();
+        self.write(&bytes)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.unread() > 0 {
+            self.discard_read_ahead();
+        }
+        self.wbuf.extend_from_slice(bytes);
+        match self.buffering {
+            Buffering::Full if self.wbuf.len() < BUFFER_SIZE => Ok(()),
+            Buffering::Full => self.write_pending(),
+            Buffering::Line if !bytes.contains(&b'\n') => Ok(()),
+            Buffering::Line | Buffering::None => self.flush(),
+        }
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        let mut written = 0;
+        while written < self.wbuf.len() {
+            match self.stream.write(&self.wbuf[written..]) {
+                Ok(0) => {
+                    self.wbuf.drain(..written);
+                    return Err(io::ErrorKind::WriteZero.into());
+                }
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.wbuf.drain(..written);
+                    return Err(e);
+                }
+            }
+        }
+        self.wbuf.clear();
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.wbuf.is_empty() {
+            return Ok(());
+        }
+        self.write_pending()?;
+        self.stream.flush()
+    }
+
+    /// Moves to a new position, which clears the end-of-file state
+    pub fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.flush()?;
+        let pos = match pos {
+            SeekFrom::Current(offset) => SeekFrom::Current(offset - self.unread() as i64),
+            pos => pos,
+        };
+        let at = self.stream.seek(pos)?;
+        self.rbuf.clear();
+        self.rpos = 0;
+        self.eof = false;
+        Ok(at)
+    }
+
+    /// The position the script has read or written up to, or None if the
+    /// stream can't seek
+    #[cfg(feature = "process")]
+    /// The processes the channel reads from or writes to, if it was
+    /// opened on a pipeline
+    pub fn pids(&self) -> Vec<u32> {
+        self.stream.pids()
+    }
+
+    /// Writes out what is pending and finishes with the stream, waiting
+    /// for a pipeline's commands to exit
+    pub fn close(mut self, name: &str) -> Result<(), TclError> {
+        self.flush()
+            .map_err(|e| io_error(format!("error closing \"{name}\""), &e))?;
+        self.closed = true;
+        self.stream.close()
+    }
+
+    pub fn tell(&mut self) -> Option<u64> {
+        let at = self.stream.seek(SeekFrom::Current(0)).ok()?;
+        Some(at + self.wbuf.len() as u64 - self.unread() as u64)
+    }
+}
+
+impl Read for Channel {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let pending = self.fill_buf()?;
+        let n = pending.len().min(buf.len());
+        buf[..n].copy_from_slice(&pending[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for Channel {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.unread() == 0 && self.fill()? == 0 {
+            self.eof = true;
+        }
+        Ok(&self.rbuf[self.rpos..])
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.rpos += n;
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        if !self.closed {
+            let _ = self.stream.close();
+        }
+    }
+}
+
+/// Reads `count` characters in `encoding`, or everything to the end of
+/// the input without a count. Fewer come back if the input ends first. It
+/// reads no further than the last character it returns, so that a prompt
+/// for one character doesn't wait for more. What can't be decoded is
+/// replaced.
+pub(crate) fn read_text(
+    input: &mut (impl BufRead + ?Sized),
+    encoding: &dyn Encoding,
+    count: Option<usize>,
+) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    let mut chars = 0;
+    // Bytes still to come for the last character started
+    let mut needed = 0;
+    let done = |chars, needed| needed == 0 && count == Some(chars);
+
+    while !done(chars, needed) {
+        let pending = match input.fill_buf() {
+            Ok(pending) => pending,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if pending.is_empty() {
+            break;
+        }
+        let mut used = 0;
+        for &b in pending {
+            if done(chars, needed) {
+                break;
+            }
+            if needed > 0 {
+                needed -= 1;
+            } else {
+                chars += 1;
+                needed = encoding.char_len(b) - 1;
+            }
+            used += 1;
+        }
+        bytes.extend_from_slice(&pending[..used]);
+        input.consume(used);
+    }
+    Ok(encoding.decode(&bytes, false).unwrap_or_default())
+}
+
+/// An error from a channel operation: `what` failed, followed by the OS
+/// message, with an errorCode of `POSIX <errno name> <message>`
+pub(crate) fn io_error(what: impl std::fmt::Display, e: &io::Error) -> TclError {
+    let message = io_message(e);
+    let code = list_from_iter(["POSIX", errno_name(e), &message]);
+    TclError::new(TclErrorKind::General, format!("{what}: {message}")).with_code(code)
+}
+
+/// The POSIX name of the errno behind an IO error, as far as Rust's
+/// error kinds tell
+fn errno_name(e: &io::Error) -> &'static str {
+    use io::ErrorKind::*;
+    match e.kind() {
+        NotFound => "ENOENT",
+        PermissionDenied => "EACCES",
+        AlreadyExists => "EEXIST",
+        IsADirectory => "EISDIR",
+        NotADirectory => "ENOTDIR",
+        DirectoryNotEmpty => "ENOTEMPTY",
+        ReadOnlyFilesystem => "EROFS",
+        StorageFull => "ENOSPC",
+        ConnectionRefused => "ECONNREFUSED",
+        ConnectionReset => "ECONNRESET",
+        ConnectionAborted => "ECONNABORTED",
+        NotConnected => "ENOTCONN",
+        AddrInUse => "EADDRINUSE",
+        AddrNotAvailable => "EADDRNOTAVAIL",
+        BrokenPipe => "EPIPE",
+        TimedOut => "ETIMEDOUT",
+        WouldBlock => "EAGAIN",
+        Interrupted => "EINTR",
+        InvalidInput => "EINVAL",
+        Unsupported => "ENOTSUP",
+        _ => "EIO",
+    }
+}
+
+/// Describes an IO error the way Tcl does, as the lowercase OS message
+/// without the error number Rust adds
+fn io_message(e: &io::Error) -> String {
+    let message = e.to_string();
+    let message = match message.find(" (os error") {
+        Some(i) => &message[..i],
+        None => &message,
+    };
+    let mut chars = message.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stream over bytes in memory that hands out a few at a time, to
+    /// show up reads that assume they get everything at once
+    struct Trickle(io::Cursor<Vec<u8>>);
+
+    impl Stream for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(3);
+            Read::read(&mut self.0, &mut buf[..n])
+        }
+
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Write::write(&mut self.0, buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            Seek::seek(&mut self.0, pos)
+        }
+    }
+
+    fn channel(text: &str) -> Channel {
+        let stream = Trickle(io::Cursor::new(text.as_bytes().to_vec()));
+        Channel::new(Box::new(stream), true, true)
+    }
+
+    #[test]
+    fn lines_are_read_across_short_reads() {
+        let mut chan = channel("one\r\ntwo\nlast");
+        assert_eq!(chan.read_line().unwrap().as_deref(), Some("one"));
+        assert_eq!(chan.tell(), Some(5));
+        assert_eq!(chan.read_line().unwrap().as_deref(), Some("two"));
+        assert!(!chan.eof());
+        assert_eq!(chan.read_line().unwrap().as_deref(), Some("last"));
+        assert!(chan.eof());
+        assert_eq!(chan.read_line().unwrap(), None);
+    }
+
+    #[test]
+    fn characters_are_counted_across_short_reads() {
+        let mut chan = channel("héllo wörld\n€uro");
+        assert_eq!(chan.read_text(Some(5)).unwrap(), "héllo");
+        assert_eq!(chan.read_text(Some(3)).unwrap(), " wö");
+        assert_eq!(chan.read_line().unwrap().as_deref(), Some("rld"));
+        assert_eq!(chan.read_text(Some(1)).unwrap(), "€");
+        assert_eq!(chan.read_text(Some(0)).unwrap(), "");
+        assert!(!chan.eof());
+        assert_eq!(chan.read_text(None).unwrap(), "uro");
+        assert!(chan.eof());
+        assert_eq!(chan.read_text(Some(4)).unwrap(), "");
+    }
+
+    #[test]
+    fn text_goes_through_the_channel_encoding() {
+        let stream = Trickle(io::Cursor::new(b"caf\xe9\nna\xefve".to_vec()));
+        let mut chan = Channel::new(Box::new(stream), true, true);
+        chan.set_encoding(encoding::find("iso8859-1").unwrap());
+        assert_eq!(chan.read_line().unwrap().as_deref(), Some("café"));
+        assert_eq!(chan.read_text(Some(3)).unwrap(), "naï");
+        chan.write_text("é€").unwrap();
+        chan.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(chan.read_text(None).unwrap(), "café\nnaïé?");
+    }
+
+    /// A stream that keeps each write it's given, to show when output
+    /// goes out
+    #[derive(Clone, Default)]
+    struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>);
+
+    impl Stream for Recorder {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+    }
+
+    #[test]
+    fn buffering_decides_when_output_goes_out() {
+        let writes = Recorder::default();
+        let mut chan = Channel::new(Box::new(writes.clone()), false, true);
+        chan.write_text("held").unwrap();
+        assert!(writes.0.lock().unwrap().is_empty());
+
+        chan.set_buffering(Buffering::Line).unwrap();
+        assert_eq!(*writes.0.lock().unwrap(), [b"held"]);
+        chan.write_text("part").unwrap();
+        chan.write_text(" line\nnext").unwrap();
+        assert_eq!(writes.0.lock().unwrap()[1], b"part line\nnext");
+
+        chan.set_buffering(Buffering::None).unwrap();
+        chan.write_text("a").unwrap();
+        chan.write_text("b").unwrap();
+        assert_eq!(writes.0.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn line_endings_are_translated() {
+        let mut chan = channel("");
+        chan.set_translation(Translation::Crlf);
+        chan.write_text("a\nb\n").unwrap();
+        chan.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(chan.read_text(None).unwrap(), "a\nb\n");
+
+        chan.set_translation(Translation::Binary);
+        chan.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(chan.read_text(None).unwrap(), "a\r\nb\r\n");
+        chan.set_translation(Translation::Lf);
+        chan.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(chan.read_line().unwrap().as_deref(), Some("a\r"));
+    }
+
+    #[test]
+    fn writes_after_reads_land_where_the_script_is() {
+        let mut chan = channel("abc\ndef\n");
+        chan.read_line().unwrap();
+        chan.write(b"XYZ").unwrap();
+        assert_eq!(chan.tell(), Some(7));
+        chan.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(chan.read_line().unwrap().as_deref(), Some("abc"));
+        assert_eq!(chan.read_line().unwrap().as_deref(), Some("XYZ"));
+    }
+}
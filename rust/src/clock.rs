@@ -0,0 +1,699 @@
+//! Dates and times for the clock command: converting between seconds since
+//! the epoch and calendar dates, and formatting and scanning them with
+//! strftime-style directives. The calendar math is the proleptic Gregorian
+//! calendar, from Howard Hinnant's days-from-civil algorithms.
+
+use crate::{TclError, TclErrorKind};
+
+/// What `clock format` uses without a -format option
+pub const DEFAULT_FORMAT: &str = "%a %b %d %H:%M:%S %Z %Y";
+
+const DAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Which time zone a time is shown in or read as
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Zone {
+    /// The system's local time zone
+    Local,
+    /// A fixed offset east of UTC, in seconds, and its name
+    Fixed(i64, String),
+}
+
+impl Zone {
+    pub fn utc() -> Zone {
+        Zone::Fixed(0, "UTC".to_string())
+    }
+
+    /// Parses a -timezone value: UTC or GMT, with or without a leading
+    /// colon, or an offset like +0530, -05:00 or +09
+    pub fn parse(name: &str) -> Result<Zone, TclError> {
+        let bare = name.strip_prefix(':').unwrap_or(name);
+        if bare.is_empty() || bare.eq_ignore_ascii_case("localtime") {
+            return Ok(Zone::Local);
+        }
+        if ["UTC", "GMT", "Z"]
+            .iter()
+            .any(|z| bare.eq_ignore_ascii_case(z))
+        {
+            return Ok(Zone::utc());
+        }
+        match parse_offset(bare) {
+            Some(offset) => Ok(Zone::Fixed(offset, format_offset(offset))),
+            None => Err(TclError::new(
+                TclErrorKind::General,
+                format!("time zone \"{name}\" not found"),
+            )),
+        }
+    }
+
+    /// The offset east of UTC at a moment, and the zone's name then
+    fn at(&self, seconds: i64) -> (i64, String) {
+        match self {
+            Zone::Fixed(offset, name) => (*offset, name.clone()),
+            Zone::Local => local_offset(seconds),
+        }
+    }
+}
+
+/// Reads an offset like +0530, -05:00 or +09 as seconds east of UTC
+fn parse_offset(s: &str) -> Option<i64> {
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'+' => (1, &s[1..]),
+        b'-' => (-1, &s[1..]),
+        _ => return None,
+    };
+    let digits: String = rest.chars().filter(|&c| c != ':').collect();
+    if !digits.bytes().all(|b| b.is_ascii_digit()) || rest.starts_with(':') {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i64>().ok()?, 0),
+        4 => (digits[..2].parse().ok()?, digits[2..].parse::<i64>().ok()?),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn format_offset(offset: i64) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    format!("{sign}{:02}{:02}", offset / 3600, offset % 3600 / 60)
+}
+
+#[cfg(unix)]
+fn local_offset(seconds: i64) -> (i64, String) {
+    use std::ffi::{CStr, c_char, c_int, c_long};
+
+    // The fields every Unix C library has, with the BSD extensions that
+    // glibc, musl and the BSDs all add at the end
+    #[repr(C)]
+    struct Tm {
+        tm_sec: c_int,
+        tm_min: c_int,
+        tm_hour: c_int,
+        tm_mday: c_int,
+        tm_mon: c_int,
+        tm_year: c_int,
+        tm_wday: c_int,
+        tm_yday: c_int,
+        tm_isdst: c_int,
+        tm_gmtoff: c_long,
+        tm_zone: *const c_char,
+    }
+
+    unsafe extern "C" {
+        fn localtime_r(time: *const i64, result: *mut Tm) -> *mut Tm;
+    }
+
+    let mut tm = std::mem::MaybeUninit::<Tm>::zeroed();
+    let res = unsafe { localtime_r(&seconds, tm.as_mut_ptr()) };
+    if res.is_null() {
+        return (0, "UTC".to_string());
+    }
+    let tm = unsafe { tm.assume_init() };
+    // A long is 32 bits on some targets
+    #[allow(clippy::unnecessary_cast)]
+    let offset = tm.tm_gmtoff as i64;
+    let name = if tm.tm_zone.is_null() {
+        format_offset(offset)
+    } else {
+        unsafe { CStr::from_ptr(tm.tm_zone) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    (offset, name)
+}
+
+#[cfg(not(unix))]
+fn local_offset(_seconds: i64) -> (i64, String) {
+    (0, "UTC".to_string())
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date, as (year, month, day), that is a number of days after
+/// 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A moment broken down into its calendar fields in some time zone
+struct Fields {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    // 0 for Sunday
+    weekday: u32,
+    // 1 for January 1st
+    yearday: u32,
+    offset: i64,
+    zone: String,
+    seconds: i64,
+}
+
+impl Fields {
+    fn new(seconds: i64, zone: &Zone) -> Fields {
+        let (offset, name) = zone.at(seconds);
+        let local = seconds + offset;
+        let days = local.div_euclid(86400);
+        let time = local.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        Fields {
+            year,
+            month,
+            day,
+            hour: (time / 3600) as u32,
+            minute: (time % 3600 / 60) as u32,
+            second: (time % 60) as u32,
+            weekday: (days + 4).rem_euclid(7) as u32,
+            yearday: (days - days_from_civil(year, 1, 1) + 1) as u32,
+            offset,
+            zone: name,
+            seconds,
+        }
+    }
+}
+
+/// Formats seconds since the epoch in a time zone. The directives are
+/// those of strftime:
+///
+/// | | |
+/// |-|-|
+/// | `%a` `%A` | abbreviated and full weekday name |
+/// | `%b` `%h` `%B` | abbreviated and full month name |
+/// | `%c` | date and time, as `%a %b %d %H:%M:%S %Y` |
+/// | `%d` `%e` | day of the month, zero- or space-padded |
+/// | `%D` `%F` | `%m/%d/%y` and `%Y-%m-%d` |
+/// | `%H` `%I` `%k` `%l` | hour on the 24- and 12-hour clock, zero- and space-padded |
+/// | `%j` | day of the year, from 001 |
+/// | `%m` `%M` `%S` | month, minute and second |
+/// | `%p` | AM or PM |
+/// | `%R` `%T` | `%H:%M` and `%H:%M:%S` |
+/// | `%s` | seconds since the epoch |
+/// | `%u` `%w` | weekday, from 1 for Monday or 0 for Sunday |
+/// | `%y` `%Y` | year without and with the century |
+/// | `%z` `%Z` | offset from UTC, as +hhmm, and the zone's name |
+/// | `%n` `%t` `%%` | newline, tab and percent sign |
+pub fn format(seconds: i64, fmt: &str, zone: &Zone) -> Result<String, TclError> {
+    let f = Fields::new(seconds, zone);
+    let mut out = String::new();
+    let mut chars = fmt.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let Some(directive) = chars.next() else {
+            out.push('%');
+            break;
+        };
+        let hour12 = if f.hour.is_multiple_of(12) {
+            12
+        } else {
+            f.hour % 12
+        };
+        let piece = match directive {
+            'a' => DAYS[f.weekday as usize][..3].to_string(),
+            'A' => DAYS[f.weekday as usize].to_string(),
+            'b' | 'h' => MONTHS[f.month as usize - 1][..3].to_string(),
+            'B' => MONTHS[f.month as usize - 1].to_string(),
+            'c' => format(seconds, "%a %b %d %H:%M:%S %Y", zone)?,
+            'd' => format!("{:02}", f.day),
+            'D' => format(seconds, "%m/%d/%y", zone)?,
+            'e' => format!("{:2}", f.day),
+            'F' => format(seconds, "%Y-%m-%d", zone)?,
+            'H' => format!("{:02}", f.hour),
+            'I' => format!("{hour12:02}"),
+            'j' => format!("{:03}", f.yearday),
+            'k' => format!("{:2}", f.hour),
+            'l' => format!("{hour12:2}"),
+            'm' => format!("{:02}", f.month),
+            'M' => format!("{:02}", f.minute),
+            'n' => "\n".to_string(),
+            'p' => if f.hour < 12 { "AM" } else { "PM" }.to_string(),
+            'R' => format(seconds, "%H:%M", zone)?,
+            's' => f.seconds.to_string(),
+            'S' => format!("{:02}", f.second),
+            't' => "\t".to_string(),
+            'T' => format(seconds, "%H:%M:%S", zone)?,
+            'u' => (if f.weekday == 0 { 7 } else { f.weekday }).to_string(),
+            'w' => f.weekday.to_string(),
+            'y' => format!("{:02}", f.year.rem_euclid(100)),
+            'Y' => format!("{:04}", f.year),
+            'z' => format_offset(f.offset),
+            'Z' => f.zone.clone(),
+            '%' => "%".to_string(),
+            _ => {
+                return Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("bad format directive \"%{directive}\""),
+                ));
+            }
+        };
+        out += &piece;
+    }
+    Ok(out)
+}
+
+/// What scanning has read so far
+#[derive(Default)]
+struct Scanned {
+    year: Option<i64>,
+    month: Option<u32>,
+    day: Option<u32>,
+    yearday: Option<u32>,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    pm: Option<bool>,
+    offset: Option<i64>,
+    seconds: Option<i64>,
+}
+
+/// Reads a time written in a format, returning seconds since the epoch.
+/// The directives are those of [`format`] except `%c`, `%n`, `%t` and the
+/// weekday ones, which are matched but don't affect the result. White
+/// space in the format matches any amount of white space. A date that
+/// isn't given is taken from `base`, in the time zone.
+pub fn scan(text: &str, fmt: &str, zone: &Zone, base: i64) -> Result<i64, TclError> {
+    let error = || {
+        TclError::new(
+            TclErrorKind::General,
+            format!("input string \"{text}\" does not match supplied format \"{fmt}\""),
+        )
+    };
+    let mut s = Scanned::default();
+    let rest = scan_into(text, fmt, &mut s).ok_or_else(error)?;
+    if !rest.trim().is_empty() {
+        return Err(error());
+    }
+    resolve(&s, zone, base).ok_or_else(error)
+}
+
+/// Reads a time in ISO 8601 form: a date as 2024-02-29 or 20240229,
+/// optionally followed by T or a space and a time as 13:45:00 or 134500,
+/// and then optionally Z or an offset
+pub fn scan_iso8601(text: &str, zone: &Zone, base: i64) -> Result<i64, TclError> {
+    let trimmed = text.trim();
+    let compact = !trimmed.contains('-') || trimmed.find('-') > Some(8);
+    let (date_fmt, time_fmt) = if compact {
+        ("%Y%m%d", "%H%M%S")
+    } else {
+        ("%Y-%m-%d", "%H:%M:%S")
+    };
+
+    let mut s = Scanned::default();
+    let rest = scan_into(trimmed, date_fmt, &mut s);
+    let rest = rest.and_then(|rest| {
+        let Some(time) = rest.strip_prefix(['T', ' ']) else {
+            return Some(rest);
+        };
+        let rest = scan_into(time, time_fmt, &mut s)?;
+        if rest.is_empty() {
+            return Some(rest);
+        }
+        if rest == "Z" {
+            s.offset = Some(0);
+            return Some("");
+        }
+        s.offset = Some(parse_offset(rest)?);
+        Some("")
+    });
+    match rest {
+        Some("") => resolve(&s, zone, base),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        TclError::new(
+            TclErrorKind::General,
+            format!("unable to convert date-time string \"{text}\""),
+        )
+    })
+}
+
+/// Reads `text` according to `fmt`, returning what follows
+fn scan_into<'a>(text: &'a str, fmt: &str, s: &mut Scanned) -> Option<&'a str> {
+    let mut text = text;
+    let mut chars = fmt.chars();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            text = text.trim_start();
+            continue;
+        }
+        if c != '%' {
+            text = text.strip_prefix(c)?;
+            continue;
+        }
+        let directive = chars.next()?;
+        // Space before a number is allowed, as with %e
+        if "dehHIjklmMSsyY".contains(directive) {
+            text = text.trim_start();
+        }
+        match directive {
+            'a' | 'A' => text = skip_name(text, &DAYS)?.1,
+            'b' | 'h' | 'B' => {
+                let (month, rest) = skip_name(text, &MONTHS)?;
+                s.month = Some(month as u32 + 1);
+                text = rest;
+            }
+            'd' | 'e' => (s.day, text) = number(text, 2).map(|(n, r)| (Some(n as u32), r))?,
+            'D' => text = scan_into(text, "%m/%d/%y", s)?,
+            'F' => text = scan_into(text, "%Y-%m-%d", s)?,
+            'H' | 'k' | 'I' | 'l' => {
+                let (n, rest) = number(text, 2)?;
+                s.hour = u32::try_from(n).ok()?;
+                text = rest;
+            }
+            'j' => (s.yearday, text) = number(text, 3).map(|(n, r)| (Some(n as u32), r))?,
+            'm' => (s.month, text) = number(text, 2).map(|(n, r)| (Some(n as u32), r))?,
+            'M' => {
+                let (n, rest) = number(text, 2)?;
+                s.minute = u32::try_from(n).ok()?;
+                text = rest;
+            }
+            'p' => {
+                let upper = text.get(..2)?.to_ascii_uppercase();
+                s.pm = Some(match upper.as_str() {
+                    "AM" => false,
+                    "PM" => true,
+                    _ => return None,
+                });
+                text = &text[2..];
+            }
+            'R' => text = scan_into(text, "%H:%M", s)?,
+            's' => {
+                let end = text
+                    .char_indices()
+                    .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+                    .map_or(text.len(), |(i, _)| i);
+                s.seconds = Some(text[..end].parse().ok()?);
+                text = &text[end..];
+            }
+            'S' => {
+                let (n, rest) = number(text, 2)?;
+                s.second = u32::try_from(n).ok()?;
+                text = rest;
+            }
+            'T' => text = scan_into(text, "%H:%M:%S", s)?,
+            'u' | 'w' => text = number(text, 1)?.1,
+            'y' => {
+                // As POSIX says: 69-99 are 1969-1999, 00-68 are 2000-2068
+                let (n, rest) = number(text, 2)?;
+                s.year = Some(if n < 69 { 2000 + n } else { 1900 + n });
+                text = rest;
+            }
+            'Y' => (s.year, text) = number(text, 4).map(|(n, r)| (Some(n), r))?,
+            'z' => {
+                let end = text
+                    .char_indices()
+                    .skip(1)
+                    .find(|&(_, c)| !(c.is_ascii_digit() || c == ':'))
+                    .map_or(text.len(), |(i, _)| i);
+                s.offset = Some(parse_offset(&text[..end])?);
+                text = &text[end..];
+            }
+            'Z' => {
+                let end = text
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(text.len());
+                let name = &text[..end];
+                if !["UTC", "GMT", "Z"]
+                    .iter()
+                    .any(|z| name.eq_ignore_ascii_case(z))
+                {
+                    return None;
+                }
+                s.offset = Some(0);
+                text = &text[end..];
+            }
+            '%' => text = text.strip_prefix('%')?,
+            'n' | 't' => text = text.trim_start(),
+            _ => return None,
+        }
+    }
+    Some(text)
+}
+
+/// Reads up to `max` digits
+fn number(text: &str, max: usize) -> Option<(i64, &str)> {
+    let end = text
+        .bytes()
+        .take(max)
+        .position(|b| !b.is_ascii_digit())
+        .unwrap_or(max.min(text.len()));
+    if end == 0 {
+        return None;
+    }
+    Some((text[..end].parse().ok()?, &text[end..]))
+}
+
+/// Reads a day or month name, in full or its first three letters,
+/// returning which it was
+fn skip_name<'a>(text: &'a str, names: &[&str]) -> Option<(usize, &'a str)> {
+    for (i, name) in names.iter().enumerate() {
+        for candidate in [*name, &name[..3]] {
+            if text
+                .get(..candidate.len())
+                .is_some_and(|t| t.eq_ignore_ascii_case(candidate))
+            {
+                return Some((i, &text[candidate.len()..]));
+            }
+        }
+    }
+    None
+}
+
+/// Turns what was scanned into seconds since the epoch
+fn resolve(s: &Scanned, zone: &Zone, base: i64) -> Option<i64> {
+    if let Some(seconds) = s.seconds {
+        return Some(seconds);
+    }
+    let base = Fields::new(base, zone);
+    let year = s.year.unwrap_or(base.year);
+    let days = match (s.month, s.day, s.yearday) {
+        (None, None, Some(yearday)) => {
+            let days_in_year = if is_leap_year(year) { 366 } else { 365 };
+            if !(1..=days_in_year).contains(&yearday) {
+                return None;
+            }
+            days_from_civil(year, 1, 1) + yearday as i64 - 1
+        }
+        (month, day, _) => {
+            let month = month.unwrap_or(if s.year.is_some() { 1 } else { base.month });
+            let day = day.unwrap_or(if s.year.is_some() || s.month.is_some() {
+                1
+            } else {
+                base.day
+            });
+            if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+                return None;
+            }
+            days_from_civil(year, month, day)
+        }
+    };
+
+    let hour = match s.pm {
+        Some(pm) if (1..=12).contains(&s.hour) => s.hour % 12 + if pm { 12 } else { 0 },
+        Some(_) => return None,
+        None => s.hour,
+    };
+    if hour > 23 || s.minute > 59 || s.second > 60 {
+        return None;
+    }
+    let local = days * 86400 + i64::from(hour * 3600 + s.minute * 60 + s.second);
+    let offset = match s.offset {
+        Some(offset) => offset,
+        None => {
+            // The offset depends on the moment, which depends on the offset.
+            // Starting from the offset at the local time read as UTC
+            // settles it except around a daylight saving change.
+            let guess = zone.at(local).0;
+            zone.at(local - guess).0
+        }
+    };
+    Some(local - offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_dates_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        for days in (-800_000..800_000).step_by(997) {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days, "{y}-{m}-{d}");
+        }
+    }
+
+    #[test]
+    fn formats_in_utc() {
+        let utc = Zone::utc();
+        // 2024-02-29 13:05:09 UTC, a Thursday
+        let t = 1709211909;
+        let cases = [
+            ("%Y-%m-%d %H:%M:%S", "2024-02-29 13:05:09"),
+            ("%a %A %b %B %h", "Thu Thursday Feb February Feb"),
+            ("%e|%j|%p|%I|%l|%k", "29|060|PM|01| 1|13"),
+            ("%D %F %R %T", "02/29/24 2024-02-29 13:05 13:05:09"),
+            ("%s %u %w %y %z %Z %%", "1709211909 4 4 24 +0000 UTC %"),
+            (DEFAULT_FORMAT, "Thu Feb 29 13:05:09 UTC 2024"),
+        ];
+        for (fmt, expected) in cases {
+            assert_eq!(format(t, fmt, &utc).unwrap(), expected, "{fmt}");
+        }
+        assert_eq!(format(-1, "%c", &utc).unwrap(), "Wed Dec 31 23:59:59 1969");
+        assert!(format(0, "%Q", &utc).is_err());
+    }
+
+    #[test]
+    fn offsets_and_zones() {
+        let india = Zone::parse("+05:30").unwrap();
+        assert_eq!(
+            format(0, "%H:%M %z %Z", &india).unwrap(),
+            "05:30 +0530 +0530"
+        );
+        assert_eq!(Zone::parse(":UTC").unwrap(), Zone::utc());
+        assert_eq!(
+            Zone::parse("-08").unwrap(),
+            Zone::Fixed(-8 * 3600, "-0800".into())
+        );
+        assert!(Zone::parse("Mars/Olympus").is_err());
+        assert!(Zone::parse("+0575").is_err());
+    }
+
+    #[test]
+    fn scanning_reverses_formatting() {
+        let zones = [Zone::utc(), Zone::parse("-0330").unwrap(), Zone::Local];
+        let fmt = "%Y-%m-%d %H:%M:%S";
+        // Around leap days, the epoch, and far from it, at noon so that no
+        // daylight saving change in the local zone gets in the way
+        let dates = [
+            (1900, 2, 28),
+            (1970, 1, 1),
+            (1999, 12, 31),
+            (2000, 2, 29),
+            (2000, 3, 1),
+            (2023, 2, 28),
+            (2024, 2, 29),
+            (2100, 3, 1),
+        ];
+        for zone in &zones {
+            for (y, m, d) in dates {
+                let t = days_from_civil(y, m, d) * 86400 + 12 * 3600 + 34 * 60 + 56;
+                let text = format(t, fmt, zone).unwrap();
+                assert_eq!(scan(&text, fmt, zone, 0).unwrap(), t, "{text} {zone:?}");
+                let text = format(t, "%A, %d %B %Y %I:%M:%S %p %z", zone).unwrap();
+                let back = scan(&text, "%A, %d %B %Y %I:%M:%S %p %z", zone, 0).unwrap();
+                assert_eq!(back, t, "{text}");
+            }
+        }
+    }
+
+    #[test]
+    fn scanning_fills_in_and_rejects() {
+        let utc = Zone::utc();
+        let base = days_from_civil(2024, 7, 4) * 86400;
+        assert_eq!(scan("10:00", "%H:%M", &utc, base).unwrap(), base + 36000);
+        assert_eq!(
+            scan("2024 061", "%Y %j", &utc, 0).unwrap(),
+            days_from_civil(2024, 3, 1) * 86400
+        );
+        assert_eq!(scan("@1234", "@%s", &utc, 0).unwrap(), 1234);
+        for (text, fmt) in [
+            ("2023-02-29", "%Y-%m-%d"),
+            ("2024-13-01", "%Y-%m-%d"),
+            ("24:00", "%H:%M"),
+            ("12:00 trailing", "%H:%M"),
+            ("13 PM", "%I %p"),
+        ] {
+            assert!(scan(text, fmt, &utc, 0).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn iso8601() {
+        let utc = Zone::utc();
+        let t = days_from_civil(2024, 2, 29) * 86400 + 13 * 3600 + 5 * 60 + 9;
+        for text in [
+            "2024-02-29T13:05:09",
+            "2024-02-29 13:05:09",
+            "20240229T130509",
+            "2024-02-29T14:05:09+01:00",
+            "2024-02-29T13:05:09Z",
+        ] {
+            assert_eq!(scan_iso8601(text, &utc, 0).unwrap(), t, "{text}");
+        }
+        assert_eq!(
+            scan_iso8601("2024-02-29", &utc, 0).unwrap(),
+            days_from_civil(2024, 2, 29) * 86400
+        );
+        assert!(scan_iso8601("yesterday", &utc, 0).is_err());
+    }
+}
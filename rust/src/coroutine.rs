@@ -0,0 +1,318 @@
+//! Coroutines, made with `coroutine` and suspended with `yield`. The
+//! evaluator keeps its state on the Rust stack, so each coroutine runs on
+//! a thread of its own. Only one side runs at a time: the interpreter
+//! itself is handed over a channel to whichever side runs next, and the
+//! other side waits for it to come back.
+//!
+//! A coroutine's procs run as if called from the global level. While it
+//! runs, its call frames take the place of the caller's above the global
+//! frame, and they are put aside again when it yields.
+
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    CallFrame, CmdBody, Interp, ScriptSource, Status, TclError, TclErrorKind, TclValue,
+    eval_stack_size, lock, panic_message,
+};
+
+/// Sent to a coroutine to run it
+enum ToCoroutine {
+    /// Continue, with the value for `yield` to return
+    Resume(Box<Interp>, String),
+    /// Unwind and finish: the coroutine command is gone. The interpreter
+    /// is a stand-in holding the coroutine's own frames, in which nothing
+    /// more is evaluated.
+    Kill(Box<Interp>),
+}
+
+/// Sent back by a coroutine when it stops running
+enum FromCoroutine {
+    Yield(Box<Interp>, String),
+    Done(Box<Interp>, Result<Status, TclError>),
+}
+
+/// The coroutine's end of the channels, which the interpreter holds while
+/// the coroutine runs so that `yield` can find it
+pub(crate) struct Link {
+    to_caller: Sender<FromCoroutine>,
+    from_caller: Mutex<Receiver<ToCoroutine>>,
+    /// Set once the coroutine's command is gone, failing every evaluation
+    /// step from then on so that its thread unwinds without running
+    /// anything
+    deleted: AtomicBool,
+}
+
+/// A coroutine, as the data of the command that resumes it
+pub(crate) struct Coroutine {
+    to_coroutine: Sender<ToCoroutine>,
+    from_coroutine: Mutex<Receiver<FromCoroutine>>,
+    link: Arc<Link>,
+    saved: Mutex<Suspended>,
+    running: AtomicBool,
+    done: AtomicBool,
+    /// The interpreter's count of coroutines, which this one leaves when
+    /// it's dropped
+    live: Arc<AtomicUsize>,
+}
+
+/// What a suspended coroutine takes out of the interpreter: its call frames
+/// above the global one, how deeply its evaluations are nested, and the
+/// files its scripts came from, with depths relative to where it runs
+#[derive(Default)]
+struct Suspended {
+    frames: Vec<CallFrame>,
+    depth: usize,
+    sources: Vec<(ScriptSource, usize)>,
+}
+
+/// Starts `words` as a coroutine, running it until it first yields or
+/// finishes. Returns the coroutine, which the caller registers as a
+/// command unless it has already finished.
+pub(crate) fn start(
+    interp: &mut Interp,
+    words: Vec<TclValue>,
+) -> Result<(Arc<Coroutine>, Result<Status, TclError>), TclError> {
+    let live = interp.live_coroutines.clone();
+    if let Some(limit) = interp.max_coroutines
+        && live.load(Ordering::SeqCst) >= limit
+    {
+        return Err(TclError::new(
+            TclErrorKind::LimitExceeded,
+            format!("coroutine limit exceeded: only {limit} coroutines allowed"),
+        ));
+    }
+    live.fetch_add(1, Ordering::SeqCst);
+
+    let (to_coroutine, from_caller) = channel();
+    let (to_caller, from_coroutine) = channel();
+    let link = Arc::new(Link {
+        to_caller,
+        from_caller: Mutex::new(from_caller),
+        deleted: AtomicBool::new(false),
+    });
+    let coroutine = Arc::new(Coroutine {
+        to_coroutine,
+        from_coroutine: Mutex::new(from_coroutine),
+        link: link.clone(),
+        saved: Mutex::new(Suspended::default()),
+        running: AtomicBool::new(false),
+        done: AtomicBool::new(false),
+        live,
+    });
+
+    let stack = eval_stack_size(interp.max_nesting_depth);
+    std::thread::Builder::new()
+        .stack_size(stack)
+        .spawn(move || run(&link, &words))
+        .map_err(|e| {
+            TclError::new(
+                TclErrorKind::General,
+                format!("couldn't start a thread for the coroutine: {e}"),
+            )
+        })?;
+
+    let res = resume(interp, &coroutine, String::new());
+    Ok((coroutine, res))
+}
+
+/// The coroutine's thread: waits to be started, runs the command, and
+/// hands the interpreter back with the outcome
+fn run(link: &Link, words: &[TclValue]) {
+    let msg = lock(&link.from_caller).recv();
+    let Ok(ToCoroutine::Resume(mut interp, _)) = msg else {
+        return;
+    };
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        interp.eval_with(|interp| interp.invoke(words))
+    }))
+    .unwrap_or_else(|payload| {
+        Err(TclError::new(
+            TclErrorKind::General,
+            format!("coroutine panicked: {}", panic_message(&*payload)),
+        ))
+    });
+    let _ = link.to_caller.send(FromCoroutine::Done(interp, res));
+}
+
+/// Runs a coroutine until it yields or finishes. Its frames and nesting
+/// depth replace the caller's while it runs, and go back into `saved`
+/// afterwards.
+pub(crate) fn resume(
+    interp: &mut Interp,
+    coroutine: &Coroutine,
+    value: String,
+) -> Result<Status, TclError> {
+    if coroutine.running.swap(true, Ordering::SeqCst) {
+        return Err(TclError::new(
+            TclErrorKind::General,
+            "coroutine is already running",
+        ));
+    }
+
+    let caller_frames = interp.callframes.split_off(1);
+    let caller_depth = interp.eval_depth;
+    let caller_sources = mem::take(&mut interp.sources);
+    let caller_link = interp.coroutine.replace(coroutine.link.clone());
+    {
+        let mut saved = lock(&coroutine.saved);
+        interp.callframes.append(&mut saved.frames);
+        interp.eval_depth = caller_depth + saved.depth;
+        interp.sources = (saved.sources.drain(..))
+            .map(|(source, depth)| (source, caller_depth + depth))
+            .collect();
+    }
+
+    let sent = coroutine
+        .to_coroutine
+        .send(ToCoroutine::Resume(Box::new(mem::take(interp)), value));
+    let reply = match sent {
+        Ok(()) => lock(&coroutine.from_coroutine).recv().ok(),
+        Err(e) => match e.0 {
+            ToCoroutine::Resume(back, _) => Some(FromCoroutine::Done(
+                back,
+                Err(TclError::new(TclErrorKind::General, "coroutine has ended")),
+            )),
+            ToCoroutine::Kill(_) => unreachable!(),
+        },
+    };
+    // Without a reply the coroutine's thread died with the interpreter,
+    // and there's nothing to put back
+    let Some(reply) = reply else {
+        coroutine.done.store(true, Ordering::SeqCst);
+        return Err(TclError::new(
+            TclErrorKind::General,
+            "coroutine thread exited unexpectedly",
+        ));
+    };
+    // A yielded value, or the outcome of the coroutine's command
+    let (back, yielded, res) = match reply {
+        FromCoroutine::Yield(back, value) => (back, Some(value), Ok(Status::Ok)),
+        FromCoroutine::Done(back, res) => {
+            coroutine.done.store(true, Ordering::SeqCst);
+            (back, None, res)
+        }
+    };
+    *interp = *back;
+
+    {
+        let mut saved = lock(&coroutine.saved);
+        saved.frames = interp.callframes.split_off(1);
+        saved.depth = interp.eval_depth.saturating_sub(caller_depth);
+        saved.sources = (interp.sources.drain(..))
+            .map(|(source, depth)| (source, depth.saturating_sub(caller_depth)))
+            .collect();
+    }
+    interp.callframes.extend(caller_frames);
+    interp.eval_depth = caller_depth;
+    interp.sources = caller_sources;
+    interp.coroutine = caller_link;
+    coroutine.running.store(false, Ordering::SeqCst);
+
+    if let Some(value) = yielded {
+        interp.set_result(value);
+    }
+    match res {
+        Ok(Status::Return) => Ok(Status::Ok),
+        res => res,
+    }
+}
+
+/// Whether the coroutine has finished, and its command should go
+pub(crate) fn is_done(coroutine: &Coroutine) -> bool {
+    coroutine.done.load(Ordering::SeqCst)
+}
+
+/// Suspends the running coroutine, handing `value` to whoever resumed it,
+/// and returns the value it's resumed with
+pub(crate) fn yield_to_caller(interp: &mut Interp, value: String) -> Result<Status, TclError> {
+    let Some(link) = interp.coroutine.clone() else {
+        return Err(TclError::new(
+            TclErrorKind::General,
+            "yield can only be called in a coroutine",
+        ));
+    };
+
+    let back = FromCoroutine::Yield(Box::new(mem::take(interp)), value);
+    if let Err(e) = link.to_caller.send(back) {
+        if let FromCoroutine::Yield(back, _) = e.0 {
+            *interp = *back;
+        }
+        return Err(TclError::new(
+            TclErrorKind::General,
+            "coroutine caller has gone",
+        ));
+    }
+
+    let msg = lock(&link.from_caller).recv();
+    match msg {
+        Ok(ToCoroutine::Resume(back, value)) => {
+            *interp = *back;
+            interp.set_result(value);
+            Ok(Status::Ok)
+        }
+        Ok(ToCoroutine::Kill(back)) => {
+            *interp = *back;
+            interp.coroutine = Some(link);
+            Err(deleted())
+        }
+        Err(_) => {
+            interp.coroutine = Some(link);
+            Err(deleted())
+        }
+    }
+}
+
+/// Whether the command of the coroutine holding `link` has been deleted
+pub(crate) fn is_deleted(link: &Link) -> bool {
+    link.deleted.load(Ordering::SeqCst)
+}
+
+/// The error that unwinds a deleted coroutine
+pub(crate) fn deleted() -> TclError {
+    TclError::new(TclErrorKind::CoroutineDeleted, "coroutine deleted")
+}
+
+/// Finds the name of the command that resumes `coroutine`, wherever it
+/// has been renamed to
+pub(crate) fn command_name(interp: &Interp, coroutine: &Arc<Coroutine>) -> Option<String> {
+    interp.commands.cmds.iter().find_map(|cmd| match &cmd.body {
+        CmdBody::WithData(data)
+            if data
+                .data()
+                .downcast_ref::<Arc<Coroutine>>()
+                .is_some_and(|data| Arc::ptr_eq(data, coroutine)) =>
+        {
+            Some(cmd.name.clone())
+        }
+        _ => None,
+    })
+}
+
+impl Drop for Coroutine {
+    /// A suspended coroutine whose command goes away is unwound on its
+    /// thread, so that the thread ends. It unwinds in a stand-in
+    /// interpreter holding its own frames, where every evaluation step
+    /// fails: no finally clause or trace runs without the commands it
+    /// would expect.
+    fn drop(&mut self) {
+        self.live.fetch_sub(1, Ordering::SeqCst);
+        if self.done.load(Ordering::SeqCst) {
+            return;
+        }
+        self.link.deleted.store(true, Ordering::SeqCst);
+        let mut stand_in = Interp::new();
+        let saved = mem::take(&mut *lock(&self.saved));
+        stand_in.callframes.extend(saved.frames);
+        stand_in.eval_depth = saved.depth + 1;
+        stand_in.sources = (saved.sources.into_iter())
+            .map(|(source, depth)| (source, depth + 1))
+            .collect();
+        let _ = self
+            .to_coroutine
+            .send(ToCoroutine::Kill(Box::new(stand_in)));
+    }
+}
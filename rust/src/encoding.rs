@@ -0,0 +1,208 @@
+//! Character encodings, for converting between the interpreter's strings
+//! and bytes read from or written to the outside. Scripts hold bytes as
+//! strings of characters U+0000 to U+00FF, one per byte, which is what
+//! `encoding convertto` returns and `encoding convertfrom` expects.
+//!
+//! Adding an encoding means implementing [`Encoding`] and listing it in
+//! [`ENCODINGS`].
+
+use crate::{TclError, TclErrorKind};
+
+pub(crate) trait Encoding: Sync {
+    fn name(&self) -> &'static str;
+
+    /// How many bytes the character starting with `lead` takes up, which
+    /// channels need to count characters as they read
+    #[cfg_attr(not(feature = "io"), allow(dead_code))]
+    fn char_len(&self, lead: u8) -> usize;
+
+    /// Decodes bytes, replacing what can't be decoded with U+FFFD, or
+    /// failing with the offset of the first bad byte if `strict`
+    fn decode(&self, bytes: &[u8], strict: bool) -> Result<String, usize>;
+
+    /// Encodes text, replacing characters the encoding doesn't have with
+    /// `?`, or failing with the index of the first if `strict`
+    fn encode(&self, text: &str, strict: bool) -> Result<Vec<u8>, usize>;
+}
+
+struct Utf8;
+
+impl Encoding for Utf8 {
+    fn name(&self) -> &'static str {
+        "utf-8"
+    }
+
+    fn char_len(&self, lead: u8) -> usize {
+        match lead {
+            0xF0.. => 4,
+            0xE0.. => 3,
+            0xC0.. => 2,
+            _ => 1,
+        }
+    }
+
+    fn decode(&self, bytes: &[u8], strict: bool) -> Result<String, usize> {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Ok(text.to_string()),
+            Err(e) if strict => Err(e.valid_up_to()),
+            Err(_) => Ok(String::from_utf8_lossy(bytes).into_owned()),
+        }
+    }
+
+    fn encode(&self, text: &str, _strict: bool) -> Result<Vec<u8>, usize> {
+        Ok(text.as_bytes().to_vec())
+    }
+}
+
+/// An encoding with one byte per character, the first `limit` code points
+/// of Unicode: ISO 8859-1 for 256, ASCII for 128
+struct SingleByte {
+    name: &'static str,
+    limit: u32,
+}
+
+impl Encoding for SingleByte {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn char_len(&self, _lead: u8) -> usize {
+        1
+    }
+
+    fn decode(&self, bytes: &[u8], strict: bool) -> Result<String, usize> {
+        bytes
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| match u32::from(b) < self.limit {
+                true => Ok(char::from(b)),
+                false if strict => Err(i),
+                false => Ok('\u{fffd}'),
+            })
+            .collect()
+    }
+
+    fn encode(&self, text: &str, strict: bool) -> Result<Vec<u8>, usize> {
+        text.chars()
+            .enumerate()
+            .map(|(i, c)| match u32::from(c) < self.limit {
+                true => Ok(c as u8),
+                false if strict => Err(i),
+                false => Ok(b'?'),
+            })
+            .collect()
+    }
+}
+
+pub(crate) static UTF8: &dyn Encoding = &Utf8;
+
+pub(crate) static ENCODINGS: &[&dyn Encoding] = &[
+    &SingleByte {
+        name: "ascii",
+        limit: 0x80,
+    },
+    &SingleByte {
+        name: "iso8859-1",
+        limit: 0x100,
+    },
+    &Utf8,
+];
+
+/// Looks up an encoding by name, ignoring case
+pub(crate) fn find(name: &str) -> Result<&'static dyn Encoding, TclError> {
+    ENCODINGS
+        .iter()
+        .copied()
+        .find(|e| e.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            TclError::new(
+                TclErrorKind::General,
+                format!("unknown encoding \"{name}\""),
+            )
+        })
+}
+
+/// Turns a string of bytes, one character each, back into bytes
+pub(crate) fn string_to_bytes(text: &str) -> Result<Vec<u8>, TclError> {
+    text.chars()
+        .map(|c| {
+            u8::try_from(u32::from(c)).map_err(|_| {
+                TclError::new(
+                    TclErrorKind::General,
+                    format!("expected byte sequence but character '{c}' was found"),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Turns bytes into a string with one character for each
+pub(crate) fn bytes_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+pub(crate) fn convert_from(
+    encoding: &dyn Encoding,
+    data: &str,
+    strict: bool,
+) -> Result<String, TclError> {
+    let bytes = string_to_bytes(data)?;
+    encoding.decode(&bytes, strict).map_err(|i| {
+        TclError::new(
+            TclErrorKind::General,
+            format!(
+                "unexpected byte sequence starting at index {i}: '\\x{:02X}'",
+                bytes[i]
+            ),
+        )
+    })
+}
+
+pub(crate) fn convert_to(
+    encoding: &dyn Encoding,
+    data: &str,
+    strict: bool,
+) -> Result<String, TclError> {
+    let bytes = encoding.encode(data, strict).map_err(|i| {
+        let c = data.chars().nth(i).unwrap();
+        TclError::new(
+            TclErrorKind::General,
+            format!(
+                "unexpected character at index {i}: 'U+{:06X}'",
+                u32::from(c)
+            ),
+        )
+    })?;
+    Ok(bytes_to_string(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latin1_and_utf8_round_trip() {
+        let latin1 = find("ISO8859-1").unwrap();
+        assert_eq!(convert_from(latin1, "caf\u{e9}", true).unwrap(), "café");
+        assert_eq!(convert_to(latin1, "café", true).unwrap(), "caf\u{e9}");
+        assert_eq!(convert_to(UTF8, "é", true).unwrap(), "\u{c3}\u{a9}");
+        assert_eq!(convert_from(UTF8, "\u{c3}\u{a9}", true).unwrap(), "é");
+    }
+
+    #[test]
+    fn bad_data_is_replaced_or_rejected() {
+        let ascii = find("ascii").unwrap();
+        assert_eq!(convert_to(ascii, "naïve", false).unwrap(), "na?ve");
+        assert_eq!(
+            convert_to(ascii, "naïve", true).unwrap_err().msg,
+            "unexpected character at index 2: 'U+0000EF'"
+        );
+        assert_eq!(convert_from(UTF8, "a\u{ff}b", false).unwrap(), "a\u{fffd}b");
+        assert_eq!(
+            convert_from(UTF8, "a\u{ff}b", true).unwrap_err().msg,
+            "unexpected byte sequence starting at index 1: '\\xFF'"
+        );
+        assert!(convert_from(UTF8, "€", false).is_err());
+        assert!(find("ebcdic").is_err());
+    }
+}
@@ -0,0 +1,163 @@
+//! Commands made of subcommands, like `string length` and `info vars`. An
+//! [`Ensemble`] looks up the subcommand, accepting any unambiguous prefix
+//! of its name, checks its arguments against a usage string and calls it,
+//! so every such command reports mistakes the same way.
+
+use crate::{CmdFunc, Interp, Status, TclError, TclErrorKind, TclValue};
+
+struct Subcommand {
+    name: String,
+    usage: String,
+    // How many arguments may follow the subcommand's name
+    min: usize,
+    max: usize,
+    func: CmdFunc,
+}
+
+/// A command that dispatches on its first argument. Build one up with
+/// [`Ensemble::subcommand`] and register it with
+/// [`Interp::register_ensemble`].
+pub struct Ensemble {
+    name: String,
+    subcommands: Vec<Subcommand>,
+}
+
+impl Ensemble {
+    pub fn new(name: &str) -> Ensemble {
+        Ensemble {
+            name: name.to_string(),
+            subcommands: Vec::new(),
+        }
+    }
+
+    /// Adds a subcommand. `usage` describes the arguments after its name
+    /// the way error messages show them, as in `string first last`, and
+    /// sets how many it takes: `?arg?` is optional, and `?arg ...?` or
+    /// `?key value ...?` stands for any number. The function is called
+    /// with the whole command, the subcommand's full name in `argv[1]`,
+    /// and no data.
+    pub fn subcommand(mut self, name: &str, usage: &str, func: CmdFunc) -> Ensemble {
+        let (min, max) = arity(usage);
+        self.subcommands.push(Subcommand {
+            name: name.to_string(),
+            usage: usage.to_string(),
+            min,
+            max,
+            func,
+        });
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The names of the subcommands, in the order they were added
+    pub fn subcommands(&self) -> impl Iterator<Item = &str> {
+        self.subcommands.iter().map(|sub| sub.name.as_str())
+    }
+
+    /// Finds the subcommand `name` is, or is an unambiguous prefix of
+    fn find(&self, name: &str) -> Result<&Subcommand, TclError> {
+        if let Some(sub) = self.subcommands.iter().find(|sub| sub.name == name) {
+            return Ok(sub);
+        }
+        let mut matches = self
+            .subcommands
+            .iter()
+            .filter(|sub| !name.is_empty() && sub.name.starts_with(name));
+        match (matches.next(), matches.next()) {
+            (Some(sub), None) => Ok(sub),
+            _ => {
+                let mut names: Vec<&str> = self.subcommands().collect();
+                names.sort();
+                let expected = match names.as_slice() {
+                    [] => String::new(),
+                    [only] => only.to_string(),
+                    [first, second] => format!("{first} or {second}"),
+                    [rest @ .., last] => format!("{}, or {last}", rest.join(", ")),
+                };
+                Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("unknown or ambiguous subcommand \"{name}\": must be {expected}"),
+                ))
+            }
+        }
+    }
+}
+
+/// How many arguments a usage string allows, at least and at most
+fn arity(usage: &str) -> (usize, usize) {
+    let (mut min, mut max) = (0, 0);
+    let mut optional = 0;
+    let mut in_optional = false;
+    for word in usage.split_whitespace() {
+        if word.starts_with('?') {
+            in_optional = true;
+        }
+        if !in_optional {
+            min += 1;
+            max += 1;
+            continue;
+        }
+        if word.trim_matches('?') == "..." {
+            return (min, usize::MAX);
+        }
+        optional += 1;
+        if word.ends_with('?') {
+            in_optional = false;
+            max += optional;
+            optional = 0;
+        }
+    }
+    (min, max)
+}
+
+/// The command an ensemble is registered as
+pub(crate) fn dispatch(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    ensemble: &Ensemble,
+) -> Result<Status, TclError> {
+    let Some(name) = argv.get(1) else {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            format!(
+                "wrong # args: should be \"{} subcommand ?arg ...?\"",
+                argv[0]
+            ),
+        ));
+    };
+    let sub = ensemble.find(name)?;
+    let args = argv.len() - 2;
+    if args < sub.min || args > sub.max {
+        let usage = [argv[0].as_str(), &sub.name, &sub.usage].join(" ");
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            format!("wrong # args: should be \"{}\"", usage.trim_end()),
+        ));
+    }
+
+    if name.as_str() == sub.name {
+        return (sub.func)(interp, argv, None);
+    }
+    let mut argv = argv.to_vec();
+    argv[1] = TclValue::from(sub.name.as_str());
+    (sub.func)(interp, &argv, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_strings_give_the_argument_counts() {
+        assert_eq!(arity(""), (0, 0));
+        assert_eq!(arity("string first last"), (3, 3));
+        assert_eq!(arity("?pattern?"), (0, 1));
+        assert_eq!(arity("?-strict? encoding data"), (2, 3));
+        assert_eq!(arity("dictionary ?key ...?"), (1, usize::MAX));
+        assert_eq!(arity("?key value ...?"), (0, usize::MAX));
+        assert_eq!(arity("?level msg?"), (0, 2));
+    }
+}
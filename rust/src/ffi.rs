@@ -0,0 +1,343 @@
+//! C bindings, declared in include/tcl3.h. An interpreter is handed out as
+//! an opaque pointer that must be released with `tcl3_interp_free`.
+//!
+//! No panic unwinds into C: every entry point catches it and returns
+//! `TCL3_PANIC` instead. An interpreter that panicked may be left half way
+//! through a command and is best freed.
+
+use crate::{Interp, Status, TclError, TclErrorKind, TclValue};
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+
+pub const TCL3_OK: c_int = 0;
+pub const TCL3_ERROR: c_int = 1;
+pub const TCL3_RETURN: c_int = 2;
+pub const TCL3_BREAK: c_int = 3;
+pub const TCL3_CONTINUE: c_int = 4;
+/// A Rust panic was caught at the boundary
+pub const TCL3_PANIC: c_int = -1;
+/// A null pointer was passed where a value was needed
+pub const TCL3_INVALID: c_int = -2;
+
+/// A command implemented in C. It receives the clientdata it was
+/// registered with and the command's words, including its name, and sets
+/// its result (or error message) with `tcl3_set_result`.
+pub type Tcl3CmdFunc = unsafe extern "C" fn(
+    clientdata: *mut c_void,
+    interp: *mut Interp,
+    argc: c_int,
+    argv: *const *const c_char,
+) -> c_int;
+
+struct CCommand {
+    func: Tcl3CmdFunc,
+    clientdata: *mut c_void,
+}
+
+// The interpreter only ever touches clientdata by handing it back to func,
+// on whichever thread the interpreter is on. Whether that's safe for the
+// data is the C side's business, as it is in Tcl.
+unsafe impl Send for CCommand {}
+unsafe impl Sync for CCommand {}
+
+fn call_c_command(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    cmd: &CCommand,
+) -> Result<Status, TclError> {
+    let words: Vec<CString> = argv.iter().map(|w| to_c_string(w.as_str())).collect();
+    let mut ptrs: Vec<*const c_char> = words.iter().map(|w| w.as_ptr()).collect();
+    ptrs.push(std::ptr::null());
+
+    let code = unsafe {
+        (cmd.func)(
+            cmd.clientdata,
+            interp as *mut Interp,
+            words.len() as c_int,
+            ptrs.as_ptr(),
+        )
+    };
+    match code {
+        TCL3_OK => Ok(Status::Ok),
+        TCL3_RETURN => Ok(Status::Return),
+        TCL3_BREAK => Ok(Status::Break),
+        TCL3_CONTINUE => Ok(Status::Continue),
+        TCL3_ERROR => Err(TclError::new(
+            TclErrorKind::General,
+            interp.take_result().to_string(),
+        )),
+        code => Err(TclError::new(
+            TclErrorKind::General,
+            format!("command \"{}\" returned invalid code {code}", argv[0]),
+        )),
+    }
+}
+
+/// C strings end at the first NUL, so a value containing one is cut short
+fn to_c_string(s: &str) -> CString {
+    let end = s.find('\0').unwrap_or(s.len());
+    CString::new(&s[..end]).unwrap()
+}
+
+/// Borrows a string from C, replacing invalid UTF-8
+unsafe fn from_c_string<'a>(s: *const c_char) -> Option<std::borrow::Cow<'a, str>> {
+    if s.is_null() {
+        return None;
+    }
+    Some(unsafe { CStr::from_ptr(s) }.to_string_lossy())
+}
+
+fn guard(f: impl FnOnce() -> c_int) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(TCL3_PANIC)
+}
+
+fn status_code(res: &Result<Status, TclError>) -> c_int {
+    match res {
+        Ok(Status::Ok) => TCL3_OK,
+        Ok(Status::Return) => TCL3_RETURN,
+        Ok(Status::Break) => TCL3_BREAK,
+        Ok(Status::Continue) => TCL3_CONTINUE,
+        Err(_) => TCL3_ERROR,
+    }
+}
+
+/// Creates an interpreter with the default commands, or returns null if
+/// that panicked
+#[unsafe(no_mangle)]
+pub extern "C" fn tcl3_interp_new() -> *mut Interp {
+    panic::catch_unwind(|| Box::into_raw(Box::new(Interp::builder().build())))
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// Frees an interpreter. Null is ignored.
+///
+/// # Safety
+/// `interp` must come from `tcl3_interp_new` and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tcl3_interp_free(interp: *mut Interp) {
+    if !interp.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(interp) })));
+    }
+}
+
+/// Evaluates a script. On error the result holds the message.
+///
+/// # Safety
+/// `interp` must be a live interpreter and `script` a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tcl3_eval(interp: *mut Interp, script: *const c_char) -> c_int {
+    guard(|| {
+        let (Some(interp), Some(script)) =
+            (unsafe { interp.as_mut() }, unsafe { from_c_string(script) })
+        else {
+            return TCL3_INVALID;
+        };
+        status_code(&interp.eval(&script))
+    })
+}
+
+/// Returns the result of the last command. The string belongs to the
+/// interpreter and stays valid until the next call into it.
+///
+/// # Safety
+/// `interp` must be a live interpreter.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tcl3_get_result(interp: *mut Interp) -> *const c_char {
+    let mut result = std::ptr::null();
+    guard(|| {
+        let Some(interp) = (unsafe { interp.as_mut() }) else {
+            return TCL3_INVALID;
+        };
+        interp.ffi_result = to_c_string(interp.result.as_str());
+        result = interp.ffi_result.as_ptr();
+        TCL3_OK
+    });
+    result
+}
+
+/// Sets the result, for commands implemented in C
+///
+/// # Safety
+/// `interp` must be a live interpreter and `value` a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tcl3_set_result(interp: *mut Interp, value: *const c_char) -> c_int {
+    guard(|| {
+        let (Some(interp), Some(value)) =
+            (unsafe { interp.as_mut() }, unsafe { from_c_string(value) })
+        else {
+            return TCL3_INVALID;
+        };
+        interp.set_result(value);
+        TCL3_OK
+    })
+}
+
+/// Sets a variable in the current frame
+///
+/// # Safety
+/// `interp` must be a live interpreter, and `name` and `value`
+/// NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tcl3_set_var(
+    interp: *mut Interp,
+    name: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    guard(|| {
+        let (Some(interp), Some(name), Some(value)) = (
+            unsafe { interp.as_mut() },
+            unsafe { from_c_string(name) },
+            unsafe { from_c_string(value) },
+        ) else {
+            return TCL3_INVALID;
+        };
+        let res = interp.set_var(&name, value);
+        if let Err(e) = &res {
+            interp.set_result(e.msg.clone());
+        }
+        status_code(&res)
+    })
+}
+
+/// Registers a command implemented in C. Fails if the name is taken.
+///
+/// # Safety
+/// `interp` must be a live interpreter and `name` a NUL-terminated string.
+/// `clientdata` is passed to `func` untouched for as long as the command
+/// exists.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tcl3_register_command(
+    interp: *mut Interp,
+    name: *const c_char,
+    func: Option<Tcl3CmdFunc>,
+    clientdata: *mut c_void,
+) -> c_int {
+    guard(|| {
+        let (Some(interp), Some(name), Some(func)) = (
+            unsafe { interp.as_mut() },
+            unsafe { from_c_string(name) },
+            func,
+        ) else {
+            return TCL3_INVALID;
+        };
+        let cmd = CCommand { func, clientdata };
+        let res = interp.register_command_with_data(&name, call_c_command, cmd);
+        if let Err(e) = &res {
+            interp.set_result(e.msg.clone());
+        }
+        status_code(&res)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn result(interp: *mut Interp) -> String {
+        unsafe { CStr::from_ptr(tcl3_get_result(interp)) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    unsafe extern "C" fn cmd_count(
+        clientdata: *mut c_void,
+        interp: *mut Interp,
+        argc: c_int,
+        argv: *const *const c_char,
+    ) -> c_int {
+        let count = unsafe { &mut *(clientdata as *mut c_int) };
+        *count += 1;
+        let last = unsafe { *argv.add(argc as usize - 1) };
+        unsafe { tcl3_set_result(interp, last) };
+        if unsafe { CStr::from_ptr(last) } == c"fail" {
+            TCL3_ERROR
+        } else {
+            TCL3_OK
+        }
+    }
+
+    #[test]
+    fn eval_and_read_results() {
+        let interp = tcl3_interp_new();
+        unsafe {
+            assert_eq!(tcl3_set_var(interp, c"x".as_ptr(), c"20".as_ptr()), TCL3_OK);
+            assert_eq!(tcl3_eval(interp, c"set y [+ $x 1]".as_ptr()), TCL3_OK);
+            assert_eq!(result(interp), "21");
+
+            assert_eq!(tcl3_eval(interp, c"nope".as_ptr()), TCL3_ERROR);
+            assert_eq!(result(interp), "command not found: \"nope\"");
+
+            // A NUL can't cross into C, so the result stops there
+            assert_eq!(tcl3_eval(interp, c"set z a\\0b".as_ptr()), TCL3_OK);
+            assert_eq!(result(interp), "a");
+
+            assert_eq!(tcl3_eval(interp, std::ptr::null()), TCL3_INVALID);
+            assert_eq!(tcl3_eval(std::ptr::null_mut(), c"".as_ptr()), TCL3_INVALID);
+            tcl3_interp_free(interp);
+        }
+    }
+
+    #[test]
+    fn c_commands_get_their_clientdata() {
+        let interp = tcl3_interp_new();
+        let mut count: c_int = 0;
+        let clientdata = &mut count as *mut c_int as *mut c_void;
+        unsafe {
+            let rc = tcl3_register_command(interp, c"count".as_ptr(), Some(cmd_count), clientdata);
+            assert_eq!(rc, TCL3_OK);
+            let rc = tcl3_register_command(interp, c"count".as_ptr(), Some(cmd_count), clientdata);
+            assert_eq!(rc, TCL3_ERROR);
+            let rc = tcl3_register_command(interp, c"x".as_ptr(), None, clientdata);
+            assert_eq!(rc, TCL3_INVALID);
+
+            assert_eq!(
+                tcl3_eval(interp, c"count a; count b {c d}".as_ptr()),
+                TCL3_OK
+            );
+            assert_eq!(result(interp), "c d");
+            assert_eq!(
+                tcl3_eval(interp, c"catch {count fail} msg; set msg".as_ptr()),
+                TCL3_OK
+            );
+            assert_eq!(result(interp), "fail");
+            tcl3_interp_free(interp);
+        }
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn panics_do_not_cross_the_boundary() {
+        assert_eq!(guard(|| panic!("boom")), TCL3_PANIC);
+    }
+
+    #[test]
+    fn header_declares_every_entry_point() {
+        let header = include_str!("../include/tcl3.h");
+        let source = include_str!("ffi.rs");
+        for line in source.lines() {
+            let line = line.trim_start();
+            let Some(rest) = line
+                .strip_prefix("pub extern \"C\" fn ")
+                .or_else(|| line.strip_prefix("pub unsafe extern \"C\" fn "))
+            else {
+                continue;
+            };
+            let name = &rest[..rest.find('(').unwrap()];
+            assert!(
+                header.contains(&format!("{name}(")),
+                "{name} missing from tcl3.h"
+            );
+        }
+        for line in source.lines() {
+            if let Some(rest) = line.strip_prefix("pub const ") {
+                let name = &rest[..rest.find(':').unwrap()];
+                assert!(
+                    header.contains(&format!("#define {name} ")),
+                    "{name} missing from tcl3.h"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Converting command arguments, with the same error messages everywhere.
+//! Each helper reads `argv[idx]`, which the caller has checked exists.
+
+use crate::{TclError, TclErrorKind, TclValue, parse_bool};
+
+pub fn get_int(argv: &[TclValue], idx: usize) -> Result<i64, TclError> {
+    let arg = &argv[idx];
+    arg.trim().parse::<i64>().map_err(|_| {
+        TclError::new(
+            TclErrorKind::InvalidNumber,
+            format!("expected integer but got \"{arg}\""),
+        )
+    })
+}
+
+pub fn get_double(argv: &[TclValue], idx: usize) -> Result<f64, TclError> {
+    let arg = &argv[idx];
+    match arg.trim().parse::<f64>() {
+        Ok(f) if !f.is_nan() => Ok(f),
+        _ => Err(TclError::new(
+            TclErrorKind::InvalidNumber,
+            format!("expected floating-point number but got \"{arg}\""),
+        )),
+    }
+}
+
+/// Accepts true/false, yes/no, on/off in any case, or a number where
+/// nonzero is true
+pub fn get_bool(argv: &[TclValue], idx: usize) -> Result<bool, TclError> {
+    parse_bool(&argv[idx])
+}
+
+/// Reads an index into something `len` long from `argv[idx]`, as
+/// [`parse_index`] does
+pub fn get_index(argv: &[TclValue], idx: usize, len: usize) -> Result<i64, TclError> {
+    parse_index(&argv[idx], len)
+}
+
+/// Reads an index into something `len` long: an integer, `end`, or either
+/// with an integer added or subtracted, as in `end-1` or `2+3`. The result
+/// may be out of range; what that means is up to the command.
+pub fn parse_index(spec: &str, len: usize) -> Result<i64, TclError> {
+    let spec = spec.trim();
+    index_value(spec, len).ok_or_else(|| {
+        TclError::new(
+            TclErrorKind::General,
+            format!("bad index \"{spec}\": must be integer?[+-]integer? or end?[+-]integer?"),
+        )
+    })
+}
+
+fn index_value(s: &str, len: usize) -> Option<i64> {
+    let (base, offset) = match s.strip_prefix("end") {
+        Some(rest) => (len as i64 - 1, rest),
+        None => {
+            // Skip a leading sign so it isn't taken for the operator
+            let split = s
+                .char_indices()
+                .skip(1)
+                .find(|&(_, c)| c == '+' || c == '-')
+                .map_or(s.len(), |(i, _)| i);
+            (s[..split].parse::<i64>().ok()?, &s[split..])
+        }
+    };
+    if offset.is_empty() {
+        return Some(base);
+    }
+
+    let (negate, n) = match offset.split_at(1) {
+        ("+", n) => (false, n),
+        ("-", n) => (true, n),
+        _ => return None,
+    };
+    // The offset is a plain integer, without a sign of its own
+    if !n.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let n = n.parse::<i64>().ok()?;
+    if negate {
+        base.checked_sub(n)
+    } else {
+        base.checked_add(n)
+    }
+}
+
+/// Checks that `argv[idx]` is one of `options`, returning the match
+pub fn get_enum<'a>(
+    argv: &[TclValue],
+    idx: usize,
+    options: &[&'a str],
+) -> Result<&'a str, TclError> {
+    let arg = argv[idx].as_str();
+    if let Some(&option) = options.iter().find(|&&o| o == arg) {
+        return Ok(option);
+    }
+
+    let expected = match options {
+        [] => String::new(),
+        [only] => only.to_string(),
+        [first, second] => format!("{first} or {second}"),
+        [rest @ .., last] => format!("{}, or {last}", rest.join(", ")),
+    };
+    Err(TclError::new(
+        TclErrorKind::General,
+        format!("bad option \"{arg}\": must be {expected}"),
+    ))
+}
+
+/// Beyond this many candidates, [`suggest`] doesn't try
+const MAX_SUGGEST_CANDIDATES: usize = 1000;
+
+/// Picks up to three of `candidates` that `name` may have been a typo
+/// for: those `name` is the start of, first, then those within a small
+/// edit distance, counting a swap of adjacent characters as one edit and
+/// ignoring case. The closest come first. Names shorter than three
+/// characters are too short to guess from, and there are no suggestions
+/// from more than a thousand candidates, where looking would cost more
+/// than an error message is worth.
+pub fn suggest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let len = name.chars().count();
+    if len < 3 {
+        return Vec::new();
+    }
+    let candidates: Vec<&str> = candidates.take(MAX_SUGGEST_CANDIDATES + 1).collect();
+    if candidates.len() > MAX_SUGGEST_CANDIDATES {
+        return Vec::new();
+    }
+    let lower = name.to_lowercase();
+    let max_distance = (len / 3).max(1);
+
+    let mut close: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .filter(|&c| c != name)
+        .filter_map(|c| {
+            let candidate = c.to_lowercase();
+            if candidate.starts_with(&lower) {
+                return Some((0, c));
+            }
+            let d = edit_distance(&lower, &candidate);
+            (d <= max_distance).then_some((d, c))
+        })
+        .collect();
+    close.sort();
+    close.dedup();
+    close.into_iter().take(3).map(|(_, c)| c).collect()
+}
+
+/// Formats suggestions as the end of an error message, as in
+/// `; did you mean "puts"?`, or returns the empty string if there are none
+pub fn did_you_mean(suggestions: &[impl AsRef<str>]) -> String {
+    let quoted: Vec<String> = suggestions
+        .iter()
+        .map(|s| format!("\"{}\"", s.as_ref()))
+        .collect();
+    match &quoted[..] {
+        [] => String::new(),
+        [only] => format!("; did you mean {only}?"),
+        [rest @ .., last] => format!("; did you mean {} or {last}?", rest.join(", ")),
+    }
+}
+
+/// The optimal string alignment distance: insertions, deletions,
+/// substitutions and swaps of adjacent characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Three rows of the table: two back, one back and the current one
+    let mut prev2 = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        cur[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            cur[j] = (prev[j] + 1).min(cur[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                cur[j] = cur[j].min(prev2[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut prev2, &mut prev);
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(words: &[&str]) -> Vec<TclValue> {
+        words.iter().map(|&w| TclValue::from(w)).collect()
+    }
+
+    #[test]
+    fn numbers() {
+        let argv = args(&["cmd", "42", " -7 ", "1.5", "x", "1e3", "nan"]);
+        assert_eq!(get_int(&argv, 1), Ok(42));
+        assert_eq!(get_int(&argv, 2), Ok(-7));
+        let e = get_int(&argv, 3).unwrap_err();
+        assert_eq!(e.kind, TclErrorKind::InvalidNumber);
+        assert_eq!(e.msg, "expected integer but got \"1.5\"");
+
+        assert_eq!(get_double(&argv, 1), Ok(42.0));
+        assert_eq!(get_double(&argv, 5), Ok(1000.0));
+        let e = get_double(&argv, 4).unwrap_err();
+        assert_eq!(e.msg, "expected floating-point number but got \"x\"");
+        assert!(get_double(&argv, 6).is_err());
+    }
+
+    #[test]
+    fn booleans() {
+        let argv = args(&["cmd", "yes", "OFF", "0", "1", "true", "maybe"]);
+        let values: Vec<_> = (1..6).map(|i| get_bool(&argv, i).unwrap()).collect();
+        assert_eq!(values, [true, false, false, true, true]);
+        let e = get_bool(&argv, 6).unwrap_err();
+        assert_eq!(e.msg, "expected boolean value but got \"maybe\"");
+    }
+
+    #[test]
+    fn indexes() {
+        let cases = [
+            ("0", Some(0)),
+            ("3", Some(3)),
+            ("-1", Some(-1)),
+            ("end", Some(4)),
+            ("end-1", Some(3)),
+            ("end+2", Some(6)),
+            ("1+2", Some(3)),
+            ("-1+2", Some(1)),
+            ("5-6", Some(-1)),
+            ("end--1", None),
+            ("end-", None),
+            ("end-x", None),
+            ("ending", None),
+            ("", None),
+            ("1.5", None),
+        ];
+        for (index, expected) in cases {
+            let argv = args(&["cmd", index]);
+            match expected {
+                Some(i) => assert_eq!(get_index(&argv, 1, 5), Ok(i), "{index}"),
+                None => {
+                    let e = get_index(&argv, 1, 5).unwrap_err();
+                    assert_eq!(
+                        e.msg,
+                        format!(
+                            "bad index \"{index}\": must be integer?[+-]integer? or end?[+-]integer?"
+                        )
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn indexes_from_strings() {
+        assert_eq!(parse_index(" end-1 ", 3), Ok(1));
+        assert_eq!(parse_index("end", 0), Ok(-1));
+        assert_eq!(
+            parse_index("x", 3).unwrap_err().msg,
+            "bad index \"x\": must be integer?[+-]integer? or end?[+-]integer?"
+        );
+    }
+
+    #[test]
+    fn suggestions() {
+        let names = ["puts", "proc", "set", "unset", "history", "interp", "info"];
+        let suggest = |name| suggest(name, names.iter().copied());
+        assert_eq!(suggest("putss"), ["puts"]);
+        assert_eq!(suggest("ptus"), ["puts"]);
+        assert_eq!(suggest("PUTS"), ["puts"]);
+        assert_eq!(suggest("hist"), ["history"]);
+        assert_eq!(suggest("inof"), ["info"]);
+        assert_eq!(suggest("sett"), ["set"]);
+        assert_eq!(suggest("inter"), ["interp"]);
+        assert!(suggest("frobnicate").is_empty());
+        assert!(suggest("xyzzy").is_empty());
+        // Too short to guess from, and nothing to suggest for a real name
+        assert!(suggest("st").is_empty());
+        assert!(suggest("puts").is_empty());
+
+        // Truncations rank ahead of closer typos
+        let vars = ["count", "counter", "countr2"];
+        assert_eq!(
+            super::suggest("counte", vars.iter().copied()),
+            ["counter", "count", "countr2"]
+        );
+        let many: Vec<String> = (0..2000).map(|i| format!("v{i}")).collect();
+        assert!(super::suggest("v12", many.iter().map(String::as_str)).is_empty());
+    }
+
+    #[test]
+    fn suggestion_messages() {
+        assert_eq!(did_you_mean(&[] as &[&str]), "");
+        assert_eq!(did_you_mean(&["puts"]), "; did you mean \"puts\"?");
+        assert_eq!(
+            did_you_mean(&["a", "b", "c"]),
+            "; did you mean \"a\", \"b\" or \"c\"?"
+        );
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("abcd", "acbd"), 1);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn enums() {
+        let argv = args(&["cmd", "glob", "fuzzy"]);
+        assert_eq!(get_enum(&argv, 1, &["exact", "glob"]), Ok("glob"));
+        let message = |options: &[&str]| get_enum(&argv, 2, options).unwrap_err().msg;
+        assert_eq!(message(&["exact"]), "bad option \"fuzzy\": must be exact");
+        assert_eq!(
+            message(&["exact", "glob"]),
+            "bad option \"fuzzy\": must be exact or glob"
+        );
+        assert_eq!(
+            message(&["exact", "glob", "regexp"]),
+            "bad option \"fuzzy\": must be exact, glob, or regexp"
+        );
+    }
+}
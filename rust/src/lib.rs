@@ -0,0 +1,8893 @@
+//! A small Tcl interpreter. Most explanatory comments are in the C++ version.
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, TryLockError};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "io")]
+mod channel;
+mod clock;
+#[cfg(feature = "coroutine")]
+mod coroutine;
+mod encoding;
+pub mod ensemble;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod helpers;
+pub mod tokens;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "io")]
+pub use channel::Stream;
+use ensemble::Ensemble;
+use helpers::{get_enum, get_index};
+
+/// The kind of a token produced by the [`Parser`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Token {
+    Esc,
+    Str,
+    Cmd,
+    Var,
+    Sep,
+    Eol,
+    Eof,
+}
+
+/// How a command finished when it didn't raise an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Return,
+    Break,
+    Continue,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TclErrorKind {
+    General,
+    Arity,
+    CommandNotFound,
+    CommandAlreadyDefined,
+    VariableNotFound,
+    InvalidNumber,
+    DivideByZero,
+    LimitExceeded,
+    /// The host asked for the evaluation to stop, through
+    /// [`Interp::interrupt_handle`]. Like running out of evaluation steps,
+    /// it unwinds the whole evaluation.
+    Interrupted,
+    /// Raised by `exit` with the status the script asked for. It unwinds
+    /// the whole evaluation, and what exiting means is up to the host.
+    Exit(i32),
+    /// The command of a suspended coroutine was deleted. It unwinds what
+    /// the coroutine was evaluating, on the coroutine's own thread.
+    CoroutineDeleted,
+}
+
+/// An error raised while evaluating a script: what kind of error it is,
+/// the message a script would see through `catch`, and optionally a
+/// machine-readable errorCode list such as `POSIX ENOENT {no such file or
+/// directory}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TclError {
+    pub kind: TclErrorKind,
+    pub msg: String,
+    pub code: Option<String>,
+}
+
+impl TclError {
+    pub fn new(kind: TclErrorKind, msg: impl Into<String>) -> TclError {
+        TclError {
+            kind,
+            msg: msg.into(),
+            code: None,
+        }
+    }
+
+    /// Sets the errorCode list scripts see for this error
+    pub fn with_code(mut self, code: impl Into<String>) -> TclError {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// The errorCode list: the one given with [`TclError::with_code`], or
+    /// one that follows from the kind of error, which is `NONE` for most
+    pub fn error_code(&self) -> String {
+        match (&self.code, self.kind) {
+            (Some(code), _) => code.clone(),
+            (None, TclErrorKind::DivideByZero) => list_from_iter(["ARITH", "DIVZERO", &self.msg]),
+            (None, _) => "NONE".to_string(),
+        }
+    }
+
+    /// Whether the error unwinds past catch and try, ending the whole
+    /// evaluation
+    fn is_uncatchable(&self) -> bool {
+        matches!(
+            self.kind,
+            TclErrorKind::LimitExceeded
+                | TclErrorKind::Interrupted
+                | TclErrorKind::Exit(_)
+                | TclErrorKind::CoroutineDeleted
+        )
+    }
+}
+
+impl std::fmt::Display for TclError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.msg)
+    }
+}
+
+impl std::error::Error for TclError {}
+
+/// A Tcl value. Every value is a string, but the number or list it parses
+/// as is cached the first time it's asked for, so that a loop counter or
+/// a list isn't reparsed on every use. Values are immutable and cheap to
+/// clone: clones share the string and its cached representations.
+///
+/// Short strings, which covers most command names and literal words, are
+/// stored inline and never allocate; they aren't worth caching.
+#[derive(Clone)]
+pub struct TclValue(Repr);
+
+const INLINE_CAP: usize = 22;
+
+#[derive(Clone)]
+enum Repr {
+    Inline { len: u8, buf: [u8; INLINE_CAP] },
+    Shared(Arc<ValueRep>),
+}
+
+#[derive(Default)]
+struct ValueRep {
+    string: String,
+    number: OnceLock<Option<Number>>,
+    list: OnceLock<Arc<[TclValue]>>,
+}
+
+impl TclValue {
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            Repr::Inline { len, buf } => {
+                std::str::from_utf8(&buf[..*len as usize]).expect("inline value is utf-8")
+            }
+            Repr::Shared(rep) => &rep.string,
+        }
+    }
+
+    /// Returns the value as a number, if it is one
+    pub fn as_number(&self) -> Option<Number> {
+        match &self.0 {
+            Repr::Inline { .. } => parse_number(self.as_str()).ok(),
+            Repr::Shared(rep) => *rep.number.get_or_init(|| parse_number(&rep.string).ok()),
+        }
+    }
+
+    /// Returns the elements of the value as a list
+    pub fn as_list(&self) -> Result<Arc<[TclValue]>, TclError> {
+        let split = |s: &str| -> Result<Arc<[TclValue]>, TclError> {
+            Ok(parse_list(s)?.into_iter().map(TclValue::from).collect())
+        };
+        match &self.0 {
+            Repr::Inline { .. } => split(self.as_str()),
+            Repr::Shared(rep) => {
+                if let Some(list) = rep.list.get() {
+                    return Ok(Arc::clone(list));
+                }
+                let list = split(&rep.string)?;
+                Ok(Arc::clone(rep.list.get_or_init(|| list)))
+            }
+        }
+    }
+
+    /// Appends to the value. The string is extended in place when it fits
+    /// inline or this is the only reference to it, and copied otherwise.
+    fn push_str(&mut self, s: &str) {
+        match &mut self.0 {
+            Repr::Inline { len, buf } if *len as usize + s.len() <= INLINE_CAP => {
+                let start = *len as usize;
+                buf[start..start + s.len()].copy_from_slice(s.as_bytes());
+                *len += s.len() as u8;
+                return;
+            }
+            Repr::Shared(rc) => {
+                if let Some(rep) = Arc::get_mut(rc) {
+                    rep.string.push_str(s);
+                    rep.number.take();
+                    rep.list.take();
+                    return;
+                }
+            }
+            Repr::Inline { .. } => {}
+        }
+        // A word being built from fragments usually gets more appended,
+        // so leave room to grow in place
+        let mut string = String::with_capacity(2 * (self.len() + s.len()));
+        string.push_str(self);
+        string.push_str(s);
+        *self = TclValue::from(string);
+    }
+
+    fn inline(s: &str) -> Option<TclValue> {
+        if s.len() > INLINE_CAP {
+            return None;
+        }
+        let mut buf = [0; INLINE_CAP];
+        buf[..s.len()].copy_from_slice(s.as_bytes());
+        Some(TclValue(Repr::Inline {
+            len: s.len() as u8,
+            buf,
+        }))
+    }
+}
+
+impl Default for TclValue {
+    fn default() -> TclValue {
+        TclValue(Repr::Inline {
+            len: 0,
+            buf: [0; INLINE_CAP],
+        })
+    }
+}
+
+impl From<String> for TclValue {
+    fn from(string: String) -> TclValue {
+        TclValue::inline(&string).unwrap_or_else(|| {
+            TclValue(Repr::Shared(Arc::new(ValueRep {
+                string,
+                ..Default::default()
+            })))
+        })
+    }
+}
+
+impl From<&str> for TclValue {
+    fn from(s: &str) -> TclValue {
+        TclValue::inline(s).unwrap_or_else(|| TclValue::from(s.to_string()))
+    }
+}
+
+impl From<Cow<'_, str>> for TclValue {
+    fn from(s: Cow<'_, str>) -> TclValue {
+        match s {
+            Cow::Borrowed(s) => TclValue::from(s),
+            Cow::Owned(s) => TclValue::from(s),
+        }
+    }
+}
+
+impl From<&String> for TclValue {
+    fn from(s: &String) -> TclValue {
+        TclValue::from(s.clone())
+    }
+}
+
+impl From<&TclValue> for TclValue {
+    fn from(v: &TclValue) -> TclValue {
+        v.clone()
+    }
+}
+
+impl From<Number> for TclValue {
+    fn from(n: Number) -> TclValue {
+        TclValue(Repr::Shared(Arc::new(ValueRep {
+            string: format_number(n, 0),
+            number: OnceLock::from(Some(n)),
+            ..Default::default()
+        })))
+    }
+}
+
+impl From<TclValue> for String {
+    fn from(v: TclValue) -> String {
+        v.as_str().to_string()
+    }
+}
+
+impl std::ops::Deref for TclValue {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for TclValue {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl std::borrow::Borrow<str> for TclValue {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl std::fmt::Display for TclValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::fmt::Debug for TclValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl PartialEq for TclValue {
+    fn eq(&self, other: &TclValue) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for TclValue {}
+
+impl std::hash::Hash for TclValue {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialEq<str> for TclValue {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for TclValue {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// A token, where its text is in the script, and the line it starts on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TokenInfo {
+    pub token: Token,
+    pub span: (usize, usize),
+    pub line: usize,
+}
+
+/// A script that can't be parsed: what's missing, and the offset and
+/// line where the brace, quote or bracket that needed it opened
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: &'static str,
+    pub offset: usize,
+    pub line: usize,
+}
+
+/// What Parser::scan found: a token, or the start of a command
+/// substitution whose end hasn't been found yet
+enum Scan {
+    Token(Token),
+    Bracket,
+}
+
+/// Splits a script into tokens, one call to [`Parser::next`] at a time.
+pub struct Parser<'a> {
+    // Because we want to keep the parser to zero allocations, we need to
+    // declare a lifetime here so we can simply take a reference to a string
+    // instead of having our own heap-allocated string.  The parser won't be
+    // allowed to outlive the string. This also makes it possible to safely
+    // give sub-strings to sub-parsers.
+    body: &'a str,
+
+    cursor: usize,
+    begin: usize,
+    end: usize,
+    token: Token,
+
+    // Line of the cursor and of the start of the current token, from 1
+    line: usize,
+    begin_line: usize,
+
+    in_string: bool,
+    in_quote: bool,
+    in_brace: bool,
+    // Nothing but blanks since the end of the last command, so that a #
+    // here starts a comment
+    at_command_start: bool,
+
+    terminating_char: u8,
+    brace_level: usize,
+    // Whether the terminating character was found
+    closed: bool,
+    // Where the open brace or quote is
+    opened: (usize, usize),
+
+    error: Option<ParseError>,
+
+    trace: bool,
+
+    // Where the body starts in the script the bracket cache covers
+    offset: usize,
+    brackets: Option<BracketCache>,
+}
+
+/// The extents of the command substitutions found so far in a script,
+/// keyed by the offset just past their opening bracket: how far their
+/// closing bracket is from there, and the number of lines in between.
+/// Evaluating a substitution parses its text again, and this saves finding
+/// the end of every bracket nested inside it once more.
+type BracketCache = Rc<RefCell<HashMap<usize, (usize, usize)>>>;
+
+impl<'a> Parser<'a> {
+    pub fn new(body: &'a str) -> Parser<'a> {
+        Parser {
+            body,
+
+            cursor: 0,
+            begin: 0,
+            end: 0,
+
+            token: Token::Eol,
+
+            line: 1,
+            begin_line: 1,
+
+            in_string: false,
+            in_quote: false,
+            in_brace: false,
+            at_command_start: true,
+
+            terminating_char: 0,
+            brace_level: 0,
+            closed: false,
+            opened: (0, 0),
+
+            error: None,
+
+            trace: false,
+
+            offset: 0,
+            brackets: None,
+        }
+    }
+
+    pub fn done(&mut self) -> bool {
+        self.cursor >= self.body.len()
+    }
+
+    /// Returns the byte at the cursor, or None at the end of the script
+    pub fn peek(&self) -> Option<u8> {
+        self.body.as_bytes().get(self.cursor).copied()
+    }
+
+    /// Returns the byte at the cursor and moves past it, or None at the
+    /// end of the script
+    pub fn getc(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.cursor += 1;
+        if c == b'\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    pub fn back(&mut self) {
+        if self.cursor == 0 {
+            return;
+        }
+        self.cursor -= 1;
+        if self.peek() == Some(b'\n') {
+            self.line -= 1;
+        }
+    }
+
+    /// Moves past the character at the cursor, all of its bytes, so that
+    /// the cursor never stops inside a multi-byte character. Token
+    /// boundaries only fall on ASCII delimiters otherwise.
+    fn skip_char(&mut self) {
+        self.getc();
+        while !self.done() && !self.body.is_char_boundary(self.cursor) {
+            self.cursor += 1;
+        }
+    }
+
+    /// Returns the error that stopped parsing, after next has returned
+    /// Eof
+    pub fn error(&self) -> Option<&ParseError> {
+        self.error.as_ref()
+    }
+
+    fn fail(&mut self, message: &'static str, offset: usize, line: usize) -> Token {
+        self.error = Some(ParseError {
+            message,
+            offset,
+            line,
+        });
+        self.token = Token::Eof;
+        self.end = self.begin;
+        self.token
+    }
+
+    /// Returns whether the cursor, just past a `$`, is at a variable name
+    fn at_var_reference(&self) -> bool {
+        let rest = &self.body.as_bytes()[self.cursor..];
+        rest.first() == Some(&b'{') || var_name_len(rest) > 0
+    }
+
+    /// Moves past the parenthesized index of an array element reference,
+    /// which can contain substitutions of its own. Returns false if it
+    /// isn't closed.
+    fn scan_var_index(&mut self) -> bool {
+        let (offset, line) = (self.cursor, self.line);
+        self.getc();
+        let mut brackets = 0;
+        loop {
+            match self.getc() {
+                None => {
+                    self.fail("missing )", offset, line);
+                    return false;
+                }
+                Some(b'\\') => self.skip_char(),
+                Some(b'[') => brackets += 1,
+                Some(b']') if brackets > 0 => brackets -= 1,
+                Some(b')') if brackets == 0 => return true,
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Returns the line the current token starts on, counting from 1
+    pub fn token_line(&self) -> usize {
+        self.begin_line
+    }
+
+    /// Skips blanks and semicolons, returning whether the command ended:
+    /// at a semicolon, or at the newline the cursor is left on
+    pub fn consume_whitespace_check_eol(&mut self) -> bool {
+        let mut eol = false;
+        while let Some(c) = self.peek() {
+            if c == b'\n' {
+                return true;
+            } else if c == b';' {
+                self.getc();
+                eol = true;
+            } else if c == b' ' || c == b'\r' || c == b'\t' {
+                self.getc();
+            } else if c == b'\\' && self.body.as_bytes().get(self.cursor + 1) == Some(&b'\n') {
+                self.getc();
+                self.getc();
+            } else {
+                break;
+            }
+        }
+        eol
+    }
+
+    pub fn recurse<'b>(&mut self, sub: &mut Parser<'b>, terminating_char: u8) {
+        sub.terminating_char = terminating_char;
+        // Brackets nested in the sub-parser's text get parsers of their
+        // own, kept on a stack rather than recursing so that deep nesting
+        // can't overflow the native stack
+        let mut nested: Vec<Parser<'b>> = Vec::new();
+        loop {
+            let p = nested.last_mut().unwrap_or(&mut *sub);
+            match p.scan() {
+                Scan::Bracket => {
+                    if !p.skip_known_bracket() {
+                        let inner = p.bracket_parser();
+                        nested.push(inner);
+                    }
+                }
+                Scan::Token(Token::Eof) => {
+                    let Some(done) = nested.pop() else {
+                        break;
+                    };
+                    let p = nested.last_mut().unwrap_or(&mut *sub);
+                    p.finish_bracket(&done);
+                }
+                Scan::Token(_) => {}
+            }
+        }
+        self.finish_bracket(sub);
+    }
+
+    /// Ends the current token as a command substitution once `sub` has
+    /// parsed the text between its brackets, moving past it. An error in
+    /// there is an error here too.
+    fn finish_bracket(&mut self, sub: &Parser) -> Token {
+        if let Some(e) = &sub.error {
+            let (offset, line) = (self.cursor + e.offset, self.line + e.line - 1);
+            return self.fail(e.message, offset, line);
+        }
+        if !sub.closed {
+            return self.fail("missing close-bracket", self.begin - 1, self.begin_line);
+        }
+
+        sub.remember_extent();
+        self.cursor += sub.cursor;
+        self.line += sub.line - 1;
+        self.finish_cmd()
+    }
+
+    /// A parser for the command in brackets that starts at the cursor
+    fn bracket_parser(&self) -> Parser<'a> {
+        let mut sub = Parser::new(&self.body[self.cursor..]);
+        sub.terminating_char = b']';
+        sub.offset = self.offset + self.cursor;
+        sub.brackets = self.brackets.clone();
+        sub
+    }
+
+    /// Records how far this bracket parser got, once it has found its
+    /// closing bracket
+    fn remember_extent(&self) {
+        if let Some(brackets) = &self.brackets {
+            brackets
+                .borrow_mut()
+                .insert(self.offset, (self.cursor, self.line - 1));
+        }
+    }
+
+    /// Moves past the command in brackets starting at the cursor if its
+    /// end is already known, finishing the token
+    fn skip_known_bracket(&mut self) -> bool {
+        let known = self
+            .brackets
+            .as_ref()
+            .and_then(|b| b.borrow().get(&(self.offset + self.cursor)).copied());
+        let Some((len, lines)) = known else {
+            return false;
+        };
+        self.cursor += len;
+        self.line += lines;
+        self.finish_cmd();
+        true
+    }
+
+    /// Ends the current token as a command substitution, once the cursor
+    /// is past its closing bracket
+    fn finish_cmd(&mut self) -> Token {
+        self.token = Token::Cmd;
+        self.end = self.cursor - 1;
+        self.token
+    }
+
+    pub fn next_impl(&mut self) -> Token {
+        match self.scan() {
+            Scan::Token(token) => token,
+            Scan::Bracket => {
+                if self.skip_known_bracket() {
+                    return self.token;
+                }
+                let mut sub = self.bracket_parser();
+                self.recurse(&mut sub, b']');
+                self.token
+            }
+        }
+    }
+
+    /// Scans the next token, stopping short at the opening bracket of a
+    /// command substitution
+    fn scan(&mut self) -> Scan {
+        if self.error.is_some() {
+            self.token = Token::Eof;
+            return Scan::Token(self.token);
+        }
+
+        self.at_command_start = match self.token {
+            Token::Eol => true,
+            Token::Sep => self.at_command_start,
+            _ => false,
+        };
+
+        if self.done() {
+            // A quote can be left open after a command substitution in it
+            if self.in_quote {
+                let (offset, line) = self.opened;
+                self.fail("missing \"", offset, line);
+                return Scan::Token(self.token);
+            }
+            if self.token != Token::Eof && self.token != Token::Eol {
+                self.token = Token::Eol;
+            } else {
+                self.token = Token::Eof;
+            }
+            return Scan::Token(self.token);
+        }
+
+        self.token = Token::Esc;
+        self.begin = self.cursor;
+        self.begin_line = self.line;
+
+        let mut adj: usize = 0;
+        let mut skipped_comment = false;
+
+        while let Some(c) = self.getc() {
+            adj = 0;
+
+            // An escaped character never ends or opens anything. Braced
+            // words keep the backslash; elsewhere it's substituted when
+            // the word is evaluated.
+            if c == b'\\' {
+                let continues_line = self.peek() == Some(b'\n');
+                if continues_line && !self.in_brace && !self.in_quote {
+                    // Outside braces and quotes, a backslash-newline
+                    // separates words like a space does
+                    if self.in_string {
+                        self.back();
+                        self.in_string = false;
+                        break;
+                    }
+                    self.getc();
+                    self.token = Token::Sep;
+                    if self.consume_whitespace_check_eol() {
+                        self.token = Token::Eol;
+                    }
+                    break;
+                }
+                self.skip_char();
+                if !self.in_brace && !self.in_quote {
+                    self.in_string = true;
+                }
+                continue;
+            }
+
+            // A bracket in a braced or quoted word of the command doesn't
+            // close it
+            if c == self.terminating_char && !self.in_brace && !self.in_quote {
+                self.closed = true;
+                self.end = self.cursor - 1;
+                return Scan::Token(Token::Eof);
+            }
+
+            match c {
+                b'{' => {
+                    if self.in_quote || self.in_string {
+                        continue;
+                    }
+
+                    if !self.in_brace {
+                        self.begin += 1;
+                        self.token = Token::Str;
+                        self.in_brace = true;
+                        self.opened = (self.cursor - 1, self.line);
+                    }
+
+                    self.brace_level += 1;
+                }
+                b'}' => {
+                    if self.in_quote || self.in_string {
+                        continue;
+                    }
+
+                    if self.brace_level > 0 {
+                        self.brace_level -= 1;
+                        if self.brace_level == 0 {
+                            self.in_brace = false;
+                            adj = 1;
+                            break;
+                        }
+                    }
+                }
+                b'[' => {
+                    if self.in_brace {
+                        continue;
+                    }
+
+                    // The text before the bracket is a token of its own,
+                    // continuing the same word
+                    if self.cursor != self.begin + 1 {
+                        self.back();
+                        self.in_string = false;
+                        break;
+                    }
+
+                    self.begin += 1;
+                    return Scan::Bracket;
+                }
+
+                b'$' => {
+                    // A $ that doesn't start a variable reference is an
+                    // ordinary character
+                    if self.in_brace || !self.at_var_reference() {
+                        if !self.in_brace && !self.in_quote {
+                            self.in_string = true;
+                        }
+                        continue;
+                    }
+
+                    // The text before the variable is a token of its own,
+                    // continuing the same word
+                    if self.cursor != self.begin + 1 {
+                        self.back();
+                        self.in_string = false;
+                        break;
+                    }
+
+                    self.begin += 1;
+                    self.token = Token::Var;
+
+                    // ${name} takes everything up to the closing brace as
+                    // the name
+                    if self.peek() == Some(b'{') {
+                        match self.body[self.cursor..].find('}') {
+                            Some(len) => {
+                                self.begin += 1;
+                                for _ in 0..=len {
+                                    self.getc();
+                                }
+                                adj = 1;
+                            }
+                            None => {
+                                let (offset, line) = (self.begin - 1, self.begin_line);
+                                self.fail("missing close-brace for variable name", offset, line);
+                                return Scan::Token(self.token);
+                            }
+                        }
+                        break;
+                    }
+
+                    self.cursor += var_name_len(&self.body.as_bytes()[self.cursor..]);
+                    if self.peek() == Some(b'(') && !self.scan_var_index() {
+                        return Scan::Token(self.token);
+                    }
+                    break;
+                }
+
+                b'#' => {
+                    // Only where a command would start does # begin a
+                    // comment; elsewhere it's an ordinary character
+                    if self.in_string || self.in_quote || self.in_brace || !self.at_command_start {
+                        if !self.in_brace && !self.in_quote {
+                            self.in_string = true;
+                        }
+                        continue;
+                    }
+
+                    while let Some(c) = self.getc() {
+                        match c {
+                            b'\n' => break,
+                            // A backslash-newline continues the comment
+                            b'\\' => {
+                                self.getc();
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    // The token starts after the comment instead
+                    self.begin = self.cursor;
+                    self.begin_line = self.line;
+                    skipped_comment = true;
+                }
+
+                b'"' => {
+                    // A quote only opens a word, as a brace does
+                    if self.in_brace || self.in_string {
+                        continue;
+                    }
+
+                    if self.in_quote {
+                        self.in_quote = false;
+                        adj = 1;
+                        break;
+                    }
+
+                    self.in_quote = true;
+                    self.opened = (self.cursor - 1, self.line);
+                    self.begin += 1;
+                    adj = 1;
+                }
+
+                b'\n' | b'\r' | b'\t' | b';' | b' ' => {
+                    if self.in_brace {
+                        continue;
+                    }
+
+                    if self.in_string {
+                        self.back();
+                        self.in_string = false;
+                        break;
+                    }
+
+                    if self.in_quote {
+                        continue;
+                    }
+
+                    self.token = if c == b'\n' || c == b';' {
+                        Token::Eol
+                    } else {
+                        Token::Sep
+                    };
+
+                    if self.consume_whitespace_check_eol() {
+                        self.token = Token::Eol;
+                    }
+                    break;
+                }
+                _ => {
+                    if !self.in_brace && !self.in_quote {
+                        self.in_string = true;
+                    }
+                }
+            }
+        }
+
+        // A comment ran to the end of the script
+        if skipped_comment && self.begin == self.cursor {
+            self.end = self.cursor;
+            return self.scan();
+        }
+
+        if self.done() && (self.in_brace || self.in_quote) {
+            let message = if self.in_brace {
+                "missing close-brace"
+            } else {
+                "missing \""
+            };
+            let (offset, line) = self.opened;
+            self.fail(message, offset, line);
+            return Scan::Token(self.token);
+        }
+
+        self.end = self.cursor - adj;
+
+        Scan::Token(self.token)
+    }
+
+    /// Returns the tokens of a script
+    pub fn tokens(body: &'a str) -> Tokens<'a> {
+        Parser::new(body).into_iter()
+    }
+
+    /// The current token's text, borrowed from the script rather than the
+    /// parser
+    pub fn token_body(&self) -> &'a str {
+        &self.body[self.begin..self.end]
+    }
+
+    /// The current token
+    pub fn current_token(&self) -> Token {
+        self.token
+    }
+
+    /// The byte offsets where the current token's text starts and ends.
+    /// Braces, quotes and brackets around a word aren't included.
+    pub fn span(&self) -> (usize, usize) {
+        (self.begin, self.end)
+    }
+
+    /// The byte offset the parser has got to
+    pub fn position(&self) -> usize {
+        self.cursor
+    }
+
+    /// The line and column where the current token's text starts, both
+    /// counting from 1. Columns count characters rather than bytes.
+    pub fn line_col(&self) -> (usize, usize) {
+        let before = &self.body[..self.begin];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (self.token_line(), before[line_start..].chars().count() + 1)
+    }
+
+    /// Moves to the next token like next, returning all there is to know
+    /// about it
+    pub fn next_info(&mut self) -> TokenInfo {
+        let token = self.next();
+        TokenInfo {
+            token,
+            span: self.span(),
+            line: self.token_line(),
+        }
+    }
+
+    // Unlike Iterator::next this keeps returning Eof at the end; see
+    // Tokens for the iterator
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Token {
+        let tk = self.next_impl();
+
+        if self.trace {
+            let (begin, end) = self.span();
+            let uppercase_type = format!("{tk:?}").to_uppercase();
+            eprintln!(
+                "{{\"type\": \"TK_{uppercase_type}\", \"begin\": {begin}, \"end\": {end}, \"body\": {:?}}}",
+                self.token_body()
+            );
+        }
+
+        tk
+    }
+}
+
+/// The tokens of a script up to its end, with their text. Parsing stops
+/// early at an error, which `error` then returns.
+pub struct Tokens<'a> {
+    parser: Parser<'a>,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn error(&self) -> Option<&ParseError> {
+        self.parser.error()
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = (TokenInfo, &'a str);
+
+    fn next(&mut self) -> Option<(TokenInfo, &'a str)> {
+        if self.parser.current_token() == Token::Eof {
+            return None;
+        }
+        let info = self.parser.next_info();
+        if info.token == Token::Eof {
+            return None;
+        }
+        Some((info, self.parser.token_body()))
+    }
+}
+
+impl<'a> IntoIterator for Parser<'a> {
+    type Item = (TokenInfo, &'a str);
+    type IntoIter = Tokens<'a>;
+
+    fn into_iter(self) -> Tokens<'a> {
+        Tokens { parser: self }
+    }
+}
+
+/// Returns whether a script is complete, rather than stopping inside a
+/// brace, quote or bracket or continuing onto another line with a
+/// backslash. This only parses the script.
+pub fn script_is_complete(s: &str) -> bool {
+    let line = s.strip_suffix('\n').unwrap_or(s);
+    let trailing_backslashes = line.bytes().rev().take_while(|&c| c == b'\\').count();
+    if trailing_backslashes % 2 == 1 {
+        return false;
+    }
+
+    let mut tokens = Parser::tokens(s);
+    tokens.by_ref().for_each(drop);
+    tokens.error().is_none()
+}
+
+/// Returns how many braces, brackets and quotes a script leaves open, so
+/// 0 for a complete script and 2 for `proc p {} {` followed by a line
+/// with `if {$x} {`. A backslash at the end of the script opens nothing.
+pub fn script_depth(s: &str) -> usize {
+    let mut tokens = Parser::tokens(s);
+    tokens.by_ref().for_each(drop);
+    let Some(e) = tokens.error() else {
+        return 0;
+    };
+    // The parser stops at the innermost construct left open. Those around
+    // it are open in the script before it, and braces in a braced word
+    // only nest without being parsed.
+    let inner = match e.message {
+        "missing close-brace" => {
+            let mut depth = 1;
+            let mut bytes = s.as_bytes()[e.offset + 1..].iter();
+            while let Some(c) = bytes.next() {
+                match c {
+                    b'\\' => {
+                        bytes.next();
+                    }
+                    b'{' => depth += 1,
+                    b'}' => depth -= 1,
+                    _ => {}
+                }
+            }
+            depth
+        }
+        _ => 1,
+    };
+    inner + script_depth(&s[..e.offset])
+}
+
+/// A script split into commands and words ahead of time, so that it can
+/// be evaluated many times without being parsed again.
+#[derive(Clone, Debug)]
+pub struct ParsedScript {
+    source: String,
+    commands: Vec<ParsedCommand>,
+    // What stopped parsing, after the commands before it
+    error: Option<ParseError>,
+}
+
+#[derive(Clone, Debug)]
+struct ParsedCommand {
+    words: Vec<ParsedWord>,
+}
+
+/// A word and the line it starts on. A word made of several parts, like
+/// `a$b[c]`, is their concatenation.
+#[derive(Clone, Debug)]
+struct ParsedWord {
+    line: usize,
+    parts: Vec<WordPart>,
+}
+
+#[derive(Clone, Debug)]
+enum WordPart {
+    Literal(TclValue),
+    Var { name: String, line: usize },
+    Cmd { script: ParsedScript, line: usize },
+}
+
+impl ParsedScript {
+    fn new(source: &str, trace: bool) -> ParsedScript {
+        let mut p = Parser::new(source);
+        p.trace = trace;
+        let mut tokens = p.into_iter();
+
+        let mut commands = Vec::new();
+        let mut words: Vec<ParsedWord> = Vec::new();
+        let mut prevtype = Token::Eol;
+        for (TokenInfo { token, line, .. }, t) in &mut tokens {
+            let prevtype = std::mem::replace(&mut prevtype, token);
+            let part = match token {
+                Token::Eof => break,
+                Token::Sep => continue,
+                Token::Eol => {
+                    if !words.is_empty() {
+                        commands.push(ParsedCommand {
+                            words: std::mem::take(&mut words),
+                        });
+                    }
+                    continue;
+                }
+                Token::Var => WordPart::Var {
+                    name: t.to_string(),
+                    line,
+                },
+                Token::Cmd => WordPart::Cmd {
+                    script: ParsedScript::new(t, trace),
+                    line,
+                },
+                Token::Esc => WordPart::Literal(TclValue::from(backslash_subst(t))),
+                Token::Str => WordPart::Literal(TclValue::from(brace_subst(t))),
+            };
+
+            match words.last_mut() {
+                Some(word) if prevtype != Token::Sep && prevtype != Token::Eol => {
+                    word.parts.push(part);
+                }
+                _ => words.push(ParsedWord {
+                    line,
+                    parts: vec![part],
+                }),
+            }
+        }
+
+        ParsedScript {
+            source: source.to_string(),
+            commands,
+            error: tokens.error().cloned(),
+        }
+    }
+}
+
+/// A piece of a script as the evaluator sees it: part of a word, or the
+/// end of a command or of the whole script. Parts with `new_word` false
+/// continue the previous word.
+enum Piece<'a, C> {
+    Part {
+        part: Part<'a, C>,
+        new_word: bool,
+        line: usize,
+    },
+    Eol,
+    Eof,
+    Error(ParseError),
+}
+
+enum Part<'a, C> {
+    Literal(Cow<'a, str>),
+    Value(&'a TclValue),
+    Var(&'a str),
+    // A command substitution and the text between its brackets
+    Cmd(C, &'a str),
+}
+
+/// Walks a script piece by piece, either parsing its text as it goes or
+/// going through a ParsedScript
+trait ScriptCursor<'a>: Sized {
+    fn next_piece(&mut self) -> Piece<'a, Self>;
+}
+
+struct TextCursor<'a> {
+    parser: Parser<'a>,
+}
+
+impl<'a> TextCursor<'a> {
+    fn new(source: &'a str, trace: bool) -> TextCursor<'a> {
+        let mut parser = Parser::new(source);
+        parser.trace = trace;
+        parser.brackets = Some(BracketCache::default());
+        TextCursor { parser }
+    }
+
+    /// A cursor for the command substitution that is the current token
+    fn substitution(&self) -> TextCursor<'a> {
+        let mut parser = Parser::new(self.parser.token_body());
+        parser.trace = self.parser.trace;
+        parser.offset = self.parser.offset + self.parser.begin;
+        parser.brackets = self.parser.brackets.clone();
+        TextCursor { parser }
+    }
+}
+
+impl<'a> ScriptCursor<'a> for TextCursor<'a> {
+    fn next_piece(&mut self) -> Piece<'a, TextCursor<'a>> {
+        loop {
+            let prevtype = self.parser.current_token();
+            let TokenInfo { token, line, .. } = self.parser.next_info();
+            let t = self.parser.token_body();
+
+            let part = match token {
+                Token::Eof => {
+                    return match self.parser.error() {
+                        Some(e) => Piece::Error(e.clone()),
+                        None => Piece::Eof,
+                    };
+                }
+                Token::Eol => return Piece::Eol,
+                Token::Sep => continue,
+                Token::Var => Part::Var(t),
+                Token::Cmd => Part::Cmd(self.substitution(), t),
+                Token::Esc => Part::Literal(backslash_subst(t)),
+                Token::Str => Part::Literal(brace_subst(t)),
+            };
+            return Piece::Part {
+                part,
+                new_word: prevtype == Token::Sep || prevtype == Token::Eol,
+                line,
+            };
+        }
+    }
+}
+
+struct ParsedCursor<'a> {
+    script: &'a ParsedScript,
+    command: usize,
+    word: usize,
+    part: usize,
+}
+
+impl<'a> ParsedCursor<'a> {
+    fn new(script: &'a ParsedScript) -> ParsedCursor<'a> {
+        ParsedCursor {
+            script,
+            command: 0,
+            word: 0,
+            part: 0,
+        }
+    }
+}
+
+impl<'a> ScriptCursor<'a> for ParsedCursor<'a> {
+    fn next_piece(&mut self) -> Piece<'a, ParsedCursor<'a>> {
+        let script = self.script;
+        loop {
+            let Some(command) = script.commands.get(self.command) else {
+                return match &script.error {
+                    Some(e) => Piece::Error(e.clone()),
+                    None => Piece::Eof,
+                };
+            };
+            let Some(word) = command.words.get(self.word) else {
+                self.command += 1;
+                self.word = 0;
+                return Piece::Eol;
+            };
+            let Some(part) = word.parts.get(self.part) else {
+                self.word += 1;
+                self.part = 0;
+                continue;
+            };
+
+            let new_word = self.part == 0;
+            self.part += 1;
+            let (part, line) = match part {
+                WordPart::Literal(value) => (Part::Value(value), word.line),
+                WordPart::Var { name, line } => (Part::Var(name), *line),
+                WordPart::Cmd { script, line } => (
+                    Part::Cmd(ParsedCursor::new(script), &script.source[..]),
+                    *line,
+                ),
+            };
+            return Piece::Part {
+                part,
+                new_word,
+                line,
+            };
+        }
+    }
+}
+
+/// A script being evaluated: the outermost one, or a command substitution
+/// within it
+struct EvalFrame<'a, C> {
+    cursor: C,
+    source: &'a str,
+    buf: ArgvBuffer,
+    // Where a substitution's result goes among the enclosing frame's words
+    new_word: bool,
+    line: usize,
+}
+
+impl<C> EvalFrame<'_, C> {
+    fn add_value(&mut self, value: TclValue, new_word: bool, line: usize) {
+        match self.buf.argv.last_mut() {
+            Some(prev) if !new_word => prev.push_str(&value),
+            _ => {
+                self.buf.argv.push(value);
+                self.buf.lines.push(line);
+            }
+        }
+    }
+
+    fn add_str(&mut self, text: &str, new_word: bool, line: usize) {
+        match self.buf.argv.last_mut() {
+            Some(prev) if !new_word => prev.push_str(text),
+            _ => {
+                self.buf.argv.push(TclValue::from(text));
+                self.buf.lines.push(line);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ProcArg {
+    name: String,
+    default: Option<TclValue>,
+}
+
+#[derive(Clone, Debug)]
+struct ProcPrivdata {
+    args: Vec<ProcArg>,
+    // The argument list as written, parsed into args
+    formals: TclValue,
+    body: TclValue,
+    // Parsed on the first call
+    parsed: OnceLock<ParsedScript>,
+    // Where the body is in the file the proc was defined in, if known
+    origin: Option<ScriptSource>,
+}
+
+#[derive(Clone, Debug)]
+struct VarTrace {
+    ops: Vec<String>,
+    command: String,
+}
+
+#[derive(Clone)]
+enum VarValue {
+    // The variable doesn't exist from the script's point of view, but is
+    // kept around because traces are attached to it
+    Undefined,
+    Scalar(TclValue),
+    Array(HashMap<String, TclValue>),
+    // Created by upvar and global; refers to a variable in another frame
+    Link { frame: usize, name: String },
+}
+
+#[derive(Clone)]
+struct Var {
+    value: VarValue,
+    traces: Vec<VarTrace>,
+}
+
+struct CallFrame {
+    vars: HashMap<String, Var>,
+}
+
+impl CallFrame {
+    pub fn new() -> CallFrame {
+        CallFrame {
+            vars: HashMap::new(),
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&Var> {
+        self.vars.get(name)
+    }
+
+    /// Returns the variable, creating an undefined one if necessary
+    fn entry(&mut self, name: &str) -> &mut Var {
+        // Look up first so that setting an existing variable doesn't
+        // allocate a key
+        if !self.vars.contains_key(name) {
+            self.vars.insert(
+                name.to_string(),
+                Var {
+                    value: VarValue::Undefined,
+                    traces: Vec::new(),
+                },
+            );
+        }
+        self.vars.get_mut(name).unwrap()
+    }
+
+    /// Returns the names of the variables that exist from the script's
+    /// point of view, including links, in no particular order.
+    fn names(&self) -> impl Iterator<Item = &str> {
+        self.vars
+            .iter()
+            .filter(|(_, v)| !matches!(v.value, VarValue::Undefined))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// The signature of a command implemented in Rust. It receives the command's
+/// words, including its name, and the privdata it was registered with.
+pub type CmdFunc =
+    fn(&mut Interp, &[TclValue], Option<Arc<dyn Any + Send + Sync>>) -> Result<Status, TclError>;
+
+/// A command registered along with its typed data.
+trait CmdWithData: Send + Sync {
+    fn call(&self, interp: &mut Interp, argv: &[TclValue]) -> Result<Status, TclError>;
+    fn data(&self) -> &dyn Any;
+}
+
+struct WithData<T> {
+    func: fn(&mut Interp, &[TclValue], &T) -> Result<Status, TclError>,
+    data: T,
+}
+
+impl<T: Send + Sync + 'static> CmdWithData for WithData<T> {
+    fn call(&self, interp: &mut Interp, argv: &[TclValue]) -> Result<Status, TclError> {
+        (self.func)(interp, argv, &self.data)
+    }
+
+    fn data(&self) -> &dyn Any {
+        &self.data
+    }
+}
+
+/// A command implemented by a Rust closure, which can keep its own state.
+type CmdClosure = dyn FnMut(&mut Interp, &[TclValue]) -> Result<Status, TclError> + Send;
+
+/// A command registered with an [`Interp`].
+pub struct Cmd {
+    name: String,
+    body: CmdBody,
+    // Run once when the command is deleted, replaced or dropped along with
+    // the interpreter, like the deleteProc of Tcl_CreateCommand
+    on_delete: Option<Box<dyn FnOnce() + Send>>,
+    // From `trace add execution`. They go with the command when it is
+    // renamed and stay when it is replaced, but not when it is deleted.
+    traces: Vec<ExecTrace>,
+}
+
+/// A callback to run when a command starts (enter) or finishes (leave)
+#[derive(Clone, Debug)]
+struct ExecTrace {
+    ops: Vec<String>,
+    command: String,
+}
+
+/// What runs when a command is invoked. Cloning it is cheap, so the
+/// interpreter can let go of the command table before calling it.
+#[derive(Clone)]
+enum CmdBody {
+    Func(CmdFunc, Option<Arc<dyn Any + Send + Sync>>),
+    WithData(Arc<dyn CmdWithData>),
+    Closure(Arc<Mutex<CmdClosure>>),
+}
+
+/// The message a panic was raised with
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+impl CmdBody {
+    /// Runs the command. A panic in it becomes a Tcl error, so a buggy
+    /// command can't take the host down with it. The interpreter state
+    /// that commands nest (frames and depth counters) is put back the way
+    /// it was before the command ran; anything else the command changed
+    /// before panicking stays changed.
+    fn call(self, interp: &mut Interp, argv: &[TclValue]) -> Result<Status, TclError> {
+        let frames = interp.callframes.len();
+        let depths = (interp.eval_depth, interp.unknown_depth);
+        let active_traces = interp.active_var_traces.len();
+        let active_exec_traces = interp.active_exec_traces.len();
+        let sources = interp.sources.len();
+
+        let payload = match panic::catch_unwind(AssertUnwindSafe(|| self.run(interp, argv))) {
+            Ok(res) => return res,
+            Err(payload) => payload,
+        };
+
+        interp.callframes.truncate(frames);
+        (interp.eval_depth, interp.unknown_depth) = depths;
+        interp.active_var_traces.truncate(active_traces);
+        interp.active_exec_traces.truncate(active_exec_traces);
+        interp.sources.truncate(sources);
+
+        Err(TclError::new(
+            TclErrorKind::General,
+            format!(
+                "command \"{}\" panicked: {}",
+                argv[0],
+                panic_message(&*payload)
+            ),
+        ))
+    }
+
+    fn run(self, interp: &mut Interp, argv: &[TclValue]) -> Result<Status, TclError> {
+        match self {
+            CmdBody::Func(cmd_func, privdata) => cmd_func(interp, argv, privdata),
+            CmdBody::WithData(cmd) => cmd.call(interp, argv),
+            CmdBody::Closure(closure) => {
+                // The closure is locked for as long as it runs, so it
+                // can't be reentered from a script it evaluates. A panic
+                // in an earlier call poisons the lock, which is harmless.
+                let mut closure = match closure.try_lock() {
+                    Ok(closure) => closure,
+                    Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+                    Err(TryLockError::WouldBlock) => {
+                        return Err(TclError::new(
+                            TclErrorKind::General,
+                            format!("can't invoke \"{}\" while it is running", argv[0]),
+                        ));
+                    }
+                };
+                closure(interp, argv)
+            }
+        }
+    }
+}
+
+/// Commands by name. Lookups go through a hash map, while the commands
+/// themselves stay in definition order so that listing them is
+/// deterministic.
+#[derive(Default)]
+struct CommandTable {
+    cmds: Vec<Cmd>,
+    index: HashMap<String, usize>,
+}
+
+impl CommandTable {
+    fn get(&self, name: &str) -> Option<&Cmd> {
+        self.index.get(name).map(|&i| &self.cmds[i])
+    }
+
+    /// Adds a command, replacing any existing command of the same name in
+    /// place. The replacement keeps the old command's execution traces.
+    fn insert(&mut self, mut cmd: Cmd) {
+        match self.index.get(&cmd.name) {
+            Some(&i) => {
+                cmd.traces = std::mem::take(&mut self.cmds[i].traces);
+                self.cmds[i] = cmd;
+            }
+            None => {
+                self.index.insert(cmd.name.clone(), self.cmds.len());
+                self.cmds.push(cmd);
+            }
+        }
+    }
+
+    fn remove(&mut self, name: &str) -> Option<Cmd> {
+        let i = self.index.remove(name)?;
+        let cmd = self.cmds.remove(i);
+        for later in &self.cmds[i..] {
+            *self.index.get_mut(&later.name).unwrap() -= 1;
+        }
+        Some(cmd)
+    }
+
+    /// Renames a command, keeping its place in the order. The new name
+    /// must not be taken.
+    fn rename(&mut self, old: &str, new: &str) -> bool {
+        let Some(i) = self.index.remove(old) else {
+            return false;
+        };
+        self.cmds[i].name = new.to_string();
+        self.index.insert(new.to_string(), i);
+        true
+    }
+
+    fn names(&self) -> impl Iterator<Item = &str> {
+        self.cmds.iter().map(|c| c.name.as_str())
+    }
+
+    /// The commands defined with `proc`, as opposed to registered from Rust
+    fn procs(&self) -> impl Iterator<Item = &Cmd> {
+        self.cmds.iter().filter(|c| c.is_proc())
+    }
+}
+
+impl Cmd {
+    fn new(name: &str, body: CmdBody) -> Cmd {
+        Cmd {
+            name: name.to_string(),
+            body,
+            on_delete: None,
+            traces: Vec::new(),
+        }
+    }
+
+    fn is_proc(&self) -> bool {
+        matches!(&self.body, CmdBody::WithData(cmd) if cmd.data().is::<ProcPrivdata>())
+    }
+}
+
+impl Drop for Cmd {
+    fn drop(&mut self) {
+        if let Some(on_delete) = self.on_delete.take() {
+            on_delete();
+        }
+    }
+}
+
+/// Script state in plain data, for saving to disk: global variables and
+/// procs. Made by [`Interp::save_state`] and read by
+/// [`Interp::load_state`].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SavedState {
+    pub vars: BTreeMap<String, SavedValue>,
+    pub procs: Vec<SavedProc>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SavedValue {
+    Scalar(String),
+    Array(BTreeMap<String, String>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SavedProc {
+    pub name: String,
+    /// The argument list, as given to `proc`
+    pub args: String,
+    pub body: String,
+}
+
+/// The state a script can build up: global variables and procs. Taken by
+/// [`Interp::snapshot`] and put back by [`Interp::restore`].
+#[derive(Clone)]
+pub struct InterpState {
+    globals: HashMap<String, Var>,
+    procs: Vec<(String, CmdBody)>,
+}
+
+/// Runs delayed work for `after`. Everything is synchronous for now, but
+/// keeping it behind its own type leaves room for a real event loop.
+#[derive(Default)]
+pub struct Scheduler {}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {}
+    }
+
+    pub fn sleep(&mut self, ms: u64) {
+        std::thread::sleep(Duration::from_millis(ms));
+    }
+}
+
+/// The groups of built-in commands an interpreter has
+#[derive(Clone, Copy, Debug, Default)]
+struct CommandSets {
+    core: bool,
+    io: bool,
+    process: bool,
+    coroutine: bool,
+    test: bool,
+}
+
+/// Configures a new [`Interp`]: which groups of built-in commands it has
+/// and the limits it runs under. By default it has every group except the
+/// test commands.
+pub struct InterpBuilder {
+    sets: CommandSets,
+    max_nesting_depth: Option<usize>,
+    eval_limit: Option<u64>,
+    max_value_len: Option<usize>,
+    max_vars_per_frame: Option<usize>,
+    max_callframes: Option<usize>,
+    max_coroutines: Option<usize>,
+}
+
+impl InterpBuilder {
+    /// An interpreter for untrusted scripts: it can only compute, not do
+    /// I/O, touch the process it runs in or start threads
+    pub fn safe() -> InterpBuilder {
+        Interp::builder()
+            .with_io(false)
+            .with_process(false)
+            .with_coroutines(false)
+    }
+
+    /// Variables, procs, flow control, text processing and math
+    pub fn with_core(mut self, enable: bool) -> InterpBuilder {
+        self.sets.core = enable;
+        self
+    }
+
+    /// Commands that read or write outside the interpreter, like `puts`.
+    /// Needs the `io` feature.
+    pub fn with_io(mut self, enable: bool) -> InterpBuilder {
+        self.sets.io = enable;
+        self
+    }
+
+    /// Commands that inspect or block the process, like `pid` and `after`.
+    /// Needs the `process` feature.
+    pub fn with_process(mut self, enable: bool) -> InterpBuilder {
+        self.sets.process = enable;
+        self
+    }
+
+    /// `coroutine` and `yield`. Each coroutine runs on a thread of its own.
+    /// Needs the `coroutine` feature.
+    pub fn with_coroutines(mut self, enable: bool) -> InterpBuilder {
+        self.sets.coroutine = enable;
+        self
+    }
+
+    /// `assert` and `assert_eq`
+    pub fn with_test_commands(mut self, enable: bool) -> InterpBuilder {
+        self.sets.test = enable;
+        self
+    }
+
+    /// See [`Interp::set_max_nesting_depth`]
+    pub fn max_nesting_depth(mut self, depth: usize) -> InterpBuilder {
+        self.max_nesting_depth = Some(depth);
+        self
+    }
+
+    /// See [`Interp::set_eval_limit`]
+    pub fn eval_limit(mut self, limit: Option<u64>) -> InterpBuilder {
+        self.eval_limit = limit;
+        self
+    }
+
+    /// See [`Interp::set_max_value_len`]
+    pub fn max_value_len(mut self, limit: Option<usize>) -> InterpBuilder {
+        self.max_value_len = limit;
+        self
+    }
+
+    /// See [`Interp::set_max_vars_per_frame`]
+    pub fn max_vars_per_frame(mut self, limit: Option<usize>) -> InterpBuilder {
+        self.max_vars_per_frame = limit;
+        self
+    }
+
+    /// See [`Interp::set_max_callframes`]
+    pub fn max_callframes(mut self, limit: Option<usize>) -> InterpBuilder {
+        self.max_callframes = limit;
+        self
+    }
+
+    /// See [`Interp::set_max_coroutines`]
+    pub fn max_coroutines(mut self, limit: Option<usize>) -> InterpBuilder {
+        self.max_coroutines = limit;
+        self
+    }
+
+    pub fn build(self) -> Interp {
+        let mut interp = Interp::new();
+        interp.register_command_sets(self.sets);
+        if let Some(depth) = self.max_nesting_depth {
+            interp.set_max_nesting_depth(depth);
+        }
+        interp.set_eval_limit(self.eval_limit);
+        interp.set_max_value_len(self.max_value_len);
+        interp.set_max_vars_per_frame(self.max_vars_per_frame);
+        interp.set_max_callframes(self.max_callframes);
+        interp.set_max_coroutines(self.max_coroutines);
+        interp
+    }
+}
+
+/// Collects output in memory, e.g. from `puts` through
+/// [`Interp::set_output`]. Clones share the same buffer, so the host can
+/// keep one and read what the interpreter wrote to the other.
+#[derive(Clone, Debug, Default)]
+pub struct OutputBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl OutputBuffer {
+    pub fn new() -> OutputBuffer {
+        OutputBuffer::default()
+    }
+
+    /// Returns the output collected so far and empties the buffer
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *lock(&self.0));
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        lock(&self.0).extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Locks output shared between interpreters. A writer that panicked
+/// midway leaves the lock poisoned, but the bytes are still fine to use.
+fn lock<T: ?Sized>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// What an eval hook, set with [`Interp::set_eval_hook`], is told about
+#[derive(Debug)]
+pub enum EvalEvent<'a> {
+    /// A command is about to run
+    CommandEnter { argv: &'a [TclValue] },
+    /// A command finished, successfully or not. Every CommandEnter is
+    /// followed by a CommandLeave.
+    CommandLeave {
+        argv: &'a [TclValue],
+        result: &'a TclValue,
+        status: &'a Result<Status, TclError>,
+        duration: Duration,
+    },
+}
+
+type EvalHook = dyn FnMut(&EvalEvent) + Send;
+
+/// What to point at where an error happened
+enum ErrorAt<'a> {
+    // A word on the error's line, if it's there as written
+    Word(&'a str),
+    // A byte offset in the script
+    Offset(usize),
+    Line,
+}
+
+/// Text with its newlines escaped, to keep a trace to a line per event
+fn one_line(text: &str) -> std::borrow::Cow<'_, str> {
+    if text.contains('\n') {
+        text.replace('\n', "\\n").into()
+    } else {
+        text.into()
+    }
+}
+
+/// The byte range of a line of a script, counting from 1, without its
+/// newline
+fn line_span(script: &str, line: usize) -> (usize, usize) {
+    let start = script
+        .match_indices('\n')
+        .nth(line.wrapping_sub(2))
+        .map_or(0, |(i, _)| i + 1);
+    let start = if line <= 1 { 0 } else { start };
+    let end = script[start..]
+        .find('\n')
+        .map_or(script.len(), |i| start + i);
+    (start, end)
+}
+
+/// Where to point for an error on a line of a script
+fn error_span_at(script: &str, line: usize, at: ErrorAt) -> (usize, usize) {
+    let (start, end) = line_span(script, line);
+    match at {
+        ErrorAt::Offset(offset) if offset < script.len() => {
+            let len = script[offset..].chars().next().map_or(1, char::len_utf8);
+            (offset, offset + len)
+        }
+        ErrorAt::Word(word) if !word.is_empty() => match script[start..end].find(word) {
+            Some(i) => (start + i, start + i + word.len()),
+            None => trimmed(script, (start, end)),
+        },
+        _ => trimmed(script, (start, end)),
+    }
+}
+
+/// A range without the blanks at either end
+fn trimmed(script: &str, (start, end): (usize, usize)) -> (usize, usize) {
+    let text = &script[start..end];
+    let lead = text.len() - text.trim_start().len();
+    (start + lead, start + text.trim_end().len().max(lead))
+}
+
+/// Where a script being evaluated came from: the file it was read from,
+/// and how many lines into the file it starts. Proc bodies keep the place
+/// they were defined, so errors in them point into the file even when the
+/// proc is called from elsewhere.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptSource {
+    pub name: String,
+    pub line_offset: usize,
+}
+
+/// Limits for [`Interp::eval_safe`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvalLimits {
+    /// See [`Interp::set_max_nesting_depth`]
+    pub max_nesting_depth: usize,
+    /// See [`Interp::set_eval_limit`]
+    pub eval_limit: u64,
+}
+
+impl Default for EvalLimits {
+    fn default() -> EvalLimits {
+        EvalLimits {
+            max_nesting_depth: 1000,
+            eval_limit: 1_000_000,
+        }
+    }
+}
+
+// The stack eval_safe evaluates on: a base, plus enough for each level of
+// nesting it allows. Parsing and evaluating expressions recurse too, but
+// no deeper than EXPR_MAX_DEPTH.
+const EVAL_SAFE_STACK: usize = 4 << 20;
+const EVAL_SAFE_STACK_PER_LEVEL: usize = 64 << 10;
+// However high the nesting limit is set, a thread asks for no more stack
+// than this
+const EVAL_SAFE_STACK_MAX: usize = 1 << 30;
+
+/// The stack size for a thread that evaluates with `max_nesting_depth`
+fn eval_stack_size(max_nesting_depth: usize) -> usize {
+    max_nesting_depth
+        .saturating_mul(EVAL_SAFE_STACK_PER_LEVEL)
+        .saturating_add(EVAL_SAFE_STACK)
+        .min(EVAL_SAFE_STACK_MAX)
+}
+
+/// How many tests run by the `test` command have passed and failed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TestCounts {
+    pub passed: u64,
+    pub failed: u64,
+}
+
+/// Counts of what an interpreter has done, since it was created or
+/// [`Interp::reset_stats`] was last called
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EvalStats {
+    /// Commands invoked, including procs and commands that failed
+    pub commands: u64,
+    /// The deepest proc call frame reached: 0 if everything ran at the top
+    /// level, as with `info level`
+    pub max_depth: usize,
+}
+
+/// A snapshot of an interpreter's state, from [`Interp::stats`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InterpStats {
+    /// Counts since the interpreter was created or the counts were reset
+    pub eval: EvalStats,
+    /// Commands invoked since the interpreter was created
+    pub commands_evaluated: u64,
+    /// The current call frame, as with [`Interp::level`]
+    pub depth: usize,
+    /// How many variables each call frame has, the global frame first
+    pub frame_vars: Vec<usize>,
+    /// Commands defined, procs included
+    pub commands: usize,
+    /// Commands defined with `proc`
+    pub procs: usize,
+    /// Bytes in the values of variables, and the keys of array elements
+    pub var_bytes: usize,
+    /// Bytes in the argument lists and bodies of procs
+    pub proc_bytes: usize,
+}
+
+/// A read-only view of a call frame, from [`Interp::frames`]
+pub struct FrameView<'a> {
+    level: usize,
+    frame: &'a CallFrame,
+}
+
+impl<'a> FrameView<'a> {
+    /// The frame's level: 0 for the global frame
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// The names of the frame's variables, including links made by `upvar`
+    /// and `global`, in no particular order
+    pub fn var_names(&self) -> impl Iterator<Item = &'a str> {
+        self.frame.names()
+    }
+}
+
+/// How many history events are kept unless `history keep` says otherwise
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+/// A Tcl interpreter: its commands, variables and the result of the last
+/// command.
+pub struct Interp {
+    commands: CommandTable,
+    callframes: Vec<CallFrame>,
+    pub result: TclValue,
+    pub trace_parser: bool,
+    /// If true, every command is written to the error output as it runs,
+    /// and what it returned after
+    pub trace_eval: bool,
+    // How many traced commands are running
+    trace_depth: usize,
+    stats: EvalStats,
+    commands_evaluated: u64,
+    // Scripts entered interactively, for the history command. The oldest
+    // kept is event number history_first.
+    history: VecDeque<String>,
+    history_first: usize,
+    history_limit: usize,
+    test_counts: TestCounts,
+    pub scheduler: Scheduler,
+    // Nonzero while the unknown handler runs, so that it cannot recurse
+    unknown_depth: usize,
+    // Variables (frame, name) whose traces are currently running
+    active_var_traces: Vec<(usize, String)>,
+    // Commands whose execution traces are currently running
+    active_exec_traces: Vec<String>,
+    // Child interpreters created with `interp create`. They get the same
+    // groups of commands as this one.
+    children: HashMap<String, Interp>,
+    // The interpreter that created this one, lent to it while `interp
+    // eval` runs it so that aliases can call back into it
+    parent: Option<Box<Interp>>,
+    // Commands taken out of reach of scripts with `interp hide`, which
+    // only the parent can run, and whether this is a safe interpreter,
+    // which starts with everything but the core commands hidden
+    hidden: CommandTable,
+    safe: bool,
+    // The coroutine running now, for yield to hand control back from
+    #[cfg(feature = "coroutine")]
+    coroutine: Option<Arc<coroutine::Link>>,
+    command_sets: CommandSets,
+    // Channels opened by the script, by name, and the number for the next
+    #[cfg(feature = "io")]
+    channels: HashMap<String, channel::Channel>,
+    #[cfg(feature = "io")]
+    next_channel: usize,
+    // Listening sockets from `socket -server`, with the command to run for
+    // each connection
+    #[cfg(feature = "io")]
+    servers: HashMap<String, (std::net::TcpListener, String)>,
+    // Whether gets has reached the end of stdin
+    #[cfg(feature = "io")]
+    stdin_eof: bool,
+    // Where input comes from and output and errors go, if not the
+    // process's stdin, stdout and stderr. Shared with child interpreters.
+    stdin: Option<Arc<Mutex<dyn BufRead + Send>>>,
+    stdout: Option<Arc<Mutex<dyn Write + Send>>>,
+    stderr: Option<Arc<Mutex<dyn Write + Send>>>,
+    eval_hook: Option<Box<EvalHook>>,
+    // The result as last handed to C, kept alive until the next call
+    #[cfg(feature = "ffi")]
+    ffi_result: std::ffi::CString,
+    // Number of nested evals and proc calls in progress
+    eval_depth: usize,
+    // Limit on eval_depth, so runaway recursion fails with an error
+    // instead of overflowing the native stack
+    max_nesting_depth: usize,
+    // Evaluation steps allowed per top-level eval, and how many are left
+    eval_limit: Option<u64>,
+    eval_fuel: u64,
+    // Set from outside, even from another thread, to stop the evaluation
+    interrupt: Arc<AtomicBool>,
+    // Limits on memory: the length of a value stored in a variable or
+    // left as a result, the variables in one frame, and the frames
+    max_value_len: Option<usize>,
+    max_vars_per_frame: Option<usize>,
+    max_callframes: Option<usize>,
+    // Limit on coroutines in existence at once, and how many there are
+    max_coroutines: Option<usize>,
+    #[cfg(feature = "coroutine")]
+    live_coroutines: Arc<std::sync::atomic::AtomicUsize>,
+    // Significant digits floats are formatted with, 0 for as many as it
+    // takes to read back the same value. Follows the global tcl_precision.
+    precision: usize,
+    // The files scripts being evaluated came from, innermost last, each
+    // with the eval_depth its commands run at. Only commands at that
+    // depth are on lines of the file; deeper ones are in braced words.
+    sources: Vec<(ScriptSource, usize)>,
+    // The line of the last word of the command being invoked, for proc
+    // to find where its body starts, unless it's in a command substitution,
+    // whose lines are counted from its opening bracket
+    last_word_line: Option<usize>,
+    // Traceback of the error being unwound: the full errorInfo text and
+    // just its context lines. Active until the error is caught.
+    error_info: String,
+    error_trace: Vec<String>,
+    error_trace_active: bool,
+    // Line of the command that raised the error being unwound, and the
+    // script that line is relative to while it can still be translated
+    // into a line of an enclosing script
+    error_line: Option<usize>,
+    error_line_script: Option<String>,
+    // The script error_line counts in, kept after translation stops, and
+    // the byte range of the failing word in it
+    error_source: Option<String>,
+    error_span: Option<(usize, usize)>,
+    // The innermost file the error is known to have happened on a line
+    // of, and that line
+    error_file: Option<(String, usize)>,
+    // Completion code for the enclosing proc, set by `return -code`
+    return_code: i64,
+    // Argument vectors of finished evals, kept to be reused by later ones
+    argv_pool: Vec<ArgvBuffer>,
+}
+
+// Hosts move interpreters into worker threads and behind mutexes, so
+// everything an interpreter holds must be Send
+const _: () = {
+    fn assert_send<T: Send>() {}
+    let _ = assert_send::<Interp>;
+};
+
+/// The words of the command being assembled, and the line each starts on
+#[derive(Default)]
+struct ArgvBuffer {
+    argv: Vec<TclValue>,
+    lines: Vec<usize>,
+}
+
+impl ArgvBuffer {
+    fn clear(&mut self) {
+        self.argv.clear();
+        self.lines.clear();
+    }
+}
+
+fn is_list_space(c: u8) -> bool {
+    c == b' ' || c == b'\t' || c == b'\n' || c == b'\r'
+}
+
+/// Decodes the backslash sequence whose text (after the backslash) starts
+/// `s`, returning the character it stands for and how many bytes of `s`
+/// it takes up. A backslash before any other character just makes that
+/// character literal.
+fn backslash_sequence(s: &str) -> (char, usize) {
+    let digits = |radix: u32, max: usize| {
+        let len = s[1..]
+            .bytes()
+            .take(max)
+            .take_while(|b| (*b as char).is_digit(radix))
+            .count();
+        let value = u32::from_str_radix(&s[1..1 + len], radix).unwrap_or(0);
+        (value, len)
+    };
+
+    let Some(c) = s.chars().next() else {
+        return ('\\', 0);
+    };
+    match c {
+        'a' => ('\x07', 1),
+        'b' => ('\x08', 1),
+        'f' => ('\x0c', 1),
+        'n' => ('\n', 1),
+        'r' => ('\r', 1),
+        't' => ('\t', 1),
+        'v' => ('\x0b', 1),
+        // A line continuation: the newline and the blanks that start the
+        // next line become one space
+        '\n' => {
+            let blanks = s[1..]
+                .bytes()
+                .take_while(|b| *b == b' ' || *b == b'\t')
+                .count();
+            (' ', 1 + blanks)
+        }
+        'x' | 'u' => {
+            let (value, len) = digits(16, if c == 'x' { 2 } else { 4 });
+            if len == 0 {
+                return (c, 1);
+            }
+            (char::from_u32(value).unwrap_or('\u{fffd}'), 1 + len)
+        }
+        '0'..='7' => {
+            let (value, len) = digits(8, 2);
+            let value = (c as u32 - '0' as u32) * 8u32.pow(len as u32) + value;
+            (char::from_u32(value & 0xff).unwrap(), 1 + len)
+        }
+        c => (c, c.len_utf8()),
+    }
+}
+
+/// Replaces each backslash-newline in a braced word, along with the
+/// blanks after it, with a single space. Braced words otherwise keep their
+/// backslashes.
+pub fn brace_subst(s: &str) -> Cow<'_, str> {
+    if !s.contains("\\\n") {
+        return Cow::Borrowed(s);
+    }
+
+    let bytes = s.as_bytes();
+    let mut out = String::with_capacity(s.len());
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            i += 1;
+        } else if bytes.get(i + 1) == Some(&b'\n') {
+            out.push_str(&s[start..i]);
+            out.push(' ');
+            i += 2;
+            while i < bytes.len() && (bytes[i] == b' ' || bytes[i] == b'\t') {
+                i += 1;
+            }
+            start = i;
+        } else {
+            // An escaped character, which stays as it is
+            i += 2;
+        }
+    }
+    out.push_str(&s[start.min(s.len())..]);
+    Cow::Owned(out)
+}
+
+/// Replaces the backslash sequences in a word: `\n`, `\t` and the other
+/// C-style escapes, `\xHH`, `\uHHHH`, octal `\ooo`, and a backslash
+/// before any other character stands for that character.
+pub fn backslash_subst(s: &str) -> Cow<'_, str> {
+    if !s.contains('\\') {
+        return Cow::Borrowed(s);
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        let (c, len) = backslash_sequence(&rest[i + 1..]);
+        out.push(c);
+        rest = &rest[i + 1 + len..];
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// Matches a string against a glob pattern: `*` matches any run of
+/// characters, `?` any single character, `[...]` any character in the set
+/// (with `a-z` ranges), and a backslash makes the next character literal.
+pub fn glob_match(pattern: &str, s: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    glob_match_at(&p, &s)
+}
+
+fn glob_match_at(p: &[char], s: &[char]) -> bool {
+    let Some(&c) = p.first() else {
+        return s.is_empty();
+    };
+
+    match c {
+        '*' => (0..=s.len()).any(|skip| glob_match_at(&p[1..], &s[skip..])),
+        '?' => !s.is_empty() && glob_match_at(&p[1..], &s[1..]),
+        '[' => {
+            let Some(end) = p.iter().position(|&c| c == ']') else {
+                return false;
+            };
+            let Some(&ch) = s.first() else {
+                return false;
+            };
+
+            let set = &p[1..end];
+            let mut i = 0;
+            let mut found = false;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == '-' {
+                    let (lo, hi) = (set[i].min(set[i + 2]), set[i].max(set[i + 2]));
+                    found |= (lo..=hi).contains(&ch);
+                    i += 3;
+                } else {
+                    found |= set[i] == ch;
+                    i += 1;
+                }
+            }
+            found && glob_match_at(&p[end + 1..], &s[1..])
+        }
+        '\\' if p.len() > 1 => s.first() == Some(&p[1]) && glob_match_at(&p[2..], &s[1..]),
+        c => s.first() == Some(&c) && glob_match_at(&p[1..], &s[1..]),
+    }
+}
+
+/// Splits a string into list elements following Tcl's rules: elements are
+/// separated by whitespace and may be grouped with braces (taken literally)
+/// or double quotes. A backslash keeps the next character from acting as a
+/// delimiter, except for `\n`, `\t` and `\r` which stand for whitespace.
+pub fn parse_list(list: &str) -> Result<Vec<String>, TclError> {
+    let bytes = list.as_bytes();
+    let mut elements = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < bytes.len() && is_list_space(bytes[i]) {
+            i += 1;
+        }
+
+        if i >= bytes.len() {
+            break;
+        }
+
+        let mut element = String::new();
+
+        match bytes[i] {
+            b'{' => {
+                let start = i + 1;
+                let mut level = 1;
+                i += 1;
+                while i < bytes.len() {
+                    match bytes[i] {
+                        b'\\' => i += 1,
+                        b'{' => level += 1,
+                        b'}' => {
+                            level -= 1;
+                            if level == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                if i >= bytes.len() {
+                    return Err(TclError::new(
+                        TclErrorKind::General,
+                        "unmatched open brace in list",
+                    ));
+                }
+                element.push_str(&list[start..i]);
+                i += 1;
+                if i < bytes.len() && !is_list_space(bytes[i]) {
+                    return Err(TclError::new(
+                        TclErrorKind::General,
+                        format!(
+                            "list element in braces followed by \"{}\" instead of space",
+                            &list[i..].split(|c: char| c.is_whitespace()).next().unwrap()
+                        ),
+                    ));
+                }
+            }
+            b'"' => {
+                i += 1;
+                let mut start = i;
+                loop {
+                    if i >= bytes.len() {
+                        return Err(TclError::new(
+                            TclErrorKind::General,
+                            "unmatched open quote in list",
+                        ));
+                    }
+                    match bytes[i] {
+                        b'\\' => {
+                            element.push_str(&list[start..i]);
+                            let (c, len) = backslash_sequence(&list[i + 1..]);
+                            element.push(c);
+                            i += 1 + len;
+                            start = i;
+                        }
+                        b'"' => break,
+                        _ => i += 1,
+                    }
+                }
+                element.push_str(&list[start..i]);
+                i += 1;
+                if i < bytes.len() && !is_list_space(bytes[i]) {
+                    return Err(TclError::new(
+                        TclErrorKind::General,
+                        format!(
+                            "list element in quotes followed by \"{}\" instead of space",
+                            &list[i..].split(|c: char| c.is_whitespace()).next().unwrap()
+                        ),
+                    ));
+                }
+            }
+            _ => {
+                let mut start = i;
+                while i < bytes.len() && !is_list_space(bytes[i]) {
+                    if bytes[i] == b'\\' {
+                        element.push_str(&list[start..i]);
+                        let (c, len) = backslash_sequence(&list[i + 1..]);
+                        element.push(c);
+                        i += 1 + len;
+                        start = i;
+                        continue;
+                    }
+                    i += 1;
+                }
+                element.push_str(&list[start..i]);
+            }
+        }
+
+        elements.push(element);
+    }
+
+    Ok(elements)
+}
+
+/// Quotes a string so that parse_list will read it back as a single
+/// element.
+pub fn quote_list_element(s: &str) -> String {
+    if s.is_empty() {
+        return "{}".to_string();
+    }
+
+    let special = |c: char| " \t\n\r{}[]$\";\\".contains(c);
+    if !s.starts_with('#') && !s.contains(special) {
+        return s.to_string();
+    }
+
+    // Prefer braces, which keep the text as-is, as long as the braces inside
+    // are balanced and the string doesn't end in a backslash.
+    let mut level: i64 = 0;
+    let mut balanced = true;
+    let mut escaped = false;
+    for c in s.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => level += 1,
+            '}' => {
+                level -= 1;
+                if level < 0 {
+                    balanced = false;
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    if balanced && level == 0 && !escaped {
+        return format!("{{{s}}}");
+    }
+
+    let mut out = String::with_capacity(s.len() * 2);
+    for c in s.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            _ if special(c) || c == '#' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Joins strings into a well-formed list.
+pub fn merge_list<S: AsRef<str>>(items: &[S]) -> String {
+    list_from_iter(items)
+}
+
+/// Builds a well-formed list, quoting each item so that parse_list reads
+/// back exactly the items given.
+pub fn list_from_iter(items: impl IntoIterator<Item = impl AsRef<str>>) -> String {
+    let mut list = String::new();
+    for item in items {
+        if !list.is_empty() {
+            list.push(' ');
+        }
+        list.push_str(&quote_list_element(item.as_ref()));
+    }
+    list
+}
+
+fn check_arity(argv: &[TclValue], min: usize, max: usize) -> Result<Status, TclError> {
+    if argv.len() < min || argv.len() > max {
+        let expected = if max == usize::MAX {
+            format!("at least {min}")
+        } else {
+            format!("{min}-{max}")
+        };
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            format!(
+                "wrong number of arguments to {name}: expected {expected}, got {len}",
+                name = argv[0],
+                len = argv.len()
+            ),
+        ));
+    }
+    Ok(Status::Ok)
+}
+
+#[cfg(feature = "io")]
+fn cmd_puts(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let mut args = &argv[1..];
+    let newline = args.len() > 1 && args[0] == "-nonewline";
+    if newline {
+        args = &args[1..];
+    }
+    let (channel, text) = match args {
+        [text] => ("stdout", text),
+        [channel, text] => (channel.as_str(), text),
+        _ => {
+            return Err(TclError::new(
+                TclErrorKind::Arity,
+                "wrong # args: should be \"puts ?-nonewline? ?channelId? string\"",
+            ));
+        }
+    };
+    let newline = if newline { "" } else { "\n" };
+
+    let res = match channel {
+        "stdout" => interp.write_output(format_args!("{text}{newline}")),
+        "stderr" => interp.write_error_output(format_args!("{text}{newline}")),
+        _ => {
+            let chan = interp.channel(channel)?;
+            if !chan.writable() {
+                return Err(channel_not_open_for(channel, "writing"));
+            }
+            chan.write_text(&format!("{text}{newline}"))
+        }
+    };
+    res.map_err(|e| channel::io_error(format!("error writing \"{channel}\""), &e))?;
+    Ok(Status::Ok)
+}
+
+#[cfg(any(feature = "io", feature = "process"))]
+fn channel_not_found(channel: &str) -> TclError {
+    TclError::new(
+        TclErrorKind::General,
+        format!("can not find channel named \"{channel}\""),
+    )
+}
+
+#[cfg(feature = "io")]
+fn channel_not_open_for(channel: &str, what: &str) -> TclError {
+    TclError::new(
+        TclErrorKind::General,
+        format!("channel \"{channel}\" wasn't opened for {what}"),
+    )
+}
+
+/// `open fileName ?access? ?permissions? ?-encoding name?`, where access
+/// is one of r, r+, w, w+, a or a+, permissions are the octal mode for a
+/// new file, and the encoding, utf-8 by default, is what the file's text
+/// is in.
+///
+/// A name starting with `|` is a pipeline of commands to run instead, as
+/// a list of words with `|` between commands, like `|sort -r | head -n 3`.
+/// Reading from the channel reads the last command's stdout, and writing
+/// to it writes the first one's stdin.
+#[cfg(feature = "io")]
+fn cmd_open(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    // A trailing -encoding option sets how the channel's text is stored
+    let (argv, encoding) = match argv {
+        [rest @ .., option, name] if rest.len() > 1 && option == "-encoding" => {
+            (rest, encoding::find(name)?)
+        }
+        _ => (argv, encoding::UTF8),
+    };
+    check_arity(argv, 2, 4)?;
+    let name = argv[1].as_str();
+    let access = if argv.len() > 2 {
+        get_enum(argv, 2, &["r", "r+", "w", "w+", "a", "a+"])?
+    } else {
+        "r"
+    };
+    let readable = access.starts_with('r') || access.ends_with('+');
+    let writable = access != "r";
+
+    if let Some(spec) = name.strip_prefix('|') {
+        let words = parse_list(spec)?;
+        let commands: Vec<Vec<String>> = words
+            .split(|word| word == "|")
+            .map(<[String]>::to_vec)
+            .collect();
+        if commands.iter().any(Vec::is_empty) {
+            return Err(TclError::new(
+                TclErrorKind::General,
+                "illegal use of | in command",
+            ));
+        }
+        let pipeline = channel::Pipeline::spawn(&commands, readable, writable)?;
+        let mut chan = channel::Channel::new(Box::new(pipeline), readable, writable);
+        chan.set_encoding(encoding);
+        let id = interp.add_channel("file", chan);
+        interp.set_result(id);
+        return Ok(Status::Ok);
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    match access {
+        "r" => options.read(true),
+        "r+" => options.read(true).write(true),
+        "w" => options.write(true).create(true).truncate(true),
+        "w+" => options.read(true).write(true).create(true).truncate(true),
+        "a" => options.append(true).create(true),
+        _ => options.read(true).append(true).create(true),
+    };
+    if let Some(mode) = argv.get(3) {
+        let digits = mode.as_str().trim_start_matches("0o");
+        let mode = u32::from_str_radix(digits, 8).map_err(|_| {
+            TclError::new(
+                TclErrorKind::General,
+                format!("expected octal permissions but got \"{mode}\""),
+            )
+        })?;
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+        #[cfg(not(unix))]
+        let _ = mode;
+    }
+    let file = options
+        .open(name)
+        .map_err(|e| channel::io_error(format!("couldn't open \"{name}\""), &e))?;
+
+    let mut chan = channel::Channel::new(Box::new(file), readable, writable);
+    chan.set_encoding(encoding);
+    let id = interp.add_channel("file", chan);
+    interp.set_result(id);
+    Ok(Status::Ok)
+}
+
+/// `source fileName` evaluates the file as a script and returns the result
+/// of its last command, or the value of a `return` in it, which ends the
+/// file early. A relative path is taken from the directory of the file
+/// doing the sourcing, as `info script` names it, so that a script can
+/// load the files next to it from wherever it is run.
+#[cfg(feature = "io")]
+fn cmd_source(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, 2)?;
+    let name = argv[1].as_str();
+    let path = match interp.script_source() {
+        Some(current) if std::path::Path::new(name).is_relative() => {
+            let dir = std::path::Path::new(&current.name).parent();
+            dir.map_or_else(|| name.into(), |dir| dir.join(name))
+        }
+        _ => std::path::PathBuf::from(name),
+    };
+    let script = std::fs::read_to_string(&path)
+        .map_err(|e| channel::io_error(format!("couldn't read file \"{name}\""), &e))?;
+    let source = ScriptSource {
+        name: path.to_string_lossy().into_owned(),
+        line_offset: 0,
+    };
+    let status = interp.eval_from_source(source, |interp| interp.eval_script(&script))?;
+    match check_loop_escape(status)? {
+        Status::Return => {
+            interp.return_code = 0;
+            Ok(Status::Ok)
+        }
+        status => Ok(status),
+    }
+}
+
+/// `socket host port` connects to a TCP server and returns a channel for
+/// the connection.
+///
+/// `socket -server command ?-myaddr addr? port` listens for connections
+/// and returns the name of the server, which `close` stops. There is no
+/// event loop to accept connections in the background, so
+/// `socket -accept server ?count?` waits for them: for each it runs
+/// `command channel address port`, stopping after count connections or
+/// when the command fails.
+#[cfg(feature = "io")]
+fn cmd_socket(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let socket_error = |e: std::io::Error| channel::io_error("couldn't open socket", &e);
+    let port = |idx: usize| -> Result<u16, TclError> {
+        let port = helpers::get_int(argv, idx)?;
+        u16::try_from(port).map_err(|_| {
+            TclError::new(
+                TclErrorKind::General,
+                format!("expected a port number but got \"{}\"", argv[idx]),
+            )
+        })
+    };
+
+    match argv.get(1).map(TclValue::as_str) {
+        Some("-server") => {
+            let (addr, port_idx) = match argv.len() {
+                4 => ("0.0.0.0", 3),
+                6 if argv[3] == "-myaddr" => (argv[4].as_str(), 5),
+                _ => {
+                    return Err(TclError::new(
+                        TclErrorKind::Arity,
+                        "wrong # args: should be \"socket -server command ?-myaddr addr? port\"",
+                    ));
+                }
+            };
+            let listener =
+                std::net::TcpListener::bind((addr, port(port_idx)?)).map_err(socket_error)?;
+            let id = format!("sock{}", interp.next_channel);
+            interp.next_channel += 1;
+            interp
+                .servers
+                .insert(id.clone(), (listener, argv[2].to_string()));
+            interp.set_result(id);
+            Ok(Status::Ok)
+        }
+        Some("-accept") => {
+            check_arity(argv, 3, 4)?;
+            let name = argv[2].as_str();
+            let mut count = match argv.get(3) {
+                Some(_) => Some(helpers::get_int(argv, 3)?),
+                None => None,
+            };
+            while count.is_none_or(|n| n > 0) {
+                let (listener, command) = interp
+                    .servers
+                    .get(name)
+                    .ok_or_else(|| channel_not_found(name))?;
+                let command = command.clone();
+                let (stream, peer) = listener
+                    .accept()
+                    .map_err(|e| channel::io_error(format!("error accepting on \"{name}\""), &e))?;
+                let id =
+                    interp.add_channel("sock", channel::Channel::new(Box::new(stream), true, true));
+                let args = [id, peer.ip().to_string(), peer.port().to_string()];
+                interp.eval(&format!("{command} {}", list_from_iter(&args)))?;
+                count = count.map(|n| n - 1);
+            }
+            interp.set_result("");
+            Ok(Status::Ok)
+        }
+        _ => {
+            if argv.len() != 3 {
+                return Err(TclError::new(
+                    TclErrorKind::Arity,
+                    "wrong # args: should be \"socket host port\" or \"socket -server command ?-myaddr addr? port\"",
+                ));
+            }
+            let stream =
+                std::net::TcpStream::connect((argv[1].as_str(), port(2)?)).map_err(socket_error)?;
+            let id =
+                interp.add_channel("sock", channel::Channel::new(Box::new(stream), true, true));
+            interp.set_result(id);
+            Ok(Status::Ok)
+        }
+    }
+}
+
+/// `close channelId`: writes out what is pending and forgets the channel.
+/// The standard channels stay open.
+#[cfg(feature = "io")]
+fn cmd_close(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, 2)?;
+    let name = argv[1].as_str();
+    if ["stdin", "stdout", "stderr"].contains(&name) || interp.servers.remove(name).is_some() {
+        return Ok(Status::Ok);
+    }
+    let chan = interp
+        .channels
+        .remove(name)
+        .ok_or_else(|| channel_not_found(name))?;
+    chan.close(name)?;
+    Ok(Status::Ok)
+}
+
+/// `seek channelId offset ?origin?`, where origin is start, current or end
+#[cfg(feature = "io")]
+fn cmd_seek(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 3, 4)?;
+    let name = argv[1].as_str();
+    let offset = helpers::get_int(argv, 2)?;
+    let origin = if argv.len() > 3 {
+        get_enum(argv, 3, &["start", "current", "end"])?
+    } else {
+        "start"
+    };
+    let seek_error = |message: &str| {
+        TclError::new(
+            TclErrorKind::General,
+            format!("error during seek on \"{name}\": {message}"),
+        )
+    };
+    if ["stdin", "stdout", "stderr"].contains(&name) {
+        return Err(seek_error("invalid argument"));
+    }
+    let pos = match origin {
+        "start" => std::io::SeekFrom::Start(
+            u64::try_from(offset).map_err(|_| seek_error("invalid argument"))?,
+        ),
+        "current" => std::io::SeekFrom::Current(offset),
+        _ => std::io::SeekFrom::End(offset),
+    };
+    interp
+        .channel(name)?
+        .seek(pos)
+        .map_err(|e| channel::io_error(format!("error during seek on \"{name}\""), &e))?;
+    Ok(Status::Ok)
+}
+
+/// `tell channelId`: the byte offset the script has reached, or -1 for a
+/// channel that can't seek
+#[cfg(feature = "io")]
+fn cmd_tell(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, 2)?;
+    let name = argv[1].as_str();
+    let at = if ["stdin", "stdout", "stderr"].contains(&name) {
+        None
+    } else {
+        interp.channel(name)?.tell()
+    };
+    interp.set_result(at.map_or(-1, |at| at as i64).to_string());
+    Ok(Status::Ok)
+}
+
+/// `eof channelId`: whether the last read from the channel reached its end
+#[cfg(feature = "io")]
+fn cmd_eof(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, 2)?;
+    let eof = match argv[1].as_str() {
+        "stdin" => interp.stdin_eof,
+        "stdout" | "stderr" => false,
+        name => interp.channel(name)?.eof(),
+    };
+    interp.set_result(if eof { "1" } else { "0" });
+    Ok(Status::Ok)
+}
+
+/// `fconfigure channelId ?-option? ?value -option value ...?` sets or reads
+/// a channel's options: `-buffering full|line|none`, `-encoding name` and
+/// `-translation auto|lf|crlf|binary`. With no options it returns them
+/// all as a dictionary. The standard channels' options can be read but
+/// not changed.
+#[cfg(feature = "io")]
+fn cmd_fconfigure(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    const OPTIONS: &[&str] = &["-buffering", "-encoding", "-translation"];
+    if argv.len() < 2 || (argv.len() > 3 && !argv.len().is_multiple_of(2)) {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            "wrong # args: should be \"fconfigure channelId ?-option value ...?\"",
+        ));
+    }
+    let name = argv[1].as_str();
+    let standard = match name {
+        "stdin" | "stdout" => Some("line"),
+        "stderr" => Some("none"),
+        _ => None,
+    };
+    let current = |interp: &mut Interp| -> Result<[&'static str; 3], TclError> {
+        Ok(match standard {
+            Some(buffering) => [buffering, encoding::UTF8.name(), "auto"],
+            None => {
+                let chan = interp.channel(name)?;
+                [
+                    chan.buffering().name(),
+                    chan.encoding().name(),
+                    chan.translation().name(),
+                ]
+            }
+        })
+    };
+
+    match argv.len() {
+        2 => {
+            let values = current(interp)?;
+            let pairs = OPTIONS.iter().zip(values).flat_map(|(o, v)| [*o, v]);
+            interp.set_result(list_from_iter(pairs));
+        }
+        3 => {
+            let option = get_enum(argv, 2, OPTIONS)?;
+            let values = current(interp)?;
+            let i = OPTIONS.iter().position(|&o| o == option).unwrap();
+            interp.set_result(values[i]);
+        }
+        _ => {
+            if standard.is_some() {
+                for i in (2..argv.len()).step_by(2) {
+                    get_enum(argv, i, OPTIONS)?;
+                }
+                return Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("can't change the options of \"{name}\""),
+                ));
+            }
+            interp.channel(name)?;
+            for i in (2..argv.len()).step_by(2) {
+                let option = get_enum(argv, i, OPTIONS)?;
+                match option {
+                    "-buffering" => {
+                        let value = get_enum(argv, i + 1, channel::Buffering::NAMES)?;
+                        interp
+                            .channel(name)?
+                            .set_buffering(channel::Buffering::from_name(value))
+                            .map_err(|e| {
+                                channel::io_error(format!("error flushing \"{name}\""), &e)
+                            })?;
+                    }
+                    "-encoding" => {
+                        let encoding = encoding::find(argv[i + 1].as_str())?;
+                        interp.channel(name)?.set_encoding(encoding);
+                    }
+                    _ => {
+                        let value = get_enum(argv, i + 1, channel::Translation::NAMES)?;
+                        interp
+                            .channel(name)?
+                            .set_translation(channel::Translation::from_name(value));
+                    }
+                }
+            }
+        }
+    }
+    Ok(Status::Ok)
+}
+
+/// `flush ?channelId?`: without a channel, flushes both stdout and stderr
+#[cfg(feature = "io")]
+fn cmd_flush(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let channels: &[&str] = match argv {
+        [_] => &["stdout", "stderr"],
+        [_, channel] => &[channel.as_str()],
+        _ => {
+            return Err(TclError::new(
+                TclErrorKind::Arity,
+                "wrong # args: should be \"flush ?channelId?\"",
+            ));
+        }
+    };
+    for &channel in channels {
+        let res = match channel {
+            "stdout" => interp.flush_output(),
+            "stderr" => interp.flush_error_output(),
+            _ => interp.channel(channel)?.flush(),
+        };
+        res.map_err(|e| channel::io_error(format!("error flushing \"{channel}\""), &e))?;
+    }
+    Ok(Status::Ok)
+}
+
+/// `gets channelId ?varName?`: returns the next line, or stores it in
+/// varName and returns its length, or -1 at the end of the input
+#[cfg(feature = "io")]
+fn cmd_gets(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    if !(2..=3).contains(&argv.len()) {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            "wrong # args: should be \"gets channelId ?varName?\"",
+        ));
+    }
+    let channel = argv[1].as_str();
+    let line = match channel {
+        "stdin" => {
+            let line = interp.read_input_line();
+            interp.stdin_eof = matches!(line, Ok(None));
+            line
+        }
+        "stdout" | "stderr" => return Err(channel_not_open_for(channel, "reading")),
+        _ => {
+            let chan = interp.channel(channel)?;
+            if !chan.readable() {
+                return Err(channel_not_open_for(channel, "reading"));
+            }
+            chan.read_line()
+        }
+    };
+    let line = line.map_err(|e| channel::io_error(format!("error reading \"{channel}\""), &e))?;
+
+    match argv.get(2) {
+        None => interp.set_result(line.unwrap_or_default()),
+        Some(var) => {
+            let len = line.as_ref().map_or(-1, |l| l.chars().count() as i64);
+            interp.set_var(var.as_str(), line.unwrap_or_default())?;
+            interp.set_result(len.to_string());
+        }
+    }
+    Ok(Status::Ok)
+}
+
+/// `read ?-nonewline? channelId` reads to the end of the input, dropping
+/// one final newline with -nonewline; `read channelId numChars` reads that
+/// many characters, or fewer at the end of the input
+#[cfg(feature = "io")]
+fn cmd_read(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let nonewline = argv.get(1).is_some_and(|arg| arg == "-nonewline");
+    let (channel, count) = match &argv[1..] {
+        [_, channel] if nonewline => (channel.as_str(), None),
+        [channel] => (channel.as_str(), None),
+        [channel, count] if !nonewline => {
+            let count = usize::try_from(helpers::get_int(argv, 2)?).map_err(|_| {
+                TclError::new(
+                    TclErrorKind::General,
+                    format!("expected non-negative integer but got \"{count}\""),
+                )
+            })?;
+            (channel.as_str(), Some(count))
+        }
+        _ => {
+            return Err(TclError::new(
+                TclErrorKind::Arity,
+                "wrong # args: should be \"read channelId ?numChars?\" or \"read ?-nonewline? channelId\"",
+            ));
+        }
+    };
+
+    let text = match channel {
+        "stdin" => {
+            let text = match &interp.stdin {
+                Some(input) => channel::read_text(&mut *lock(input), encoding::UTF8, count),
+                None => channel::read_text(&mut std::io::stdin().lock(), encoding::UTF8, count),
+            };
+            interp.stdin_eof = text
+                .as_ref()
+                .is_ok_and(|text| count.is_none_or(|n| text.chars().count() < n));
+            text
+        }
+        "stdout" | "stderr" => return Err(channel_not_open_for(channel, "reading")),
+        _ => {
+            let chan = interp.channel(channel)?;
+            if !chan.readable() {
+                return Err(channel_not_open_for(channel, "reading"));
+            }
+            chan.read_text(count)
+        }
+    };
+    let mut text =
+        text.map_err(|e| channel::io_error(format!("error reading \"{channel}\""), &e))?;
+    if nonewline && text.ends_with('\n') {
+        text.pop();
+    }
+    interp.set_result(text);
+    Ok(Status::Ok)
+}
+
+fn cmd_set(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, 3)?;
+
+    // With no value, set just reads the variable
+    if let Some(value) = argv.get(2) {
+        interp.set_var(&argv[1], value)?;
+    }
+    let value = interp.get_var(&argv[1])?;
+    interp.set_result(value);
+    Ok(Status::Ok)
+}
+
+fn call_proc(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    ppd: &ProcPrivdata,
+) -> Result<Status, TclError> {
+    // A final formal named "args" collects any remaining arguments
+    let variadic = ppd
+        .args
+        .last()
+        .is_some_and(|a| a.name == "args" && a.default.is_none());
+    let formals = if variadic {
+        &ppd.args[..ppd.args.len() - 1]
+    } else {
+        &ppd.args[..]
+    };
+    let max = formals.len();
+    let min = formals.iter().filter(|a| a.default.is_none()).count();
+    let given = argv.len() - 1;
+
+    if given < min || (given > max && !variadic) {
+        let mut usage = argv[0].to_string();
+        for arg in formals {
+            if arg.default.is_some() {
+                usage.push_str(&format!(" ?{}?", arg.name));
+            } else {
+                usage.push_str(&format!(" {}", arg.name));
+            }
+        }
+        if variadic {
+            usage.push_str(" ?arg ...?");
+        }
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            format!("wrong # args: should be \"{usage}\""),
+        ));
+    }
+
+    if let Some(limit) = interp.max_callframes
+        && interp.callframes.len() >= limit
+    {
+        return Err(TclError::new(
+            TclErrorKind::LimitExceeded,
+            format!("call frame limit exceeded: only {limit} frames allowed"),
+        ));
+    }
+    interp.callframes.push(CallFrame::new());
+    interp.stats.max_depth = interp.stats.max_depth.max(interp.level());
+    interp.eval_depth += 1;
+    let res = bind_and_eval_proc(interp, ppd, formals, variadic, argv);
+    interp.eval_depth -= 1;
+    interp.callframes.pop();
+
+    let status = res?;
+    let status = check_loop_escape(status)?;
+    if status != Status::Return {
+        return Ok(status);
+    }
+
+    // The proc completes with whatever code `return` asked for. Codes
+    // without a Status of their own are reported as errors.
+    match std::mem::take(&mut interp.return_code) {
+        0 => Ok(Status::Ok),
+        2 => Ok(Status::Return),
+        3 => Ok(Status::Break),
+        4 => Ok(Status::Continue),
+        _ => Err(TclError::new(
+            TclErrorKind::General,
+            interp.take_result().to_string(),
+        )),
+    }
+}
+
+/// Binds a proc's arguments in the freshly pushed frame and runs its body.
+/// Kept apart from call_proc so the frame is popped however this exits.
+fn bind_and_eval_proc(
+    interp: &mut Interp,
+    ppd: &ProcPrivdata,
+    formals: &[ProcArg],
+    variadic: bool,
+    argv: &[TclValue],
+) -> Result<Status, TclError> {
+    for (i, arg) in formals.iter().enumerate() {
+        let value = match argv.get(i + 1) {
+            Some(value) => value,
+            None => arg.default.as_ref().unwrap(),
+        };
+        interp.set_var(&arg.name, value)?;
+    }
+
+    if variadic {
+        let rest = argv.get(formals.len() + 1..).unwrap_or_default();
+        interp.set_var("args", merge_list(rest))?;
+    }
+
+    let body = ppd.parsed.get_or_init(|| interp.parse_script(&ppd.body));
+    match &ppd.origin {
+        Some(origin) => interp.eval_from_source(origin.clone(), |interp| interp.run_parsed(body)),
+        None => interp.eval_parsed(body),
+    }
+}
+
+/// Turns a break or continue that has escaped to the top of a proc body
+/// or script into an error.
+fn check_loop_escape(status: Status) -> Result<Status, TclError> {
+    let name = match status {
+        Status::Break => "break",
+        Status::Continue => "continue",
+        _ => return Ok(status),
+    };
+    Err(TclError::new(
+        TclErrorKind::General,
+        format!("invoked \"{name}\" outside of a loop"),
+    ))
+}
+
+fn cmd_if(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    // Check the shape of the whole command before evaluating anything:
+    // cond ?then? body ?elseif cond ?then? body ...? ?else body?
+    let mut branches: Vec<(&TclValue, &TclValue)> = Vec::new();
+    let mut else_body: Option<&TclValue> = None;
+    let mut i = 1;
+    loop {
+        if i + 1 >= argv.len() {
+            return if_usage();
+        }
+        let cond = &argv[i];
+        i += 1;
+        if argv[i].as_str() == "then" {
+            i += 1;
+            if i == argv.len() {
+                return if_usage();
+            }
+        }
+        branches.push((cond, &argv[i]));
+        i += 1;
+
+        if i == argv.len() {
+            break;
+        }
+
+        match argv[i].as_str() {
+            "elseif" => i += 1,
+            "else" if i + 2 == argv.len() => {
+                else_body = Some(&argv[i + 1]);
+                break;
+            }
+            "else" => return if_usage(),
+            // The else keyword is optional before a final body
+            _ if i + 1 == argv.len() => {
+                else_body = Some(&argv[i]);
+                break;
+            }
+            word => {
+                return Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("invalid keyword \"{word}\" in if: must be elseif or else"),
+                ));
+            }
+        }
+    }
+
+    for (cond, body) in branches {
+        if eval_condition(interp, cond)? {
+            return interp.eval(body);
+        }
+    }
+
+    match else_body {
+        Some(body) => interp.eval(body),
+        None => {
+            interp.set_result(String::new());
+            Ok(Status::Ok)
+        }
+    }
+}
+
+fn if_usage() -> Result<Status, TclError> {
+    Err(TclError::new(
+        TclErrorKind::Arity,
+        "wrong # args: should be \"if cond body ?elseif cond body ...? ?else body?\"",
+    ))
+}
+
+/// Parses a proc's formal argument list. Each formal is itself a list:
+/// either a bare name or `{name default}`.
+fn parse_formals(proc_name: &str, spec: &str) -> Result<Vec<ProcArg>, TclError> {
+    let mut formals = Vec::new();
+
+    let in_formals = |mut e: TclError| {
+        e.msg = format!("{} (argument list of proc \"{proc_name}\")", e.msg);
+        e
+    };
+
+    let specs = parse_list(spec).map_err(in_formals)?;
+
+    for formal in specs {
+        let mut parts = parse_list(&formal).map_err(in_formals)?;
+
+        if parts.is_empty() || parts.len() > 2 {
+            return Err(TclError::new(
+                TclErrorKind::General,
+                format!("invalid argument specifier \"{formal}\" in proc \"{proc_name}\""),
+            ));
+        }
+
+        let default = if parts.len() == 2 {
+            parts.pop().map(TclValue::from)
+        } else {
+            None
+        };
+        formals.push(ProcArg {
+            name: parts.pop().unwrap(),
+            default,
+        });
+    }
+
+    Ok(formals)
+}
+
+fn cmd_proc(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 4, 4)?;
+
+    interp.define_proc(&argv[1], &argv[2], &argv[3])?;
+
+    Ok(Status::Ok)
+}
+
+fn cmd_unset(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, usize::MAX)?;
+    for name in &argv[1..] {
+        interp.unset_var(name)?;
+    }
+    Ok(Status::Ok)
+}
+
+/// Creates `local` in the current frame as a link to `other` in `frame`
+fn link_var(
+    interp: &mut Interp,
+    frame: usize,
+    other: &str,
+    local: &str,
+) -> Result<Status, TclError> {
+    let current = interp.level();
+    if frame == current && other == local {
+        return Err(TclError::new(
+            TclErrorKind::General,
+            "can't upvar from variable to itself",
+        ));
+    }
+
+    let cf = &mut interp.callframes[current];
+    if let Some(var) = cf.find(local)
+        && !matches!(var.value, VarValue::Undefined)
+    {
+        return Err(TclError::new(
+            TclErrorKind::General,
+            format!("variable \"{local}\" already exists"),
+        ));
+    }
+
+    cf.entry(local).value = VarValue::Link {
+        frame,
+        name: other.to_string(),
+    };
+    Ok(Status::Ok)
+}
+
+fn cmd_upvar(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    // The level is optional, which is detectable from the argument count
+    let has_level = argv.len().is_multiple_of(2);
+    if argv.len() < 3 {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            "wrong # args: should be \"upvar ?level? otherVar localVar ?otherVar localVar ...?\"",
+        ));
+    }
+
+    let current = interp.level();
+    let level = if has_level { argv[1].as_str() } else { "1" };
+    let frame = match level.strip_prefix('#') {
+        Some(abs) => abs.parse::<usize>().ok().filter(|f| *f <= current),
+        None => level
+            .parse::<usize>()
+            .ok()
+            .and_then(|l| current.checked_sub(l)),
+    };
+    let Some(frame) = frame else {
+        return Err(TclError::new(
+            TclErrorKind::General,
+            format!("bad level \"{level}\""),
+        ));
+    };
+
+    let start = if has_level { 2 } else { 1 };
+    for pair in argv[start..].chunks(2) {
+        link_var(interp, frame, &pair[0], &pair[1])?;
+    }
+    Ok(Status::Ok)
+}
+
+fn cmd_global(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, usize::MAX)?;
+
+    // At the top level, global has nothing to do
+    if interp.level() == 0 {
+        return Ok(Status::Ok);
+    }
+
+    for name in &argv[1..] {
+        // global ::x makes a local x
+        let local = name.strip_prefix("::").unwrap_or(name);
+        link_var(interp, 0, name, local)?;
+    }
+    Ok(Status::Ok)
+}
+
+/// Parses the list of operations a trace is for, each one of `allowed`
+fn parse_trace_ops(spec: &str, allowed: &[&str]) -> Result<Vec<String>, TclError> {
+    let expected = match allowed {
+        [first, second] => format!("{first} or {second}"),
+        [rest @ .., last] => format!("{}, or {last}", rest.join(", ")),
+        [] => String::new(),
+    };
+    let mut ops = parse_list(spec)?;
+    if ops.is_empty() {
+        return Err(TclError::new(
+            TclErrorKind::General,
+            format!("bad operation list \"\": must be one or more of {expected}"),
+        ));
+    }
+    for op in &ops {
+        if !allowed.contains(&op.as_str()) {
+            return Err(TclError::new(
+                TclErrorKind::General,
+                format!("bad operation \"{op}\": must be {expected}"),
+            ));
+        }
+    }
+    ops.sort();
+    ops.dedup();
+    Ok(ops)
+}
+
+/// `trace add|info|remove execution|variable name ?opList command?`
+fn trace_ensemble() -> Ensemble {
+    Ensemble::new("trace")
+        .subcommand("add", "type name ?opList command?", cmd_trace)
+        .subcommand("info", "type name ?opList command?", cmd_trace)
+        .subcommand("remove", "type name ?opList command?", cmd_trace)
+}
+
+fn cmd_trace(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    if get_enum(argv, 2, &["execution", "variable"])? == "execution" {
+        return trace_execution(interp, argv);
+    }
+
+    let current = interp.level();
+    let (frame, target) = interp.resolve_var(current, &argv[3]);
+
+    match argv[1].as_str() {
+        "add" | "remove" => {
+            if argv.len() != 6 {
+                return Err(TclError::new(
+                    TclErrorKind::Arity,
+                    format!(
+                        "wrong # args: should be \"trace {} variable name opList command\"",
+                        argv[1]
+                    ),
+                ));
+            }
+
+            let trace = VarTrace {
+                ops: parse_trace_ops(&argv[4], &["read", "unset", "write"])?,
+                command: argv[5].to_string(),
+            };
+            let var = interp.callframes[frame].entry(&target);
+
+            if argv[1] == "add" {
+                var.traces.push(trace);
+            } else if let Some(pos) = var
+                .traces
+                .iter()
+                .position(|t| t.ops == trace.ops && t.command == trace.command)
+            {
+                var.traces.remove(pos);
+            }
+            Ok(Status::Ok)
+        }
+        "info" => {
+            if argv.len() != 4 {
+                return Err(TclError::new(
+                    TclErrorKind::Arity,
+                    "wrong # args: should be \"trace info variable name\"",
+                ));
+            }
+
+            let info: Vec<String> = interp.callframes[frame]
+                .find(&target)
+                .map(|var| {
+                    var.traces
+                        .iter()
+                        .map(|t| merge_list(&[merge_list(&t.ops), t.command.clone()]))
+                        .collect()
+                })
+                .unwrap_or_default();
+            interp.set_result(merge_list(&info));
+            Ok(Status::Ok)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// `trace add|remove execution name opList command` and
+/// `trace info execution name`, where the operations are enter and leave.
+/// Before the command runs, `command cmdString enter` is evaluated, and
+/// after it, `command cmdString code result leave`, where cmdString is
+/// the command's words as a list and code is as `catch` returns.
+fn trace_execution(interp: &mut Interp, argv: &[TclValue]) -> Result<Status, TclError> {
+    let name = argv[3].as_str();
+    let usage = match argv[1].as_str() {
+        "info" => "trace info execution name",
+        "add" => "trace add execution name opList command",
+        _ => "trace remove execution name opList command",
+    };
+    if argv.len() != if argv[1] == "info" { 4 } else { 6 } {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            format!("wrong # args: should be \"{usage}\""),
+        ));
+    }
+    let Some(&i) = interp.commands.index.get(name) else {
+        return Err(TclError::new(
+            TclErrorKind::CommandNotFound,
+            format!("unknown command \"{name}\""),
+        ));
+    };
+    let traces = &mut interp.commands.cmds[i].traces;
+
+    if argv[1] == "info" {
+        let info: Vec<String> = traces
+            .iter()
+            .map(|t| merge_list(&[merge_list(&t.ops), t.command.clone()]))
+            .collect();
+        interp.set_result(merge_list(&info));
+        return Ok(Status::Ok);
+    }
+    let trace = ExecTrace {
+        ops: parse_trace_ops(&argv[4], &["enter", "leave"])?,
+        command: argv[5].to_string(),
+    };
+    if argv[1] == "add" {
+        traces.push(trace);
+    } else if let Some(pos) = traces
+        .iter()
+        .position(|t| t.ops == trace.ops && t.command == trace.command)
+    {
+        traces.remove(pos);
+    }
+    Ok(Status::Ok)
+}
+
+fn cmd_rename(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 3, 3)?;
+
+    let old = &argv[1];
+    let new = &argv[2];
+
+    if interp.get_command(old).is_none() {
+        let action = if new.is_empty() { "delete" } else { "rename" };
+        return Err(TclError::new(
+            TclErrorKind::CommandNotFound,
+            format!("can't {action} \"{old}\": command doesn't exist"),
+        ));
+    }
+
+    if new.is_empty() {
+        interp.unregister_command(old)?;
+        return Ok(Status::Ok);
+    }
+
+    if interp.get_command(new).is_some() {
+        return Err(TclError::new(
+            TclErrorKind::CommandAlreadyDefined,
+            format!("can't rename to \"{new}\": command already exists"),
+        ));
+    }
+
+    interp.commands.rename(old, new);
+    Ok(Status::Ok)
+}
+
+fn cmd_while(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 3, 3)?;
+
+    let cond = &argv[1];
+    // The body is parsed once, on the first iteration
+    let mut body = None;
+
+    loop {
+        if !eval_condition(interp, cond)? {
+            return Ok(Status::Ok);
+        }
+
+        let body = body.get_or_insert_with(|| interp.parse_script(&argv[2]));
+        let res2 = interp.eval_parsed(body)?;
+
+        if res2 == Status::Continue || res2 == Status::Ok {
+            continue;
+        } else if res2 == Status::Break {
+            break;
+        } else {
+            return Ok(res2);
+        }
+    }
+    Ok(Status::Ok)
+}
+
+/// `foreach varList list ?varList list ...? body`. Each iteration takes
+/// as many elements from each list as its varList has variables, so
+/// `foreach {k v} $pairs` walks pairs and `foreach a $l1 b $l2` walks two
+/// lists side by side. Iteration goes on until every list is used up, with
+/// variables past the end of a shorter list set to the empty string.
+fn cmd_foreach(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let wrong_args = || {
+        TclError::new(
+            TclErrorKind::Arity,
+            "wrong # args: should be \"foreach varList list ?varList list ...? command\"",
+        )
+    };
+    if argv.len() < 4 || !argv.len().is_multiple_of(2) {
+        return Err(wrong_args());
+    }
+
+    let mut pairs = Vec::new();
+    for pair in argv[1..argv.len() - 1].chunks(2) {
+        let vars = pair[0].as_list()?;
+        if vars.is_empty() {
+            return Err(wrong_args());
+        }
+        pairs.push((vars, pair[1].as_list()?));
+    }
+    let iterations = pairs
+        .iter()
+        .map(|(vars, list)| list.len().div_ceil(vars.len()))
+        .max()
+        .unwrap_or(0);
+
+    // The body is parsed once, on the first iteration
+    let mut body = None;
+    for i in 0..iterations {
+        for (vars, list) in &pairs {
+            for (j, var) in vars.iter().enumerate() {
+                let value = list.get(i * vars.len() + j).map_or("", TclValue::as_str);
+                interp.set_var(var, value)?;
+            }
+        }
+
+        let body = body.get_or_insert_with(|| interp.parse_script(&argv[argv.len() - 1]));
+        match interp.eval_parsed(body)? {
+            Status::Ok | Status::Continue => {}
+            Status::Break => break,
+            status => return Ok(status),
+        }
+    }
+    interp.set_result("");
+    Ok(Status::Ok)
+}
+
+fn cmd_continue(
+    _interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 1, 1)?;
+    Ok(Status::Continue)
+}
+
+fn cmd_break(
+    _interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 1, 1)?;
+    Ok(Status::Break)
+}
+
+fn cmd_return(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let mut code = 0;
+    let mut i = 1;
+    while i + 1 < argv.len() && argv[i] == "-code" {
+        code = parse_status_code(&argv[i + 1])?;
+        i += 2;
+    }
+    if argv.len() > i + 1 {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            "wrong # args: should be \"return ?-code code? ?value?\"",
+        ));
+    }
+
+    interp.set_result(argv.get(i).cloned().unwrap_or_default());
+    interp.return_code = code;
+    Ok(Status::Return)
+}
+
+#[cfg(feature = "process")]
+fn cmd_after(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, 3)?;
+
+    let Ok(ms) = u64::try_from(helpers::get_int(argv, 1)?) else {
+        return Err(TclError::new(
+            TclErrorKind::InvalidNumber,
+            format!("expected non-negative integer but got \"{}\"", argv[1]),
+        ));
+    };
+
+    interp.scheduler.sleep(ms);
+
+    if argv.len() == 3 {
+        return interp.eval(&argv[2]);
+    }
+
+    Ok(Status::Ok)
+}
+
+#[cfg(feature = "regex")]
+fn compile_regex(pattern: &str, nocase: bool) -> Result<regex::Regex, TclError> {
+    regex::RegexBuilder::new(pattern)
+        .case_insensitive(nocase)
+        .build()
+        .map_err(|e| {
+            TclError::new(
+                TclErrorKind::General,
+                format!("couldn't compile regular expression pattern: {e}"),
+            )
+        })
+}
+
+#[cfg(feature = "regex")]
+fn cmd_regexp(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let mut nocase = false;
+    let mut all = false;
+    let mut i = 1;
+
+    while i < argv.len() && argv[i].starts_with('-') {
+        match get_enum(argv, i, &["-all", "-nocase", "--"])? {
+            "-all" => all = true,
+            "-nocase" => nocase = true,
+            _ => {
+                i += 1;
+                break;
+            }
+        }
+        i += 1;
+    }
+
+    if argv.len() < i + 2 {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            format!(
+                "wrong # args: should be \"{} ?-option ...? exp string ?matchVar? ?subMatchVar ...?\"",
+                argv[0]
+            ),
+        ));
+    }
+
+    let re = compile_regex(&argv[i], nocase)?;
+    let string = &argv[i + 1];
+    let vars = &argv[i + 2..];
+
+    let mut count = 0;
+    let mut last = None;
+    for caps in re.captures_iter(string) {
+        count += 1;
+        last = Some(caps);
+        if !all {
+            break;
+        }
+    }
+
+    // Variables are only touched on a match; groups that did not take part
+    // in the match are set to the empty string, like Tcl does.
+    if let Some(caps) = last {
+        for (n, var) in vars.iter().enumerate() {
+            let value = caps.get(n).map_or("", |m| m.as_str());
+            interp.set_var(var, value)?;
+        }
+    }
+
+    interp.set_result(format!("{count}"));
+    Ok(Status::Ok)
+}
+
+/// Expands a regsub replacement spec: `&` and `\0` are the whole match,
+/// `\1`-`\9` are capture groups, and `\&` / `\\` are literals.
+#[cfg(feature = "regex")]
+fn regsub_expand(caps: &regex::Captures, spec: &str, out: &mut String) {
+    let mut chars = spec.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => out.push_str(caps.get(0).map_or("", |m| m.as_str())),
+            '\\' => match chars.peek().copied() {
+                Some(d @ '0'..='9') => {
+                    chars.next();
+                    let n = d as usize - '0' as usize;
+                    out.push_str(caps.get(n).map_or("", |m| m.as_str()));
+                }
+                Some(e @ ('&' | '\\')) => {
+                    chars.next();
+                    out.push(e);
+                }
+                _ => out.push('\\'),
+            },
+            _ => out.push(c),
+        }
+    }
+}
+
+#[cfg(feature = "regex")]
+fn cmd_regsub(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let mut nocase = false;
+    let mut all = false;
+    let mut i = 1;
+
+    while i < argv.len() && argv[i].starts_with('-') {
+        match get_enum(argv, i, &["-all", "-nocase", "--"])? {
+            "-all" => all = true,
+            "-nocase" => nocase = true,
+            _ => {
+                i += 1;
+                break;
+            }
+        }
+        i += 1;
+    }
+
+    if argv.len() < i + 3 || argv.len() > i + 4 {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            format!(
+                "wrong # args: should be \"{} ?-option ...? exp string subSpec ?varName?\"",
+                argv[0]
+            ),
+        ));
+    }
+
+    let re = compile_regex(&argv[i], nocase)?;
+    let string = &argv[i + 1];
+    let spec = &argv[i + 2];
+
+    let mut out = String::with_capacity(string.len());
+    let mut last_end = 0;
+    let mut count = 0;
+    for caps in re.captures_iter(string) {
+        let m = caps.get(0).unwrap();
+        out.push_str(&string[last_end..m.start()]);
+        regsub_expand(&caps, spec, &mut out);
+        last_end = m.end();
+        count += 1;
+        if !all {
+            break;
+        }
+    }
+    out.push_str(&string[last_end..]);
+
+    if let Some(var) = argv.get(i + 3) {
+        interp.set_var(var, &out)?;
+        interp.set_result(format!("{count}"));
+    } else {
+        interp.set_result(out);
+    }
+    Ok(Status::Ok)
+}
+
+/// `interp subcommand ?arg ...?`: child interpreters, their commands and
+/// their limits
+fn interp_ensemble() -> Ensemble {
+    let alias_usage = "srcPath srcCmd ?targetPath targetCmd ?arg ...??";
+    Ensemble::new("interp")
+        .subcommand("alias", alias_usage, cmd_interp)
+        .subcommand("create", "?-safe? ?path?", cmd_interp)
+        .subcommand("delete", "path ?path ...?", cmd_interp)
+        .subcommand("eval", "path arg ?arg ...?", cmd_interp)
+        .subcommand("exists", "path", cmd_interp)
+        .subcommand("expose", "path hiddenCmdName ?exposedCmdName?", cmd_interp)
+        .subcommand("hide", "path exposedCmdName ?hiddenCmdName?", cmd_interp)
+        .subcommand("invokehidden", "path cmd ?arg ...?", cmd_interp)
+        .subcommand("recursionlimit", "path ?newlimit?", cmd_interp)
+        .subcommand("steplimit", "path ?newlimit?", cmd_interp)
+}
+
+fn cmd_interp(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    match argv[1].as_str() {
+        "alias" => interp_alias(interp, argv),
+        "hide" | "expose" => {
+            let child = find_child(interp, &argv[2])?;
+            let hide = argv[1] == "hide";
+            let (from, to) = if hide {
+                (&mut child.commands, &mut child.hidden)
+            } else {
+                (&mut child.hidden, &mut child.commands)
+            };
+            let name = argv[3].as_str();
+            let new_name = argv.get(4).map_or(name, TclValue::as_str);
+            if from.get(name).is_none() {
+                let what = if hide { "" } else { "hidden " };
+                return Err(TclError::new(
+                    TclErrorKind::CommandNotFound,
+                    format!("unknown {what}command \"{name}\""),
+                ));
+            }
+            if to.get(new_name).is_some() {
+                let what = if hide { "hidden" } else { "exposed" };
+                return Err(TclError::new(
+                    TclErrorKind::CommandAlreadyDefined,
+                    format!("{what} command \"{new_name}\" already exists"),
+                ));
+            }
+            let mut cmd = from.remove(name).unwrap();
+            cmd.name = new_name.to_string();
+            to.insert(cmd);
+            Ok(Status::Ok)
+        }
+        "invokehidden" => {
+            let child = find_child(interp, &argv[2])?;
+            let name = argv[3].as_str();
+            let Some(cmd) = child.hidden.get(name) else {
+                return Err(TclError::new(
+                    TclErrorKind::CommandNotFound,
+                    format!("unknown hidden command \"{name}\""),
+                ));
+            };
+            let body = cmd.body.clone();
+            let res = child.eval_with(|child| body.call(child, &argv[3..]));
+            let result = child.take_result();
+            interp.set_result(result);
+            match res {
+                Ok(Status::Return) => Ok(Status::Ok),
+                res => res,
+            }
+        }
+        "create" => {
+            let safe = argv.get(2).is_some_and(|arg| arg == "-safe");
+            let argv = if safe { &argv[1..] } else { argv };
+            if argv.len() == 4 {
+                return Err(TclError::new(
+                    TclErrorKind::Arity,
+                    "wrong # args: should be \"interp create ?-safe? ?path?\"",
+                ));
+            }
+            let name = match argv.get(2) {
+                Some(name) => name.to_string(),
+                None => (0..)
+                    .map(|n| format!("interp{n}"))
+                    .find(|n| !interp.children.contains_key(n))
+                    .unwrap(),
+            };
+
+            if interp.children.contains_key(&name) {
+                return Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("interpreter named \"{name}\" already exists"),
+                ));
+            }
+
+            let mut child = Interp::new();
+            child.safe = safe || interp.safe;
+            if child.safe {
+                child.register_core_commands();
+                let core = child.commands.cmds.len();
+                child.register_command_sets(interp.command_sets);
+                let unsafe_names: Vec<String> = child
+                    .commands
+                    .names()
+                    .skip(core)
+                    .map(String::from)
+                    .collect();
+                for name in unsafe_names {
+                    let cmd = child.commands.remove(&name).unwrap();
+                    child.hidden.insert(cmd);
+                }
+            } else {
+                child.register_command_sets(interp.command_sets);
+            }
+            child.interrupt = interp.interrupt.clone();
+            child.trace_parser = interp.trace_parser;
+            child.trace_eval = interp.trace_eval;
+            child.stdin = interp.stdin.clone();
+            child.stdout = interp.stdout.clone();
+            child.stderr = interp.stderr.clone();
+            interp.children.insert(name.to_string(), child);
+
+            interp.set_result(name);
+            Ok(Status::Ok)
+        }
+        "eval" => {
+            let name = argv[2].as_str();
+
+            // The child is taken out of the map while it runs so that the
+            // parent isn't borrowed
+            let Some(mut child) = interp.children.remove(name) else {
+                return Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("could not find interpreter \"{name}\""),
+                ));
+            };
+
+            // The child runs within what's left of this interpreter's
+            // limits too, so that nesting interpreters can't escape them
+            let limits = (child.max_nesting_depth, child.eval_limit);
+            let depth_left = interp.max_nesting_depth.saturating_sub(interp.eval_depth);
+            child.max_nesting_depth = child.max_nesting_depth.min(depth_left);
+            let inherited = interp.eval_limit.is_some()
+                && child
+                    .eval_limit
+                    .is_none_or(|limit| interp.eval_fuel < limit);
+            if inherited {
+                child.set_eval_limit(Some(interp.eval_fuel));
+            }
+
+            // This interpreter is lent to the child while it runs, for its
+            // aliases, and left empty here until it comes back
+            let script = argv[3..].join(" ");
+            child.parent = Some(Box::new(std::mem::take(interp)));
+            let res = child.eval(&script);
+            if let Some(parent) = child.parent.take() {
+                *interp = *parent;
+            }
+            let result = child.take_result();
+            if inherited {
+                interp.eval_fuel = child.eval_fuel;
+            }
+            child.max_nesting_depth = limits.0;
+            child.set_eval_limit(limits.1);
+            interp.children.insert(name.to_string(), child);
+            if inherited && matches!(&res, Err(e) if e.kind == TclErrorKind::LimitExceeded) {
+                interp.consume_fuel()?;
+            }
+
+            match res {
+                Ok(status) => {
+                    interp.set_result(result);
+                    if status == Status::Return {
+                        Ok(Status::Ok)
+                    } else {
+                        Ok(status)
+                    }
+                }
+                Err(mut e) => {
+                    // Running out of the child's limit is an ordinary
+                    // error for the parent
+                    if e.kind == TclErrorKind::LimitExceeded {
+                        e.kind = TclErrorKind::General;
+                    }
+                    e.msg = format!("{name}: {}", e.msg);
+                    Err(e)
+                }
+            }
+        }
+        "delete" => {
+            for name in &argv[2..] {
+                if interp.children.remove(name.as_str()).is_none() {
+                    return Err(TclError::new(
+                        TclErrorKind::General,
+                        format!("could not find interpreter \"{name}\""),
+                    ));
+                }
+            }
+            Ok(Status::Ok)
+        }
+        "exists" => {
+            let exists = interp.children.contains_key(argv[2].as_str());
+            interp.set_result(format!("{}", exists as i64));
+            Ok(Status::Ok)
+        }
+        "recursionlimit" | "steplimit" => {
+            let path = argv[2].as_str();
+            let steps = argv[1] == "steplimit";
+
+            // An empty step limit removes it
+            let limit = match argv.get(3) {
+                Some(arg) if steps && arg.is_empty() => Some(None),
+                Some(arg) => match arg.parse::<usize>() {
+                    Ok(limit) if limit > 0 => Some(Some(limit)),
+                    _ => {
+                        let what = if steps { "step" } else { "recursion" };
+                        return Err(TclError::new(
+                            TclErrorKind::InvalidNumber,
+                            format!("bad {what} limit \"{arg}\": must be a positive integer"),
+                        ));
+                    }
+                },
+                None => None,
+            };
+
+            // An empty path names this interpreter
+            let target = if path.is_empty() {
+                &mut *interp
+            } else {
+                match interp.children.get_mut(path) {
+                    Some(child) => child,
+                    None => {
+                        return Err(TclError::new(
+                            TclErrorKind::General,
+                            format!("could not find interpreter \"{path}\""),
+                        ));
+                    }
+                }
+            };
+
+            let current = if steps {
+                if let Some(limit) = limit {
+                    target.set_eval_limit(limit.map(|l| l as u64));
+                }
+                target
+                    .eval_limit()
+                    .map(|l| l.to_string())
+                    .unwrap_or_default()
+            } else {
+                if let Some(Some(limit)) = limit {
+                    target.set_max_nesting_depth(limit);
+                }
+                target.max_nesting_depth().to_string()
+            };
+            interp.set_result(current);
+            Ok(Status::Ok)
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn find_child<'a>(interp: &'a mut Interp, path: &str) -> Result<&'a mut Interp, TclError> {
+    interp.children.get_mut(path).ok_or_else(|| {
+        TclError::new(
+            TclErrorKind::General,
+            format!("could not find interpreter \"{path}\""),
+        )
+    })
+}
+
+/// Where an alias made with `interp alias` sends its calls: the command
+/// to run and the words to put before the caller's, in the interpreter
+/// that made it or, if the alias is in this one, here
+struct Alias {
+    in_parent: bool,
+    target: Vec<String>,
+}
+
+/// `interp alias srcPath srcCmd ?targetPath targetCmd ?arg ...??`. With
+/// a target, creates `srcCmd` in the interpreter at srcPath to call
+/// targetCmd here, with the args before the caller's; an empty target
+/// deletes the alias; without one, returns what the alias calls. The
+/// empty path names this interpreter.
+fn interp_alias(interp: &mut Interp, argv: &[TclValue]) -> Result<Status, TclError> {
+    if argv.len() < 4 {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            "wrong # args: should be \"interp alias srcPath srcCmd ?targetPath targetCmd ?arg ...??\"",
+        ));
+    }
+    let (path, name) = (argv[2].as_str(), argv[3].as_str());
+    if let Some(target_path) = argv.get(4)
+        && !target_path.is_empty()
+    {
+        return Err(TclError::new(
+            TclErrorKind::General,
+            format!(
+                "could not find interpreter \"{target_path}\": aliases can only call the interpreter that makes them"
+            ),
+        ));
+    }
+    let src = if path.is_empty() {
+        &mut *interp
+    } else {
+        match interp.children.get_mut(path) {
+            Some(child) => child,
+            None => {
+                return Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("could not find interpreter \"{path}\""),
+                ));
+            }
+        }
+    };
+
+    let target = src
+        .command_data::<Alias>(name)
+        .map(|alias| alias.target.clone());
+    let result = match argv.len() {
+        4 => match target {
+            Some(target) => list_from_iter(&target),
+            None => {
+                return Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("alias \"{name}\" not found"),
+                ));
+            }
+        },
+        5 => {
+            if target.is_none() {
+                return Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("alias \"{name}\" not found"),
+                ));
+            }
+            src.unregister_command(name)?;
+            String::new()
+        }
+        _ => {
+            let alias = Alias {
+                in_parent: !path.is_empty(),
+                target: argv[5..].iter().map(TclValue::to_string).collect(),
+            };
+            let _ = src.unregister_command(name);
+            src.register_command_with_data(name, cmd_alias, alias)?;
+            name.to_string()
+        }
+    };
+    interp.set_result(result);
+    Ok(Status::Ok)
+}
+
+/// Runs an alias's target command with the caller's words after its own,
+/// handing back the result or error. A break or continue can't reach a
+/// loop in the other interpreter, so from the parent it's an error.
+fn cmd_alias(interp: &mut Interp, argv: &[TclValue], alias: &Alias) -> Result<Status, TclError> {
+    let words: Vec<TclValue> = alias
+        .target
+        .iter()
+        .map(TclValue::from)
+        .chain(argv[1..].iter().cloned())
+        .collect();
+    if !alias.in_parent {
+        return interp.invoke(&words);
+    }
+
+    let Some(mut parent) = interp.parent.take() else {
+        return Err(TclError::new(
+            TclErrorKind::General,
+            format!("alias \"{}\" can't reach its target interpreter", argv[0]),
+        ));
+    };
+    let res = parent.eval_with(|parent| parent.invoke(&words));
+    let result = parent.take_result();
+    interp.parent = Some(parent);
+    match res {
+        Ok(Status::Return) => {
+            interp.set_result(result);
+            Ok(Status::Ok)
+        }
+        Ok(status) => {
+            interp.set_result(result);
+            check_loop_escape(status)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// `coroutine name command ?arg ...?` runs the command until it yields,
+/// returning the yielded value, and creates `name` to resume it. Unless
+/// it has already finished: then the result is the command's, and there
+/// is nothing to resume.
+#[cfg(feature = "coroutine")]
+fn cmd_coroutine(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 3, usize::MAX)?;
+    let name = argv[1].as_str();
+    if interp.get_command(name).is_some() {
+        return Err(TclError::new(
+            TclErrorKind::CommandAlreadyDefined,
+            format!("command \"{name}\" already exists"),
+        ));
+    }
+
+    // The command exists while the coroutine first runs, as it does
+    // whenever it's resumed
+    let (started, res) = coroutine::start(interp, argv[2..].to_vec())?;
+    if !coroutine::is_done(&started) {
+        interp.register_command_with_data(name, cmd_resume, started)?;
+    }
+    res
+}
+
+/// The command of a coroutine: `name ?value?` resumes it, with value as
+/// the result of the `yield` it's suspended in. The command goes away
+/// once the coroutine finishes.
+#[cfg(feature = "coroutine")]
+fn cmd_resume(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    started: &Arc<coroutine::Coroutine>,
+) -> Result<Status, TclError> {
+    if argv.len() > 2 {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            format!("wrong # args: should be \"{} ?value?\"", argv[0]),
+        ));
+    }
+    let value = argv.get(1).map(TclValue::to_string).unwrap_or_default();
+    let res = coroutine::resume(interp, started, value);
+    if coroutine::is_done(started)
+        && let Some(name) = coroutine::command_name(interp, started)
+    {
+        let _ = interp.unregister_command(&name);
+    }
+    res
+}
+
+/// `yield ?value?` suspends the running coroutine, making value the
+/// result of the command that resumed it
+#[cfg(feature = "coroutine")]
+fn cmd_yield(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 1, 2)?;
+    let value = argv.get(1).map(TclValue::to_string).unwrap_or_default();
+    coroutine::yield_to_caller(interp, value)
+}
+
+/// Maps an evaluation outcome to Tcl's numeric return code
+fn status_code(res: &Result<Status, TclError>) -> i64 {
+    match res {
+        Ok(Status::Ok) => 0,
+        Err(_) => 1,
+        Ok(Status::Return) => 2,
+        Ok(Status::Break) => 3,
+        Ok(Status::Continue) => 4,
+    }
+}
+
+fn parse_status_code(code: &str) -> Result<i64, TclError> {
+    match code {
+        "ok" => Ok(0),
+        "error" => Ok(1),
+        "return" => Ok(2),
+        "break" => Ok(3),
+        "continue" => Ok(4),
+        _ => code.parse::<i64>().map_err(|_| TclError::new(TclErrorKind::General, format!(
+                "bad completion code \"{code}\": must be ok, error, return, break, continue, or an integer"
+            ))),
+    }
+}
+
+/// `error message ?info? ?code?`. A nonempty info starts the errorInfo
+/// traceback in place of the message, as when rethrowing a caught error,
+/// and code becomes errorCode.
+fn cmd_error(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, 4)?;
+    let mut err = TclError::new(TclErrorKind::General, argv[1].clone());
+    if let Some(code) = argv.get(3) {
+        err = err.with_code(code.as_str());
+    }
+    if let Some(info) = argv.get(2).filter(|info| !info.is_empty()) {
+        interp.error_info = info.to_string();
+        interp.error_trace_active = true;
+        let _ = interp.set_var_at(0, "errorInfo", info.as_str());
+        let _ = interp.set_var_at(0, "errorCode", err.error_code());
+    }
+    Err(err)
+}
+
+/// The options dictionary of `catch` and `try` handlers: the return code
+/// and, for errors, errorCode and errorInfo
+fn return_options(interp: &Interp, res: &Result<Status, TclError>) -> String {
+    let code = status_code(res).to_string();
+    match res {
+        Err(e) => list_from_iter([
+            "-code",
+            &code,
+            "-level",
+            "0",
+            "-errorcode",
+            &e.error_code(),
+            "-errorinfo",
+            &interp.error_info,
+        ]),
+        Ok(_) => list_from_iter(["-code", &code, "-level", "0"]),
+    }
+}
+
+fn cmd_catch(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, 4)?;
+
+    let res = interp.eval(&argv[1]);
+    if let Err(e) = &res
+        && e.is_uncatchable()
+    {
+        return res;
+    }
+    let options = return_options(interp, &res);
+    interp.clear_error_trace();
+    let result = match &res {
+        Ok(_) => interp.take_result(),
+        Err(e) => e.msg.as_str().into(),
+    };
+
+    if let Some(var) = argv.get(2) {
+        interp.set_var(var, &result)?;
+    }
+    if let Some(var) = argv.get(3) {
+        interp.set_var(var, options)?;
+    }
+
+    interp.set_result(format!("{}", status_code(&res)));
+    Ok(Status::Ok)
+}
+
+fn cmd_try(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, usize::MAX)?;
+
+    // Split the arguments into `on code varList script` handlers and an
+    // optional trailing `finally script`
+    let mut handlers: Vec<(i64, &TclValue, &TclValue)> = Vec::new();
+    let mut finally: Option<&TclValue> = None;
+    let mut i = 2;
+    while i < argv.len() {
+        match argv[i].as_str() {
+            "on" if i + 3 < argv.len() => {
+                let code = parse_status_code(&argv[i + 1])?;
+                handlers.push((code, &argv[i + 2], &argv[i + 3]));
+                i += 4;
+            }
+            "finally" if i + 2 == argv.len() => {
+                finally = Some(&argv[i + 1]);
+                i += 2;
+            }
+            _ => {
+                return Err(TclError::new(
+                    TclErrorKind::Arity,
+                    "wrong # args: should be \"try body ?on code varList script ...? ?finally script?\"",
+                ));
+            }
+        }
+    }
+
+    let mut res = interp.eval(&argv[1]);
+    if let Err(e) = &res
+        && e.is_uncatchable()
+    {
+        return res;
+    }
+    let code = status_code(&res);
+
+    if let Some(pos) = handlers.iter().position(|h| h.0 == code) {
+        let options = return_options(interp, &res);
+        interp.clear_error_trace();
+        let result = match &res {
+            Ok(_) => interp.take_result(),
+            Err(e) => e.msg.as_str().into(),
+        };
+
+        // A script of "-" falls through to the next handler's script
+        let Some(script) = handlers[pos..].iter().map(|h| h.2).find(|s| *s != "-") else {
+            return Err(TclError::new(
+                TclErrorKind::General,
+                "last non-finally clause must not have a body of \"-\"",
+            ));
+        };
+
+        let vars = parse_list(handlers[pos].1)?;
+        if let Some(var) = vars.first() {
+            interp.set_var(var, &result)?;
+        }
+        if let Some(var) = vars.get(1) {
+            interp.set_var(var, options)?;
+        }
+
+        res = interp.eval(script);
+    }
+
+    // The finally script always runs, and the earlier outcome stands unless
+    // the finally script itself fails
+    if let Some(finally) = finally {
+        let result = interp.take_result();
+        interp.eval(finally)?;
+        interp.result = result;
+    }
+
+    res
+}
+
+/// Parses a Tcl boolean: true/false, yes/no, on/off in any case, or a
+/// number where nonzero is true.
+pub fn parse_bool(s: &str) -> Result<bool, TclError> {
+    match s.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => return Ok(true),
+        "false" | "no" | "off" => return Ok(false),
+        _ => {}
+    }
+
+    if let Ok(i) = s.parse::<i64>() {
+        return Ok(i != 0);
+    }
+    if let Ok(f) = s.trim().parse::<f64>() {
+        return Ok(f != 0.0);
+    }
+
+    Err(TclError::new(
+        TclErrorKind::InvalidNumber,
+        format!("expected boolean value but got \"{s}\""),
+    ))
+}
+
+/// Evaluates an if/while condition as an expression. For compatibility
+/// with picol-style scripts, a condition that doesn't parse as an
+/// expression (such as `{== $x 1}`) is evaluated as a script instead, and
+/// its result must be a boolean. A lone word is never run as a command:
+/// `if maybe` is a bad boolean, not a call to `maybe`.
+fn eval_condition(interp: &mut Interp, cond: &str) -> Result<bool, TclError> {
+    if let Ok(node) = parse_expr(cond) {
+        let v = eval_expr_node(interp, &node)?;
+        return expr_bool(v);
+    }
+    let word = cond.trim();
+    if !word.contains(char::is_whitespace) {
+        return parse_bool(word);
+    }
+
+    let result = interp.eval_str(cond)?;
+    parse_bool(&result)
+}
+
+fn cmd_assert(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, 3)?;
+
+    if eval_condition(interp, &argv[1])? {
+        interp.set_result(String::new());
+        return Ok(Status::Ok);
+    }
+
+    let mut msg = format!("assertion failed: {}", argv[1]);
+    if let Some(extra) = argv.get(2) {
+        msg.push_str(&format!(": {extra}"));
+    }
+    Err(TclError::new(TclErrorKind::General, msg))
+}
+
+fn cmd_assert_eq(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 3, 3)?;
+
+    if argv[1] == argv[2] {
+        interp.set_result(String::new());
+        return Ok(Status::Ok);
+    }
+
+    Err(TclError::new(
+        TclErrorKind::General,
+        format!(
+            "assertion failed: expected \"{}\", got \"{}\"",
+            argv[2], argv[1]
+        ),
+    ))
+}
+
+/// `test name description body result`, or `test name description
+/// ?-option value ...?` with the options -setup, -body, -cleanup, -result
+/// and -returnCodes. Runs the body and checks its result and completion
+/// code, printing a report if they're not what was expected.
+fn cmd_test(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 3, usize::MAX)?;
+    let (name, description) = (&argv[1], &argv[2]);
+
+    let empty = TclValue::default();
+    let (mut setup, mut body, mut cleanup, mut expected) = (&empty, &empty, &empty, &empty);
+    let mut codes = vec![0, 2];
+    if argv.len() == 5 && !argv[3].as_str().starts_with('-') {
+        (body, expected) = (&argv[3], &argv[4]);
+    } else {
+        if argv.len().is_multiple_of(2) {
+            return Err(TclError::new(
+                TclErrorKind::Arity,
+                format!(
+                    "wrong # args: should be \"{} name description ?-option value ...?\"",
+                    argv[0]
+                ),
+            ));
+        }
+        for pair in argv[3..].chunks(2) {
+            let value = &pair[1];
+            match pair[0].as_str() {
+                "-setup" => setup = value,
+                "-body" => body = value,
+                "-cleanup" => cleanup = value,
+                "-result" => expected = value,
+                "-returnCodes" => {
+                    codes = value
+                        .as_list()?
+                        .iter()
+                        .map(|code| parse_status_code(code))
+                        .collect::<Result<_, _>>()?;
+                }
+                option => {
+                    return Err(TclError::new(
+                        TclErrorKind::General,
+                        format!(
+                            "bad option \"{option}\": must be -body, -cleanup, -result, -returnCodes, or -setup"
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    interp.eval(setup)?;
+    let res = interp.eval(body);
+    if let Err(e) = &res
+        && e.is_uncatchable()
+    {
+        return res;
+    }
+    interp.clear_error_trace();
+    let actual = match &res {
+        Ok(_) => interp.take_result(),
+        Err(e) => e.msg.as_str().into(),
+    };
+    interp.eval(cleanup)?;
+
+    let code = status_code(&res);
+    let mut report = String::new();
+    if !codes.contains(&code) {
+        let names: Vec<String> = codes.iter().map(|c| status_name(*c)).collect();
+        report += &format!(
+            "---- Return code should have been one of: {}\n",
+            names.join(" ")
+        );
+        report += &format!("---- Return code was: {}\n", status_name(code));
+    }
+    if actual != *expected {
+        report += &format!("---- Result was:\n{actual}\n");
+        report += &format!("---- Result should have been (exact matching):\n{expected}\n");
+    }
+
+    if report.is_empty() {
+        interp.test_counts.passed += 1;
+    } else {
+        interp.test_counts.failed += 1;
+        let _ = interp.write_output(format_args!(
+            "\n==== {name} {description} FAILED\n==== Contents of test case:\n{body}\n{report}==== {name} FAILED\n\n"
+        ));
+    }
+    interp.set_result(String::new());
+    Ok(Status::Ok)
+}
+
+/// The name of a completion code, or the number if it hasn't one
+fn status_name(code: i64) -> String {
+    match code {
+        0 => "ok".to_string(),
+        1 => "error".to_string(),
+        2 => "return".to_string(),
+        3 => "break".to_string(),
+        4 => "continue".to_string(),
+        _ => code.to_string(),
+    }
+}
+
+/// `testreport ?name?`: prints how many tests have passed and failed, and
+/// returns the number that failed
+fn cmd_testreport(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 1, 2)?;
+    let counts = interp.test_counts;
+    let prefix = argv
+        .get(1)
+        .map_or(String::new(), |name| format!("{name}:\t"));
+    interp
+        .write_output(format_args!(
+            "{prefix}Total\t{}\tPassed\t{}\tFailed\t{}\n",
+            counts.passed + counts.failed,
+            counts.passed,
+            counts.failed
+        ))
+        .map_err(|e| {
+            TclError::new(
+                TclErrorKind::General,
+                format!("error writing \"stdout\": {e}"),
+            )
+        })?;
+    interp.set_result(counts.failed.to_string());
+    Ok(Status::Ok)
+}
+
+#[cfg(feature = "process")]
+fn cmd_pid(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 1, 2)?;
+
+    // A channel's pids are those of the pipeline it was opened on, so
+    // there are none for files and sockets
+    if let Some(name) = argv.get(1) {
+        #[cfg(feature = "io")]
+        if ["stdin", "stdout", "stderr"].contains(&name.as_str()) {
+            interp.set_result("");
+            return Ok(Status::Ok);
+        }
+        #[cfg(feature = "io")]
+        if let Some(chan) = interp.channels.get(name.as_str()) {
+            interp.set_result(list_from_iter(chan.pids().iter().map(u32::to_string)));
+            return Ok(Status::Ok);
+        }
+        return Err(channel_not_found(name));
+    }
+
+    interp.set_result(format!("{}", std::process::id()));
+    Ok(Status::Ok)
+}
+
+#[cfg(feature = "process")]
+fn cmd_exit(
+    _interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 1, 2)?;
+
+    let code = match argv.get(1) {
+        Some(_) => i32::try_from(helpers::get_int(argv, 1)?).map_err(|_| {
+            TclError::new(
+                TclErrorKind::InvalidNumber,
+                format!("exit code out of range: \"{}\"", argv[1]),
+            )
+        })?,
+        None => 0,
+    };
+    Err(TclError::new(
+        TclErrorKind::Exit(code),
+        format!("exit {code}"),
+    ))
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    unsafe extern "C" {
+        fn gethostname(name: *mut std::ffi::c_char, len: usize) -> std::ffi::c_int;
+    }
+
+    let mut buf = [0u8; 256];
+    let rc = unsafe { gethostname(buf.as_mut_ptr() as *mut std::ffi::c_char, buf.len()) };
+    if rc != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+/// `info commands|hostname|script|subcommands|vars`
+fn info_ensemble() -> Ensemble {
+    Ensemble::new("info")
+        .subcommand("commands", "?pattern?", info_commands)
+        .subcommand("hostname", "", info_hostname)
+        .subcommand("script", "", info_script)
+        .subcommand("subcommands", "command", info_subcommands)
+        .subcommand("vars", "?pattern?", info_vars)
+}
+
+fn info_commands(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let names: Vec<String> = interp
+        .commands
+        .names()
+        .filter(|name| argv.get(2).is_none_or(|p| glob_match(p, name)))
+        .map(str::to_string)
+        .collect();
+    interp.set_result(merge_list(&names));
+    Ok(Status::Ok)
+}
+
+fn info_hostname(
+    interp: &mut Interp,
+    _argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let Some(name) = hostname() else {
+        return Err(TclError::new(
+            TclErrorKind::General,
+            "unable to determine hostname",
+        ));
+    };
+    interp.set_result(name);
+    Ok(Status::Ok)
+}
+
+/// `info script`: the file the script running now came from, which in a
+/// proc body is the file the proc was defined in. Empty for scripts that
+/// didn't come from a file, like those typed at the REPL.
+fn info_script(
+    interp: &mut Interp,
+    _argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let name = interp
+        .script_source()
+        .map_or_else(String::new, |source| source.name.clone());
+    interp.set_result(name);
+    Ok(Status::Ok)
+}
+
+/// `info subcommands command`: the subcommands of an ensemble
+fn info_subcommands(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let name = argv[2].as_str();
+    let Some(ensemble) = interp.command_data::<Ensemble>(name) else {
+        return Err(TclError::new(
+            TclErrorKind::General,
+            format!("\"{name}\" isn't an ensemble command"),
+        ));
+    };
+    let names = list_from_iter(ensemble.subcommands());
+    interp.set_result(names);
+    Ok(Status::Ok)
+}
+
+fn info_vars(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let mut names: Vec<&str> = interp
+        .var_names()
+        .filter(|name| argv.get(2).is_none_or(|p| glob_match(p, name)))
+        .collect();
+    names.sort();
+    let names = merge_list(&names);
+    interp.set_result(names);
+    Ok(Status::Ok)
+}
+
+/// `history ?clear|keep ?count?|redo ?event?|list?`. Events are numbered
+/// from 1 when the interpreter starts or the history is cleared; `redo`
+/// also takes a negative event counting back from the latest.
+fn cmd_history(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let option = if argv.len() > 1 {
+        get_enum(argv, 1, &["clear", "keep", "list", "redo"])?
+    } else {
+        "list"
+    };
+    match option {
+        "list" => {
+            check_arity(argv, 1, 2)?;
+            let lines: Vec<String> = (interp.history_first..)
+                .zip(&interp.history)
+                .map(|(n, script)| format!("{n:>6}  {}", script.replace('\n', "\n\t")))
+                .collect();
+            interp.set_result(lines.join("\n"));
+        }
+        "clear" => {
+            check_arity(argv, 2, 2)?;
+            interp.history.clear();
+            interp.history_first = 1;
+            interp.set_result("");
+        }
+        "keep" => {
+            check_arity(argv, 2, 3)?;
+            if argv.len() == 3 {
+                let limit = usize::try_from(helpers::get_int(argv, 2)?).map_err(|_| {
+                    TclError::new(
+                        TclErrorKind::General,
+                        format!("illegal keep count \"{}\"", argv[2]),
+                    )
+                })?;
+                interp.set_history_limit(limit);
+                interp.set_result("");
+            } else {
+                interp.set_result(interp.history_limit.to_string());
+            }
+        }
+        "redo" => {
+            check_arity(argv, 2, 3)?;
+            // The entry for this command is replaced by the script it
+            // redoes, so that redoing never records redo itself
+            let own_entry = interp
+                .history
+                .back()
+                .is_some_and(|last| last.trim_start().starts_with("history"));
+            let latest = interp.history_first + interp.history.len() - usize::from(own_entry);
+            let event = match argv.get(2) {
+                Some(_) => helpers::get_int(argv, 2)?,
+                None => -1,
+            };
+            let number = if event > 0 {
+                event
+            } else {
+                latest as i64 + event
+            };
+            let script = match usize::try_from(number) {
+                Ok(n) if n >= interp.history_first && n < latest => {
+                    interp.history[n - interp.history_first].clone()
+                }
+                Ok(n) if n >= latest => {
+                    return Err(TclError::new(
+                        TclErrorKind::General,
+                        format!("event \"{event}\" hasn't occurred yet"),
+                    ));
+                }
+                _ => {
+                    return Err(TclError::new(
+                        TclErrorKind::General,
+                        format!("event \"{event}\" is too far in the past"),
+                    ));
+                }
+            };
+            if own_entry {
+                *interp.history.back_mut().unwrap() = script.clone();
+            }
+            return interp.eval(&script);
+        }
+        _ => unreachable!(),
+    }
+    Ok(Status::Ok)
+}
+
+/// `clock seconds|milliseconds|microseconds`, and
+/// `clock format time ?-format fmt? ?-gmt bool? ?-timezone zone?` and
+/// `clock scan text ?-format fmt? ?-gmt bool? ?-timezone zone? ?-base time?`.
+/// Without -format, scan reads ISO 8601 dates and times.
+fn clock_ensemble() -> Ensemble {
+    Ensemble::new("clock")
+        .subcommand("format", "clockval ?-option value ...?", cmd_clock)
+        .subcommand("microseconds", "", cmd_clock)
+        .subcommand("milliseconds", "", cmd_clock)
+        .subcommand("scan", "string ?-option value ...?", cmd_clock)
+        .subcommand("seconds", "", cmd_clock)
+}
+
+fn cmd_clock(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let since_epoch = || {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+    };
+
+    let subcommand = argv[1].as_str();
+    let now = match subcommand {
+        "seconds" => Some(since_epoch().as_secs().to_string()),
+        "milliseconds" => Some(since_epoch().as_millis().to_string()),
+        "microseconds" => Some(since_epoch().as_micros().to_string()),
+        _ => None,
+    };
+    if let Some(now) = now {
+        interp.set_result(now);
+        return Ok(Status::Ok);
+    }
+
+    if argv.len().is_multiple_of(2) {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            format!(
+                "wrong # args: should be \"clock {subcommand} {} ?-option value ...?\"",
+                if subcommand == "format" {
+                    "clockval"
+                } else {
+                    "string"
+                }
+            ),
+        ));
+    }
+    let mut fmt = None;
+    let mut zone = clock::Zone::Local;
+    let mut base = None;
+    for idx in (3..argv.len()).step_by(2) {
+        let options: &[&str] = if subcommand == "scan" {
+            &["-base", "-format", "-gmt", "-timezone"]
+        } else {
+            &["-format", "-gmt", "-timezone"]
+        };
+        match get_enum(argv, idx, options)? {
+            "-format" => fmt = Some(argv[idx + 1].as_str()),
+            "-gmt" => {
+                if helpers::get_bool(argv, idx + 1)? {
+                    zone = clock::Zone::utc();
+                }
+            }
+            "-timezone" => zone = clock::Zone::parse(&argv[idx + 1])?,
+            _ => base = Some(helpers::get_int(argv, idx + 1)?),
+        }
+    }
+
+    let result = if subcommand == "format" {
+        let seconds = helpers::get_int(argv, 2)?;
+        clock::format(seconds, fmt.unwrap_or(clock::DEFAULT_FORMAT), &zone)?
+    } else {
+        let base = base.unwrap_or_else(|| since_epoch().as_secs() as i64);
+        match fmt {
+            Some(fmt) => clock::scan(&argv[2], fmt, &zone, base)?,
+            None => clock::scan_iso8601(&argv[2], &zone, base)?,
+        }
+        .to_string()
+    };
+    interp.set_result(result);
+    Ok(Status::Ok)
+}
+
+/// Clamps a range of indexes from [`get_index`] to something `len` long,
+/// returning None if nothing is left of it
+fn clamp_range(first: i64, last: i64, len: usize) -> Option<(usize, usize)> {
+    let first = first.max(0);
+    let last = last.min(len as i64 - 1);
+    (first <= last).then_some((first as usize, last as usize))
+}
+
+/// `lindex list ?index ...?`: each index picks an element of what the one
+/// before picked. An index out of range gives the empty string.
+fn cmd_lindex(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, usize::MAX)?;
+    let mut value = argv[1].clone();
+    for idx in 2..argv.len() {
+        let list = value.as_list()?;
+        let i = get_index(argv, idx, list.len())?;
+        match usize::try_from(i).ok().and_then(|i| list.get(i)) {
+            Some(element) => value = element.clone(),
+            None => {
+                interp.set_result("");
+                return Ok(Status::Ok);
+            }
+        }
+    }
+    interp.set_result(value);
+    Ok(Status::Ok)
+}
+
+/// `lrange list first last`, clamping the range to the list
+fn cmd_lrange(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 4, 4)?;
+    let list = argv[1].as_list()?;
+    let first = get_index(argv, 2, list.len())?;
+    let last = get_index(argv, 3, list.len())?;
+    let range = match clamp_range(first, last, list.len()) {
+        Some((first, last)) => list_from_iter(list[first..=last].iter().map(TclValue::as_str)),
+        None => String::new(),
+    };
+    interp.set_result(range);
+    Ok(Status::Ok)
+}
+
+/// `linsert list index element ?element ...?`. The elements go before the
+/// one at index; `end` is past the last element, so they go after it.
+fn cmd_linsert(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 3, usize::MAX)?;
+    let list = argv[1].as_list()?;
+    let at = get_index(argv, 2, list.len() + 1)?.clamp(0, list.len() as i64) as usize;
+    let items = list[..at]
+        .iter()
+        .chain(&argv[3..])
+        .chain(&list[at..])
+        .map(TclValue::as_str);
+    interp.set_result(list_from_iter(items));
+    Ok(Status::Ok)
+}
+
+/// `lset varName ?index ...? newValue`: replaces the element the indexes
+/// pick, as with lindex, or the whole value without any. An index just
+/// past the end appends; anything else out of range is an error.
+fn cmd_lset(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 3, usize::MAX)?;
+    let value = interp.get_var(&argv[1])?;
+    let indexes: Vec<usize> = (2..argv.len() - 1).collect();
+    let value = lset_path(&value, argv, &indexes, &argv[argv.len() - 1])?;
+    interp.set_var(&argv[1], value.as_str())?;
+    interp.set_result(value);
+    Ok(Status::Ok)
+}
+
+fn lset_path(
+    value: &TclValue,
+    argv: &[TclValue],
+    indexes: &[usize],
+    new_value: &TclValue,
+) -> Result<String, TclError> {
+    let Some((&idx, rest)) = indexes.split_first() else {
+        return Ok(new_value.to_string());
+    };
+    let mut items: Vec<String> = value.as_list()?.iter().map(TclValue::to_string).collect();
+    let i = get_index(argv, idx, items.len())?;
+    let i = match usize::try_from(i) {
+        Ok(i) if i <= items.len() => i,
+        _ => {
+            return Err(TclError::new(
+                TclErrorKind::General,
+                "list index out of range",
+            ));
+        }
+    };
+    if i == items.len() {
+        items.push(String::new());
+    }
+    items[i] = lset_path(&TclValue::from(&items[i]), argv, rest, new_value)?;
+    Ok(list_from_iter(&items))
+}
+
+/// A dictionary as scripts see it: a list of alternating keys and values,
+/// kept in the order keys were first added
+#[derive(Default)]
+struct Dict {
+    entries: Vec<(String, String)>,
+    // Where each key is in entries
+    index: HashMap<String, usize>,
+}
+
+impl Dict {
+    fn parse(value: &TclValue) -> Result<Dict, TclError> {
+        let items = value.as_list()?;
+        if !items.len().is_multiple_of(2) {
+            return Err(TclError::new(
+                TclErrorKind::General,
+                "missing value to go with key",
+            ));
+        }
+        let mut dict = Dict {
+            entries: Vec::with_capacity(items.len() / 2),
+            index: HashMap::with_capacity(items.len() / 2),
+        };
+        for pair in items.chunks(2) {
+            dict.set(pair[0].as_str(), pair[1].to_string());
+        }
+        Ok(dict)
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        let i = *self.index.get(key)?;
+        Some(self.entries[i].1.as_str())
+    }
+
+    /// Replaces the value of `key` where it is, or adds it at the end
+    fn set(&mut self, key: &str, value: String) {
+        match self.index.get(key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.index.insert(key.to_string(), self.entries.len());
+                self.entries.push((key.to_string(), value));
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        let Some(i) = self.index.remove(key) else {
+            return;
+        };
+        self.entries.remove(i);
+        for j in self.index.values_mut() {
+            if *j > i {
+                *j -= 1;
+            }
+        }
+    }
+
+    fn to_list(&self) -> String {
+        list_from_iter(self.entries.iter().flat_map(|(k, v)| [k, v]))
+    }
+}
+
+fn key_not_known(key: &str) -> TclError {
+    TclError::new(
+        TclErrorKind::General,
+        format!("key \"{key}\" not known in dictionary"),
+    )
+}
+
+/// Follows `keys` down through nested dictionaries, failing on the first
+/// that isn't there
+fn dict_get_path(value: &TclValue, keys: &[TclValue]) -> Result<TclValue, TclError> {
+    let mut value = value.clone();
+    for key in keys {
+        value = match Dict::parse(&value)?.get(key) {
+            Some(v) => TclValue::from(v),
+            None => return Err(key_not_known(key)),
+        };
+    }
+    Ok(value)
+}
+
+/// Sets the value at the end of `keys`, creating dictionaries on the way
+/// down for keys that aren't there
+fn dict_set_path(value: &str, keys: &[TclValue], new_value: &str) -> Result<String, TclError> {
+    let Some((key, rest)) = keys.split_first() else {
+        return Ok(new_value.to_string());
+    };
+    let mut dict = Dict::parse(&TclValue::from(value))?;
+    let inner = dict.get(key).unwrap_or_default();
+    let inner = dict_set_path(inner, rest, new_value)?;
+    dict.set(key, inner);
+    Ok(dict.to_list())
+}
+
+/// The value of a dictionary variable, or an empty dictionary if the
+/// variable doesn't exist yet
+fn dict_var(interp: &Interp, name: &str) -> Result<Dict, TclError> {
+    match interp.var(name) {
+        Some(value) => Dict::parse(&TclValue::from(value)),
+        None => Ok(Dict::default()),
+    }
+}
+
+/// `dict create|exists|get|incr|keys|merge|set|size|unset|update|values`.
+/// get, exists and set take a path of keys into nested dictionaries.
+fn dict_ensemble() -> Ensemble {
+    Ensemble::new("dict")
+        .subcommand("create", "?key value ...?", cmd_dict)
+        .subcommand("exists", "dictionary key ?key ...?", cmd_dict)
+        .subcommand("get", "dictionary ?key ...?", cmd_dict)
+        .subcommand("incr", "dictVarName key ?increment?", cmd_dict)
+        .subcommand("keys", "dictionary", cmd_dict)
+        .subcommand("merge", "?dictionary ...?", cmd_dict)
+        .subcommand("set", "dictVarName key ?key ...? value", cmd_dict)
+        .subcommand("size", "dictionary", cmd_dict)
+        .subcommand("unset", "dictVarName key", cmd_dict)
+        .subcommand(
+            "update",
+            "dictVarName key varName ?key varName ...? script",
+            cmd_dict,
+        )
+        .subcommand("values", "dictionary", cmd_dict)
+}
+
+fn cmd_dict(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let sub = argv[1].as_str();
+
+    let result = match sub {
+        "create" => {
+            if !argv.len().is_multiple_of(2) {
+                return Err(TclError::new(
+                    TclErrorKind::Arity,
+                    "wrong # args: should be \"dict create ?key value ...?\"",
+                ));
+            }
+            let mut dict = Dict::default();
+            for pair in argv[2..].chunks(2) {
+                dict.set(&pair[0], pair[1].to_string());
+            }
+            dict.to_list()
+        }
+        "exists" => {
+            let exists = dict_get_path(&argv[2], &argv[3..]).is_ok();
+            (if exists { "1" } else { "0" }).to_string()
+        }
+        "get" => dict_get_path(&argv[2], &argv[3..])?.to_string(),
+        "incr" => {
+            let amount = if argv.len() == 5 {
+                helpers::get_int(argv, 4)?
+            } else {
+                1
+            };
+            let mut dict = dict_var(interp, &argv[2])?;
+            let old = match dict.get(&argv[3]) {
+                Some(old) => old.parse::<i64>().map_err(|_| {
+                    TclError::new(
+                        TclErrorKind::General,
+                        format!("expected integer but got \"{old}\""),
+                    )
+                })?,
+                None => 0,
+            };
+            dict.set(&argv[3], old.wrapping_add(amount).to_string());
+            let list = dict.to_list();
+            interp.set_var(&argv[2], list.as_str())?;
+            list
+        }
+        "keys" | "values" => {
+            let dict = Dict::parse(&argv[2])?;
+            let keys = sub == "keys";
+            list_from_iter(dict.entries.iter().map(|(k, v)| if keys { k } else { v }))
+        }
+        "merge" => {
+            let mut merged = Dict::default();
+            for value in &argv[2..] {
+                for (k, v) in Dict::parse(value)?.entries {
+                    merged.set(&k, v);
+                }
+            }
+            merged.to_list()
+        }
+        "set" => {
+            check_arity(argv, 5, usize::MAX)?;
+            let old = interp.var(&argv[2]).unwrap_or_default().to_string();
+            let keys = &argv[3..argv.len() - 1];
+            let list = dict_set_path(&old, keys, &argv[argv.len() - 1])?;
+            interp.set_var(&argv[2], list.as_str())?;
+            list
+        }
+        "size" => Dict::parse(&argv[2])?.entries.len().to_string(),
+        "unset" => {
+            let mut dict = dict_var(interp, &argv[2])?;
+            dict.remove(&argv[3]);
+            let list = dict.to_list();
+            interp.set_var(&argv[2], list.as_str())?;
+            list
+        }
+        "update" => return dict_update(interp, argv),
+        _ => unreachable!(),
+    };
+    interp.set_result(result);
+    Ok(Status::Ok)
+}
+
+/// `dict update varName key localVar ?key localVar ...? body`. Each value
+/// is copied into its local before the body runs, and copied back after,
+/// whether or not the body succeeded, so an error partway through still
+/// keeps the changes made before it. A local that was unset removes its
+/// key; if the dictionary variable itself was unset, nothing is written.
+fn dict_update(interp: &mut Interp, argv: &[TclValue]) -> Result<Status, TclError> {
+    if argv.len() < 6 || !argv.len().is_multiple_of(2) {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            "wrong # args: should be \"dict update varName key varName ?key varName ...? script\"",
+        ));
+    }
+    let pairs = &argv[3..argv.len() - 1];
+    let dict = Dict::parse(&interp.get_var(&argv[2])?)?;
+    for pair in pairs.chunks(2) {
+        match dict.get(&pair[0]) {
+            Some(value) => {
+                interp.set_var(&pair[1], value)?;
+            }
+            None => {
+                let _ = interp.unset_var(&pair[1]);
+            }
+        }
+    }
+
+    let res = interp.eval(&argv[argv.len() - 1]);
+
+    if interp.var(&argv[2]).is_some() {
+        let written = dict_var(interp, &argv[2]).and_then(|mut dict| {
+            for pair in pairs.chunks(2) {
+                match interp.var(&pair[1]) {
+                    Some(value) => dict.set(&pair[0], value.to_string()),
+                    None => dict.remove(&pair[0]),
+                }
+            }
+            interp.set_var(&argv[2], dict.to_list().as_str())
+        });
+        // The body's own error says more than one from writing back
+        if res.is_ok() {
+            written?;
+        }
+    }
+    res
+}
+
+/// `encoding convertfrom|convertto ?-strict? encoding data`, between text
+/// and strings of bytes, and `encoding names|system`. Without -strict, what
+/// can't be converted is replaced rather than an error.
+fn encoding_ensemble() -> Ensemble {
+    Ensemble::new("encoding")
+        .subcommand("convertfrom", "?-strict? encoding data", encoding_convert)
+        .subcommand("convertto", "?-strict? encoding data", encoding_convert)
+        .subcommand("names", "", encoding_names)
+        .subcommand("system", "", encoding_system)
+}
+
+fn encoding_convert(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let sub = argv[1].as_str();
+    let strict = argv.len() == 5 && argv[2] == "-strict";
+    let [name, data] = &argv[2 + usize::from(strict)..] else {
+        return Err(TclError::new(
+            TclErrorKind::Arity,
+            format!("wrong # args: should be \"encoding {sub} ?-strict? encoding data\""),
+        ));
+    };
+    let encoding = encoding::find(name)?;
+    let result = if sub == "convertfrom" {
+        encoding::convert_from(encoding, data, strict)?
+    } else {
+        encoding::convert_to(encoding, data, strict)?
+    };
+    interp.set_result(result);
+    Ok(Status::Ok)
+}
+
+fn encoding_names(
+    interp: &mut Interp,
+    _argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    interp.set_result(list_from_iter(encoding::ENCODINGS.iter().map(|e| e.name())));
+    Ok(Status::Ok)
+}
+
+fn encoding_system(
+    interp: &mut Interp,
+    _argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    interp.set_result(encoding::UTF8.name());
+    Ok(Status::Ok)
+}
+
+/// `string index|length|range`, counting in characters
+fn string_ensemble() -> Ensemble {
+    Ensemble::new("string")
+        .subcommand("index", "string charIndex", string_index)
+        .subcommand("length", "string", string_length)
+        .subcommand("range", "string first last", string_range)
+}
+
+fn string_index(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let s = argv[2].as_str();
+    let i = get_index(argv, 3, s.chars().count())?;
+    let c = usize::try_from(i).ok().and_then(|i| s.chars().nth(i));
+    interp.set_result(c.map(String::from).unwrap_or_default());
+    Ok(Status::Ok)
+}
+
+fn string_length(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    interp.set_result(argv[2].chars().count().to_string());
+    Ok(Status::Ok)
+}
+
+fn string_range(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let s = argv[2].as_str();
+    let len = s.chars().count();
+    let first = get_index(argv, 3, len)?;
+    let last = get_index(argv, 4, len)?;
+    let range: String = match clamp_range(first, last, len) {
+        Some((first, last)) => s.chars().skip(first).take(last - first + 1).collect(),
+        None => String::new(),
+    };
+    interp.set_result(range);
+    Ok(Status::Ok)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+}
+
+/// Parses an operand as an integer, falling back to a float
+fn parse_number(s: &str) -> Result<Number, TclError> {
+    if let Ok(i) = s.parse::<i64>() {
+        return Ok(Number::Int(i));
+    }
+    if let Ok(f) = s.trim().parse::<f64>() {
+        return Ok(Number::Float(f));
+    }
+    Err(TclError::new(
+        TclErrorKind::General,
+        format!("invalid number: '{s}'"),
+    ))
+}
+
+/// Like `parse_number`, but uses the number cached on the value
+fn value_number(v: &TclValue) -> Result<Number, TclError> {
+    v.as_number().map_or_else(|| parse_number(v), Ok)
+}
+
+/// The most significant digits `tcl_precision` may ask for, enough to
+/// tell any two doubles apart
+pub const MAX_PRECISION: usize = 17;
+
+/// Formats a float with `precision` significant digits, as C's `%g`
+/// would, or with 0 in its shortest round-tripping form, switching to
+/// an exponent past 17 digits or below 1e-4. Either way integral values
+/// keep a trailing ".0" so they stay floats when re-parsed. Infinities
+/// are `Inf` and `-Inf`, as in Tcl.
+pub fn format_double(f: f64, precision: usize) -> String {
+    if f.is_nan() {
+        return "NaN".to_string();
+    }
+    if f.is_infinite() {
+        return if f < 0.0 { "-Inf" } else { "Inf" }.to_string();
+    }
+    let s = if precision == 0 {
+        // Rust's exponent form has the shortest digits that round-trip
+        let sci = format!("{f:e}");
+        let (mantissa, exp) = sci.split_once('e').unwrap();
+        let exp: i32 = exp.parse().unwrap();
+        if exp < -4 || exp >= MAX_PRECISION as i32 {
+            format_exponent(mantissa, exp)
+        } else {
+            format!("{f}")
+        }
+    } else {
+        // The exponent after rounding to the digits asked for decides
+        // between fixed and scientific notation
+        let sci = format!("{:.*e}", precision - 1, f);
+        let (mantissa, exp) = sci.split_once('e').unwrap();
+        let exp: i32 = exp.parse().unwrap();
+        if exp < -4 || exp >= precision as i32 {
+            format_exponent(trim_fraction(mantissa), exp)
+        } else {
+            let decimals = (precision as i32 - 1 - exp) as usize;
+            trim_fraction(&format!("{f:.decimals$}")).to_string()
+        }
+    };
+    if !s.contains(['.', 'e']) {
+        format!("{s}.0")
+    } else {
+        s
+    }
+}
+
+/// Joins a mantissa and exponent the way `%g` does, as in `1.5e+20`
+fn format_exponent(mantissa: &str, exp: i32) -> String {
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{mantissa}e{sign}{:02}", exp.abs())
+}
+
+/// Drops trailing zeros after a decimal point, and the point if that's
+/// all that's left
+fn trim_fraction(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+pub fn format_number(n: Number, precision: usize) -> String {
+    match n {
+        Number::Int(i) => format!("{i}"),
+        Number::Float(f) => format_double(f, precision),
+    }
+}
+
+/// Raised by `**` rather than answering infinity, as Tcl does
+fn zero_to_negative_power() -> TclError {
+    let msg = "exponentiation of zero by negative power";
+    TclError::new(TclErrorKind::General, msg).with_code(list_from_iter(["ARITH", "DOMAIN", msg]))
+}
+
+fn float_op(op: &str, a: f64, b: f64) -> Result<Number, TclError> {
+    if op == "/" && b == 0.0 {
+        return Err(TclError::new(TclErrorKind::DivideByZero, "divide by zero"));
+    }
+    if op == "**" && a == 0.0 && b < 0.0 {
+        return Err(zero_to_negative_power());
+    }
+
+    let r = match op {
+        "+" => a + b,
+        "-" => a - b,
+        "*" => a * b,
+        "/" => a / b,
+        "**" => a.powf(b),
+        ">" => return Ok(Number::Int((a > b) as i64)),
+        "<" => return Ok(Number::Int((a < b) as i64)),
+        "==" => return Ok(Number::Int((a == b) as i64)),
+        "!=" => return Ok(Number::Int((a != b) as i64)),
+        ">=" => return Ok(Number::Int((a >= b) as i64)),
+        "<=" => return Ok(Number::Int((a <= b) as i64)),
+        _ => {
+            return Err(TclError::new(
+                TclErrorKind::General,
+                format!("can't use floating-point value as operand of \"{op}\""),
+            ));
+        }
+    };
+    Ok(Number::Float(r))
+}
+
+fn int_op(op: &str, a: i64, b: i64) -> Result<Number, TclError> {
+    let r = match op {
+        "/" | "%" if b == 0 => {
+            return Err(TclError::new(TclErrorKind::DivideByZero, "divide by zero"));
+        }
+        "+" | "-" | "*" | "/" => {
+            let r = match op {
+                "+" => a.checked_add(b),
+                "-" => a.checked_sub(b),
+                "*" => a.checked_mul(b),
+                _ => a.checked_div(b),
+            };
+            let Some(r) = r else {
+                return Err(TclError::new(TclErrorKind::General, "integer overflow"));
+            };
+            // Tcl rounds quotients down, to match the remainder below, so
+            // that a == a / b * b + a % b
+            if op == "/" && (a < 0) != (b < 0) && r * b != a {
+                r - 1
+            } else {
+                r
+            }
+        }
+        "%" => {
+            // The only overflowing case, MIN % -1, is mathematically zero
+            let mut r = a.checked_rem(b).unwrap_or(0);
+            // Tcl gives the remainder the sign of the divisor
+            if r != 0 && (r < 0) != (b < 0) {
+                r += b;
+            }
+            r
+        }
+        "**" => {
+            // A negative exponent can't give an integer result
+            if b < 0 {
+                if a == 0 {
+                    return Err(zero_to_negative_power());
+                }
+                return float_op(op, a as f64, b as f64);
+            }
+            let Some(r) = u32::try_from(b).ok().and_then(|b| a.checked_pow(b)) else {
+                return Err(TclError::new(TclErrorKind::General, "integer overflow"));
+            };
+            r
+        }
+        "&" => a & b,
+        "|" => a | b,
+        "^" => a ^ b,
+        "<<" | ">>" => {
+            if b < 0 {
+                return Err(TclError::new(
+                    TclErrorKind::General,
+                    "negative shift argument",
+                ));
+            }
+            if b >= 64 {
+                return Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("shift count too large: {b}"),
+                ));
+            }
+            let r = if op == "<<" { a << b } else { a >> b };
+            // Bits shifted out of the top (including the sign) overflow
+            if op == "<<" && r >> b != a {
+                return Err(TclError::new(TclErrorKind::General, "integer overflow"));
+            }
+            r
+        }
+        ">" => (a > b) as i64,
+        "<" => (a < b) as i64,
+        "==" => (a == b) as i64,
+        "!=" => (a != b) as i64,
+        ">=" => (a >= b) as i64,
+        "<=" => (a <= b) as i64,
+        _ => {
+            return Err(TclError::new(
+                TclErrorKind::General,
+                format!("unknown operator: '{op}'"),
+            ));
+        }
+    };
+    Ok(Number::Int(r))
+}
+
+/// Applies a binary math operator. Two integers stay on the integer path;
+/// anything involving a float is computed in floating point.
+pub fn binary_op(op: &str, a: Number, b: Number) -> Result<Number, TclError> {
+    match (a, b) {
+        (Number::Int(a), Number::Int(b)) => int_op(op, a, b),
+        (a, b) => float_op(op, a.as_f64(), b.as_f64()),
+    }
+}
+
+fn cmd_math(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 3, 3)?;
+
+    let a = value_number(&argv[1])?;
+    let b = value_number(&argv[2])?;
+    let r = binary_op(&argv[0], a, b)?;
+
+    interp.set_result(interp.number_value(r));
+    Ok(Status::Ok)
+}
+
+/// Finds the `]` matching the `[` at `open`, parsing the command in
+/// between as a command substitution would be
+fn find_close_bracket(s: &str, open: usize) -> Option<usize> {
+    let mut parser = Parser::new(s);
+    parser.cursor = open + 1;
+    parser.begin = open + 1;
+    let mut sub = parser.bracket_parser();
+    parser.recurse(&mut sub, b']');
+    match parser.error {
+        Some(_) => None,
+        None => Some(parser.cursor - 1),
+    }
+}
+
+/// Splits an array element reference like `prices(apple)` into the
+/// array's name and the index
+fn split_array_element(name: &str) -> Option<(&str, &str)> {
+    let open = name.find('(')?;
+    if !name.ends_with(')') {
+        return None;
+    }
+    Some((&name[..open], &name[open + 1..name.len() - 1]))
+}
+
+/// Splits a variable name into the variable that holds it and, for an
+/// array element, the index
+fn split_var_name(name: &str) -> (&str, Option<&str>) {
+    match split_array_element(name) {
+        Some((array, index)) => (array, Some(index)),
+        None => (name, None),
+    }
+}
+
+fn is_var_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b':'
+}
+
+/// Returns the length of the variable name at the start of `s`: letters,
+/// digits, underscores and `::` namespace separators. Other characters
+/// than ASCII count as letters.
+fn var_name_len(s: &[u8]) -> usize {
+    let mut len = 0;
+    while let Some(&c) = s.get(len) {
+        if c.is_ascii_alphanumeric() || c == b'_' || !c.is_ascii() {
+            len += 1;
+        } else if c == b':' && s.get(len + 1) == Some(&b':') {
+            len += 2;
+        } else {
+            break;
+        }
+    }
+    len
+}
+
+/// Scans the variable reference starting at the `$` at `dollar`, returning
+/// the name and the offset just past the reference. `${name}` takes
+/// everything up to the closing brace, and an array element's name
+/// includes its parenthesized index.
+fn scan_var_name(s: &str, dollar: usize) -> Option<(&str, usize)> {
+    let bytes = s.as_bytes();
+    let start = dollar + 1;
+
+    if bytes.get(start) == Some(&b'{') {
+        let end = s[start..].find('}')? + start;
+        return Some((&s[start + 1..end], end + 1));
+    }
+
+    let mut end = start + var_name_len(&bytes[start..]);
+    if end == start {
+        return None;
+    }
+    if bytes.get(end) == Some(&b'(')
+        && let Some(close) = s[end..].find(')')
+    {
+        end += close + 1;
+    }
+    Some((&s[start..end], end))
+}
+
+/// Performs variable, command and backslash substitution on a string, as
+/// for a double-quoted word.
+fn subst(interp: &mut Interp, s: &str) -> Result<String, TclError> {
+    let bytes = s.as_bytes();
+    let mut out = String::with_capacity(s.len());
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                out.push_str(&s[start..i]);
+                let (c, len) = backslash_sequence(&s[i + 1..]);
+                out.push(c);
+                i += 1 + len;
+                start = i;
+            }
+            b'$' => match scan_var_name(s, i) {
+                Some((name, end)) => {
+                    out.push_str(&s[start..i]);
+                    out.push_str(&interp.substitute_var(name)?);
+                    i = end;
+                    start = i;
+                }
+                None => i += 1,
+            },
+            b'[' => {
+                let Some(end) = find_close_bracket(s, i) else {
+                    return Err(TclError::new(
+                        TclErrorKind::General,
+                        "missing close-bracket",
+                    ));
+                };
+                out.push_str(&s[start..i]);
+                out.push_str(&interp.eval_str(&s[i + 1..end])?);
+                i = end + 1;
+                start = i;
+            }
+            _ => i += 1,
+        }
+    }
+
+    out.push_str(&s[start..]);
+    Ok(out)
+}
+
+/// Parses a number the way expr reads its operands: decimal integers,
+/// 0x/0o/0b integers, and floats.
+fn parse_expr_number(s: &str) -> Option<Number> {
+    let t = s.trim();
+    if let Ok(i) = t.parse::<i64>() {
+        return Some(Number::Int(i));
+    }
+
+    let (neg, digits) = match t.strip_prefix('-') {
+        Some(d) => (true, d),
+        None => (false, t.strip_prefix('+').unwrap_or(t)),
+    };
+    for (prefix, radix) in [("0x", 16), ("0X", 16), ("0o", 8), ("0b", 2)] {
+        if let Some(d) = digits.strip_prefix(prefix) {
+            let i = i64::from_str_radix(d, radix).ok()?;
+            return Some(Number::Int(if neg { -i } else { i }));
+        }
+    }
+
+    // Rust's float parser accepts words like "infinity" that Tcl doesn't
+    let lower = digits.to_ascii_lowercase();
+    if digits.bytes().any(|c| c.is_ascii_digit()) || lower == "inf" || lower == "nan" {
+        return t.parse::<f64>().ok().map(Number::Float);
+    }
+    None
+}
+
+#[derive(Clone, Debug)]
+enum ExprValue {
+    Num(Number),
+    Str(String),
+}
+
+impl ExprValue {
+    fn from_string(s: String) -> ExprValue {
+        match parse_expr_number(&s) {
+            Some(n) => ExprValue::Num(n),
+            None => ExprValue::Str(s),
+        }
+    }
+
+    fn into_string(self, precision: usize) -> String {
+        match self {
+            ExprValue::Num(n) => format_number(n, precision),
+            ExprValue::Str(s) => s,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ExprNode {
+    Number(Number),
+    // Braced text and barewords, used as-is
+    Literal(String),
+    Var(String),
+    Cmd(String),
+    // Double-quoted text, which still needs substitution
+    Quoted(String),
+    Unary(&'static str, Box<ExprNode>),
+    Binary(&'static str, Box<ExprNode>, Box<ExprNode>),
+    Ternary(Box<ExprNode>, Box<ExprNode>, Box<ExprNode>),
+    // A math function and its arguments
+    Call(&'static str, Vec<ExprNode>),
+}
+
+// Binary operators and their precedence, longest spellings first so that
+// e.g. `<<` isn't read as `<`
+const EXPR_BINARY_OPS: [(&str, u8); 21] = [
+    ("**", 13),
+    ("<<", 10),
+    (">>", 10),
+    ("<=", 9),
+    (">=", 9),
+    ("==", 8),
+    ("!=", 8),
+    ("eq", 7),
+    ("ne", 7),
+    ("&&", 2),
+    ("||", 1),
+    ("*", 12),
+    ("/", 12),
+    ("%", 12),
+    ("+", 11),
+    ("-", 11),
+    ("<", 9),
+    (">", 9),
+    ("&", 5),
+    ("^", 4),
+    ("|", 3),
+];
+
+// How deep an expression's tree can be. Parsing, evaluating and dropping
+// it all recurse, so this keeps a hostile expression from overflowing the
+// stack.
+const EXPR_MAX_DEPTH: usize = 1000;
+
+struct ExprParser<'a> {
+    src: &'a str,
+    pos: usize,
+    // How deep the tree being parsed is at this point, at most
+    depth: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn error<T>(&self, msg: &str) -> Result<T, String> {
+        Err(format!(
+            "syntax error in expression \"{}\": {msg}",
+            self.src
+        ))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_ws(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Goes a level deeper into the tree
+    fn descend(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > EXPR_MAX_DEPTH {
+            return self.error("expression is nested too deeply");
+        }
+        Ok(())
+    }
+
+    fn peek_binary_op(&mut self) -> Option<(&'static str, u8)> {
+        self.skip_ws();
+        let rest = &self.src[self.pos..];
+        EXPR_BINARY_OPS.iter().copied().find(|(op, _)| {
+            rest.starts_with(op)
+                && (!op.as_bytes()[0].is_ascii_alphabetic()
+                    || !rest
+                        .as_bytes()
+                        .get(op.len())
+                        .is_some_and(|c| is_var_char(*c)))
+        })
+    }
+
+    fn parse_ternary(&mut self) -> Result<ExprNode, String> {
+        let depth = self.depth;
+        self.descend()?;
+        let node = self.parse_ternary_at_depth();
+        self.depth = depth;
+        node
+    }
+
+    fn parse_ternary_at_depth(&mut self) -> Result<ExprNode, String> {
+        let cond = self.parse_binary(1)?;
+        self.skip_ws();
+        if self.peek() != Some(b'?') {
+            return Ok(cond);
+        }
+        self.pos += 1;
+
+        let then = self.parse_ternary()?;
+        self.skip_ws();
+        if self.peek() != Some(b':') {
+            return self.error("missing \":\" in ternary conditional");
+        }
+        self.pos += 1;
+
+        let otherwise = self.parse_ternary()?;
+        Ok(ExprNode::Ternary(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    fn parse_binary(&mut self, min_prec: u8) -> Result<ExprNode, String> {
+        let depth = self.depth;
+        let node = self.parse_binary_chain(min_prec);
+        self.depth = depth;
+        node
+    }
+
+    fn parse_binary_chain(&mut self, min_prec: u8) -> Result<ExprNode, String> {
+        let mut lhs = self.parse_unary()?;
+
+        while let Some((op, prec)) = self.peek_binary_op() {
+            if prec < min_prec {
+                break;
+            }
+            self.pos += op.len();
+            // Each operator in a chain puts what came before a level down
+            self.descend()?;
+
+            // ** is right-associative, everything else left-associative
+            let next = if op == "**" { prec } else { prec + 1 };
+            let rhs = self.parse_binary(next)?;
+            lhs = ExprNode::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<ExprNode, String> {
+        self.skip_ws();
+        let op = match self.peek() {
+            Some(b'-') => "-",
+            Some(b'+') => "+",
+            Some(b'~') => "~",
+            Some(b'!') => "!",
+            _ => return self.parse_primary(),
+        };
+        self.pos += 1;
+        let depth = self.depth;
+        self.descend()?;
+        let operand = self.parse_unary();
+        self.depth = depth;
+        Ok(ExprNode::Unary(op, Box::new(operand?)))
+    }
+
+    fn parse_primary(&mut self) -> Result<ExprNode, String> {
+        self.skip_ws();
+        let start = self.pos;
+        let bytes = self.src.as_bytes();
+
+        let Some(c) = self.peek() else {
+            return self.error("premature end of expression");
+        };
+
+        match c {
+            b'(' => {
+                self.pos += 1;
+                let inner = self.parse_ternary()?;
+                self.skip_ws();
+                if self.peek() != Some(b')') {
+                    return self.error("missing close parenthesis");
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            b'$' => {
+                let Some((name, end)) = scan_var_name(self.src, start) else {
+                    return self.error("invalid variable reference");
+                };
+                self.pos = end;
+                Ok(ExprNode::Var(name.to_string()))
+            }
+            b'[' => {
+                let Some(end) = find_close_bracket(self.src, start) else {
+                    return self.error("missing close-bracket");
+                };
+                self.pos = end + 1;
+                Ok(ExprNode::Cmd(self.src[start + 1..end].to_string()))
+            }
+            b'"' => {
+                let mut i = start + 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                if i >= bytes.len() {
+                    return self.error("missing \"");
+                }
+                self.pos = i + 1;
+                Ok(ExprNode::Quoted(self.src[start + 1..i].to_string()))
+            }
+            b'{' => {
+                let mut level = 0;
+                let mut i = start;
+                while i < bytes.len() {
+                    match bytes[i] {
+                        b'\\' => i += 1,
+                        b'{' => level += 1,
+                        b'}' => {
+                            level -= 1;
+                            if level == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                if i >= bytes.len() {
+                    return self.error("missing close-brace");
+                }
+                self.pos = i + 1;
+                Ok(ExprNode::Literal(self.src[start + 1..i].to_string()))
+            }
+            b'0'..=b'9' | b'.' => {
+                let mut i = start;
+                while i < bytes.len() {
+                    let c = bytes[i];
+                    let exponent_sign = (c == b'+' || c == b'-')
+                        && matches!(bytes[i - 1], b'e' | b'E')
+                        && !self.src[start..i].starts_with("0x");
+                    if c.is_ascii_alphanumeric() || c == b'.' || exponent_sign {
+                        i += 1;
+                    } else {
+                        break;
+                    }
+                }
+                let word = &self.src[start..i];
+                let Some(n) = parse_expr_number(word) else {
+                    return self.error(&format!("invalid number \"{word}\""));
+                };
+                self.pos = i;
+                Ok(ExprNode::Number(n))
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut i = start;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                let word = &self.src[start..i];
+                self.pos = i;
+                self.skip_ws();
+                if self.peek() == Some(b'(') {
+                    return self.parse_call(word);
+                }
+                let lower = word.to_ascii_lowercase();
+                let known = matches!(
+                    lower.as_str(),
+                    "true" | "false" | "yes" | "no" | "on" | "off" | "inf" | "nan"
+                );
+                if !known {
+                    return self.error(&format!("invalid bareword \"{word}\""));
+                }
+                self.pos = i;
+                Ok(ExprNode::Literal(word.to_string()))
+            }
+            _ => self.error(&format!(
+                "unexpected character \"{}\"",
+                self.src[start..].chars().next().unwrap()
+            )),
+        }
+    }
+
+    /// Parses the arguments of a call to a math function, from the opening
+    /// parenthesis after its name
+    fn parse_call(&mut self, name: &str) -> Result<ExprNode, String> {
+        let Some(&(name, _)) = MATH_FUNCS.iter().find(|(f, _)| *f == name) else {
+            return self.error(&format!("unknown math function \"{name}\""));
+        };
+        self.pos += 1;
+        let mut args = Vec::new();
+        self.skip_ws();
+        if self.peek() == Some(b')') {
+            self.pos += 1;
+            return Ok(ExprNode::Call(name, args));
+        }
+        loop {
+            args.push(self.parse_ternary()?);
+            self.skip_ws();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b')') => {
+                    self.pos += 1;
+                    return Ok(ExprNode::Call(name, args));
+                }
+                _ => return self.error("missing close parenthesis"),
+            }
+        }
+    }
+}
+
+// The functions expr knows, with how many arguments they take: at least
+// one for min and max, written as 0
+const MATH_FUNCS: [(&str, usize); 25] = [
+    ("abs", 1),
+    ("acos", 1),
+    ("asin", 1),
+    ("atan", 1),
+    ("atan2", 2),
+    ("ceil", 1),
+    ("cos", 1),
+    ("cosh", 1),
+    ("double", 1),
+    ("entier", 1),
+    ("exp", 1),
+    ("floor", 1),
+    ("fmod", 2),
+    ("hypot", 2),
+    ("int", 1),
+    ("log", 1),
+    ("log10", 1),
+    ("max", 0),
+    ("min", 0),
+    ("pow", 2),
+    ("round", 1),
+    ("sin", 1),
+    ("sinh", 1),
+    ("sqrt", 1),
+    ("tan", 1),
+];
+
+fn domain_error() -> TclError {
+    let msg = "domain error: argument not in valid range";
+    TclError::new(TclErrorKind::General, msg).with_code(list_from_iter(["ARITH", "DOMAIN", msg]))
+}
+
+/// Converts a float to an integer, which must fit
+fn float_to_int(f: f64) -> Result<Number, TclError> {
+    if f.is_nan() || f < i64::MIN as f64 || f >= i64::MAX as f64 {
+        return Err(TclError::new(
+            TclErrorKind::General,
+            "integer value too large to represent",
+        ));
+    }
+    Ok(Number::Int(f as i64))
+}
+
+/// Calls a math function. int and entier truncate toward zero, round
+/// rounds half away from zero, and both give integers; abs, min and max
+/// keep their arguments' type; everything else gives a float.
+fn math_func(name: &str, args: &[Number]) -> Result<Number, TclError> {
+    let arity = MATH_FUNCS
+        .iter()
+        .find(|(f, _)| *f == name)
+        .map_or(1, |f| f.1);
+    if args.len() < arity.max(1) || (arity > 0 && args.len() > arity) {
+        let which = if args.len() < arity.max(1) {
+            "few"
+        } else {
+            "many"
+        };
+        return Err(TclError::new(
+            TclErrorKind::General,
+            format!("too {which} arguments for math function \"{name}\""),
+        ));
+    }
+    let x = args[0].as_f64();
+    let y = args.get(1).map_or(0.0, |n| n.as_f64());
+    let float = |f: f64| Ok(Number::Float(f));
+    match name {
+        "abs" => match args[0] {
+            Number::Int(i) => i
+                .checked_abs()
+                .map(Number::Int)
+                .ok_or_else(|| TclError::new(TclErrorKind::General, "integer overflow")),
+            Number::Float(f) => float(f.abs()),
+        },
+        "min" | "max" => {
+            let mut best = args[0];
+            for &n in &args[1..] {
+                let better = if name == "min" {
+                    n.as_f64() < best.as_f64()
+                } else {
+                    n.as_f64() > best.as_f64()
+                };
+                if better {
+                    best = n;
+                }
+            }
+            Ok(best)
+        }
+        "int" | "entier" => match args[0] {
+            Number::Int(i) => Ok(Number::Int(i)),
+            Number::Float(f) => float_to_int(f.trunc()),
+        },
+        "round" => match args[0] {
+            Number::Int(i) => Ok(Number::Int(i)),
+            // f64::round already rounds half away from zero
+            Number::Float(f) => float_to_int(f.round()),
+        },
+        "double" => float(x),
+        "sqrt" if x < 0.0 => Err(domain_error()),
+        "sqrt" => float(x.sqrt()),
+        "log" | "log10" if x <= 0.0 => Err(domain_error()),
+        "log" => float(x.ln()),
+        "log10" => float(x.log10()),
+        "asin" | "acos" if !(-1.0..=1.0).contains(&x) => Err(domain_error()),
+        "asin" => float(x.asin()),
+        "acos" => float(x.acos()),
+        "atan" => float(x.atan()),
+        "atan2" => float(x.atan2(y)),
+        "sin" => float(x.sin()),
+        "cos" => float(x.cos()),
+        "tan" => float(x.tan()),
+        "sinh" => float(x.sinh()),
+        "cosh" => float(x.cosh()),
+        "exp" => float(x.exp()),
+        "floor" => float(x.floor()),
+        "ceil" => float(x.ceil()),
+        "pow" if x < 0.0 && y.fract() != 0.0 => Err(domain_error()),
+        "pow" if x == 0.0 && y < 0.0 => Err(domain_error()),
+        "pow" => float(x.powf(y)),
+        "fmod" if y == 0.0 => Err(domain_error()),
+        "fmod" => float(x % y),
+        "hypot" => float(x.hypot(y)),
+        _ => unreachable!("math function {name}"),
+    }
+}
+
+/// Parses an expression into a tree, or returns a syntax error message
+fn parse_expr(src: &str) -> Result<ExprNode, String> {
+    let mut p = ExprParser {
+        src,
+        pos: 0,
+        depth: 0,
+    };
+    let node = p.parse_ternary()?;
+    p.skip_ws();
+    if p.pos < src.len() {
+        return p.error(&format!("extra tokens at \"{}\"", &src[p.pos..]));
+    }
+    Ok(node)
+}
+
+fn expr_bool(v: ExprValue) -> Result<bool, TclError> {
+    match v {
+        ExprValue::Num(Number::Int(i)) => Ok(i != 0),
+        ExprValue::Num(Number::Float(f)) => Ok(f != 0.0),
+        ExprValue::Str(s) => parse_bool(&s),
+    }
+}
+
+fn expr_number(op: &str, v: ExprValue) -> Result<Number, TclError> {
+    match v {
+        ExprValue::Num(n) => Ok(n),
+        ExprValue::Str(s) if s.is_empty() => Err(TclError::new(
+            TclErrorKind::General,
+            format!("can't use empty string as operand of \"{op}\""),
+        )),
+        ExprValue::Str(s) => Err(TclError::new(
+            TclErrorKind::General,
+            format!("can't use non-numeric string \"{s}\" as operand of \"{op}\""),
+        )),
+    }
+}
+
+fn eval_expr_node(interp: &mut Interp, node: &ExprNode) -> Result<ExprValue, TclError> {
+    match node {
+        ExprNode::Number(n) => Ok(ExprValue::Num(*n)),
+        ExprNode::Literal(s) => Ok(ExprValue::from_string(s.clone())),
+        ExprNode::Var(name) => Ok(ExprValue::from_string(interp.substitute_var(name)?.into())),
+        ExprNode::Cmd(script) => {
+            let result = interp.eval_str(script)?;
+            Ok(ExprValue::from_string(result))
+        }
+        ExprNode::Quoted(s) => Ok(ExprValue::from_string(subst(interp, s)?)),
+        ExprNode::Unary(op, operand) => {
+            let v = eval_expr_node(interp, operand)?;
+            if *op == "!" {
+                return Ok(ExprValue::Num(Number::Int(!expr_bool(v)? as i64)));
+            }
+
+            let n = match (*op, expr_number(op, v)?) {
+                ("-", Number::Int(i)) => match i.checked_neg() {
+                    Some(i) => Number::Int(i),
+                    None => {
+                        return Err(TclError::new(TclErrorKind::General, "integer overflow"));
+                    }
+                },
+                ("-", Number::Float(f)) => Number::Float(-f),
+                ("~", Number::Int(i)) => Number::Int(!i),
+                ("~", Number::Float(_)) => {
+                    return Err(TclError::new(
+                        TclErrorKind::General,
+                        "can't use floating-point value as operand of \"~\"",
+                    ));
+                }
+                (_, n) => n,
+            };
+            Ok(ExprValue::Num(n))
+        }
+        ExprNode::Binary(op @ ("&&" | "||"), lhs, rhs) => {
+            // The right-hand side is only evaluated when it's needed
+            let l = eval_expr_node(interp, lhs)?;
+            let l = expr_bool(l)?;
+            let result = if (*op == "&&") != l {
+                l
+            } else {
+                let r = eval_expr_node(interp, rhs)?;
+                expr_bool(r)?
+            };
+            Ok(ExprValue::Num(Number::Int(result as i64)))
+        }
+        ExprNode::Binary(op, lhs, rhs) => {
+            let l = eval_expr_node(interp, lhs)?;
+            let r = eval_expr_node(interp, rhs)?;
+
+            match (*op, l, r) {
+                ("eq" | "ne", l, r) => {
+                    let equal = l.into_string(0) == r.into_string(0);
+                    Ok(ExprValue::Num(Number::Int((equal == (*op == "eq")) as i64)))
+                }
+                (_, ExprValue::Num(a), ExprValue::Num(b)) => {
+                    Ok(ExprValue::Num(binary_op(op, a, b)?))
+                }
+                ("<" | ">" | "<=" | ">=" | "==" | "!=", l, r) => {
+                    // Comparisons involving a non-number compare as strings
+                    let ord = l.into_string(0).cmp(&r.into_string(0));
+                    let result = match *op {
+                        "<" => ord.is_lt(),
+                        ">" => ord.is_gt(),
+                        "<=" => ord.is_le(),
+                        ">=" => ord.is_ge(),
+                        "==" => ord.is_eq(),
+                        _ => ord.is_ne(),
+                    };
+                    Ok(ExprValue::Num(Number::Int(result as i64)))
+                }
+                (_, l, r) => {
+                    let a = expr_number(op, l)?;
+                    let b = expr_number(op, r)?;
+                    Ok(ExprValue::Num(binary_op(op, a, b)?))
+                }
+            }
+        }
+        ExprNode::Call(name, args) => {
+            let mut numbers = Vec::with_capacity(args.len());
+            for arg in args {
+                let v = eval_expr_node(interp, arg)?;
+                numbers.push(expr_number(name, v)?);
+            }
+            Ok(ExprValue::Num(math_func(name, &numbers)?))
+        }
+        ExprNode::Ternary(cond, then, otherwise) => {
+            let c = eval_expr_node(interp, cond)?;
+            if expr_bool(c)? {
+                eval_expr_node(interp, then)
+            } else {
+                eval_expr_node(interp, otherwise)
+            }
+        }
+    }
+}
+
+/// Evaluates an expression, returning its value as a string
+pub fn eval_expr(interp: &mut Interp, src: &str) -> Result<String, TclError> {
+    let node = parse_expr(src).map_err(|msg| TclError::new(TclErrorKind::General, msg))?;
+    Ok(eval_expr_node(interp, &node)?.into_string(interp.precision))
+}
+
+fn cmd_expr(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, usize::MAX)?;
+
+    let src = argv[1..].join(" ");
+    let value = eval_expr(interp, &src)?;
+    interp.set_result(value);
+    Ok(Status::Ok)
+}
+
+fn cmd_abs(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 2, 2)?;
+
+    let result = match parse_number(&argv[1])? {
+        Number::Int(i) => match i.checked_abs() {
+            Some(i) => format!("{i}"),
+            None => {
+                return Err(TclError::new(TclErrorKind::General, "integer overflow"));
+            }
+        },
+        Number::Float(f) => format_double(f.abs(), interp.precision),
+    };
+    interp.set_result(result);
+    Ok(Status::Ok)
+}
+
+fn cmd_minmax(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    check_arity(argv, 3, usize::MAX)?;
+
+    let want_max = argv[0] == "max";
+    let mut best = 1;
+    let mut best_num = parse_number(&argv[1])?;
+
+    for (i, arg) in argv.iter().enumerate().skip(2) {
+        let num = parse_number(arg)?;
+        let greater = match (num, best_num) {
+            (Number::Int(a), Number::Int(b)) => a > b,
+            (a, b) => a.as_f64() > b.as_f64(),
+        };
+        let less = match (num, best_num) {
+            (Number::Int(a), Number::Int(b)) => a < b,
+            (a, b) => a.as_f64() < b.as_f64(),
+        };
+        if (want_max && greater) || (!want_max && less) {
+            best = i;
+            best_num = num;
+        }
+    }
+
+    interp.set_result(argv[best].clone());
+    Ok(Status::Ok)
+}
+
+impl Default for Interp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interp {
+    pub fn new() -> Interp {
+        let mut interp = Interp {
+            commands: CommandTable::default(),
+            callframes: Vec::new(),
+            result: TclValue::default(),
+            trace_parser: false,
+            trace_eval: false,
+            trace_depth: 0,
+            stats: EvalStats::default(),
+            commands_evaluated: 0,
+            history: VecDeque::new(),
+            history_first: 1,
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            test_counts: TestCounts::default(),
+            scheduler: Scheduler::new(),
+            unknown_depth: 0,
+            eval_depth: 0,
+            max_nesting_depth: 1000,
+            eval_limit: None,
+            eval_fuel: 0,
+            interrupt: Arc::new(AtomicBool::new(false)),
+            max_value_len: None,
+            max_vars_per_frame: None,
+            max_callframes: None,
+            max_coroutines: None,
+            #[cfg(feature = "coroutine")]
+            live_coroutines: Arc::default(),
+            precision: 0,
+            error_info: String::new(),
+            error_trace: Vec::new(),
+            error_trace_active: false,
+            error_line: None,
+            error_line_script: None,
+            error_source: None,
+            error_span: None,
+            error_file: None,
+            sources: Vec::new(),
+            last_word_line: None,
+            return_code: 0,
+            argv_pool: Vec::new(),
+            active_var_traces: Vec::new(),
+            active_exec_traces: Vec::new(),
+            children: HashMap::new(),
+            parent: None,
+            hidden: CommandTable::default(),
+            safe: false,
+            #[cfg(feature = "coroutine")]
+            coroutine: None,
+            command_sets: CommandSets::default(),
+            #[cfg(feature = "io")]
+            channels: HashMap::new(),
+            #[cfg(feature = "io")]
+            next_channel: 3,
+            #[cfg(feature = "io")]
+            servers: HashMap::new(),
+            #[cfg(feature = "io")]
+            stdin_eof: false,
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            eval_hook: None,
+            #[cfg(feature = "ffi")]
+            ffi_result: std::ffi::CString::default(),
+        };
+        interp.callframes.push(CallFrame::new());
+        interp
+    }
+
+    /// Follows upvar/global links from `name` in `frame` to the frame and
+    /// name that actually hold the variable. A name starting with `::` is
+    /// a global, whichever frame it's used from.
+    fn resolve_var<'a>(&self, frame: usize, name: &'a str) -> (usize, Cow<'a, str>) {
+        let (mut frame, mut name) = match name.strip_prefix("::") {
+            Some(global) => (0, Cow::Borrowed(global)),
+            None => (frame, Cow::Borrowed(name)),
+        };
+        while let Some(Var {
+            value: VarValue::Link { frame: f, name: n },
+            ..
+        }) = self.callframes[frame].find(&name)
+        {
+            (frame, name) = match n.strip_prefix("::") {
+                Some(global) => (0, Cow::Owned(global.to_string())),
+                None => (*f, Cow::Owned(n.clone())),
+            };
+        }
+        (frame, name)
+    }
+
+    /// Returns the maximum depth of nested evaluations and proc calls
+    pub fn max_nesting_depth(&self) -> usize {
+        self.max_nesting_depth
+    }
+
+    /// Sets the maximum depth of nested evaluations and proc calls.
+    /// Exceeding it is a Tcl error rather than a crash.
+    pub fn set_max_nesting_depth(&mut self, depth: usize) {
+        self.max_nesting_depth = depth;
+    }
+
+    /// Limits the number of evaluation steps (scripts evaluated plus
+    /// commands invoked) that each top-level call to eval may take, or
+    /// removes the limit with None. The count starts over with every
+    /// top-level eval, and with every call to this method. Running out
+    /// aborts the whole evaluation with TclError::LimitExceeded, which
+    /// catch and try don't intercept.
+    pub fn set_eval_limit(&mut self, limit: Option<u64>) {
+        self.eval_limit = limit;
+        self.eval_fuel = limit.unwrap_or(0);
+    }
+
+    pub fn eval_limit(&self) -> Option<u64> {
+        self.eval_limit
+    }
+
+    /// A flag that stops evaluation when set: the next evaluation step
+    /// fails with TclErrorKind::Interrupted. It stays set, failing every
+    /// evaluation after, until the host clears it. It can be set from
+    /// another thread or a signal handler. Child interpreters share it.
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    /// Uses up one evaluation step, failing once the limit is reached, if
+    /// the evaluation has been interrupted, or if it's the unwinding of a
+    /// deleted coroutine
+    fn consume_fuel(&mut self) -> Result<(), TclError> {
+        if self.interrupt.load(Ordering::Relaxed) {
+            return Err(TclError::new(
+                TclErrorKind::Interrupted,
+                "evaluation interrupted",
+            ));
+        }
+        #[cfg(feature = "coroutine")]
+        if let Some(link) = &self.coroutine
+            && coroutine::is_deleted(link)
+        {
+            return Err(coroutine::deleted());
+        }
+        let Some(limit) = self.eval_limit else {
+            return Ok(());
+        };
+        if self.eval_fuel == 0 {
+            return Err(TclError::new(
+                TclErrorKind::LimitExceeded,
+                format!("evaluation limit exceeded after {limit} steps"),
+            ));
+        }
+        self.eval_fuel -= 1;
+        Ok(())
+    }
+
+    /// Limits the length in bytes of any value stored in a variable or
+    /// left as a command's result, or removes the limit with None. Like
+    /// running out of evaluation steps, going over it is a
+    /// TclError::LimitExceeded that catch and try don't intercept.
+    pub fn set_max_value_len(&mut self, limit: Option<usize>) {
+        self.max_value_len = limit;
+    }
+
+    pub fn max_value_len(&self) -> Option<usize> {
+        self.max_value_len
+    }
+
+    /// Limits how many variables each call frame may hold, or removes the
+    /// limit with None. Array elements count as part of their array.
+    pub fn set_max_vars_per_frame(&mut self, limit: Option<usize>) {
+        self.max_vars_per_frame = limit;
+    }
+
+    pub fn max_vars_per_frame(&self) -> Option<usize> {
+        self.max_vars_per_frame
+    }
+
+    /// Limits how many call frames there may be at once, the global frame
+    /// included, or removes the limit with None
+    pub fn set_max_callframes(&mut self, limit: Option<usize>) {
+        self.max_callframes = limit;
+    }
+
+    pub fn max_callframes(&self) -> Option<usize> {
+        self.max_callframes
+    }
+
+    /// Limits how many coroutines may exist at once, suspended or running,
+    /// or removes the limit with None. Each one holds a thread.
+    pub fn set_max_coroutines(&mut self, limit: Option<usize>) {
+        self.max_coroutines = limit;
+    }
+
+    pub fn max_coroutines(&self) -> Option<usize> {
+        self.max_coroutines
+    }
+
+    /// The significant digits floats are formatted with, as set by the
+    /// global variable `tcl_precision`. 0, the default, means the fewest
+    /// digits that read back as the same value.
+    pub fn precision(&self) -> usize {
+        self.precision
+    }
+
+    /// A number as a value, formatted with the interpreter's precision
+    pub fn number_value(&self, n: Number) -> TclValue {
+        if self.precision == 0 {
+            return TclValue::from(n);
+        }
+        TclValue(Repr::Shared(Arc::new(ValueRep {
+            string: format_number(n, self.precision),
+            number: OnceLock::from(Some(n)),
+            ..Default::default()
+        })))
+    }
+
+    /// Checks a value for the global `tcl_precision` and starts using it
+    fn set_precision(&mut self, name: &str, value: &TclValue) -> Result<(), TclError> {
+        match value.trim().parse::<usize>() {
+            Ok(precision) if precision <= MAX_PRECISION => {
+                self.precision = precision;
+                Ok(())
+            }
+            _ => Err(TclError::new(
+                TclErrorKind::General,
+                format!("can't set \"{name}\": improper value for precision"),
+            )),
+        }
+    }
+
+    fn check_value_len(&self, len: usize) -> Result<(), TclError> {
+        match self.max_value_len {
+            Some(limit) if len > limit => Err(TclError::new(
+                TclErrorKind::LimitExceeded,
+                format!("value length limit exceeded: {len} bytes, over the limit of {limit}"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns how many commands have run and how deeply procs have nested,
+    /// along with the current call frames, the commands defined and roughly
+    /// how much memory variables and procs take up
+    pub fn stats(&self) -> InterpStats {
+        let var_bytes = self
+            .callframes
+            .iter()
+            .flat_map(|frame| frame.vars.values())
+            .map(|var| match &var.value {
+                VarValue::Scalar(v) => v.as_str().len(),
+                VarValue::Array(elements) => elements
+                    .iter()
+                    .map(|(k, v)| k.len() + v.as_str().len())
+                    .sum(),
+                VarValue::Undefined | VarValue::Link { .. } => 0,
+            })
+            .sum();
+        let proc_bytes = self
+            .proc_names()
+            .filter_map(|name| self.proc_definition(name))
+            .map(|(formals, body)| formals.len() + body.len())
+            .sum();
+        InterpStats {
+            eval: self.stats,
+            commands_evaluated: self.commands_evaluated,
+            depth: self.level(),
+            frame_vars: self.frames().map(|f| f.var_names().count()).collect(),
+            commands: self.commands.cmds.len(),
+            procs: self.commands.procs().count(),
+            var_bytes,
+            proc_bytes,
+        }
+    }
+
+    /// Adds a script to the list the history command shows. Front-ends
+    /// record each script before evaluating it, as the REPL does, so that
+    /// `history redo` can replace its own entry with what it redoes.
+    pub fn record_history(&mut self, script: &str) {
+        self.history.push_back(script.to_string());
+        self.trim_history();
+    }
+
+    /// Sets how many history events are kept
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        self.trim_history();
+    }
+
+    fn trim_history(&mut self) {
+        while self.history.len() > self.history_limit {
+            self.history.pop_front();
+            self.history_first += 1;
+        }
+    }
+
+    /// Returns the call frames, the global frame first
+    pub fn frames(&self) -> impl Iterator<Item = FrameView<'_>> {
+        self.callframes
+            .iter()
+            .enumerate()
+            .map(|(level, frame)| FrameView { level, frame })
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = EvalStats::default();
+    }
+
+    /// Returns the traceback of the most recent error, one line per
+    /// command it unwound through, innermost first.
+    pub fn error_trace(&self) -> String {
+        self.error_trace.join("\n")
+    }
+
+    /// Records a failed command invocation in the traceback and in the
+    /// global errorInfo variable. The first command to fail starts a new
+    /// traceback, each one it unwinds through adds a line.
+    fn add_error_trace(
+        &mut self,
+        err: &TclError,
+        argv: &[TclValue],
+        line: usize,
+        substitution: bool,
+    ) {
+        let mut cmd = merge_list(argv);
+        if let Some((end, _)) = cmd.char_indices().nth(150) {
+            cmd.truncate(end);
+            cmd.push_str("...");
+        }
+
+        let file_line = if substitution {
+            None
+        } else {
+            self.file_line(line)
+        };
+        let place = match file_line {
+            Some((file, line)) => format!("file \"{file}\" line {line}"),
+            None => format!("line {line}"),
+        };
+        let line = if self.error_trace_active {
+            format!("    invoked from within \"{cmd}\" ({place})")
+        } else {
+            self.error_info = err.msg.clone();
+            self.error_trace_active = true;
+            let _ = self.set_var_at(0, "errorCode", err.error_code());
+            format!("    while executing \"{cmd}\" ({place})")
+        };
+        self.error_info.push('\n');
+        self.error_info.push_str(&line);
+        self.error_trace.push(line);
+
+        let info = self.error_info.clone();
+        let _ = self.set_var_at(0, "errorInfo", &info);
+    }
+
+    /// The file and line in it of `line` of the script running now, if the
+    /// script is one read from a file rather than a braced word within it
+    fn file_line(&self, line: usize) -> Option<(&str, usize)> {
+        match self.sources.last() {
+            Some((source, depth)) if *depth == self.eval_depth => {
+                Some((&source.name, source.line_offset + line))
+            }
+            _ => None,
+        }
+    }
+
+    /// Runs `run` as the evaluation of a script that came from `source`
+    fn eval_from_source(
+        &mut self,
+        source: ScriptSource,
+        run: impl FnOnce(&mut Interp) -> Result<Status, TclError>,
+    ) -> Result<Status, TclError> {
+        self.sources.push((source, self.eval_depth + 1));
+        let res = self.eval_with(run);
+        let (source, _) = self.sources.pop().unwrap();
+
+        // An error's line that has been translated all the way out to this
+        // script is a line of the file
+        if res.is_err()
+            && self.error_file.is_none()
+            && self.error_line_script.is_some()
+            && let Some(line) = self.error_line
+        {
+            self.error_file = Some((source.name, source.line_offset + line));
+        }
+        res
+    }
+
+    /// Where the script being evaluated came from: the innermost file being
+    /// evaluated, or the definition of the proc whose body is running.
+    /// None for scripts that didn't come from a file.
+    pub fn script_source(&self) -> Option<&ScriptSource> {
+        self.sources.last().map(|(source, _)| source)
+    }
+
+    /// Returns the file and the line in it of the most recent error, for
+    /// an error in a script that came from a file: the innermost such
+    /// file, so an error in a proc body names the file the proc was
+    /// defined in.
+    pub fn error_file(&self) -> Option<(&str, usize)> {
+        self.error_file
+            .as_ref()
+            .map(|(name, line)| (name.as_str(), *line))
+    }
+
+    /// Marks the current error as handled, so the next one starts a fresh
+    /// traceback. errorInfo keeps describing the handled error until then.
+    fn clear_error_trace(&mut self) {
+        self.error_trace.clear();
+        self.error_trace_active = false;
+        self.error_line = None;
+        self.error_line_script = None;
+        self.error_source = None;
+        self.error_span = None;
+        self.error_file = None;
+    }
+
+    /// Returns the line of the most recent error. It counts from the
+    /// start of the top-level script when the error happened in braced or
+    /// bracketed text there, such as the body of an `if`, and from the
+    /// start of the proc body when it happened in a proc.
+    pub fn error_line(&self) -> Option<usize> {
+        self.error_line
+    }
+
+    /// Returns the script that error_line counts in: the top-level script,
+    /// or the body of the proc the error happened in.
+    pub fn error_source(&self) -> Option<&str> {
+        self.error_source.as_deref()
+    }
+
+    /// Returns the byte range in error_source of what failed: the name of
+    /// the command, the variable, or where a parse error was found. If
+    /// the word can't be found in the source text, e.g. because it was
+    /// substituted, this covers the whole line instead.
+    pub fn error_span(&self) -> Option<(usize, usize)> {
+        self.error_span
+    }
+
+    /// Records that an error unwound through the command on `line` of
+    /// `script`. If the error was raised inside one of `words`, such as
+    /// the body of an `if`, its line is translated to a line of `script`.
+    /// Otherwise it stays relative to where it was raised, e.g. a proc
+    /// body, and the traceback supplies the context.
+    fn note_error_line(&mut self, script: &str, line: usize, words: &[(&str, usize)], at: ErrorAt) {
+        let Some(line_in_word) = self.error_line else {
+            self.error_line = Some(line);
+            self.error_line_script = Some(script.to_string());
+            self.error_source = Some(script.to_string());
+            self.error_span = Some(error_span_at(script, line, at));
+            return;
+        };
+
+        let Some(inner) = self.error_line_script.take() else {
+            return;
+        };
+        if let Some(&(word, word_line)) = words.iter().find(|(w, _)| *w == inner) {
+            self.error_line = Some(word_line + line_in_word - 1);
+            self.error_line_script = Some(script.to_string());
+            self.error_source = Some(script.to_string());
+            // The word is the text of the script, unless it was quoted and
+            // had something substituted
+            let from = line_span(script, word_line).0;
+            let base = script[from..].find(word).map(|i| from + i);
+            self.error_span = match (self.error_span, base) {
+                (Some((start, end)), Some(base)) => Some((base + start, base + end)),
+                _ => Some(line_span(script, self.error_line.unwrap())),
+            };
+        }
+    }
+
+    pub fn set_result(&mut self, value: impl Into<TclValue>) {
+        self.result = value.into();
+    }
+
+    /// Captures the global variables and procs. Commands registered from
+    /// Rust aren't part of it and are left alone by restore.
+    pub fn snapshot(&self) -> InterpState {
+        InterpState {
+            globals: self.callframes[0].vars.clone(),
+            procs: self
+                .commands
+                .procs()
+                .map(|cmd| (cmd.name.clone(), cmd.body.clone()))
+                .collect(),
+        }
+    }
+
+    /// Puts back the global variables and procs of a snapshot, dropping
+    /// any defined since. A proc that replaced a Rust command stays
+    /// dropped rather than bringing the command back.
+    pub fn restore(&mut self, state: &InterpState) {
+        self.reset();
+        self.callframes[0].vars = state.globals.clone();
+        for (name, body) in &state.procs {
+            self.commands.insert(Cmd::new(name, body.clone()));
+        }
+    }
+
+    /// Copies the global variables and procs out as plain data. Links made
+    /// by `upvar` aren't saved.
+    pub fn save_state(&self) -> SavedState {
+        let vars = self.callframes[0]
+            .vars
+            .iter()
+            .filter_map(|(name, var)| {
+                let value = match &var.value {
+                    VarValue::Scalar(value) => SavedValue::Scalar(value.to_string()),
+                    VarValue::Array(elements) => SavedValue::Array(
+                        elements
+                            .iter()
+                            .map(|(k, v)| (k.clone(), v.to_string()))
+                            .collect(),
+                    ),
+                    VarValue::Undefined | VarValue::Link { .. } => return None,
+                };
+                Some((name.clone(), value))
+            })
+            .collect();
+        let procs = self
+            .commands
+            .procs()
+            .map(|cmd| {
+                let ppd = self.command_data::<ProcPrivdata>(&cmd.name).unwrap();
+                SavedProc {
+                    name: cmd.name.clone(),
+                    args: ppd.formals.to_string(),
+                    body: ppd.body.to_string(),
+                }
+            })
+            .collect();
+        SavedState { vars, procs }
+    }
+
+    /// Defines the global variables and procs of a saved state, replacing
+    /// any of the same name
+    pub fn load_state(&mut self, state: &SavedState) -> Result<Status, TclError> {
+        for (name, value) in &state.vars {
+            match value {
+                SavedValue::Scalar(value) => {
+                    self.unset_global(name);
+                    self.set_var_at(0, name, value.as_str())?;
+                }
+                SavedValue::Array(elements) => {
+                    self.unset_global(name);
+                    // An empty array still exists
+                    self.callframes[0].entry(name).value = VarValue::Array(HashMap::new());
+                    for (index, value) in elements {
+                        self.set_var_at(0, &format!("{name}({index})"), value.as_str())?;
+                    }
+                }
+            }
+        }
+        for proc in &state.procs {
+            self.define_proc(
+                &proc.name,
+                &TclValue::from(proc.args.as_str()),
+                &TclValue::from(proc.body.as_str()),
+            )?;
+        }
+        Ok(Status::Ok)
+    }
+
+    /// Forgets a global's value so it can be loaded with another shape
+    fn unset_global(&mut self, name: &str) {
+        if let Some(var) = self.callframes[0].vars.get_mut(name) {
+            var.value = VarValue::Undefined;
+        }
+    }
+
+    /// Removes all global variables and procs, keeping the commands
+    /// registered from Rust
+    pub fn reset(&mut self) {
+        self.callframes[0].vars.clear();
+        let procs: Vec<String> = self.commands.procs().map(|cmd| cmd.name.clone()).collect();
+        for name in procs {
+            self.commands.remove(&name);
+        }
+    }
+
+    /// Sends output, like that of `puts`, to `out` instead of the
+    /// process's stdout, for hosts that have no stdout or want to show it
+    /// themselves
+    pub fn set_output(&mut self, out: impl Write + Send + 'static) {
+        self.stdout = Some(Arc::new(Mutex::new(out)));
+    }
+
+    /// Sends error output to `err` instead of the process's stderr
+    pub fn set_error_output(&mut self, err: impl Write + Send + 'static) {
+        self.stderr = Some(Arc::new(Mutex::new(err)));
+    }
+
+    /// Reads input, like that of `gets stdin`, from `input` instead of the
+    /// process's stdin
+    pub fn set_input(&mut self, input: impl BufRead + Send + 'static) {
+        self.stdin = Some(Arc::new(Mutex::new(input)));
+    }
+
+    /// Reads a line of the interpreter's input, without its line ending.
+    /// Returns None at the end of the input.
+    pub fn read_input_line(&self) -> std::io::Result<Option<String>> {
+        let mut line = String::new();
+        let n = match &self.stdin {
+            Some(input) => lock(input).read_line(&mut line)?,
+            None => std::io::stdin().lock().read_line(&mut line)?,
+        };
+        if n == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    /// Writes to the interpreter's output
+    pub fn write_output(&self, text: std::fmt::Arguments) -> std::io::Result<()> {
+        match &self.stdout {
+            Some(out) => lock(out).write_fmt(text),
+            None => std::io::stdout().write_fmt(text),
+        }
+    }
+
+    /// Writes to the interpreter's error output
+    pub fn write_error_output(&self, text: std::fmt::Arguments) -> std::io::Result<()> {
+        match &self.stderr {
+            Some(err) => lock(err).write_fmt(text),
+            None => std::io::stderr().write_fmt(text),
+        }
+    }
+
+    /// Returns a channel opened by the script
+    #[cfg(feature = "io")]
+    fn channel(&mut self, name: &str) -> Result<&mut channel::Channel, TclError> {
+        self.channels
+            .get_mut(name)
+            .ok_or_else(|| channel_not_found(name))
+    }
+
+    /// Adds a channel that reads from and writes to a stream of the
+    /// embedder's, and returns its name: `name` followed by a number, like
+    /// `console3`. Scripts use it as they would a file from `open`, and
+    /// `close` calls the stream's close. Any channels left open are closed
+    /// when the interpreter is dropped.
+    #[cfg(feature = "io")]
+    pub fn register_channel(&mut self, name: &str, stream: Box<dyn Stream>) -> String {
+        self.add_channel(name, channel::Channel::new(stream, true, true))
+    }
+
+    /// Adds a channel, naming it with the prefix and the next number
+    #[cfg(feature = "io")]
+    fn add_channel(&mut self, prefix: &str, chan: channel::Channel) -> String {
+        let id = format!("{prefix}{}", self.next_channel);
+        self.next_channel += 1;
+        self.channels.insert(id.clone(), chan);
+        id
+    }
+
+    /// Flushes the interpreter's output
+    pub fn flush_output(&self) -> std::io::Result<()> {
+        match &self.stdout {
+            Some(out) => lock(out).flush(),
+            None => std::io::stdout().flush(),
+        }
+    }
+
+    /// Flushes the interpreter's error output
+    pub fn flush_error_output(&self) -> std::io::Result<()> {
+        match &self.stderr {
+            Some(err) => lock(err).flush(),
+            None => std::io::stderr().flush(),
+        }
+    }
+
+    /// Takes the result, leaving the empty string in its place
+    pub fn take_result(&mut self) -> TclValue {
+        std::mem::take(&mut self.result)
+    }
+
+    /// Returns the index of the current call frame: 0 at the top level,
+    /// one more for each proc call in progress.
+    pub fn level(&self) -> usize {
+        self.callframes.len() - 1
+    }
+
+    fn check_level(&mut self, frame: usize) -> Result<(), TclError> {
+        if frame >= self.callframes.len() {
+            return Err(TclError::new(
+                TclErrorKind::General,
+                format!("bad level \"{frame}\""),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Sets a variable in the current frame. Procs only see their own
+    /// locals; other frames are reached through `global` and `upvar`.
+    pub fn set_var(&mut self, name: &str, value: impl Into<TclValue>) -> Result<Status, TclError> {
+        self.set_var_at(self.level(), name, value)
+    }
+
+    /// Sets a variable in the given frame, following any links it has
+    pub fn set_var_at(
+        &mut self,
+        frame: usize,
+        name: &str,
+        value: impl Into<TclValue>,
+    ) -> Result<Status, TclError> {
+        self.check_level(frame)?;
+        let (base, index) = split_var_name(name);
+        let (frame, target) = self.resolve_var(frame, base);
+        let value = value.into();
+        self.check_value_len(value.len())?;
+        if frame == 0 && target == "tcl_precision" && index.is_none() {
+            self.set_precision(name, &value)?;
+        }
+        if let Some(limit) = self.max_vars_per_frame
+            && self.callframes[frame].find(&target).is_none()
+            && self.callframes[frame].vars.len() >= limit
+        {
+            return Err(TclError::new(
+                TclErrorKind::LimitExceeded,
+                format!("variable limit exceeded: a frame can hold only {limit} variables"),
+            ));
+        }
+
+        let var = self.callframes[frame].entry(&target);
+        match (&mut var.value, index) {
+            (VarValue::Array(_), None) => {
+                return Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("can't set \"{name}\": variable is array"),
+                ));
+            }
+            (value_slot, None) => *value_slot = VarValue::Scalar(value),
+            (VarValue::Array(elements), Some(index)) => {
+                elements.insert(index.to_string(), value);
+            }
+            (VarValue::Undefined, Some(index)) => {
+                let elements = HashMap::from([(index.to_string(), value)]);
+                var.value = VarValue::Array(elements);
+            }
+            (_, Some(_)) => {
+                return Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("can't set \"{name}\": variable isn't array"),
+                ));
+            }
+        }
+        let traces = var.traces.clone();
+
+        self.run_var_traces(frame, &target, name, "write", &traces)?;
+        Ok(Status::Ok)
+    }
+
+    /// Sets a global variable, whichever frame is current
+    pub fn set_global(
+        &mut self,
+        name: &str,
+        value: impl Into<TclValue>,
+    ) -> Result<Status, TclError> {
+        self.set_var_at(0, name, value)
+    }
+
+    /// Looks up a variable in the current frame, following any links it
+    /// has, without running read traces. `name(index)` names an array
+    /// element. Returns None if there is no such variable or element, or if
+    /// the variable is an array.
+    pub fn var(&self, name: &str) -> Option<&str> {
+        let (base, index) = split_var_name(name);
+        let (frame, target) = self.resolve_var(self.level(), base);
+        match (&self.callframes[frame].find(&target)?.value, index) {
+            (VarValue::Scalar(value), None) => Some(value.as_str()),
+            (VarValue::Array(elements), Some(index)) => elements.get(index).map(TclValue::as_str),
+            _ => None,
+        }
+    }
+
+    /// Returns the element names of an array in the current frame, in no
+    /// particular order, or None if the variable isn't an array.
+    pub fn array_names(&self, name: &str) -> Option<impl Iterator<Item = &str>> {
+        let (frame, target) = self.resolve_var(self.level(), name);
+        match &self.callframes[frame].find(&target)?.value {
+            VarValue::Array(elements) => Some(elements.keys().map(String::as_str)),
+            _ => None,
+        }
+    }
+
+    /// Returns the names of the variables visible in the current frame, in
+    /// no particular order
+    pub fn var_names(&self) -> impl Iterator<Item = &str> {
+        self.callframes[self.level()].names()
+    }
+
+    /// Reads the variable named by a `$` substitution. An array index can
+    /// itself contain substitutions, as in `$prices($fruit)`.
+    fn substitute_var(&mut self, name: &str) -> Result<TclValue, TclError> {
+        if let Some((array, index)) = split_array_element(name)
+            && index.contains(['$', '[', '\\'])
+        {
+            let index = subst(self, index)?;
+            return self.get_var(&format!("{array}({index})"));
+        }
+        self.get_var(name)
+    }
+
+    /// Reads a variable from the current frame
+    pub fn get_var(&mut self, name: &str) -> Result<TclValue, TclError> {
+        self.get_var_at(self.level(), name)
+    }
+
+    /// Reads a variable from the given frame, following any links it has
+    pub fn get_var_at(&mut self, frame: usize, name: &str) -> Result<TclValue, TclError> {
+        self.check_level(frame)?;
+        let (base, index) = split_var_name(name);
+        let (frame, target) = self.resolve_var(frame, base);
+
+        // Read traces run first, which lets them compute the value lazily
+        if let Some(var) = self.callframes[frame].find(&target) {
+            let traces = var.traces.clone();
+            self.run_var_traces(frame, &target, name, "read", &traces)?;
+        }
+
+        let problem = match (
+            self.callframes[frame].find(&target).map(|v| &v.value),
+            index,
+        ) {
+            (Some(VarValue::Scalar(value)), None) => return Ok(value.clone()),
+            (Some(VarValue::Array(elements)), Some(index)) => match elements.get(index) {
+                Some(value) => return Ok(value.clone()),
+                None => "no such element in array",
+            },
+            (Some(VarValue::Array(_)), None) => "variable is array",
+            (Some(VarValue::Scalar(_)), Some(_)) => "variable isn't array",
+            (None, None) => {
+                // Suggest variables from here and from the globals
+                let here = self.callframes[frame].names();
+                let globals = self.callframes[0].names().filter(|_| frame != 0);
+                let suggestions: Vec<String> = helpers::suggest(name, here.chain(globals))
+                    .into_iter()
+                    .map(|var| format!("${var}"))
+                    .collect();
+                return Err(TclError::new(
+                    TclErrorKind::VariableNotFound,
+                    format!(
+                        "can't read \"{name}\": no such variable{}",
+                        helpers::did_you_mean(&suggestions)
+                    ),
+                ));
+            }
+            _ => "no such variable",
+        };
+        Err(TclError::new(
+            TclErrorKind::VariableNotFound,
+            format!("can't read \"{name}\": {problem}"),
+        ))
+    }
+
+    pub fn unset_var(&mut self, name: &str) -> Result<Status, TclError> {
+        let (base, index) = split_var_name(name);
+        let (frame, target) = self.resolve_var(self.level(), base);
+
+        let cf = &mut self.callframes[frame];
+        if let Some(index) = index {
+            let removed = match cf.vars.get_mut(target.as_ref()) {
+                Some(Var {
+                    value: VarValue::Array(elements),
+                    ..
+                }) => elements.remove(index).is_some(),
+                _ => false,
+            };
+            if !removed {
+                return Err(TclError::new(
+                    TclErrorKind::VariableNotFound,
+                    format!("can't unset \"{name}\": no such element in array"),
+                ));
+            }
+            let traces = cf.find(&target).unwrap().traces.clone();
+            self.run_var_traces(frame, &target, name, "unset", &traces)?;
+            return Ok(Status::Ok);
+        }
+
+        let exists = cf
+            .find(&target)
+            .is_some_and(|v| matches!(v.value, VarValue::Scalar(_) | VarValue::Array(_)));
+        if !exists {
+            return Err(TclError::new(
+                TclErrorKind::VariableNotFound,
+                format!("can't unset \"{name}\": no such variable"),
+            ));
+        }
+
+        // Traces are removed along with the variable, after firing
+        let var = cf.vars.remove(target.as_ref()).unwrap();
+        if frame == 0 && target == "tcl_precision" {
+            self.precision = 0;
+        }
+        self.run_var_traces(frame, &target, name, "unset", &var.traces)?;
+        Ok(Status::Ok)
+    }
+
+    /// Invokes the traces registered for `op` as `command name op`, in the
+    /// frame where the access happened. While a variable's traces run,
+    /// further accesses to it from the callbacks are not traced.
+    fn run_var_traces(
+        &mut self,
+        frame: usize,
+        target: &str,
+        name: &str,
+        op: &str,
+        traces: &[VarTrace],
+    ) -> Result<(), TclError> {
+        if traces.is_empty()
+            || self
+                .active_var_traces
+                .iter()
+                .any(|(f, n)| *f == frame && n == target)
+        {
+            return Ok(());
+        }
+
+        self.active_var_traces.push((frame, target.to_string()));
+        let saved = self.take_result();
+
+        let mut res = Ok(());
+        for trace in traces.iter().filter(|t| t.ops.iter().any(|o| o == op)) {
+            let script = format!("{} {} {op}", trace.command, quote_list_element(name));
+            if let Err(e) = self.eval(&script) {
+                res = Err(e);
+                break;
+            }
+        }
+
+        self.active_var_traces.pop();
+
+        match res {
+            Ok(()) => {
+                self.result = saved;
+                Ok(())
+            }
+            Err(mut e) => {
+                let verb = match op {
+                    "read" => "read",
+                    "write" => "set",
+                    _ => "unset",
+                };
+                e.msg = format!("can't {verb} \"{name}\": {}", e.msg);
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the names of all commands, in the order they were defined
+    pub fn command_names(&self) -> impl Iterator<Item = &str> {
+        self.commands.names()
+    }
+
+    /// Returns the names of the commands defined with `proc`, in the order
+    /// they were defined
+    pub fn proc_names(&self) -> impl Iterator<Item = &str> {
+        self.command_names()
+            .filter(|name| self.command_data::<ProcPrivdata>(name).is_some())
+    }
+
+    /// Returns the argument list and body of a proc, as they were written
+    pub fn proc_definition(&self, name: &str) -> Option<(&str, &str)> {
+        let ppd = self.command_data::<ProcPrivdata>(name)?;
+        Some((ppd.formals.as_str(), ppd.body.as_str()))
+    }
+
+    pub fn get_command(&self, name: &str) -> Option<&Cmd> {
+        self.commands.get(name)
+    }
+
+    pub fn register_command(
+        &mut self,
+        name: &str,
+        cmd: CmdFunc,
+        privdata: Option<Arc<dyn Any + Send + Sync>>,
+    ) -> Result<Status, TclError> {
+        self.add_command(name, CmdBody::Func(cmd, privdata))
+    }
+
+    /// Registers a command implemented by a closure. Like
+    /// [`Interp::register_command`], this fails if the name is taken.
+    pub fn register_closure(
+        &mut self,
+        name: &str,
+        cmd: impl FnMut(&mut Interp, &[TclValue]) -> Result<Status, TclError> + Send + 'static,
+    ) -> Result<Status, TclError> {
+        self.add_command(name, CmdBody::Closure(Arc::new(Mutex::new(cmd))))
+    }
+
+    /// Registers a command that is handed a reference to `data` whenever
+    /// it runs. Commands that should share state can each be given an
+    /// `Arc` to it.
+    pub fn register_command_with_data<T: Send + Sync + 'static>(
+        &mut self,
+        name: &str,
+        cmd: fn(&mut Interp, &[TclValue], &T) -> Result<Status, TclError>,
+        data: T,
+    ) -> Result<Status, TclError> {
+        self.add_command(
+            name,
+            CmdBody::WithData(Arc::new(WithData { func: cmd, data })),
+        )
+    }
+
+    /// Registers a command made of subcommands. Like
+    /// [`Interp::register_command`], this fails if the name is taken.
+    pub fn register_ensemble(&mut self, ensemble: Ensemble) -> Result<Status, TclError> {
+        let name = ensemble.name().to_string();
+        self.register_command_with_data(&name, ensemble::dispatch, ensemble)
+    }
+
+    /// Like [`Interp::register_command_with_data`], but replaces any
+    /// existing command with the same name.
+    pub fn replace_command_with_data<T: Send + Sync + 'static>(
+        &mut self,
+        name: &str,
+        cmd: fn(&mut Interp, &[TclValue], &T) -> Result<Status, TclError>,
+        data: T,
+    ) {
+        self.commands.insert(Cmd::new(
+            name,
+            CmdBody::WithData(Arc::new(WithData { func: cmd, data })),
+        ));
+    }
+
+    /// Defines a proc, replacing any command of the same name
+    fn define_proc(
+        &mut self,
+        name: &str,
+        formals: &TclValue,
+        body: &TclValue,
+    ) -> Result<Status, TclError> {
+        // The body is the last word of the proc command, so it starts on
+        // the line that word does, if this is a line of a file
+        let origin = self
+            .last_word_line
+            .and_then(|line| self.file_line(line))
+            .map(|(file, line)| ScriptSource {
+                name: file.to_string(),
+                line_offset: line - 1,
+            });
+        let ppd = ProcPrivdata {
+            args: parse_formals(name, formals)?,
+            formals: formals.clone(),
+            body: body.clone(),
+            parsed: OnceLock::new(),
+            origin,
+        };
+        self.replace_command_with_data(name, call_proc, ppd);
+        Ok(Status::Ok)
+    }
+
+    /// The data a command was registered with by
+    /// [`Interp::register_command_with_data`], if it has data of type `T`.
+    pub fn command_data<T: 'static>(&self, name: &str) -> Option<&T> {
+        match &self.get_command(name)?.body {
+            CmdBody::WithData(cmd) => cmd.data().downcast_ref(),
+            _ => None,
+        }
+    }
+
+    fn add_command(&mut self, name: &str, body: CmdBody) -> Result<Status, TclError> {
+        if self.get_command(name).is_some() {
+            return Err(TclError::new(
+                TclErrorKind::CommandAlreadyDefined,
+                format!("command already defined: '{name}'"),
+            ));
+        }
+
+        self.commands.insert(Cmd::new(name, body));
+
+        Ok(Status::Ok)
+    }
+
+    /// Deletes a command, running its delete callback if it has one. The
+    /// name can be registered again afterwards.
+    pub fn unregister_command(&mut self, name: &str) -> Result<(), TclError> {
+        match self.commands.remove(name) {
+            Some(_) => Ok(()),
+            None => Err(TclError::new(
+                TclErrorKind::CommandNotFound,
+                format!("can't delete \"{name}\": command doesn't exist"),
+            )),
+        }
+    }
+
+    /// Sets a callback to run when the command goes away: when it is
+    /// unregistered, renamed to the empty string, replaced, or dropped
+    /// along with the interpreter. It runs exactly once, and renaming the
+    /// command keeps it. Use it to release whatever the command's data
+    /// holds on to.
+    pub fn set_delete_callback(
+        &mut self,
+        name: &str,
+        on_delete: impl FnOnce() + Send + 'static,
+    ) -> Result<(), TclError> {
+        let Some(&i) = self.commands.index.get(name) else {
+            return Err(TclError::new(
+                TclErrorKind::CommandNotFound,
+                format!("command not found: \"{name}\""),
+            ));
+        };
+        self.commands.cmds[i].on_delete = Some(Box::new(on_delete));
+        Ok(())
+    }
+
+    /// Registers a command, replacing any existing command with the same
+    /// name (and dropping its privdata).
+    pub fn replace_command(
+        &mut self,
+        name: &str,
+        cmd: CmdFunc,
+        privdata: Option<Arc<dyn Any + Send + Sync>>,
+    ) {
+        self.commands
+            .insert(Cmd::new(name, CmdBody::Func(cmd, privdata)));
+    }
+
+    /// Starts configuring a new interpreter
+    pub fn builder() -> InterpBuilder {
+        InterpBuilder {
+            sets: CommandSets {
+                core: true,
+                io: true,
+                process: true,
+                coroutine: true,
+                test: false,
+            },
+            max_nesting_depth: None,
+            eval_limit: None,
+            max_value_len: None,
+            max_vars_per_frame: None,
+            max_callframes: None,
+            max_coroutines: None,
+        }
+    }
+
+    fn register_command_sets(&mut self, sets: CommandSets) {
+        if sets.core {
+            self.register_core_commands();
+        }
+        #[cfg(feature = "io")]
+        if sets.io {
+            self.register_io_commands();
+        }
+        #[cfg(feature = "process")]
+        if sets.process {
+            self.register_process_commands();
+        }
+        #[cfg(feature = "coroutine")]
+        if sets.coroutine {
+            self.register_coroutine_commands();
+        }
+        if sets.test {
+            self.register_test_commands();
+        }
+    }
+
+    /// Commands that only compute: variables, procs, flow control, text
+    /// processing and math
+    pub fn register_core_commands(&mut self) {
+        self.command_sets.core = true;
+
+        // Basics
+        let _ = self.register_command("set", cmd_set, None);
+        let _ = self.register_command("unset", cmd_unset, None);
+        let _ = self.register_command("upvar", cmd_upvar, None);
+        let _ = self.register_command("global", cmd_global, None);
+        let _ = self.register_ensemble(trace_ensemble());
+
+        // Procs and flow control
+        let _ = self.register_command("proc", cmd_proc, None);
+        let _ = self.register_command("rename", cmd_rename, None);
+        let _ = self.register_command("return", cmd_return, None);
+        let _ = self.register_command("if", cmd_if, None);
+        let _ = self.register_command("continue", cmd_continue, None);
+        let _ = self.register_command("break", cmd_break, None);
+        let _ = self.register_command("while", cmd_while, None);
+        let _ = self.register_command("foreach", cmd_foreach, None);
+        let _ = self.register_command("error", cmd_error, None);
+        let _ = self.register_command("catch", cmd_catch, None);
+        let _ = self.register_command("try", cmd_try, None);
+        let _ = self.register_ensemble(interp_ensemble());
+        let _ = self.register_ensemble(info_ensemble());
+        let _ = self.register_command("history", cmd_history, None);
+        let _ = self.register_ensemble(clock_ensemble());
+
+        // Lists
+        let _ = self.register_command("lindex", cmd_lindex, None);
+        let _ = self.register_command("lrange", cmd_lrange, None);
+        let _ = self.register_command("linsert", cmd_linsert, None);
+        let _ = self.register_command("lset", cmd_lset, None);
+
+        // Dictionaries
+        let _ = self.register_ensemble(dict_ensemble());
+
+        // Text processing
+        let _ = self.register_ensemble(string_ensemble());
+        let _ = self.register_ensemble(encoding_ensemble());
+        #[cfg(feature = "regex")]
+        let _ = self.register_command("regexp", cmd_regexp, None);
+        #[cfg(feature = "regex")]
+        let _ = self.register_command("regsub", cmd_regsub, None);
+
+        // Math
+        let _ = self.register_command("expr", cmd_expr, None);
+        let _ = self.register_command("+", cmd_math, None);
+        let _ = self.register_command("-", cmd_math, None);
+        let _ = self.register_command("*", cmd_math, None);
+        let _ = self.register_command("/", cmd_math, None);
+        let _ = self.register_command(">", cmd_math, None);
+        let _ = self.register_command("<", cmd_math, None);
+        let _ = self.register_command(">=", cmd_math, None);
+        let _ = self.register_command("<=", cmd_math, None);
+        let _ = self.register_command("==", cmd_math, None);
+        let _ = self.register_command("!=", cmd_math, None);
+        let _ = self.register_command("%", cmd_math, None);
+        let _ = self.register_command("&", cmd_math, None);
+        let _ = self.register_command("|", cmd_math, None);
+        let _ = self.register_command("^", cmd_math, None);
+        let _ = self.register_command("<<", cmd_math, None);
+        let _ = self.register_command(">>", cmd_math, None);
+        let _ = self.register_command("**", cmd_math, None);
+        let _ = self.register_command("abs", cmd_abs, None);
+        let _ = self.register_command("min", cmd_minmax, None);
+        let _ = self.register_command("max", cmd_minmax, None);
+    }
+
+    /// Calls `hook` before and after every command runs, for profiling,
+    /// debugging or auditing. The hook has no access to the interpreter, so
+    /// it can't evaluate scripts itself.
+    pub fn set_eval_hook(&mut self, hook: impl FnMut(&EvalEvent) + Send + 'static) {
+        self.eval_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_eval_hook(&mut self) {
+        self.eval_hook = None;
+    }
+
+    /// Runs a command, tracing it if trace_eval is set. Each command gets a
+    /// line as it starts and one as it returns, indented and numbered by
+    /// how deeply it's nested:
+    ///
+    /// ```text
+    /// 1> double 4
+    ///   2> * 4 2
+    ///   2< ok 8
+    /// 1< ok 8
+    /// ```
+    fn invoke(&mut self, argv: &[TclValue]) -> Result<Status, TclError> {
+        self.stats.commands += 1;
+        self.commands_evaluated += 1;
+        if !self.trace_eval {
+            return self.invoke_hooked(argv);
+        }
+        self.trace_depth += 1;
+        let depth = self.trace_depth;
+        let indent = "  ".repeat(depth - 1);
+        let command = list_from_iter(argv.iter().map(TclValue::as_str));
+        let _ = self.write_error_output(format_args!("{indent}{depth}> {}\n", one_line(&command)));
+
+        let status = self.invoke_hooked(argv);
+        self.trace_depth -= 1;
+
+        let (code, value) = match &status {
+            Ok(Status::Ok) => ("ok", self.result.to_string()),
+            Ok(Status::Return) => ("return", self.result.to_string()),
+            Ok(Status::Break) => ("break", String::new()),
+            Ok(Status::Continue) => ("continue", String::new()),
+            Err(e) => ("error", e.to_string()),
+        };
+        let sep = if value.is_empty() { "" } else { " " };
+        let value = one_line(&value);
+        let _ = self.write_error_output(format_args!("{indent}{depth}< {code}{sep}{value}\n"));
+        status
+    }
+
+    /// Runs a command, telling the eval hook about it if there is one
+    fn invoke_hooked(&mut self, argv: &[TclValue]) -> Result<Status, TclError> {
+        let Some(hook) = &mut self.eval_hook else {
+            return self.dispatch(argv);
+        };
+        hook(&EvalEvent::CommandEnter { argv });
+
+        let start = Instant::now();
+        let status = self.dispatch(argv);
+        let duration = start.elapsed();
+
+        // The hook may have been cleared by the command
+        if let Some(hook) = &mut self.eval_hook {
+            hook(&EvalEvent::CommandLeave {
+                argv,
+                result: &self.result,
+                status: &status,
+                duration,
+            });
+        }
+        status
+    }
+
+    /// Looks up and runs the command named by argv[0]. Unresolved names are
+    /// handed to the `unknown` command, if there is one, as
+    /// `unknown name arg ...`.
+    fn dispatch(&mut self, argv: &[TclValue]) -> Result<Status, TclError> {
+        self.consume_fuel()?;
+
+        // Commands that don't set a result return the empty string
+        self.set_result(String::new());
+        let status = self.call_command(argv)?;
+        self.check_value_len(self.result.len())?;
+        Ok(status)
+    }
+
+    fn call_command(&mut self, argv: &[TclValue]) -> Result<Status, TclError> {
+        let cmd_name = &argv[0];
+
+        if let Some(cmd) = self.get_command(cmd_name) {
+            let body = cmd.body.clone();
+            if cmd.traces.is_empty()
+                || self
+                    .active_exec_traces
+                    .iter()
+                    .any(|n| n == cmd_name.as_str())
+            {
+                return body.call(self, argv);
+            }
+            let traces = cmd.traces.clone();
+            return self.call_traced(body, argv, &traces);
+        }
+
+        if self.unknown_depth == 0
+            && let Some(cmd) = self.get_command("unknown")
+        {
+            let body = cmd.body.clone();
+
+            let mut unknown_argv = Vec::with_capacity(argv.len() + 1);
+            unknown_argv.push(TclValue::from("unknown"));
+            unknown_argv.extend_from_slice(argv);
+
+            self.unknown_depth += 1;
+            let res = body.call(self, &unknown_argv);
+            self.unknown_depth -= 1;
+            return res;
+        }
+
+        let suggestions = helpers::suggest(cmd_name, self.command_names());
+        Err(TclError::new(
+            TclErrorKind::CommandNotFound,
+            format!(
+                "command not found: \"{cmd_name}\"{}",
+                helpers::did_you_mean(&suggestions)
+            ),
+        ))
+    }
+
+    /// Runs a command with its execution traces around it. While they run,
+    /// the command's own traces don't fire, so a callback can call it. An
+    /// error in a callback is the command's error.
+    fn call_traced(
+        &mut self,
+        body: CmdBody,
+        argv: &[TclValue],
+        traces: &[ExecTrace],
+    ) -> Result<Status, TclError> {
+        let command = quote_list_element(&list_from_iter(argv.iter().map(TclValue::as_str)));
+        let for_op =
+            |op: &'static str| traces.iter().filter(move |t| t.ops.iter().any(|o| o == op));
+
+        self.active_exec_traces.push(argv[0].to_string());
+        let res = for_op("enter").try_for_each(|t| {
+            self.eval(&format!("{} {command} enter", t.command))
+                .map(drop)
+        });
+        self.active_exec_traces.pop();
+        res?;
+
+        self.set_result(String::new());
+        let res = body.call(self, argv);
+        if for_op("leave").next().is_none() {
+            return res;
+        }
+
+        let result = match &res {
+            Ok(_) => self.take_result(),
+            Err(e) => TclValue::from(e.to_string()),
+        };
+        let code = status_code(&res);
+        self.active_exec_traces.push(argv[0].to_string());
+        let traced = for_op("leave").try_for_each(|t| {
+            let script = format!(
+                "{} {command} {code} {} leave",
+                t.command,
+                quote_list_element(&result)
+            );
+            self.eval(&script).map(drop)
+        });
+        self.active_exec_traces.pop();
+        traced?;
+        if res.is_ok() {
+            self.result = result;
+        }
+        res
+    }
+
+    /// Commands that read or write outside the interpreter
+    #[cfg(feature = "io")]
+    pub fn register_io_commands(&mut self) {
+        self.command_sets.io = true;
+        let _ = self.register_command("puts", cmd_puts, None);
+        let _ = self.register_command("flush", cmd_flush, None);
+        let _ = self.register_command("gets", cmd_gets, None);
+        let _ = self.register_command("read", cmd_read, None);
+        let _ = self.register_command("open", cmd_open, None);
+        let _ = self.register_command("source", cmd_source, None);
+        let _ = self.register_command("close", cmd_close, None);
+        let _ = self.register_command("seek", cmd_seek, None);
+        let _ = self.register_command("tell", cmd_tell, None);
+        let _ = self.register_command("eof", cmd_eof, None);
+        let _ = self.register_command("fconfigure", cmd_fconfigure, None);
+        let _ = self.register_command("socket", cmd_socket, None);
+    }
+
+    /// Commands that inspect or block the process the interpreter runs in
+    #[cfg(feature = "process")]
+    pub fn register_process_commands(&mut self) {
+        self.command_sets.process = true;
+        let _ = self.register_command("pid", cmd_pid, None);
+        let _ = self.register_command("after", cmd_after, None);
+        let _ = self.register_command("exit", cmd_exit, None);
+    }
+
+    /// `coroutine` and `yield`. They are kept out of the core set because
+    /// each coroutine runs on a thread.
+    #[cfg(feature = "coroutine")]
+    pub fn register_coroutine_commands(&mut self) {
+        self.command_sets.coroutine = true;
+        let _ = self.register_command("coroutine", cmd_coroutine, None);
+        let _ = self.register_command("yield", cmd_yield, None);
+    }
+
+    /// Commands for writing tests in Tcl. These are kept out of the core
+    /// set so that embedders can leave them out.
+    pub fn register_test_commands(&mut self) {
+        self.command_sets.test = true;
+        let _ = self.register_command("assert", cmd_assert, None);
+        let _ = self.register_command("assert_eq", cmd_assert_eq, None);
+        let _ = self.register_command("test", cmd_test, None);
+        let _ = self.register_command("testreport", cmd_testreport, None);
+    }
+
+    /// Returns how many tests run by the `test` command have passed and
+    /// failed
+    pub fn test_counts(&self) -> &TestCounts {
+        &self.test_counts
+    }
+
+    pub fn eval(&mut self, str: &str) -> Result<Status, TclError> {
+        self.eval_with(|interp| interp.eval_script(str))
+    }
+
+    /// Evaluates a script from a source that isn't trusted, returning its
+    /// result. It runs under the given nesting and step limits, so it
+    /// can't recurse or loop forever, and whatever the script is, this
+    /// doesn't panic: a panic inside the interpreter becomes an error, and
+    /// the interpreter is left at the top level, still usable. The limits
+    /// the interpreter had before are put back afterwards.
+    pub fn eval_safe(&mut self, script: &str, limits: EvalLimits) -> Result<String, TclError> {
+        let saved = (self.max_nesting_depth, self.eval_limit);
+        self.set_max_nesting_depth(limits.max_nesting_depth);
+        self.set_eval_limit(Some(limits.eval_limit));
+
+        let stack = eval_stack_size(limits.max_nesting_depth);
+        // A thread of its own gives it a stack of a known size, and catches
+        // a panic when joined
+        let res = std::thread::scope(|scope| {
+            let thread = std::thread::Builder::new().stack_size(stack);
+            match thread.spawn_scoped(scope, || self.eval_str(script)) {
+                Ok(handle) => handle.join(),
+                Err(e) => Ok(Err(TclError::new(
+                    TclErrorKind::General,
+                    format!("couldn't start a thread to evaluate on: {e}"),
+                ))),
+            }
+        });
+
+        self.set_max_nesting_depth(saved.0);
+        self.set_eval_limit(saved.1);
+        res.unwrap_or_else(|payload| {
+            self.callframes.truncate(1);
+            (self.eval_depth, self.unknown_depth, self.trace_depth) = (0, 0, 0);
+            self.active_var_traces.clear();
+            self.active_exec_traces.clear();
+            self.sources.clear();
+            Err(TclError::new(
+                TclErrorKind::General,
+                format!("evaluation panicked: {}", panic_message(&*payload)),
+            ))
+        })
+    }
+
+    /// Parses a script once so that it can be evaluated repeatedly with
+    /// eval_parsed
+    pub fn parse_script(&self, str: &str) -> ParsedScript {
+        ParsedScript::new(str, self.trace_parser)
+    }
+
+    /// Evaluates a script parsed by parse_script. This behaves exactly like
+    /// eval on the script's source.
+    pub fn eval_parsed(&mut self, script: &ParsedScript) -> Result<Status, TclError> {
+        self.eval_with(|interp| interp.run_parsed(script))
+    }
+
+    fn eval_with(
+        &mut self,
+        run: impl FnOnce(&mut Interp) -> Result<Status, TclError>,
+    ) -> Result<Status, TclError> {
+        let res = self.eval_checked(run);
+
+        // Scripts see an error's message as the result, e.g. through catch
+        if let Err(e) = &res {
+            self.set_result(e.msg.clone());
+        }
+        res
+    }
+
+    /// Evaluates a script and returns its result: the result of the last
+    /// command, or of `return`. An empty script returns the empty string.
+    pub fn eval_str(&mut self, str: &str) -> Result<String, TclError> {
+        self.set_result(String::new());
+        self.eval(str)?;
+        Ok(self.result.to_string())
+    }
+
+    /// Evaluates a script read from the file `name` and returns its
+    /// result. Errors in it, and in procs it defines, say which file and
+    /// line they happened on, through [`Interp::error_file`] and the
+    /// traceback.
+    pub fn eval_source(&mut self, name: &str, script: &str) -> Result<String, TclError> {
+        self.set_result(String::new());
+        let source = ScriptSource {
+            name: name.to_string(),
+            line_offset: 0,
+        };
+        self.eval_from_source(source, |interp| interp.eval_script(script))?;
+        Ok(self.result.to_string())
+    }
+
+    /// Invokes a command with the given words and returns its result. The
+    /// words are passed as they are, without any substitution, so this is
+    /// the safe way to hand data from the host to a script.
+    pub fn call(&mut self, argv: &[&str]) -> Result<String, TclError> {
+        if argv.is_empty() {
+            return Err(TclError::new(
+                TclErrorKind::Arity,
+                "wrong # args: should be \"command ?arg ...?\"",
+            ));
+        }
+        let argv: Vec<TclValue> = argv.iter().map(|&word| TclValue::from(word)).collect();
+        self.eval_with(|interp| interp.invoke(&argv))?;
+        Ok(self.result.to_string())
+    }
+
+    /// Evaluates a script within the nesting and step limits
+    fn eval_checked(
+        &mut self,
+        run: impl FnOnce(&mut Interp) -> Result<Status, TclError>,
+    ) -> Result<Status, TclError> {
+        if self.eval_depth >= self.max_nesting_depth {
+            return Err(TclError::new(
+                TclErrorKind::General,
+                "too many nested evaluations (infinite loop?)",
+            ));
+        }
+
+        if self.eval_depth == 0 {
+            self.eval_fuel = self.eval_limit.unwrap_or(0);
+            self.clear_error_trace();
+        }
+        self.consume_fuel()?;
+
+        self.eval_depth += 1;
+        let res = run(self);
+        self.eval_depth -= 1;
+
+        // Loops catch break and continue from their bodies, so one that
+        // reaches the outermost eval wasn't inside a loop
+        match res {
+            Ok(status) if self.eval_depth == 0 => check_loop_escape(status),
+            res => res,
+        }
+    }
+
+    /// Invokes a command of the script, recording where it was if it fails
+    fn invoke_at(
+        &mut self,
+        str: &str,
+        argv: &[TclValue],
+        word_lines: &[usize],
+        substitution: bool,
+    ) -> Result<Status, TclError> {
+        self.last_word_line = word_lines.last().filter(|_| !substitution).copied();
+        self.invoke(argv)
+            .inspect_err(|e| self.note_command_error(str, argv, word_lines, substitution, e))
+    }
+
+    /// Records that an error unwound through a command of the script
+    fn note_command_error(
+        &mut self,
+        str: &str,
+        argv: &[TclValue],
+        word_lines: &[usize],
+        substitution: bool,
+        e: &TclError,
+    ) {
+        self.add_error_trace(e, argv, word_lines[0], substitution);
+        let words: Vec<(&str, usize)> = argv
+            .iter()
+            .map(TclValue::as_str)
+            .zip(word_lines.iter().copied())
+            .collect();
+        self.note_error_line(str, word_lines[0], &words, ErrorAt::Word(argv[0].as_str()));
+    }
+
+    fn run_parsed(&mut self, script: &ParsedScript) -> Result<Status, TclError> {
+        self.run_script(&script.source, ParsedCursor::new(script))
+    }
+
+    fn eval_script(&mut self, str: &str) -> Result<Status, TclError> {
+        self.run_script(str, TextCursor::new(str, self.trace_parser))
+    }
+
+    /// Evaluates a script. Command substitutions are evaluated on an
+    /// explicit stack of frames rather than by recursing, so how deeply
+    /// brackets can nest is limited by memory and not the native stack.
+    fn run_script<'a, C: ScriptCursor<'a>>(
+        &mut self,
+        source: &'a str,
+        cursor: C,
+    ) -> Result<Status, TclError> {
+        let mut stack = vec![self.eval_frame(source, cursor, true, 0)];
+        let res = self.run_frames(&mut stack);
+        for frame in stack {
+            self.recycle_argv(frame.buf);
+        }
+        res
+    }
+
+    fn run_frames<'a, C: ScriptCursor<'a>>(
+        &mut self,
+        stack: &mut Vec<EvalFrame<'a, C>>,
+    ) -> Result<Status, TclError> {
+        loop {
+            let substitution = stack.len() > 1;
+            let frame = stack.last_mut().unwrap();
+            let (part, new_word, line) = match frame.cursor.next_piece() {
+                Piece::Part {
+                    part,
+                    new_word,
+                    line,
+                } => (part, new_word, line),
+                Piece::Eol => {
+                    if !frame.buf.argv.is_empty() {
+                        let (argv, lines) = (&frame.buf.argv, &frame.buf.lines);
+                        match self.invoke_at(frame.source, argv, lines, substitution) {
+                            Ok(Status::Ok) => {}
+                            // A return ends a command substitution early
+                            // with its value, but break and continue
+                            // can't escape one
+                            Ok(Status::Return) if substitution => {
+                                self.finish_frame(stack);
+                                continue;
+                            }
+                            Ok(status) if substitution => {
+                                let e = check_loop_escape(status).unwrap_err();
+                                let (argv, lines) = (&frame.buf.argv, &frame.buf.lines);
+                                self.note_command_error(frame.source, argv, lines, true, &e);
+                                return Err(self.unwind(stack, e));
+                            }
+                            Ok(status) => return Ok(status),
+                            Err(e) => return Err(self.unwind(stack, e)),
+                        }
+                    }
+                    frame.buf.clear();
+                    continue;
+                }
+                Piece::Eof => {
+                    if !self.finish_frame(stack) {
+                        return Ok(Status::Ok);
+                    }
+                    continue;
+                }
+                Piece::Error(e) => {
+                    self.note_error_line(frame.source, e.line, &[], ErrorAt::Offset(e.offset));
+                    let e = TclError::new(TclErrorKind::General, e.message);
+                    return Err(self.unwind(stack, e));
+                }
+            };
+
+            match part {
+                Part::Literal(text) => frame.add_str(&text, new_word, line),
+                Part::Value(value) => frame.add_value(value.clone(), new_word, line),
+                Part::Var(name) => match self.substitute_var(name) {
+                    Ok(value) => frame.add_value(value, new_word, line),
+                    Err(e) => {
+                        let at = ErrorAt::Word(&format!("${name}"));
+                        self.note_error_line(frame.source, line, &[], at);
+                        return Err(self.unwind(stack, e));
+                    }
+                },
+                Part::Cmd(cursor, source) => {
+                    let frame = self.eval_frame(source, cursor, new_word, line);
+                    stack.push(frame);
+                    // A substitution is an evaluation step like any eval
+                    if let Err(e) = self.consume_fuel() {
+                        return Err(self.unwind(stack, e));
+                    }
+                }
+            }
+        }
+    }
+
+    fn eval_frame<'a, C>(
+        &mut self,
+        source: &'a str,
+        cursor: C,
+        new_word: bool,
+        line: usize,
+    ) -> EvalFrame<'a, C> {
+        EvalFrame {
+            cursor,
+            source,
+            buf: self.argv_pool.pop().unwrap_or_default(),
+            new_word,
+            line,
+        }
+    }
+
+    /// Ends the innermost frame. A finished command substitution becomes
+    /// part of a word in the script around it; returns false if it was
+    /// the outermost script that finished.
+    fn finish_frame<C>(&mut self, stack: &mut Vec<EvalFrame<'_, C>>) -> bool {
+        let done = stack.pop().unwrap();
+        self.recycle_argv(done.buf);
+        let Some(frame) = stack.last_mut() else {
+            return false;
+        };
+        frame.add_value(self.result.clone(), done.new_word, done.line);
+        true
+    }
+
+    fn recycle_argv(&mut self, mut buf: ArgvBuffer) {
+        buf.clear();
+        self.argv_pool.push(buf);
+    }
+
+    /// Pops the command substitutions an error passes through, noting for
+    /// each where it was in the script around it
+    fn unwind<C>(&mut self, stack: &mut Vec<EvalFrame<'_, C>>, e: TclError) -> TclError {
+        while stack.len() > 1 {
+            let frame = stack.pop().unwrap();
+            self.recycle_argv(frame.buf);
+            let parent = stack.last().unwrap();
+            let words = [(frame.source, frame.line)];
+            self.note_error_line(parent.source, frame.line, &words, ErrorAt::Line);
+        }
+        e
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    /// Counts allocations made by the current thread, so tests can check
+    /// that evaluation doesn't allocate more than it needs to
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+        static ALLOCATED_BYTES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            let _ = ALLOCATED_BYTES.try_with(|n| n.set(n.get() + layout.size()));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+    /// Returns the number of allocations made by `f` and their total size
+    fn count_allocations(f: impl FnOnce()) -> (usize, usize) {
+        let before = (ALLOCATIONS.with(Cell::get), ALLOCATED_BYTES.with(Cell::get));
+        f();
+        (
+            ALLOCATIONS.with(Cell::get) - before.0,
+            ALLOCATED_BYTES.with(Cell::get) - before.1,
+        )
+    }
+
+    fn cmd_keep(
+        _interp: &mut Interp,
+        argv: &[TclValue],
+        privdata: Option<Arc<dyn Any + Send + Sync>>,
+    ) -> Result<Status, TclError> {
+        let kept = privdata.unwrap();
+        let kept = kept.downcast_ref::<Mutex<Vec<TclValue>>>().unwrap();
+        kept.lock().unwrap().push(argv[1].clone());
+        Ok(Status::Ok)
+    }
+
+    #[test]
+    fn values_are_shared_through_proc_calls() {
+        let mut interp = Interp::new();
+        interp.register_core_commands();
+        let kept = Arc::new(Mutex::new(Vec::<TclValue>::new()));
+        let _ = interp.register_command("keep", cmd_keep, Some(kept.clone()));
+
+        let big = TclValue::from("x".repeat(1 << 20));
+        interp.set_var("big", &big).unwrap();
+        interp
+            .eval(
+                "proc pass {n v} {
+                    if {== $n 0} {
+                        keep $v
+                    } else {
+                        pass [- $n 1] $v
+                    }
+                }
+                pass 10 $big",
+            )
+            .unwrap();
+
+        let kept = kept.lock().unwrap();
+        assert_eq!(kept.len(), 1);
+        let (Repr::Shared(a), Repr::Shared(b)) = (&kept[0].0, &big.0) else {
+            panic!("large values should be shared");
+        };
+        assert!(Arc::ptr_eq(a, b));
+    }
+
+    #[test]
+    fn literal_words_do_not_allocate() {
+        let mut interp = Interp::new();
+        interp.register_core_commands();
+        let script = "set x 1\n".repeat(1000);
+        interp.eval("set x 0").unwrap();
+
+        let (allocations, _) = count_allocations(|| {
+            interp.eval(&script).unwrap();
+        });
+        assert!(allocations < 100, "{allocations} allocations");
+    }
+
+    #[test]
+    fn words_built_from_many_fragments() {
+        let mut interp = Interp::new();
+        interp.register_core_commands();
+        interp.set_var("a", "ab").unwrap();
+        let script = format!("set w {}", "[set a]".repeat(10_000));
+
+        let (_, bytes) = count_allocations(|| {
+            interp.eval(&script).unwrap();
+        });
+        // Copying the word for every fragment would allocate hundreds of
+        // megabytes in total
+        assert_eq!(interp.get_var("w").unwrap().len(), 20_000);
+        assert!(bytes < 5_000_000, "{bytes} bytes allocated");
+    }
+
+    #[test]
+    fn script_starting_with_a_fragment() {
+        let mut interp = Interp::new();
+        interp.register_core_commands();
+        interp.set_var("cmd", "se").unwrap();
+        interp.eval("$cmd[set cmd]").unwrap_err();
+        assert_eq!(interp.eval_str("[set cmd]t y 2").unwrap(), "2");
+    }
+
+    #[test]
+    fn proc_bodies_are_parsed_once() {
+        let mut interp = Interp::new();
+        interp.register_core_commands();
+        interp.eval("proc f {n} {+ $n 1}").unwrap();
+
+        let parsed = |interp: &Interp| {
+            let ppd = interp.command_data::<ProcPrivdata>("f").unwrap();
+            ppd.parsed.get().map(|p| p as *const ParsedScript)
+        };
+        assert!(parsed(&interp).is_none());
+
+        assert_eq!(interp.eval_str("f 1").unwrap(), "2");
+        let first = parsed(&interp).unwrap();
+        assert_eq!(interp.eval_str("f 2").unwrap(), "3");
+        assert_eq!(parsed(&interp).unwrap(), first);
+    }
+
+    #[test]
+    fn parsed_scripts_can_be_evaluated_repeatedly() {
+        let mut interp = Interp::new();
+        interp.register_core_commands();
+        let script = interp.parse_script("set y [+ $x 1]$x");
+
+        for x in ["1", "2"] {
+            interp.set_var("x", x).unwrap();
+            interp.eval_parsed(&script).unwrap();
+        }
+        assert_eq!(interp.get_var("y").unwrap().as_str(), "32");
+    }
+
+    #[test]
+    fn nested_evaluation_reuses_argv_buffers() {
+        let mut interp = Interp::new();
+        interp.register_core_commands();
+        interp.eval("set x 1").unwrap();
+        let script = "set y [set x]\nset x [set y]\n".repeat(500);
+
+        let (allocations, _) = count_allocations(|| {
+            interp.eval(&script).unwrap();
+        });
+        assert!(allocations < 100, "{allocations} allocations");
+    }
+
+    #[test]
+    fn deeply_nested_command_substitution() {
+        let mut interp = Interp::new();
+        interp.register_core_commands();
+        interp.eval("proc i {x} {return $x}").unwrap();
+        let depth = 10_000;
+        let script = format!("set y {}1{}", "[i ".repeat(depth), "]".repeat(depth));
+
+        assert_eq!(interp.eval_str(&script).unwrap(), "1");
+    }
+
+    #[test]
+    fn concatenation_copies_shared_values() {
+        let mut interp = Interp::new();
+        interp.register_core_commands();
+        let base = TclValue::from("abc");
+        interp.set_var("base", &base).unwrap();
+        interp.eval("set joined \"$base def\"").unwrap();
+
+        assert_eq!(base.as_str(), "abc");
+        assert_eq!(interp.get_var("base").unwrap().as_str(), "abc");
+        assert_eq!(interp.get_var("joined").unwrap().as_str(), "abc def");
+    }
+
+    #[test]
+    fn tokens_end_on_character_boundaries() {
+        let scripts = [
+            "set 名 \\😀x\n",
+            "puts \"é\\ü $名\" {中 {文}} [🎉 \\é]\n",
+            "# 😀 \\中\nputs ${名}ü\\\n\té",
+            "set a(ключ) [set b(中) \\é]",
+        ];
+        for script in scripts {
+            Parser::tokens(script).for_each(drop);
+        }
+    }
+
+    #[test]
+    fn truncated_scripts_do_not_panic() {
+        let scripts = [
+            "\"abc",
+            "{abc",
+            "$",
+            "[cmd",
+            "#comment-with-no-newline",
+            "set x \"a [set y {b c}] ${z}\" ;# done\n",
+            "set a(k) [set b \\\n {x [y] \"z\"}]\\",
+        ];
+        let mut interp = Interp::new();
+        interp.register_core_commands();
+        for script in scripts {
+            // Every prefix, so that the input ends in each awkward place
+            for end in (0..=script.len()).filter(|&end| script.is_char_boundary(end)) {
+                let prefix = &script[..end];
+                Parser::tokens(prefix).for_each(drop);
+                let _ = interp.eval(prefix);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_errors_point_where_the_construct_opened() {
+        let error = |script| {
+            let mut tokens = Parser::tokens(script);
+            tokens.by_ref().for_each(drop);
+            tokens.error().cloned()
+        };
+        let at = |message, offset, line| {
+            Some(ParseError {
+                message,
+                offset,
+                line,
+            })
+        };
+
+        assert_eq!(
+            error("set a 1\nputs {x {y}\n"),
+            at("missing close-brace", 13, 2)
+        );
+        assert_eq!(error("puts [a {b} [c {d"), at("missing close-brace", 15, 1));
+        assert_eq!(error("x [y\n[z"), at("missing close-bracket", 5, 2));
+        assert_eq!(error("x \"a $b\nc"), at("missing \"", 2, 1));
+        assert_eq!(error("puts {a} [b] \"c\""), None);
+    }
+
+    #[test]
+    fn token_spans_and_positions() {
+        let script = "set a {x\n  y}\nputs [set a] \"é\" $a\n";
+        let mut parser = Parser::new(script);
+        let mut tokens = Vec::new();
+        loop {
+            let info = parser.next_info();
+            if info.token == Token::Eof {
+                break;
+            }
+            assert_eq!(info.span, parser.span());
+            assert_eq!(info.line, parser.line_col().0);
+            tokens.push((
+                info.token,
+                parser.token_body(),
+                info.span,
+                parser.line_col(),
+            ));
+        }
+
+        assert_eq!(
+            tokens,
+            [
+                (Token::Esc, "set", (0, 3), (1, 1)),
+                (Token::Sep, " ", (3, 4), (1, 4)),
+                (Token::Esc, "a", (4, 5), (1, 5)),
+                (Token::Sep, " ", (5, 6), (1, 6)),
+                (Token::Str, "x\n  y", (7, 12), (1, 8)),
+                (Token::Eol, "\n", (13, 14), (2, 5)),
+                (Token::Esc, "puts", (14, 18), (3, 1)),
+                (Token::Sep, " ", (18, 19), (3, 5)),
+                (Token::Cmd, "set a", (20, 25), (3, 7)),
+                (Token::Sep, " ", (26, 27), (3, 13)),
+                (Token::Esc, "é", (28, 30), (3, 15)),
+                (Token::Sep, " ", (31, 32), (3, 17)),
+                (Token::Var, "a", (33, 34), (3, 19)),
+                (Token::Eol, "\n", (34, 35), (3, 20)),
+            ]
+        );
+        assert_eq!(parser.position(), script.len());
+    }
+
+    #[test]
+    fn iterating_over_tokens() {
+        let script = "set a {b c}; puts [set a]$a\n# done";
+        let mut parser = Parser::new(script);
+        let mut expected = Vec::new();
+        loop {
+            let info = parser.next_info();
+            if info.token == Token::Eof {
+                break;
+            }
+            expected.push((info, parser.token_body()));
+        }
+
+        let tokens: Vec<_> = Parser::tokens(script).collect();
+        assert_eq!(tokens, expected);
+        let kinds: Vec<Token> = tokens.iter().map(|(info, _)| info.token).collect();
+        assert_eq!(
+            kinds,
+            [
+                Token::Esc,
+                Token::Sep,
+                Token::Esc,
+                Token::Sep,
+                Token::Str,
+                Token::Eol,
+                Token::Esc,
+                Token::Sep,
+                Token::Cmd,
+                Token::Var,
+                Token::Eol,
+                // The end of the comment
+                Token::Eol,
+            ]
+        );
+
+        let mut tokens = Parser::tokens("puts {a");
+        assert_eq!(tokens.by_ref().count(), 2);
+        assert_eq!(tokens.next(), None);
+        assert_eq!(
+            tokens.error().map(|e| e.message),
+            Some("missing close-brace")
+        );
+    }
+
+    #[test]
+    fn complete_and_incomplete_scripts() {
+        let cases = [
+            ("", true),
+            ("puts hello", true),
+            ("proc f {x} {", false),
+            ("proc f {x} {\n    return $x\n}", true),
+            ("puts \"}\"", true),
+            ("puts {\"", false),
+            ("puts {\"}", true),
+            ("puts \"abc", false),
+            ("puts \"a {b\"", true),
+            ("puts \"a [b", false),
+            ("puts \"a [b]", false),
+            ("puts \"a [b]\"", true),
+            ("set x [set y", false),
+            ("set x [set y {]}", false),
+            ("set x [set y {]}]", true),
+            ("set x [set y \"]\"]", true),
+            ("set x \\{", true),
+            ("puts {a \\}", false),
+            ("# a comment with a {", true),
+            ("set x 1 ;# {", true),
+            ("puts a \\", false),
+            ("puts a \\\n", false),
+            ("puts a\\\\", true),
+            ("puts ${a", false),
+            ("puts ${a}", true),
+        ];
+        for (script, complete) in cases {
+            assert_eq!(script_is_complete(script), complete, "{script:?}");
+        }
+    }
+
+    #[test]
+    fn script_depth_counts_what_is_left_open() {
+        let cases = [
+            ("set x 1", 0),
+            ("puts a \\", 0),
+            ("proc p {} {", 1),
+            ("proc p {} {\n  if {$x} {", 2),
+            ("proc p {} {\n  if {$x} {\n    puts \\{", 2),
+            ("proc p {} {\n  if {$x} {\n  }", 1),
+            ("set x [foo", 1),
+            ("set x [foo [bar {a", 3),
+            ("puts \"a [b", 2),
+            ("puts \"a [b {c]", 3),
+            ("puts \"a", 1),
+            ("puts ${a", 1),
+            ("while 1 {\n  set y [list \"a", 1),
+        ];
+        for (script, depth) in cases {
+            assert_eq!(script_depth(script), depth, "{script:?}");
+        }
+    }
+
+    #[test]
+    fn lists_round_trip() {
+        // A small xorshift generator keeps the test deterministic
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize % n
+        };
+        let pieces = [
+            "", "a", "{", "}", "\"", "\\", "#", " ", "\t", "\n", "\r", "$", "[", "]", ";", "é",
+            "\\\n", "{}", "\\{", "x y", "\u{b}", "\u{c}",
+        ];
+
+        for _ in 0..2000 {
+            let items: Vec<String> = (0..next(5))
+                .map(|_| (0..next(6)).map(|_| pieces[next(pieces.len())]).collect())
+                .collect();
+            let list = list_from_iter(&items);
+            assert_eq!(parse_list(&list).unwrap(), items, "{list:?}");
+        }
+
+        assert_eq!(list_from_iter(["a b", "", "#c"]), "{a b} {} {#c}");
+        assert_eq!(
+            parse_list("a {b c} \"d e\" f\\ g").unwrap(),
+            ["a", "b c", "d e", "f g"]
+        );
+    }
+
+    #[test]
+    fn doubles_format_to_round_trip() {
+        let awkward = [
+            0.1,
+            1.0 / 3.0,
+            0.1 + 0.2,
+            -2.5e-8,
+            1e21,
+            1e300,
+            5e-324,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            f64::EPSILON,
+            123456789.12345679,
+            -0.0,
+        ];
+        for f in awkward {
+            for precision in [0, MAX_PRECISION] {
+                let s = format_double(f, precision);
+                assert_eq!(s.parse::<f64>().unwrap().to_bits(), f.to_bits(), "{s}");
+            }
+        }
+        assert_eq!(format_double(0.1 + 0.2, 0), "0.30000000000000004");
+
+        // Past 17 digits, or below 1e-4, the shortest form takes an exponent
+        assert_eq!(format_double(1e20, 0), "1e+20");
+        assert_eq!(format_double(1e300, 0), "1e+300");
+        assert_eq!(format_double(-1.5e17, 0), "-1.5e+17");
+        assert_eq!(format_double(1e16, 0), "10000000000000000.0");
+        assert_eq!(format_double(1e-10, 0), "1e-10");
+        assert_eq!(format_double(1.25e-5, 0), "1.25e-05");
+        assert_eq!(format_double(0.0001, 0), "0.0001");
+        assert_eq!(format_double(f64::INFINITY, 0), "Inf");
+        assert_eq!(format_double(f64::NEG_INFINITY, MAX_PRECISION), "-Inf");
+    }
+
+    #[test]
+    fn integral_doubles_keep_their_point() {
+        assert_eq!(format_double(2.0, 0), "2.0");
+        assert_eq!(format_double(-0.0, 0), "-0.0");
+        assert_eq!(format_double(100.0, 4), "100.0");
+        assert_eq!(format_double(1e16, 0), "10000000000000000.0");
+    }
+
+    #[test]
+    fn precision_rounds_to_significant_digits() {
+        assert_eq!(format_double(1.0 / 3.0, 4), "0.3333");
+        assert_eq!(format_double(2.0 / 3.0, 4), "0.6667");
+        assert_eq!(format_double(123456.0, 4), "1.235e+05");
+        assert_eq!(format_double(9.99951, 4), "10.0");
+        assert_eq!(format_double(0.000123456, 4), "0.0001235");
+        assert_eq!(format_double(0.0000123456, 4), "1.235e-05");
+        assert_eq!(format_double(1.5, 4), "1.5");
+        assert_eq!(format_double(-1.0 / 3.0, 1), "-0.3");
+    }
+}
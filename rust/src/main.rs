@@ -1,11 +1,196 @@
-mod tcl;
 use clap::Parser;
-use std::ffi::{CStr, CString, c_char};
-use tcl::tcl::*;
+use completion::Completions;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tcl3::{
+    EvalStats, Interp, TclError, TclErrorKind, Token, list_from_iter, script_depth,
+    script_is_complete,
+};
 
-unsafe extern "C" {
-    fn linenoise(prompt: *const c_char) -> *mut c_char;
-    fn linenoiseFree(ptr: *mut c_char);
+/// Exit status for a script that failed with an error
+const EXIT_ERROR: i32 = 1;
+/// Exit status when the script file couldn't be read
+const EXIT_UNREADABLE: i32 = 2;
+/// Exit status when Ctrl-C stopped the script, as shells report SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Ctrl-C sets the interpreter's interrupt flag, which stops whatever it's
+/// evaluating, rather than killing the process. A second Ctrl-C before the
+/// flag is cleared, as when a command is blocked and never gets to check
+/// it, exits after all.
+#[cfg(unix)]
+mod sigint {
+    use std::ffi::c_int;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, OnceLock};
+
+    const SIGINT: c_int = 2;
+
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn _exit(status: c_int) -> !;
+    }
+
+    static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+    extern "C" fn on_sigint(_signum: c_int) {
+        if let Some(flag) = FLAG.get()
+            && flag.swap(true, Ordering::SeqCst)
+        {
+            unsafe { _exit(super::EXIT_INTERRUPTED) }
+        }
+    }
+
+    pub fn install(flag: Arc<AtomicBool>) {
+        if FLAG.set(flag).is_ok() {
+            unsafe { signal(SIGINT, on_sigint) };
+        }
+    }
+}
+
+/// Where the REPL gets its input
+trait LineReader {
+    /// Shows the prompt and reads a line, without its newline. None means
+    /// the input ended or the user asked to stop.
+    fn read_line(&mut self, prompt: &str) -> Option<String>;
+
+    /// Whether the last read_line returned None because the user pressed
+    /// Ctrl-C, rather than because the input ended
+    fn interrupted(&self) -> bool {
+        false
+    }
+
+    /// Makes an entry available for recall, for readers that can
+    fn add_history(&mut self, _entry: &str) {}
+
+    /// Sets what Tab completes to, for readers that can
+    fn set_completions(&mut self, _names: Completions) {}
+}
+
+#[cfg(feature = "linenoise")]
+mod linenoise {
+    use super::Completions;
+    use std::cell::RefCell;
+    use std::ffi::{CStr, CString, c_char, c_int};
+
+    #[repr(C)]
+    struct LinenoiseCompletions {
+        _private: [u8; 0],
+    }
+
+    type CompletionCallback = extern "C" fn(*const c_char, *mut LinenoiseCompletions);
+
+    unsafe extern "C" {
+        fn linenoise(prompt: *const c_char) -> *mut c_char;
+        fn linenoiseFree(ptr: *mut c_char);
+        fn linenoiseHistoryAdd(line: *const c_char) -> c_int;
+        fn linenoiseHistorySetMaxLen(len: c_int) -> c_int;
+        fn linenoiseSetCompletionCallback(callback: CompletionCallback);
+        fn linenoiseAddCompletion(lc: *mut LinenoiseCompletions, completion: *const c_char);
+    }
+
+    thread_local! {
+        // The completion callback takes no user data, so the names it
+        // completes to are left here before each prompt
+        static COMPLETIONS: RefCell<Completions> = RefCell::default();
+    }
+
+    extern "C" fn complete_line(buf: *const c_char, lc: *mut LinenoiseCompletions) {
+        let line = unsafe { CStr::from_ptr(buf) }.to_string_lossy();
+        let candidates = COMPLETIONS
+            .with(|names| super::completion::complete(&names.borrow(), &line, line.len()));
+        for candidate in candidates {
+            if candidate == line {
+                continue;
+            }
+            if let Ok(candidate) = CString::new(candidate) {
+                unsafe { linenoiseAddCompletion(lc, candidate.as_ptr()) };
+            }
+        }
+    }
+
+    /// Reads lines with editing, through the linenoise C library
+    pub struct Linenoise {
+        interrupted: bool,
+    }
+
+    impl Linenoise {
+        /// linenoise keeps a single history, so only one of these should
+        /// exist at a time
+        pub fn new(history_len: usize) -> Linenoise {
+            unsafe {
+                linenoiseHistorySetMaxLen(history_len.clamp(1, c_int::MAX as usize) as c_int);
+                linenoiseSetCompletionCallback(complete_line);
+            }
+            Linenoise { interrupted: false }
+        }
+    }
+
+    impl super::LineReader for Linenoise {
+        fn read_line(&mut self, prompt: &str) -> Option<String> {
+            let prompt = CString::new(prompt).unwrap();
+            let ptr = unsafe { linenoise(prompt.as_ptr()) };
+            if ptr.is_null() {
+                // linenoise sets errno to EAGAIN for Ctrl-C
+                self.interrupted =
+                    std::io::Error::last_os_error().kind() == std::io::ErrorKind::WouldBlock;
+                return None;
+            }
+            self.interrupted = false;
+
+            let line = unsafe { CStr::from_ptr(ptr) }
+                .to_string_lossy()
+                .into_owned();
+            unsafe {
+                linenoiseFree(ptr);
+            }
+            Some(line)
+        }
+
+        fn interrupted(&self) -> bool {
+            self.interrupted
+        }
+
+        fn add_history(&mut self, entry: &str) {
+            if let Ok(entry) = CString::new(entry) {
+                unsafe {
+                    linenoiseHistoryAdd(entry.as_ptr());
+                }
+            }
+        }
+
+        fn set_completions(&mut self, names: Completions) {
+            COMPLETIONS.set(names);
+        }
+    }
+}
+
+/// Reads plain lines from stdin, for builds without linenoise
+#[cfg(not(feature = "linenoise"))]
+struct StdinReader;
+
+#[cfg(not(feature = "linenoise"))]
+impl LineReader for StdinReader {
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        use std::io::{BufRead, Write};
+
+        let mut stdout = std::io::stdout();
+        let _ = write!(stdout, "{prompt}");
+        let _ = stdout.flush();
+
+        let mut line = String::new();
+        match std::io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                let end = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(end);
+                Some(line)
+            }
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -14,67 +199,1428 @@ struct Args {
     #[arg(short, long, default_value_t = false)]
     trace_parser: bool,
 
+    /// If true, print each command to stderr as it runs, and its result
+    #[arg(long, default_value_t = false)]
+    trace_eval: bool,
+
+    /// If true, print the tokens of the script as a JSON array instead of
+    /// evaluating it
+    #[arg(long, default_value_t = false)]
+    dump_tokens: bool,
+
+    /// If true, print to stderr how long the script file and each -c script
+    /// took, how many commands they ran and how deeply procs nested
+    #[arg(long, default_value_t = false)]
+    time: bool,
+
     /// If true, open a read eval print loop
     #[arg(short, long, default_value_t = false)]
     repl: bool,
 
-    /// Files to evaluate
+    /// Where the REPL keeps its history. Defaults to $TCL3_HISTORY, or
+    /// ~/.tcl3_history if that isn't set.
+    #[arg(long, value_name = "PATH")]
+    history_file: Option<String>,
+
+    /// Number of REPL history entries to keep
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    history_size: usize,
+
+    /// Startup file the REPL evaluates before its first prompt. Defaults
+    /// to $TCL3RC, or ~/.tcl3rc if that isn't set.
+    #[arg(long, value_name = "PATH")]
+    rcfile: Option<String>,
+
+    /// If true, the REPL doesn't evaluate a startup file
+    #[arg(long, default_value_t = false)]
+    norc: bool,
+
+    /// Script to evaluate after the script file. May be given more than
+    /// once, and the scripts run in order.
+    #[arg(short = 'c', long = "command", value_name = "SCRIPT")]
+    commands: Vec<String>,
+
+    /// Print the result of each top-level command of the script file and
+    /// -c scripts, when it isn't empty, as the REPL does
+    #[arg(long, default_value_t = false)]
+    echo: bool,
+
+    /// The script to evaluate, followed by its arguments. "-" reads the
+    /// script from stdin, as does giving no script, no -c and no --repl
+    /// when stdin isn't a terminal. Words after a leading -- are all
+    /// arguments, for -c scripts and the REPL.
     #[arg(
-        value_name = "FILES",
-        num_args = 1..,
+        value_name = "SCRIPT",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
     )]
-    files: Vec<String>,
+    script: Vec<String>,
+}
+
+/// Collects lines typed at the REPL until they make a complete script, so
+/// that a proc or loop can be entered over several lines.
+#[derive(Default)]
+struct ScriptBuffer {
+    pending: String,
+}
+
+impl ScriptBuffer {
+    /// Adds a line, returning the script once it is complete. Newlines are
+    /// kept so that line numbers in errors match what was typed.
+    fn push_line(&mut self, line: &str) -> Option<String> {
+        if self.pending.is_empty() && line.trim().is_empty() {
+            return None;
+        }
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        if script_is_complete(&self.pending) {
+            Some(std::mem::take(&mut self.pending))
+        } else {
+            None
+        }
+    }
+
+    /// Whether lines are waiting for the rest of their script
+    fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// How many braces, brackets and quotes the lines so far leave open
+    fn depth(&self) -> usize {
+        script_depth(&self.pending)
+    }
+
+    fn cancel(&mut self) {
+        self.pending.clear();
+    }
+}
+
+/// Tab completion, which only the linenoise reader offers
+mod completion {
+    #![cfg_attr(not(feature = "linenoise"), allow(dead_code))]
+
+    use tcl3::Interp;
+    use tcl3::ensemble::Ensemble;
+
+    /// The names Tab completes to, copied from the interpreter before each
+    /// prompt
+    #[derive(Clone, Debug, Default)]
+    pub struct Completions {
+        pub commands: Vec<String>,
+        pub vars: Vec<String>,
+        /// Ensemble commands and their subcommands, as `info subcommands`
+        /// lists them
+        pub subcommands: Vec<(String, Vec<String>)>,
+    }
+
+    impl Completions {
+        pub fn from_interp(i: &Interp) -> Completions {
+            let sorted = |names: &mut dyn Iterator<Item = &str>| {
+                let mut names: Vec<String> = names.map(str::to_string).collect();
+                names.sort();
+                names
+            };
+            let commands = sorted(&mut i.command_names());
+            let subcommands = (commands.iter())
+                .filter_map(|name| {
+                    let ensemble = i.command_data::<Ensemble>(name)?;
+                    Some((name.clone(), sorted(&mut ensemble.subcommands())))
+                })
+                .collect();
+            Completions {
+                commands,
+                vars: sorted(&mut i.var_names()),
+                subcommands,
+            }
+        }
+    }
+
+    /// Completes the word that ends at `cursor`: a command name where a
+    /// command starts, a variable name after `$`, or a subcommand after a
+    /// command that takes one. Returns the whole line for each match, or just
+    /// the line unchanged if nothing matches.
+    pub fn complete(names: &Completions, line: &str, cursor: usize) -> Vec<String> {
+        let (before, after) = line.split_at(cursor);
+        let (head, word) = before.split_at(word_start(before));
+
+        let candidates: Vec<String> = if let Some(prefix) = word.strip_prefix('$') {
+            names
+                .vars
+                .iter()
+                .filter(|name| name.starts_with(prefix))
+                .map(|name| format!("${name}"))
+                .collect()
+        } else if starts_command(head) {
+            names
+                .commands
+                .iter()
+                .filter(|name| name.starts_with(word))
+                .cloned()
+                .collect()
+        } else {
+            let command = head.trim_end();
+            let (command_head, command) = command.split_at(word_start(command));
+            let subcommands = (names.subcommands.iter())
+                .find(|(name, _)| name == command && starts_command(command_head))
+                .map_or(&[][..], |(_, subcommands)| subcommands);
+            subcommands
+                .iter()
+                .filter(|name| name.starts_with(word))
+                .cloned()
+                .collect()
+        };
+
+        if candidates.is_empty() {
+            return vec![line.to_string()];
+        }
+        candidates
+            .into_iter()
+            .map(|candidate| format!("{head}{candidate}{after}"))
+            .collect()
+    }
+
+    /// Where the word that `text` ends with begins
+    fn word_start(text: &str) -> usize {
+        text.char_indices()
+            .rev()
+            .find(|&(_, c)| c.is_whitespace() || matches!(c, '[' | ';' | '{' | '"'))
+            .map_or(0, |(i, c)| i + c.len_utf8())
+    }
+
+    /// Whether a word after `head` would be a command name
+    fn starts_command(head: &str) -> bool {
+        let head = head.trim_end_matches([' ', '\t']);
+        head.is_empty() || head.ends_with(['[', ';', '\n'])
+    }
+}
+
+/// Where history is kept: the --history-file flag, then $TCL3_HISTORY,
+/// then ~/.tcl3_history. Setting $TCL3_HISTORY to the empty string turns
+/// history off.
+fn history_path(flag: Option<&str>, env: Option<&str>, home: Option<&str>) -> Option<PathBuf> {
+    match (flag, env, home) {
+        (Some(path), _, _) => Some(PathBuf::from(path)),
+        (None, Some(""), _) => None,
+        (None, Some(path), _) => Some(PathBuf::from(path)),
+        (None, None, Some(home)) => Some(Path::new(home).join(".tcl3_history")),
+        (None, None, None) => None,
+    }
+}
+
+/// The startup file the REPL evaluates: the --rcfile flag, then $TCL3RC,
+/// then ~/.tcl3rc. Setting $TCL3RC to the empty string turns it off.
+fn rc_path(flag: Option<&str>, env: Option<&str>, home: Option<&str>) -> Option<PathBuf> {
+    match (flag, env, home) {
+        (Some(path), _, _) => Some(PathBuf::from(path)),
+        (None, Some(""), _) => None,
+        (None, Some(path), _) => Some(PathBuf::from(path)),
+        (None, None, Some(home)) => Some(Path::new(home).join(".tcl3rc")),
+        (None, None, None) => None,
+    }
+}
+
+/// Evaluates the startup file in the interpreter the REPL goes on to use,
+/// so the procs and variables it defines are there at the prompt. A file
+/// that doesn't exist is skipped, and one that fails is reported without
+/// stopping the REPL. Returns the exit code if the file called exit.
+fn load_rc(i: &mut Interp, path: &Path) -> Option<i32> {
+    let script = fs::read_to_string(path).ok()?;
+    let name = path.to_string_lossy();
+    let e = i.eval_source(&name, &script).err()?;
+    match e.kind {
+        TclErrorKind::Exit(code) => return Some(code),
+        TclErrorKind::Interrupted => {
+            i.interrupt_handle().store(false, Ordering::SeqCst);
+            let _ = i.write_error_output(format_args!("interrupted\n"));
+        }
+        _ => {
+            let msg = match i.error_file() {
+                Some((file, line)) => format!("{file}: {e} (line {line})"),
+                None => format!("{name}: {e}"),
+            };
+            let _ = i.write_error_output(format_args!("Error: {msg}\n"));
+            let trace = i.error_trace();
+            if !trace.is_empty() {
+                let _ = i.write_error_output(format_args!("{trace}\n"));
+            }
+        }
+    }
+    None
+}
+
+/// Scripts entered at the REPL, oldest first. Each complete script is one
+/// entry, so a proc typed over several lines is recalled whole.
+struct History {
+    entries: Vec<String>,
+    max_len: usize,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    fn new(max_len: usize, path: Option<PathBuf>) -> History {
+        History {
+            entries: Vec::new(),
+            max_len,
+            path,
+        }
+    }
+
+    /// Reads the history file, if there is one
+    fn load(max_len: usize, path: Option<PathBuf>) -> History {
+        let mut history = History::new(max_len, path);
+        if let Some(text) = history
+            .path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+        {
+            for line in text.lines() {
+                history.add(&decode_history_entry(line));
+            }
+        }
+        history
+    }
+
+    /// Adds an entry unless it is blank or repeats the last one, dropping
+    /// the oldest once there are too many. Returns whether it was added.
+    fn add(&mut self, entry: &str) -> bool {
+        let entry = entry.trim_end();
+        if entry.trim().is_empty()
+            || self.max_len == 0
+            || self.entries.last().is_some_and(|last| last == entry)
+        {
+            return false;
+        }
+        self.entries.push(entry.to_string());
+        if self.entries.len() > self.max_len {
+            let excess = self.entries.len() - self.max_len;
+            self.entries.drain(..excess);
+        }
+        true
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut text = String::new();
+        for entry in &self.entries {
+            text.push_str(&encode_history_entry(entry));
+            text.push('\n');
+        }
+        fs::write(path, text)
+    }
+}
+
+/// The history file has one entry per line, so newlines in an entry are
+/// written as \n and backslashes doubled
+fn encode_history_entry(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn decode_history_entry(line: &str) -> String {
+    let mut entry = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                entry.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                entry.push('\\');
+                chars.next();
+            }
+            _ => entry.push(c),
+        }
+    }
+    entry
 }
 
 fn main() {
     let args = Args::parse();
+    let raw: Vec<String> = std::env::args().collect();
+    let (script, argv) = split_script_args(&raw, args.script);
 
-    let mut i = Interp::new();
-    i.register_core_commands();
+    let mut i = Interp::builder().with_test_commands(true).build();
 
     i.trace_parser = args.trace_parser;
+    i.trace_eval = args.trace_eval;
+    #[cfg(unix)]
+    sigint::install(i.interrupt_handle());
+
+    let script = script.or_else(|| {
+        let stdin_only = args.commands.is_empty() && !args.repl;
+        (stdin_only && !std::io::stdin().is_terminal()).then(|| "-".to_string())
+    });
+
+    // As in tclsh, argv0 is the script, or the interpreter if there's none
+    let argv0 = script.as_deref().unwrap_or(&raw[0]);
+    let _ = i.set_global("argv0", argv0);
+    let _ = i.set_global("argv", list_from_iter(&argv));
+    let _ = i.set_global("argc", argv.len().to_string());
 
-    for file in args.files {
-        let contents = std::fs::read_to_string(&file).unwrap_or_else(|e| {
-            eprintln!("Error reading file: {e}");
-            std::process::exit(1);
+    if let Some(file) = &script {
+        let (name, contents) = if file == "-" {
+            ("<stdin>", std::io::read_to_string(std::io::stdin()))
+        } else {
+            (file.as_str(), fs::read_to_string(file))
+        };
+        let contents = contents.unwrap_or_else(|e| {
+            eprintln!("Error: couldn't read \"{name}\": {e}");
+            std::process::exit(EXIT_UNREADABLE);
         });
+        if args.dump_tokens {
+            std::process::exit(print_tokens(name, &contents));
+        }
+        run_script(&mut i, name, &contents, args.echo, args.time, true);
+    }
+    if args.dump_tokens {
+        // Offsets and lines are only meaningful in a single script
+        let [script] = &args.commands[..] else {
+            eprintln!("Error: --dump-tokens takes a single script");
+            std::process::exit(EXIT_ERROR);
+        };
+        std::process::exit(print_tokens("-c", script));
+    }
+    for script in &args.commands {
+        run_script(&mut i, "-c", script, args.echo, args.time, false);
+    }
+    // Failed tests fail the run, so that test scripts can gate CI
+    if i.test_counts().failed > 0 && !args.repl {
+        std::process::exit(EXIT_ERROR);
+    }
 
-        let res = i.eval(&contents);
+    if args.repl {
+        let path = history_path(
+            args.history_file.as_deref(),
+            std::env::var("TCL3_HISTORY").ok().as_deref(),
+            std::env::var("HOME").ok().as_deref(),
+        );
+        let mut history = History::load(args.history_size, path);
 
-        if res.is_err() {
-            eprintln!("Error: {:?} {:?}", res.err().unwrap(), i.result);
+        // Only a session someone is typing into gets the startup file
+        if !args.norc
+            && std::io::stdin().is_terminal()
+            && let Some(path) = rc_path(
+                args.rcfile.as_deref(),
+                std::env::var("TCL3RC").ok().as_deref(),
+                std::env::var("HOME").ok().as_deref(),
+            )
+            && let Some(code) = load_rc(&mut i, &path)
+        {
+            std::process::exit(code);
+        }
 
-            std::process::exit(1);
+        #[cfg(feature = "linenoise")]
+        let mut reader = linenoise::Linenoise::new(args.history_size);
+        #[cfg(not(feature = "linenoise"))]
+        let mut reader = StdinReader;
+        for entry in &history.entries {
+            reader.add_history(entry);
         }
+        std::process::exit(repl(&mut i, &mut reader, &mut history));
     }
+}
 
-    if args.repl {
-        let prompt = CString::new("> ").unwrap();
-        loop {
-            let ptr = unsafe {
-                let ptr = linenoise(prompt.as_ptr());
-                if ptr.is_null() {
-                    break;
-                }
-                ptr
+/// Splits the words after the options into the script and its arguments.
+/// Those words are always the tail of the command line, so a -- just
+/// before them means there's no script and they're all arguments.
+fn split_script_args(raw: &[String], mut words: Vec<String>) -> (Option<String>, Vec<String>) {
+    let separated =
+        raw.len() > words.len() && !words.is_empty() && raw[raw.len() - words.len() - 1] == "--";
+    if separated || words.is_empty() {
+        return (None, words);
+    }
+    let script = words.remove(0);
+    (Some(script), words)
+}
+
+/// What --time reports for a script
+fn format_timing(name: &str, elapsed: Duration, stats: &EvalStats) -> String {
+    format!(
+        "time: {name}: {:.3} ms, {} command{}, max depth {}",
+        elapsed.as_secs_f64() * 1000.0,
+        stats.commands,
+        if stats.commands == 1 { "" } else { "s" },
+        stats.max_depth
+    )
+}
+
+/// Prints the tokens of a script to stdout, returning the exit status:
+/// EXIT_ERROR if it doesn't parse, after the tokens before the error
+fn print_tokens(name: &str, script: &str) -> i32 {
+    let (json, error) = dump_tokens(script);
+    print!("{json}");
+    match error {
+        Some(e) => {
+            eprintln!("Error: {name}: {} (line {})", e.message, e.line);
+            EXIT_ERROR
+        }
+        None => 0,
+    }
+}
+
+/// The tokens of a script as a JSON array, one token to a line, and the
+/// parse error that stopped them early if there was one
+fn dump_tokens(script: &str) -> (String, Option<tcl3::ParseError>) {
+    let mut tokens = tcl3::Parser::tokens(script);
+    let mut json = String::from("[");
+    for (n, (info, body)) in tokens.by_ref().enumerate() {
+        let (begin, end) = info.span;
+        let line_start = script[..begin].rfind('\n').map_or(0, |i| i + 1);
+        let column = script[line_start..begin].chars().count() + 1;
+        let kind = format!("{:?}", info.token).to_uppercase();
+        json += if n == 0 { "\n" } else { ",\n" };
+        json += &format!(
+            "  {{\"type\": \"TK_{kind}\", \"begin\": {begin}, \"end\": {end}, \"line\": {}, \"column\": {column}, \"body\": {}}}",
+            info.line,
+            json_string(body)
+        );
+    }
+    json += if json.len() > 1 { "\n]\n" } else { "]\n" };
+    (json, tokens.error().cloned())
+}
+
+/// A string as a JSON string literal
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Evaluates a file or -c script, exiting with its traceback if it fails.
+/// A file's errors name it and the line in it.
+fn run_script(i: &mut Interp, name: &str, script: &str, echo: bool, time: bool, file: bool) {
+    i.reset_stats();
+    let start = Instant::now();
+    let file = file.then_some(name);
+    let res = if echo {
+        eval_echoed(i, script, file)
+    } else {
+        eval_in(i, script, file).map(drop)
+    };
+    if time {
+        eprintln!("{}", format_timing(name, start.elapsed(), &i.stats().eval));
+    }
+    let Err(e) = res else {
+        return;
+    };
+    match e.kind {
+        TclErrorKind::Exit(code) => std::process::exit(code),
+        TclErrorKind::Interrupted => {
+            eprintln!("interrupted");
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        _ => {}
+    }
+
+    let msg = match (i.error_file(), i.error_line()) {
+        (Some((file, line)), _) => format!("{file}: {e} (line {line})"),
+        (None, Some(line)) => format!("{name}: {e} (line {line})"),
+        (None, None) => format!("{name}: {e}"),
+    };
+    match (i.error_source(), i.error_span()) {
+        (Some(src), Some(span)) => {
+            // A proc body's lines are counted from the start of the body
+            let offset = match (i.error_file(), i.error_line()) {
+                (Some((_, file_line)), Some(line)) => file_line.saturating_sub(line),
+                _ => 0,
             };
+            eprint!("{}", render_error(src, span, offset, &msg, use_color()))
+        }
+        _ => eprintln!("Error: {msg}"),
+    }
+    let trace = i.error_trace();
+    if !trace.is_empty() {
+        eprintln!("{trace}");
+    }
+    std::process::exit(EXIT_ERROR);
+}
+
+/// Whether to color what goes to stderr: only for a terminal, and not if
+/// NO_COLOR is set to anything
+fn use_color() -> bool {
+    std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
+
+/// Formats an error with the line of source it happened on and a caret
+/// under the byte range span of it. The line is numbered as in the file,
+/// for source that starts `line_offset` lines into it:
+///
+/// ```text
+/// Error: test.tcl: invalid command name "nope" (line 2)
+///   2 |     nope 1 2
+///     |     ^^^^
+/// ```
+fn render_error(
+    src: &str,
+    span: (usize, usize),
+    line_offset: usize,
+    msg: &str,
+    color: bool,
+) -> String {
+    let (red, blue, reset) = if color {
+        ("\x1b[1;31m", "\x1b[34m", "\x1b[0m")
+    } else {
+        ("", "", "")
+    };
+    let mut out = format!("{red}Error:{reset} {msg}\n");
+
+    let start = span.0.min(src.len());
+    let line_start = src[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = src[start..].find('\n').map_or(src.len(), |i| start + i);
+    let text = &src[line_start..line_end];
+    let end = span.1.clamp(start, line_end);
+
+    let line = src[..line_start].matches('\n').count() + 1 + line_offset;
+    let gutter = " ".repeat(line.to_string().len());
+    // Tabs stay tabs, so the caret lines up however wide they are shown
+    let pad: String = src[line_start..start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let carets = "^".repeat(src[start..end].chars().count().max(1));
+    out += &format!("{blue}  {line} |{reset} {text}\n");
+    out += &format!("{blue}  {gutter} |{reset} {pad}{red}{carets}{reset}\n");
+    out
+}
+
+/// Evaluates a script one top-level command at a time, printing each
+/// non-empty result to the interpreter's output
+fn eval_echoed(i: &mut Interp, script: &str, file: Option<&str>) -> Result<(), TclError> {
+    for (line, command) in top_level_commands(script) {
+        // Blank lines in front keep line numbers in errors right
+        let command = format!("{}{command}", "\n".repeat(line - 1));
+        let result = eval_in(i, &command, file)?;
+        if !result.is_empty() {
+            let _ = i.write_output(format_args!("{result}\n"));
+        }
+    }
+    Ok(())
+}
+
+/// Evaluates a script, as one read from `file` if it was
+fn eval_in(i: &mut Interp, script: &str, file: Option<&str>) -> Result<String, TclError> {
+    match file {
+        Some(name) => i.eval_source(name, script),
+        None => i.eval_str(script),
+    }
+}
+
+/// Splits a script into its top-level commands, each with the line it
+/// starts on. Whatever follows a parse error is left as the last piece, so
+/// that evaluating it reports the error.
+fn top_level_commands(script: &str) -> Vec<(usize, &str)> {
+    let mut parser = tcl3::Parser::new(script);
+    let mut commands = Vec::new();
+    let (mut start, mut line) = (0, 1);
+    loop {
+        let token = parser.next();
+        if parser.error().is_some() || token == Token::Eof {
+            break;
+        }
+        if token == Token::Eol {
+            let end = parser.position();
+            commands.push((line, &script[start..end]));
+            line += script[start..end].matches('\n').count();
+            start = end;
+        }
+    }
+    if !script[start..].is_empty() {
+        commands.push((line, &script[start..]));
+    }
+    commands
+}
+
+/// What a REPL meta-command asks for
+#[derive(Debug, PartialEq)]
+enum Meta {
+    Print(String),
+    Quit,
+}
+
+const META_HELP: &str = "\
+:vars          list global variables and their values
+:procs         list procs
+:body NAME     show the body of a proc
+:trace on|off  trace parsing and evaluation
+:quit          leave the REPL
+";
 
-            let cline = unsafe { CStr::from_ptr(ptr) };
+/// Runs a line of REPL input that starts with `:`, a shortcut for looking
+/// around the interpreter that isn't Tcl
+fn meta_command(i: &mut Interp, line: &str) -> Meta {
+    let mut words = line.trim()[1..].split_whitespace();
+    let text = match (words.next(), words.next(), words.next()) {
+        (Some("vars"), None, _) => {
+            let mut names: Vec<&str> = i.var_names().collect();
+            names.sort();
+            names
+                .into_iter()
+                .map(|name| match i.var(name) {
+                    Some(value) => format!("{name} = {value}\n"),
+                    None => format!("{name} (array)\n"),
+                })
+                .collect()
+        }
+        (Some("procs"), None, _) => i.proc_names().map(|name| format!("{name}\n")).collect(),
+        (Some("body"), Some(name), None) => match i.proc_definition(name) {
+            Some((_, body)) => format!("{}\n", body.trim_matches('\n')),
+            None => format!("\"{name}\" isn't a proc\n"),
+        },
+        (Some("trace"), Some(state @ ("on" | "off")), None) => {
+            i.trace_parser = state == "on";
+            i.trace_eval = state == "on";
+            String::new()
+        }
+        (Some("quit"), None, _) => return Meta::Quit,
+        _ => META_HELP.to_string(),
+    };
+    Meta::Print(text)
+}
 
-            let line = cline.to_string_lossy().into_owned();
+/// The REPL's prompt: for a new script, or with `pending` for the next
+/// line of one, with the depth of what its lines leave open. As in tclsh,
+/// a script can set its own by defining tcl_prompt1 for new scripts and
+/// tcl_prompt2 for the lines after, as a variable holding a script or as
+/// a proc, whose result is the prompt. It falls back on the default if
+/// that fails.
+fn prompt(i: &mut Interp, pending: Option<usize>) -> String {
+    let name = match pending {
+        None => "tcl_prompt1",
+        Some(_) => "tcl_prompt2",
+    };
+    let script = match i.var(name) {
+        Some(script) => Some(script.to_string()),
+        None => i.get_command(name).is_some().then(|| name.to_string()),
+    };
+    if let Some(prompt) = script.and_then(|script| i.eval_str(&script).ok()) {
+        return prompt;
+    }
+    match pending {
+        None => "> ".to_string(),
+        // A line continued with a backslash leaves nothing open
+        Some(0) => ">> ".to_string(),
+        Some(depth) => format!("({depth})>> "),
+    }
+}
 
-            let res = i.eval(line.as_str());
+/// Reads scripts and evaluates them, printing their results, until the
+/// input ends or a script calls exit. Returns the status to exit with.
+/// History is saved after every script, so it survives however the
+/// process exits.
+fn repl(i: &mut Interp, reader: &mut impl LineReader, history: &mut History) -> i32 {
+    let interrupt = i.interrupt_handle();
+    let mut buffer = ScriptBuffer::default();
+    loop {
+        let pending = buffer.is_pending().then(|| buffer.depth());
+        let prompt = prompt(i, pending);
+        reader.set_completions(Completions::from_interp(i));
+        let Some(line) = reader.read_line(&prompt) else {
+            // Ctrl-C abandons a script being entered; otherwise it quits,
+            // as does Ctrl-D
+            if reader.interrupted() && buffer.is_pending() {
+                buffer.cancel();
+                continue;
+            }
+            break;
+        };
 
-            if res.is_ok() {
-                println!("{:?}", res.ok().unwrap());
-            } else {
-                eprintln!("Error: {:?} {:?}", res.err().unwrap(), i.result);
+        if line.trim() == ".cancel" {
+            buffer.cancel();
+            continue;
+        }
+        // Only a line on its own can be a meta-command, not one inside a
+        // script being entered
+        if !buffer.is_pending() && line.trim_start().starts_with(':') {
+            if history.add(&line) {
+                reader.add_history(history.entries.last().unwrap());
+                let _ = history.save();
+            }
+            match meta_command(i, &line) {
+                Meta::Print(text) => {
+                    let _ = i.write_output(format_args!("{text}"));
+                }
+                Meta::Quit => break,
             }
+            continue;
+        }
+        let Some(script) = buffer.push_line(&line) else {
+            continue;
+        };
+        if history.add(&script) {
+            reader.add_history(history.entries.last().unwrap());
+            let _ = history.save();
+        }
 
-            unsafe {
-                linenoiseFree(ptr);
+        i.record_history(script.trim_end());
+        // Ctrl-C pressed while nothing ran has nothing to stop
+        interrupt.store(false, Ordering::SeqCst);
+        match i.eval_str(&script) {
+            Ok(result) if result.is_empty() => {}
+            Ok(result) => {
+                let _ = i.write_output(format_args!("{result}\n"));
+            }
+            Err(e) => match e.kind {
+                TclErrorKind::Exit(code) => return code,
+                TclErrorKind::Interrupted => {
+                    interrupt.store(false, Ordering::SeqCst);
+                    let _ = i.write_error_output(format_args!("interrupted\n"));
+                }
+                _ => {
+                    let _ = i.write_error_output(format_args!("Error: {e}\n"));
+                }
+            },
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::completion::complete;
+    use super::*;
+
+    fn scripts(lines: &[&str]) -> Vec<String> {
+        let mut buffer = ScriptBuffer::default();
+        lines
+            .iter()
+            .filter_map(|line| buffer.push_line(line))
+            .collect()
+    }
+
+    /// Replays lines, reporting Ctrl-C where a line is None
+    struct Script {
+        lines: std::vec::IntoIter<Option<&'static str>>,
+        prompts: Vec<String>,
+        interrupted: bool,
+        history: Vec<String>,
+    }
+
+    impl Script {
+        fn new(lines: Vec<Option<&'static str>>) -> Script {
+            Script {
+                lines: lines.into_iter(),
+                prompts: Vec::new(),
+                interrupted: false,
+                history: Vec::new(),
             }
         }
     }
+
+    impl LineReader for Script {
+        fn read_line(&mut self, prompt: &str) -> Option<String> {
+            self.prompts.push(prompt.to_string());
+            let line = self.lines.next();
+            self.interrupted = matches!(line, Some(None));
+            line.flatten().map(str::to_string)
+        }
+
+        fn interrupted(&self) -> bool {
+            self.interrupted
+        }
+
+        fn add_history(&mut self, entry: &str) {
+            self.history.push(entry.to_string());
+        }
+    }
+
+    #[test]
+    fn repl_evaluates_complete_scripts() {
+        let mut interp = Interp::builder().build();
+        let output = tcl3::OutputBuffer::new();
+        let errors = tcl3::OutputBuffer::new();
+        interp.set_output(output.clone());
+        interp.set_error_output(errors.clone());
+
+        let mut reader = Script::new(vec![
+            Some("proc double {x} {"),
+            Some("    * $x 2"),
+            Some("}"),
+            Some("double 21"),
+            Some("set pending {"),
+            None,
+            Some("nope"),
+            Some("set done 1"),
+        ]);
+        let mut history = History::new(10, None);
+        assert_eq!(repl(&mut interp, &mut reader, &mut history), 0);
+
+        assert_eq!(output.take(), "42\n1\n");
+        assert_eq!(errors.take(), "Error: command not found: \"nope\"\n");
+        assert_eq!(interp.var("pending"), None);
+        assert_eq!(
+            reader.prompts,
+            [
+                "> ", "(1)>> ", "(1)>> ", "> ", "> ", "(1)>> ", "> ", "> ", "> "
+            ]
+        );
+        let entries = [
+            "proc double {x} {\n    * $x 2\n}",
+            "double 21",
+            "nope",
+            "set done 1",
+        ];
+        assert_eq!(reader.history, entries);
+        assert_eq!(history.entries, entries);
+    }
+
+    #[test]
+    fn repl_reports_interruptions_and_carries_on() {
+        let mut interp = Interp::builder().build();
+        let output = tcl3::OutputBuffer::new();
+        let errors = tcl3::OutputBuffer::new();
+        interp.set_output(output.clone());
+        interp.set_error_output(errors.clone());
+        let flag = interp.interrupt_handle();
+        interp
+            .register_closure("ctrl-c", move |_, _| {
+                flag.store(true, Ordering::SeqCst);
+                Ok(tcl3::Status::Ok)
+            })
+            .unwrap();
+
+        let mut reader = Script::new(vec![
+            Some("set n 0; while 1 { set n [+ $n 1]; if {$n == 5} { ctrl-c } }"),
+            Some("set n"),
+        ]);
+        let mut history = History::new(10, None);
+        assert_eq!(repl(&mut interp, &mut reader, &mut history), 0);
+
+        assert_eq!(errors.take(), "interrupted\n");
+        assert_eq!(output.take(), "5\n");
+    }
+
+    #[test]
+    fn repl_feeds_the_history_command() {
+        let mut interp = Interp::builder().build();
+        let output = tcl3::OutputBuffer::new();
+        interp.set_output(output.clone());
+
+        let mut reader = Script::new(vec![
+            Some("set n 0"),
+            Some("set n [+ $n 1]"),
+            Some("history redo"),
+            Some("history redo"),
+            Some("history"),
+        ]);
+        let mut history = History::new(10, None);
+        assert_eq!(repl(&mut interp, &mut reader, &mut history), 0);
+
+        assert_eq!(interp.var("n"), Some("3"));
+        let listing = "     1  set n 0\n     2  set n [+ $n 1]\n     3  set n [+ $n 1]\n     4  set n [+ $n 1]\n     5  history\n";
+        assert!(output.take().ends_with(listing));
+    }
+
+    fn names() -> Completions {
+        Completions {
+            commands: ["if", "info", "interp", "proc", "puts", "set"]
+                .map(String::from)
+                .to_vec(),
+            vars: ["count", "counter", "name"].map(String::from).to_vec(),
+            subcommands: Completions::from_interp(&Interp::builder().build()).subcommands,
+        }
+    }
+
+    fn completions(line: &str) -> Vec<String> {
+        complete(&names(), line, line.len())
+    }
+
+    #[test]
+    fn commands_complete_by_prefix() {
+        assert_eq!(completions("in"), ["info", "interp"]);
+        assert_eq!(completions("set a [pu"), ["set a [puts"]);
+        assert_eq!(completions("set a 1; pr"), ["set a 1; proc"]);
+        // Only where a command starts
+        assert_eq!(completions("set pu"), ["set pu"]);
+        // The text after the cursor stays put
+        assert_eq!(complete(&names(), "pu hello", 2), ["puts hello"]);
+    }
+
+    #[test]
+    fn variables_complete_after_dollar() {
+        assert_eq!(completions("puts $cou"), ["puts $count", "puts $counter"]);
+        assert_eq!(completions("puts \"$na"), ["puts \"$name"]);
+        assert_eq!(completions("$"), ["$count", "$counter", "$name"]);
+    }
+
+    #[test]
+    fn subcommands_complete_after_their_command() {
+        assert_eq!(completions("info v"), ["info vars"]);
+        assert_eq!(
+            completions("set x [interp e"),
+            [
+                "set x [interp eval",
+                "set x [interp exists",
+                "set x [interp expose"
+            ]
+        );
+        assert_eq!(
+            completions("trace "),
+            ["trace add", "trace info", "trace remove"]
+        );
+        assert_eq!(completions("set info v"), ["set info v"]);
+        // Every ensemble completes, from what it registered
+        assert_eq!(completions("dict u"), ["dict unset", "dict update"]);
+        assert_eq!(completions("string l"), ["string length"]);
+        assert_eq!(
+            completions("clock m"),
+            ["clock microseconds", "clock milliseconds"]
+        );
+        assert_eq!(completions("encoding s"), ["encoding system"]);
+        assert_eq!(completions("info s"), ["info script", "info subcommands"]);
+        assert_eq!(completions("interp h"), ["interp hide"]);
+        assert_eq!(completions("interp i"), ["interp invokehidden"]);
+    }
+
+    #[test]
+    fn nothing_to_complete_leaves_the_line() {
+        assert_eq!(completions("zzz"), ["zzz"]);
+        assert_eq!(completions("puts $zzz"), ["puts $zzz"]);
+        assert_eq!(completions("info zzz"), ["info zzz"]);
+    }
+
+    #[test]
+    fn script_comes_before_its_arguments() {
+        let split = |raw: &[&str], words: &[&str]| {
+            let raw: Vec<String> = raw.iter().map(|w| w.to_string()).collect();
+            let words = words.iter().map(|w| w.to_string()).collect();
+            split_script_args(&raw, words)
+        };
+        assert_eq!(
+            split(&["tcl", "s.tcl", "a", "b"], &["s.tcl", "a", "b"]),
+            (
+                Some("s.tcl".to_string()),
+                vec!["a".to_string(), "b".to_string()]
+            )
+        );
+        assert_eq!(
+            split(&["tcl", "-c", "x", "--", "a"], &["a"]),
+            (None, vec!["a".to_string()])
+        );
+        // A -- after the script is one of its arguments
+        assert_eq!(
+            split(&["tcl", "s.tcl", "--", "a"], &["s.tcl", "--", "a"]),
+            (
+                Some("s.tcl".to_string()),
+                vec!["--".to_string(), "a".to_string()]
+            )
+        );
+        assert_eq!(split(&["tcl", "-r"], &[]), (None, vec![]));
+    }
+
+    #[test]
+    fn commands_are_split_at_the_top_level() {
+        let script = "set a 1; set b [\n+ 1 2]\n\n# note\nproc p {} {\n  x\n}\nputs {";
+        let commands: Vec<_> = top_level_commands(script)
+            .into_iter()
+            .filter(|(_, command)| !command.trim().is_empty())
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                (1, "set a 1; "),
+                (1, "set b [\n+ 1 2]\n"),
+                (4, "# note\nproc p {} {\n  x\n}\n"),
+                (8, "puts {"),
+            ]
+        );
+    }
+
+    #[test]
+    fn echo_prints_non_empty_results() {
+        let echoed = |script: &str| {
+            let mut interp = Interp::builder().build();
+            let output = tcl3::OutputBuffer::new();
+            interp.set_output(output.clone());
+            let res = eval_echoed(&mut interp, script, None);
+            (output.take(), res.map_err(|_| interp.error_line()))
+        };
+        assert_eq!(echoed("set x 5"), ("5\n".to_string(), Ok(())));
+        assert_eq!(echoed("puts hi"), ("hi\n".to_string(), Ok(())));
+        assert_eq!(echoed(""), (String::new(), Ok(())));
+        assert_eq!(
+            echoed("set x 5; proc p {} {}\n+ $x 1\n\nset y {a b}"),
+            ("5\n6\na b\n".to_string(), Ok(()))
+        );
+        assert_eq!(echoed("set x 1\n\nnope"), ("1\n".to_string(), Err(Some(3))));
+    }
+
+    #[test]
+    fn exit_ends_the_repl() {
+        let mut interp = Interp::builder().build();
+        let mut reader = Script::new(vec![Some("catch {exit 3}"), Some("set never 1")]);
+        let mut history = History::new(10, None);
+        assert_eq!(repl(&mut interp, &mut reader, &mut history), 3);
+        assert_eq!(interp.var("never"), None);
+    }
+
+    #[test]
+    fn prompts_show_what_is_left_open() {
+        let mut interp = Interp::builder().build();
+        assert_eq!(prompt(&mut interp, None), "> ");
+        assert_eq!(prompt(&mut interp, Some(0)), ">> ");
+        assert_eq!(prompt(&mut interp, Some(2)), "(2)>> ");
+
+        let mut reader = Script::new(vec![
+            Some("proc p {} {"),
+            Some("  if 1 {"),
+            Some("    puts \\"),
+            Some("      [set x]"),
+            Some("  }"),
+            Some("}"),
+        ]);
+        let mut history = History::new(10, None);
+        assert_eq!(repl(&mut interp, &mut reader, &mut history), 0);
+        assert_eq!(
+            reader.prompts,
+            ["> ", "(1)>> ", "(2)>> ", "(2)>> ", "(2)>> ", "(1)>> ", "> "]
+        );
+    }
+
+    #[test]
+    fn scripts_can_set_their_own_prompts() {
+        let mut interp = Interp::builder().build();
+        interp.set_output(tcl3::OutputBuffer::new());
+        let mut reader = Script::new(vec![
+            Some("set n 0"),
+            Some("proc tcl_prompt1 {} { global n; set n [+ $n 1]; return \"$n% \" }"),
+            Some("set tcl_prompt2 {return ...}"),
+            Some("set x {"),
+            Some("}"),
+            Some("proc tcl_prompt1 {} { error broken }"),
+            Some("set x 1"),
+        ]);
+        let mut history = History::new(10, None);
+        assert_eq!(repl(&mut interp, &mut reader, &mut history), 0);
+        assert_eq!(
+            reader.prompts,
+            ["> ", "> ", "1% ", "2% ", "...", "3% ", "> ", "> "]
+        );
+    }
+
+    #[test]
+    fn history_path_prefers_the_flag() {
+        let path = |flag, env, home| history_path(flag, env, home);
+        assert_eq!(
+            path(Some("/tmp/h"), Some("/env/h"), Some("/home/u")),
+            Some(PathBuf::from("/tmp/h"))
+        );
+        assert_eq!(
+            path(None, Some("/env/h"), Some("/home/u")),
+            Some(PathBuf::from("/env/h"))
+        );
+        assert_eq!(
+            path(None, None, Some("/home/u")),
+            Some(PathBuf::from("/home/u/.tcl3_history"))
+        );
+        assert_eq!(path(None, Some(""), Some("/home/u")), None);
+        assert_eq!(path(None, None, None), None);
+    }
+
+    #[test]
+    fn rc_path_lets_the_environment_override_home() {
+        let path = |flag, env, home| rc_path(flag, env, home);
+        assert_eq!(
+            path(Some("/tmp/rc"), Some("/env/rc"), Some("/home/u")),
+            Some(PathBuf::from("/tmp/rc"))
+        );
+        assert_eq!(
+            path(None, Some("/env/rc"), Some("/home/u")),
+            Some(PathBuf::from("/env/rc"))
+        );
+        assert_eq!(
+            path(None, None, Some("/home/u")),
+            Some(PathBuf::from("/home/u/.tcl3rc"))
+        );
+        assert_eq!(path(None, Some(""), Some("/home/u")), None);
+        assert_eq!(path(None, None, None), None);
+    }
+
+    #[test]
+    fn missing_rc_files_are_skipped_quietly() {
+        let mut interp = Interp::builder().build();
+        let errors = tcl3::OutputBuffer::new();
+        interp.set_error_output(errors.clone());
+        let path = std::env::temp_dir().join(format!("tcl3-no-rc-{}", std::process::id()));
+        assert_eq!(load_rc(&mut interp, &path), None);
+        assert_eq!(errors.take(), "");
+    }
+
+    #[test]
+    fn a_failing_rc_file_still_reaches_the_prompt() {
+        let path = std::env::temp_dir().join(format!("tcl3-rc-{}", std::process::id()));
+        fs::write(&path, "proc double {x} { * $x 2 }\nset greeting hi\nnope\n").unwrap();
+        let mut interp = Interp::builder().build();
+        let output = tcl3::OutputBuffer::new();
+        let errors = tcl3::OutputBuffer::new();
+        interp.set_output(output.clone());
+        interp.set_error_output(errors.clone());
+        assert_eq!(load_rc(&mut interp, &path), None);
+        let name = path.display();
+        assert_eq!(
+            errors.take(),
+            format!(
+                "Error: {name}: command not found: \"nope\" (line 3)\n    \
+                 while executing \"nope\" (file \"{name}\" line 3)\n"
+            )
+        );
+
+        // What the file defined before failing is there at the prompt
+        let mut reader = Script::new(vec![Some("double 21"), Some("set greeting")]);
+        let mut history = History::new(10, None);
+        assert_eq!(repl(&mut interp, &mut reader, &mut history), 0);
+        assert_eq!(output.take(), "42\nhi\n");
+        assert_eq!(reader.prompts, ["> ", "> ", "> "]);
+
+        fs::write(&path, "exit 4\n").unwrap();
+        assert_eq!(load_rc(&mut interp, &path), Some(4));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn history_skips_repeats_and_keeps_the_newest() {
+        let mut history = History::new(3, None);
+        for entry in ["a\n", "a", "", "  \n", "b", "a", "c", "d"] {
+            history.add(entry);
+        }
+        assert_eq!(history.entries, ["a", "c", "d"]);
+
+        let mut off = History::new(0, None);
+        assert!(!off.add("a"));
+    }
+
+    #[test]
+    fn history_entries_survive_the_file() {
+        let entries = ["proc p {} {\n  puts \\n\n}", "a\\", "plain"];
+        for entry in entries {
+            let line = encode_history_entry(entry);
+            assert!(!line.contains('\n'));
+            assert_eq!(decode_history_entry(&line), entry);
+        }
+
+        let path = std::env::temp_dir().join(format!("tcl3-history-{}", std::process::id()));
+        let mut history = History::new(10, Some(path.clone()));
+        for entry in entries {
+            history.add(entry);
+        }
+        history.save().unwrap();
+        let loaded = History::load(2, Some(path.clone()));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.entries, entries[1..]);
+    }
+
+    #[test]
+    fn complete_lines_run_at_once() {
+        assert_eq!(
+            scripts(&["set a 1", "", "puts $a"]),
+            ["set a 1\n", "puts $a\n"]
+        );
+    }
+
+    #[test]
+    fn incomplete_lines_are_collected() {
+        let lines = [
+            "proc add {a b} {",
+            "",
+            "    + $a $b",
+            "}",
+            "puts \"one",
+            "two\"",
+            "set x [add 1 \\",
+            "2]",
+        ];
+        assert_eq!(
+            scripts(&lines),
+            [
+                "proc add {a b} {\n\n    + $a $b\n}\n",
+                "puts \"one\ntwo\"\n",
+                "set x [add 1 \\\n2]\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn cancel_discards_pending_lines() {
+        let mut buffer = ScriptBuffer::default();
+        assert_eq!(buffer.push_line("while 1 {"), None);
+        assert!(buffer.is_pending());
+        buffer.cancel();
+        assert!(!buffer.is_pending());
+        assert_eq!(buffer.push_line("set b 2").as_deref(), Some("set b 2\n"));
+    }
+
+    #[test]
+    fn errors_point_at_the_failing_word() {
+        let script = "set x 1\nproc f {} {\n\tnope 1 2\n}\nf\n";
+        let mut interp = Interp::builder().build();
+        assert!(interp.eval_str(script).is_err());
+        let (src, span) = (interp.error_source().unwrap(), interp.error_span().unwrap());
+        assert_eq!(
+            render_error(src, span, 0, "test.tcl: command not found: \"nope\"", false),
+            "Error: test.tcl: command not found: \"nope\"\n  \
+             2 | \tnope 1 2\n  \
+             \x20 | \t^^^^\n"
+        );
+
+        let script = "set a 1\n\nputs [string length $missing]\n";
+        assert!(interp.eval_str(script).is_err());
+        let (src, span) = (interp.error_source().unwrap(), interp.error_span().unwrap());
+        assert_eq!(&src[span.0..span.1], "$missing");
+        assert_eq!(
+            render_error(src, span, 0, "msg", true),
+            "\x1b[1;31mError:\x1b[0m msg\n\
+             \x1b[34m  3 |\x1b[0m puts [string length $missing]\n\
+             \x1b[34m    |\x1b[0m                     \x1b[1;31m^^^^^^^^\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn parse_errors_point_where_they_were_found() {
+        let script = "set a 1\nset b {x\n";
+        let mut interp = Interp::builder().build();
+        assert!(interp.eval_str(script).is_err());
+        let (src, span) = (interp.error_source().unwrap(), interp.error_span().unwrap());
+        assert_eq!(
+            render_error(src, span, 0, "missing close-brace", false),
+            "Error: missing close-brace\n  2 | set b {x\n    |       ^\n"
+        );
+    }
+
+    #[test]
+    fn meta_commands_look_around_the_interpreter() {
+        let mut interp = Interp::builder().build();
+        interp
+            .eval("set b 2; set a {x y}; set arr(k) v; proc double {x} {\n  * $x 2\n}")
+            .unwrap();
+        let print = |text: &str| Meta::Print(text.to_string());
+
+        assert_eq!(
+            meta_command(&mut interp, ":vars"),
+            print("a = x y\narr (array)\nb = 2\n")
+        );
+        assert_eq!(meta_command(&mut interp, " :procs "), print("double\n"));
+        assert_eq!(
+            meta_command(&mut interp, ":body double"),
+            print("  * $x 2\n")
+        );
+        assert_eq!(
+            meta_command(&mut interp, ":body set"),
+            print("\"set\" isn't a proc\n")
+        );
+
+        assert_eq!(meta_command(&mut interp, ":trace on"), print(""));
+        assert!(interp.trace_parser && interp.trace_eval);
+        assert_eq!(meta_command(&mut interp, ":trace off"), print(""));
+        assert!(!interp.trace_parser && !interp.trace_eval);
+
+        assert_eq!(meta_command(&mut interp, ":quit"), Meta::Quit);
+        assert_eq!(meta_command(&mut interp, ":nope"), print(META_HELP));
+        assert_eq!(meta_command(&mut interp, ":trace maybe"), print(META_HELP));
+    }
+
+    #[test]
+    fn meta_commands_are_only_whole_lines() {
+        let mut interp = Interp::builder().build();
+        let (output, errors) = (tcl3::OutputBuffer::new(), tcl3::OutputBuffer::new());
+        interp.set_output(output.clone());
+        interp.set_error_output(errors.clone());
+        let mut reader = Script::new(vec![
+            Some("set s {"),
+            Some(":procs"),
+            Some("}"),
+            Some(":procs"),
+            Some(":quit"),
+            Some("set after 1"),
+        ]);
+        let mut history = History::new(10, None);
+        assert_eq!(repl(&mut interp, &mut reader, &mut history), 0);
+
+        assert_eq!(output.take(), "\n:procs\n\n");
+        assert_eq!(errors.take(), "");
+        assert_eq!(interp.var("after"), None);
+        assert_eq!(history.entries, ["set s {\n:procs\n}", ":procs", ":quit"]);
+    }
+
+    /// Parses a token dump, checking that it is valid JSON
+    fn parse_tokens(json: &str) -> Vec<serde_json::Value> {
+        serde_json::from_str(json).unwrap_or_else(|e| panic!("{e}: {json}"))
+    }
+
+    #[test]
+    fn tokens_dump_as_json() {
+        let (json, error) = dump_tokens("set a \"x\\\"y\"\n\tputs [b]$c\n");
+        assert_eq!(error, None);
+        let tokens = parse_tokens(&json);
+        let fields: Vec<_> = tokens
+            .iter()
+            .map(|token| {
+                (
+                    token["type"].as_str().unwrap(),
+                    token["begin"].as_u64().unwrap(),
+                    token["end"].as_u64().unwrap(),
+                    token["line"].as_u64().unwrap(),
+                    token["column"].as_u64().unwrap(),
+                    token["body"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("TK_ESC", 0, 3, 1, 1, "set"),
+                ("TK_SEP", 3, 4, 1, 4, " "),
+                ("TK_ESC", 4, 5, 1, 5, "a"),
+                ("TK_SEP", 5, 6, 1, 6, " "),
+                ("TK_ESC", 7, 11, 1, 8, "x\\\"y"),
+                ("TK_EOL", 12, 14, 1, 13, "\n\t"),
+                ("TK_ESC", 14, 18, 2, 2, "puts"),
+                ("TK_SEP", 18, 19, 2, 6, " "),
+                ("TK_CMD", 20, 21, 2, 8, "b"),
+                ("TK_VAR", 23, 24, 2, 11, "c"),
+                ("TK_EOL", 24, 25, 2, 12, "\n"),
+            ]
+        );
+
+        let (json, error) = dump_tokens("");
+        assert_eq!((parse_tokens(&json), error), (vec![], None));
+        let (json, error) = dump_tokens("a {");
+        let tokens = parse_tokens(&json);
+        assert_eq!(tokens.last().unwrap()["body"], " ");
+        assert_eq!(error.unwrap().message, "missing close-brace");
+    }
+
+    #[test]
+    fn json_strings_escape_what_they_must() {
+        for s in ["plain é", "\"\\\n\r\t\x01\x1b"] {
+            let parsed: String = serde_json::from_str(&json_string(s)).unwrap();
+            assert_eq!(parsed, s);
+        }
+        // Control characters can't appear raw in JSON
+        assert!(!json_string("\x01\x1b").chars().any(char::is_control));
+    }
+
+    #[test]
+    fn timings_fit_on_a_line() {
+        let stats = EvalStats {
+            commands: 42,
+            max_depth: 3,
+        };
+        assert_eq!(
+            format_timing("-c", Duration::from_micros(1500), &stats),
+            "time: -c: 1.500 ms, 42 commands, max depth 3"
+        );
+    }
 }
@@ -0,0 +1,161 @@
+//! Looking at a script's tokens: a table of them to read, and a check that
+//! together with the delimiters and comments between them they make up the
+//! whole script, which is how the original text can be rebuilt from spans.
+
+use crate::{Parser, Token, TokenInfo};
+
+/// Characters the parser steps over without putting them in a token: the
+/// braces, quotes and brackets around words, and the `$` of a variable
+const DELIMITERS: &[char] = &['{', '}', '"', '[', ']', '$'];
+
+/// Where the tokens of a script don't account for its text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoverageGap {
+    /// Text between two tokens, as a byte range, that is neither
+    /// delimiters nor a comment
+    Uncovered(usize, usize),
+    /// A token whose span starts before the previous token's ended
+    Overlap(usize, usize),
+}
+
+impl std::fmt::Display for CoverageGap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoverageGap::Uncovered(start, end) => {
+                write!(f, "bytes {start}..{end} aren't covered by a token")
+            }
+            CoverageGap::Overlap(start, end) => {
+                write!(f, "token at {start}..{end} overlaps the one before")
+            }
+        }
+    }
+}
+
+/// The tokens of a script up to its end or a parse error. The end of line
+/// the parser adds when a script doesn't end with one repeats the span of
+/// the token before it, and is left out.
+fn spanned_tokens(script: &str) -> Vec<(TokenInfo, &str)> {
+    let mut tokens: Vec<(TokenInfo, &str)> = Vec::new();
+    for (info, body) in Parser::tokens(script) {
+        let repeated = tokens
+            .last()
+            .is_some_and(|(last, _)| last.span == info.span);
+        if !(info.token == Token::Eol && repeated) {
+            tokens.push((info, body));
+        }
+    }
+    tokens
+}
+
+/// Formats the tokens of a script as a table, one token to a line, with
+/// where each starts, its byte range, its type and its text:
+///
+/// ```text
+///  LINE:COL   BEGIN..END    TYPE BODY
+///     1:1         0..3      ESC  "set"
+/// ```
+///
+/// A parse error is reported on a last line of its own.
+pub fn dump_tokens(script: &str) -> String {
+    let mut out = String::from(" LINE:COL   BEGIN..END    TYPE BODY\n");
+    for (info, body) in spanned_tokens(script) {
+        let (begin, end) = info.span;
+        let line_start = script[..begin].rfind('\n').map_or(0, |i| i + 1);
+        let column = script[line_start..begin].chars().count() + 1;
+        let kind = format!("{:?}", info.token).to_uppercase();
+        out += &format!(
+            "{:>5}:{column:<4} {begin:>6}..{end:<6} {kind:<4} {body:?}\n",
+            info.line
+        );
+    }
+
+    let mut tokens = Parser::tokens(script);
+    tokens.by_ref().for_each(drop);
+    if let Some(e) = tokens.error() {
+        out += &format!("error: {} (line {})\n", e.message, e.line);
+    }
+    out
+}
+
+/// Checks that a script's tokens cover it: that they come in order without
+/// overlapping, and that the text between them is only delimiters and
+/// comments. The script can then be rebuilt exactly from the tokens' spans
+/// and what they skip. Parsing stops at an error, and so does the check.
+pub fn verify_coverage(script: &str) -> Result<(), CoverageGap> {
+    let tokens = spanned_tokens(script);
+    let mut pos = 0;
+    for (info, _) in &tokens {
+        let (begin, end) = info.span;
+        if begin < pos {
+            return Err(CoverageGap::Overlap(begin, end));
+        }
+        check_skipped(script, pos, begin)?;
+        pos = end;
+    }
+
+    let mut rest = Parser::tokens(script);
+    rest.by_ref().for_each(drop);
+    if rest.error().is_none() {
+        check_skipped(script, pos, script.len())?;
+    }
+    Ok(())
+}
+
+/// Checks that the text from start to end is delimiters and comments
+fn check_skipped(script: &str, start: usize, end: usize) -> Result<(), CoverageGap> {
+    let mut chars = script[start..end].char_indices();
+    while let Some((i, c)) = chars.next() {
+        if DELIMITERS.contains(&c) {
+            continue;
+        }
+        if c != '#' {
+            return Err(CoverageGap::Uncovered(start + i, end));
+        }
+        // A comment runs to the end of its line, or further after a
+        // backslash-newline
+        while let Some((_, c)) = chars.next() {
+            match c {
+                '\n' => break,
+                '\\' => {
+                    chars.next();
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_dump_as_a_table() {
+        assert_eq!(
+            dump_tokens("set a {b c}\nputs $a"),
+            " LINE:COL   BEGIN..END    TYPE BODY
+    1:1         0..3      ESC  \"set\"
+    1:4         3..4      SEP  \" \"
+    1:5         4..5      ESC  \"a\"
+    1:6         5..6      SEP  \" \"
+    1:8         7..10     STR  \"b c\"
+    1:12       11..12     EOL  \"\\n\"
+    2:1        12..16     ESC  \"puts\"
+    2:5        16..17     SEP  \" \"
+    2:7        18..19     VAR  \"a\"
+"
+        );
+        assert!(dump_tokens("a {").ends_with("error: missing close-brace (line 1)\n"));
+    }
+
+    #[test]
+    fn gaps_must_be_delimiters_or_comments() {
+        assert_eq!(check_skipped("{[\"$", 0, 4), Ok(()));
+        assert_eq!(check_skipped("x # a\\\nb\n", 2, 9), Ok(()));
+        assert_eq!(
+            check_skipped("{x}", 0, 3),
+            Err(CoverageGap::Uncovered(1, 3))
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! JavaScript bindings through wasm-bindgen, for running the interpreter in
+//! a browser. Build with
+//! `cargo build --lib --target wasm32-unknown-unknown --no-default-features --features io,regex,wasm`.
+//!
+//! There is no stdout in a browser, so `puts` writes into a buffer that
+//! the page reads back with `output()` after each eval.
+
+use crate::{Interp, OutputBuffer};
+use wasm_bindgen::prelude::*;
+
+/// An interpreter with the commands that make sense in a browser: no
+/// process commands, and output kept for the page to show
+#[wasm_bindgen]
+pub struct WasmInterp {
+    interp: Interp,
+    output: OutputBuffer,
+}
+
+#[wasm_bindgen]
+impl WasmInterp {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmInterp {
+        let mut interp = Interp::builder().with_process(false).build();
+        let output = OutputBuffer::new();
+        interp.set_output(output.clone());
+        interp.set_error_output(output.clone());
+        WasmInterp { interp, output }
+    }
+
+    /// Evaluates a script and returns its result. An error is thrown as a
+    /// JavaScript Error carrying the Tcl error message.
+    pub fn eval(&mut self, script: &str) -> Result<String, JsError> {
+        self.interp
+            .eval_str(script)
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Returns what the scripts have written since the last call
+    pub fn output(&self) -> String {
+        self.output.take()
+    }
+}
+
+impl Default for WasmInterp {
+    fn default() -> WasmInterp {
+        WasmInterp::new()
+    }
+}
@@ -0,0 +1,230 @@
+//! Runs the command line interpreter the way a shell would.
+
+#![cfg(feature = "repl")]
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn tcl(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tcl"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[cfg(feature = "io")]
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn inline_scripts_run_in_order() {
+    let output = tcl(&[
+        "-c",
+        "set x 2",
+        "--command",
+        "puts [+ $x 1]",
+        "-c",
+        "puts done",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "3\ndone\n");
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn inline_scripts_run_after_files() {
+    let file = std::env::temp_dir().join(format!("tcl3-cli-{}.tcl", std::process::id()));
+    std::fs::write(&file, "set x from-file\n").unwrap();
+    let output = tcl(&["-c", "puts $x", file.to_str().unwrap()]);
+    std::fs::remove_file(&file).unwrap();
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "from-file\n");
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn scripts_know_where_they_are() {
+    let dir = std::env::temp_dir().join(format!("tcl3-cli-tree-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    let main = dir.join("main.tcl");
+    std::fs::write(&main, "puts [info script]\nsource lib/helpers.tcl\n").unwrap();
+    std::fs::write(dir.join("lib/helpers.tcl"), "puts [info script]\n").unwrap();
+
+    // Run from elsewhere, the relative source still finds lib/
+    let output = Command::new(env!("CARGO_BIN_EXE_tcl"))
+        .arg(&main)
+        .current_dir(dir.parent().unwrap())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let helpers = dir.join("lib").join("helpers.tcl");
+    assert_eq!(
+        stdout(&output),
+        format!("{}\n{}\n", main.display(), helpers.display())
+    );
+
+    let output = tcl(&["-c", "puts <[info script]>"]);
+    assert_eq!(stdout(&output), "<>\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failing_inline_scripts_exit_nonzero() {
+    let output = tcl(&[
+        "-c",
+        "set a 1",
+        "-c",
+        "set a 2\nnope",
+        "-c",
+        "error unreachable",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("Error: -c: command not found: \"nope\" (line 2)\n"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("unreachable"));
+}
+
+fn tcl_with_stdin(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tcl"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn scripts_are_read_from_stdin() {
+    let output = tcl_with_stdin(&[], "set x 4\nputs [* $x $x]\n");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "16\n");
+
+    let output = tcl_with_stdin(&["-", "a", "b"], "puts \"$argv0 $argc\"\n");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "- 2\n");
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn scripts_prompt_and_read_lines() {
+    let script = "puts -nonewline stdout {> }; flush stdout; gets stdin name; puts \"hi $name\"";
+    let output = tcl_with_stdin(&["-c", script], "bob\n");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "> hi bob\n");
+}
+
+#[test]
+fn stdin_errors_name_stdin() {
+    let output = tcl_with_stdin(&["-"], "set a 1\nnope\n");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("Error: <stdin>: command not found: \"nope\" (line 2)\n"),
+        "{stderr}"
+    );
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn scripts_get_their_arguments() {
+    let file = std::env::temp_dir().join(format!("tcl3-cli-args-{}.tcl", std::process::id()));
+    std::fs::write(&file, "puts $argc; puts $argv0; puts $argv\n").unwrap();
+    let path = file.to_str().unwrap();
+    let words = ["release", "x86 64", "{", "a}b", "-v", "--", ""];
+    let mut args = vec![path];
+    args.extend(words);
+    let output = tcl(&args);
+    std::fs::remove_file(&file).unwrap();
+    assert!(output.status.success());
+
+    let lines: Vec<&str> = stdout(&output).lines().collect();
+    assert_eq!(lines[..2], ["7", path]);
+    assert_eq!(tcl3::parse_list(lines[2]).unwrap(), words);
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn arguments_after_a_separator_have_no_script() {
+    let output = tcl(&["-c", "puts \"$argc $argv\"", "--", "a b", "c"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "2 {a b} c\n");
+
+    let output = tcl(&["-c", "puts $argc"]);
+    assert_eq!(stdout(&output), "0\n");
+}
+
+#[test]
+#[cfg(feature = "process")]
+fn exit_status_reaches_the_shell() {
+    let output = tcl(&["-c", "catch {exit 3}; error unreachable"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stderr.is_empty());
+
+    assert_eq!(tcl(&["-c", "exit"]).status.code(), Some(0));
+}
+
+#[test]
+fn trace_eval_goes_to_stderr() {
+    let output = tcl(&["--trace-eval", "-c", "proc f {} {set a 1}; f"]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.lines().skip(2).collect::<Vec<_>>(),
+        ["1> f", "  2> set a 1", "  2< ok 1", "1< ok 1"]
+    );
+}
+
+#[test]
+fn dump_tokens_does_not_evaluate() {
+    let output = tcl_with_stdin(&["--dump-tokens"], "exit 3\n");
+    assert!(output.status.success());
+    let tokens: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(tokens.len(), 4);
+    assert_eq!(
+        tokens[2],
+        serde_json::json!({
+            "type": "TK_ESC", "begin": 5, "end": 6, "line": 1, "column": 6, "body": "3"
+        })
+    );
+
+    let output = tcl(&["--dump-tokens", "-c", "a", "-c", "b"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn failed_tests_fail_the_run() {
+    let output = tcl(&["-c", "test t-1 {} {set a 1} 1"]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = tcl(&["-c", "test t-1 {} {set a 1} 2", "-c", "set after 1"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn failures_have_their_own_status() {
+    let output = tcl(&["-c", "set a {"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = tcl(&["/nonexistent/script.tcl"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("Error: couldn't read \"/nonexistent/script.tcl\": "),
+        "{stderr}"
+    );
+}
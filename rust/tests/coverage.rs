@@ -0,0 +1,57 @@
+//! Checks that the parser's tokens cover every script in the repository,
+//! and every combination of a set of tricky pieces of syntax, so that a
+//! parser change can't drop or double-count part of a script unnoticed.
+
+use std::path::Path;
+use tcl3::tokens::verify_coverage;
+
+const PIECES: &[&str] = &[
+    "set", " ", "\n", ";", "\t", "{a b}", "{", "}", "\"q $v\"", "\"", "[cmd x]", "[", "]", "$v",
+    "${v w}", "$a(i)", "$a($j)", "# note\n", "#", "\\\n", "\\", "\\{", "é", "x\\ y", "$", "\r\n",
+];
+
+fn check(name: &str, script: &str) {
+    if let Err(gap) = verify_coverage(script) {
+        panic!(
+            "{name}: {gap}\n{script:?}\n{}",
+            tcl3::tokens::dump_tokens(script)
+        );
+    }
+}
+
+#[test]
+fn repository_scripts_are_covered() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let dirs = [
+        root.join("../test"),
+        root.join("tests/lang"),
+        root.join("fuzz/regressions"),
+    ];
+    let mut checked = 0;
+    for dir in dirs {
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "tcl") {
+                check(
+                    &path.display().to_string(),
+                    &std::fs::read_to_string(&path).unwrap(),
+                );
+                checked += 1;
+            }
+        }
+    }
+    assert!(checked > 50);
+}
+
+#[test]
+fn combinations_of_syntax_are_covered() {
+    for a in PIECES {
+        for b in PIECES {
+            for c in PIECES {
+                let script = format!("{a}{b}{c}");
+                check("combination", &script);
+                check("command", &format!("cmd {script} end"));
+            }
+        }
+    }
+}
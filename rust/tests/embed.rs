@@ -0,0 +1,1959 @@
+//! Drives the interpreter purely through the library, the way an embedding
+//! application would.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tcl3::ensemble::Ensemble;
+use tcl3::{
+    EvalEvent, EvalStats, Interp, Parser, SavedValue, Status, TclError, TclErrorKind, TclValue,
+    Token,
+};
+
+fn cmd_double(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let n: i64 = argv[1].as_str().parse().unwrap();
+    interp.set_result((n * 2).to_string());
+    Ok(Status::Ok)
+}
+
+#[test]
+fn eval_a_script_and_read_the_result() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp.register_command("double", cmd_double, None).unwrap();
+
+    let status = interp
+        .eval("proc square {x} {* $x $x}; set n [square 4]; double $n")
+        .unwrap();
+    assert_eq!(status, Status::Ok);
+    assert_eq!(interp.take_result().as_str(), "32");
+    assert_eq!(interp.get_var("n").unwrap().as_str(), "16");
+}
+
+#[test]
+fn eval_str_returns_the_last_result() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+
+    assert_eq!(interp.eval_str("set x 5").unwrap(), "5");
+    #[cfg(feature = "io")]
+    {
+        interp.register_io_commands();
+        assert_eq!(interp.eval_str("puts hi").unwrap(), "");
+    }
+    assert_eq!(interp.eval_str("").unwrap(), "");
+    let e = interp.eval_str("error {went wrong}").unwrap_err();
+    assert_eq!(e.to_string(), "went wrong");
+}
+
+#[test]
+fn variables_are_shared_with_the_script() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+
+    interp.set_global("limit", "10").unwrap();
+    interp.set_global("color(fg)", "black").unwrap();
+    interp
+        .eval(
+            "proc raise {} {global limit; set limit [* $limit 2]; set local 1}
+            raise
+            set color(bg) white",
+        )
+        .unwrap();
+
+    assert_eq!(interp.var("limit"), Some("20"));
+    assert_eq!(interp.var("local"), None);
+    assert_eq!(interp.var("color(bg)"), Some("white"));
+    assert_eq!(interp.var("color"), None);
+    assert_eq!(interp.var("limit(x)"), None);
+
+    let mut elements: Vec<&str> = interp.array_names("color").unwrap().collect();
+    elements.sort();
+    assert_eq!(elements, ["bg", "fg"]);
+    assert!(interp.array_names("limit").is_none());
+
+    let mut names: Vec<&str> = interp.var_names().collect();
+    names.sort();
+    assert_eq!(names, ["color", "limit"]);
+}
+
+#[test]
+fn call_passes_words_through_untouched() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp
+        .eval("proc echo {a b} {return \"$a|$b\"}; proc stop {} {break}")
+        .unwrap();
+
+    assert_eq!(
+        interp.call(&["echo", "[exit]", "{ $x"]).unwrap(),
+        "[exit]|{ $x"
+    );
+    assert_eq!(interp.call(&["set", "v", "a b"]).unwrap(), "a b");
+    assert_eq!(interp.var("v"), Some("a b"));
+
+    let e = interp.call(&["no_such_command", "x"]).unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::CommandNotFound);
+    assert_eq!(e.to_string(), "command not found: \"no_such_command\"");
+
+    let e = interp.call(&["stop"]).unwrap_err();
+    assert_eq!(e.to_string(), "invoked \"break\" outside of a loop");
+    assert_eq!(
+        interp.call(&["echo", "only"]).unwrap_err().kind,
+        TclErrorKind::Arity
+    );
+    assert_eq!(interp.call(&[]).unwrap_err().kind, TclErrorKind::Arity);
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn safe_interps_only_compute() {
+    let mut interp = tcl3::InterpBuilder::safe().build();
+
+    let unsafe_cmds = [
+        "puts hi",
+        "pid",
+        "after 1",
+        "open f",
+        "open {|echo hi}",
+        "source f.tcl",
+        "socket localhost 80",
+        "coroutine c yield",
+    ];
+    for cmd in unsafe_cmds {
+        let e = interp.eval(cmd).unwrap_err();
+        assert_eq!(e.kind, TclErrorKind::CommandNotFound, "{cmd}");
+    }
+    // They're left out by the preset, not missing altogether
+    #[cfg(all(feature = "io", feature = "process", feature = "coroutine"))]
+    {
+        let full = Interp::builder().build();
+        for cmd in unsafe_cmds {
+            let name = cmd.split(' ').next().unwrap();
+            assert!(full.get_command(name).is_some(), "{name}");
+        }
+    }
+
+    let script = "proc fib {n} {
+            if {< $n 2} {return $n}
+            + [fib [- $n 1]] [fib [- $n 2]]
+        }
+        set i 0
+        set out {}
+        while {< $i 5} {
+            set out \"$out[fib $i] \"
+            set i [+ $i 1]
+        }
+        regsub -all { } $out ,";
+    assert_eq!(interp.eval_str(script).unwrap(), "0,1,1,2,3,");
+
+    // Child interpreters are no more capable than their parent
+    let e = interp
+        .eval("interp create child; interp eval child pid")
+        .unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::CommandNotFound);
+}
+
+#[test]
+#[cfg(feature = "process")]
+fn builders_set_limits() {
+    let mut interp = Interp::builder()
+        .with_io(false)
+        .max_nesting_depth(20)
+        .eval_limit(Some(1000))
+        .build();
+    assert_eq!(interp.max_nesting_depth(), 20);
+    assert_eq!(interp.eval_limit(), Some(1000));
+    assert_eq!(
+        interp.eval_str("pid").unwrap(),
+        std::process::id().to_string()
+    );
+
+    let e = interp.eval("proc f {} {f}; f").unwrap_err();
+    assert!(e.to_string().contains("too many nested"), "{e}");
+    let e = interp.eval("while 1 {}").unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::LimitExceeded);
+}
+
+#[test]
+fn step_limits_end_the_whole_evaluation() {
+    let mut interp = Interp::builder().with_io(false).build();
+    interp.eval("interp steplimit {} 50").unwrap();
+    let e = interp
+        .eval("set n 0; while 1 {catch {error inner}; set n [+ $n 1]}; set after 1")
+        .unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::LimitExceeded);
+    assert_eq!(e.to_string(), "evaluation limit exceeded after 50 steps");
+    assert_eq!(interp.var("after"), None);
+
+    // The count starts over with each top-level eval
+    interp.eval("set n 0").unwrap();
+    assert_eq!(interp.var("n"), Some("0"));
+}
+
+#[test]
+#[cfg(feature = "process")]
+fn after_blocks_for_at_least_the_delay() {
+    let mut interp = Interp::builder().with_io(false).build();
+    let start = std::time::Instant::now();
+    interp.eval("after 50").unwrap();
+    assert!(start.elapsed() >= std::time::Duration::from_millis(50));
+}
+
+#[test]
+#[cfg(not(any(feature = "io", feature = "process")))]
+fn command_groups_can_be_compiled_out() {
+    let mut interp = Interp::builder().build();
+    for cmd in ["puts hi", "pid", "after 1"] {
+        let e = interp.eval(cmd).unwrap_err();
+        assert_eq!(e.kind, TclErrorKind::CommandNotFound, "{cmd}");
+    }
+    assert_eq!(interp.eval_str("set x [+ 1 2]").unwrap(), "3");
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn output_can_be_captured() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp.register_io_commands();
+    let output = tcl3::OutputBuffer::new();
+    interp.set_output(output.clone());
+
+    interp
+        .eval("puts hello; interp create child; interp eval child {puts {from child}}")
+        .unwrap();
+    assert_eq!(output.take(), "hello\nfrom child\n");
+    assert_eq!(output.take(), "");
+
+    interp.eval("puts again").unwrap();
+    assert_eq!(output.take(), "again\n");
+}
+
+#[test]
+#[cfg(all(feature = "wasm", feature = "io"))]
+fn wasm_interp_collects_output() {
+    let mut interp = tcl3::wasm::WasmInterp::new();
+    assert_eq!(
+        interp
+            .eval("puts hi; puts stderr oops; expr {6 * 7}")
+            .unwrap(),
+        "42"
+    );
+    assert_eq!(interp.output(), "hi\noops\n");
+    assert_eq!(interp.output(), "");
+    // Commands that touch the process are left out
+    interp.eval("set n [info commands pid]").unwrap();
+    assert_eq!(interp.eval("set n").unwrap(), "");
+}
+
+#[cfg(feature = "io")]
+struct Closed;
+
+#[cfg(feature = "io")]
+impl std::io::Write for Closed {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn output_and_errors_go_to_their_writers() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp.register_io_commands();
+    let (out, err) = (tcl3::OutputBuffer::new(), tcl3::OutputBuffer::new());
+    interp.set_output(out.clone());
+    interp.set_error_output(err.clone());
+
+    interp
+        .eval("puts -nonewline a; puts -nonewline stdout b; puts c; puts stderr oops; puts -nonewline")
+        .unwrap();
+    assert_eq!(out.take(), "abc\n-nonewline\n");
+    assert_eq!(err.take(), "oops\n");
+
+    let e = interp.eval("puts nowhere text").unwrap_err();
+    assert_eq!(e.to_string(), "can not find channel named \"nowhere\"");
+    assert_eq!(
+        interp.eval("puts a b c d").unwrap_err().kind,
+        TclErrorKind::Arity
+    );
+
+    interp.set_output(Closed);
+    let e = interp.eval("puts lost").unwrap_err();
+    assert!(e.to_string().starts_with("error writing \"stdout\""), "{e}");
+}
+
+/// Records what was written and when it was flushed
+#[cfg(feature = "io")]
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<String>>>);
+
+#[cfg(feature = "io")]
+impl std::io::Write for Recorder {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let text = String::from_utf8_lossy(buf).into_owned();
+        self.0.lock().unwrap().push(text);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.lock().unwrap().push("<flush>".to_string());
+        Ok(())
+    }
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn prompts_are_flushed_before_reading_input() {
+    let mut interp = Interp::builder().build();
+    let (out, err) = (Recorder::default(), Recorder::default());
+    interp.set_output(out.clone());
+    interp.set_error_output(err.clone());
+    interp.set_input(std::io::Cursor::new("alice\r\nbob\n"));
+
+    interp
+        .eval("puts -nonewline stdout {> }; flush stdout; set n [gets stdin name]")
+        .unwrap();
+    assert_eq!(out.0.lock().unwrap().concat(), "> <flush>");
+    assert_eq!(interp.get_var("name").unwrap(), "alice");
+    assert_eq!(interp.get_var("n").unwrap(), "5");
+
+    assert_eq!(interp.eval_str("gets stdin").unwrap(), "bob");
+    assert_eq!(interp.eval_str("gets stdin line").unwrap(), "-1");
+    assert_eq!(interp.get_var("line").unwrap(), "");
+
+    interp
+        .eval("puts stderr oops; flush stderr; flush")
+        .unwrap();
+    assert_eq!(err.0.lock().unwrap().concat(), "oops\n<flush><flush>");
+    assert_eq!(out.0.lock().unwrap().concat(), "> <flush><flush>");
+
+    for cmd in ["flush nowhere", "gets nowhere"] {
+        let e = interp.eval(cmd).unwrap_err();
+        assert_eq!(e.to_string(), "can not find channel named \"nowhere\"");
+    }
+    assert_eq!(
+        interp.eval("flush a b").unwrap_err().kind,
+        TclErrorKind::Arity
+    );
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn files_can_be_reread_from_the_middle() {
+    let path = std::env::temp_dir().join(format!("tcl3-seek-{}.txt", std::process::id()));
+    let mut interp = Interp::builder().build();
+    interp.set_var("path", path.to_str().unwrap()).unwrap();
+
+    interp
+        .eval(
+            "set f [open $path w]; puts $f alpha; puts $f beta; puts -nonewline $f gamma; close $f",
+        )
+        .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "alpha\nbeta\ngamma"
+    );
+
+    // The first gets reads the whole file into the channel's buffer, but
+    // tell still reports where the script is
+    interp.eval("set f [open $path]").unwrap();
+    assert_eq!(interp.eval_str("tell $f").unwrap(), "0");
+    assert_eq!(interp.eval_str("gets $f").unwrap(), "alpha");
+    assert_eq!(interp.eval_str("tell $f").unwrap(), "6");
+    assert_eq!(interp.eval_str("gets $f").unwrap(), "beta");
+    assert_eq!(interp.eval_str("seek $f 6; gets $f").unwrap(), "beta");
+    assert_eq!(
+        interp.eval_str("seek $f -11 current; tell $f").unwrap(),
+        "0"
+    );
+    assert_eq!(interp.eval_str("seek $f -5 end; gets $f").unwrap(), "gamma");
+
+    // eof is about the last read, not the position
+    assert_eq!(interp.eval_str("eof $f").unwrap(), "1");
+    assert_eq!(interp.eval_str("seek $f 0 end; eof $f").unwrap(), "0");
+    assert_eq!(interp.eval_str("gets $f line").unwrap(), "-1");
+    assert_eq!(interp.eval_str("eof $f").unwrap(), "1");
+
+    let e = interp.eval("puts $f more").unwrap_err();
+    assert!(e.to_string().ends_with("wasn't opened for writing"), "{e}");
+    interp.eval("close $f").unwrap();
+    let e = interp.eval("gets $f").unwrap_err();
+    assert!(
+        e.to_string().starts_with("can not find channel named"),
+        "{e}"
+    );
+
+    // Writing after reading lands where the script had read up to
+    interp
+        .eval("set f [open $path r+]; gets $f; puts $f BETA; seek $f 0; gets $f; set line [gets $f]; close $f")
+        .unwrap();
+    assert_eq!(interp.get_var("line").unwrap(), "BETA");
+    std::fs::remove_file(&path).unwrap();
+
+    let e = interp.eval("seek stdin 0").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "error during seek on \"stdin\": invalid argument"
+    );
+    assert_eq!(interp.eval_str("tell stdin").unwrap(), "-1");
+    let e = interp.eval("open $path").unwrap_err();
+    assert!(e.to_string().starts_with("couldn't open"), "{e}");
+    assert!(
+        e.to_string().ends_with(": no such file or directory"),
+        "{e}"
+    );
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn sockets_exchange_lines_over_loopback() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let (listening, ready) = std::sync::mpsc::channel();
+    let server = std::thread::spawn(move || {
+        let mut interp = Interp::builder().build();
+        interp.set_var("port", port.to_string()).unwrap();
+        interp
+            .eval(
+                "proc serve {chan addr port} {
+                    global heard peer
+                    set heard [gets $chan]
+                    set peer $addr
+                    puts $chan \"echo $heard\"
+                    close $chan
+                }
+                set s [socket -server serve -myaddr 127.0.0.1 $port]",
+            )
+            .unwrap();
+        listening.send(()).unwrap();
+        interp.eval("socket -accept $s 1; close $s").unwrap();
+        (
+            interp.get_var("heard").unwrap().to_string(),
+            interp.get_var("peer").unwrap().to_string(),
+        )
+    });
+    ready.recv().unwrap();
+
+    let mut interp = Interp::builder().build();
+    interp.set_var("port", port.to_string()).unwrap();
+    let reply = interp
+        .eval_str("set s [socket 127.0.0.1 $port]; puts $s hello; set reply [gets $s]; close $s; set reply")
+        .unwrap();
+    assert_eq!(reply, "echo hello");
+    assert_eq!(
+        server.join().unwrap(),
+        ("hello".to_string(), "127.0.0.1".to_string())
+    );
+
+    // Nothing listens on the port once the server is closed
+    let e = interp.eval("socket 127.0.0.1 $port").unwrap_err();
+    assert_eq!(e.to_string(), "couldn't open socket: connection refused");
+    let e = interp.eval("socket -accept nosuch").unwrap_err();
+    assert_eq!(e.to_string(), "can not find channel named \"nosuch\"");
+}
+
+#[test]
+fn snapshots_roll_back_script_state() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    let calls = Arc::new(Mutex::new(0));
+    let counter = Arc::clone(&calls);
+    interp
+        .register_closure("tick", move |_, _| {
+            *counter.lock().unwrap() += 1;
+            Ok(Status::Ok)
+        })
+        .unwrap();
+
+    interp
+        .eval("set level 1; set scores(alice) 10; proc bonus {n} {+ $n 5}")
+        .unwrap();
+    let state = interp.snapshot();
+
+    interp
+        .eval("set level 2; unset scores; set extra 1; proc bonus {n} {* $n 100}; proc added {} {}")
+        .unwrap();
+    assert_eq!(interp.eval_str("bonus 1").unwrap(), "100");
+
+    interp.restore(&state);
+    assert_eq!(interp.var("level"), Some("1"));
+    assert_eq!(interp.var("scores(alice)"), Some("10"));
+    assert_eq!(interp.var("extra"), None);
+    assert_eq!(interp.eval_str("bonus 1").unwrap(), "6");
+    assert!(interp.eval("added").is_err());
+    interp.eval("tick").unwrap();
+
+    // Resetting keeps Rust commands and builtins
+    interp.reset();
+    assert_eq!(interp.var_names().count(), 0);
+    assert!(interp.eval("bonus 1").is_err());
+    interp.eval("tick; set x 1").unwrap();
+    assert_eq!(*calls.lock().unwrap(), 2);
+
+    // A snapshot can be restored more than once
+    interp.restore(&state);
+    assert_eq!(interp.eval_str("bonus $level").unwrap(), "6");
+}
+
+#[test]
+fn saved_state_round_trips_into_a_new_interp() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp
+        .eval(
+            "set name {Ada L}; set inventory(sword) 1; set inventory(gold) 30
+            proc greet {who {greeting Hello}} {return \"$greeting, $who\"}",
+        )
+        .unwrap();
+    let state = interp.save_state();
+    assert_eq!(
+        state.vars["inventory"],
+        SavedValue::Array(BTreeMap::from([
+            ("gold".to_string(), "30".to_string()),
+            ("sword".to_string(), "1".to_string()),
+        ]))
+    );
+    assert_eq!(state.procs[0].args, "who {greeting Hello}");
+
+    let mut loaded = Interp::new();
+    loaded.register_core_commands();
+    loaded
+        .eval("set inventory old; proc greet {} {return replaced}; proc other {} {}")
+        .unwrap();
+    loaded.load_state(&state.clone()).unwrap();
+
+    assert_eq!(loaded.var("name"), Some("Ada L"));
+    assert_eq!(loaded.var("inventory(gold)"), Some("30"));
+    assert_eq!(loaded.eval_str("greet $name").unwrap(), "Hello, Ada L");
+    assert_eq!(loaded.eval_str("greet you Hi").unwrap(), "Hi, you");
+    // Merging leaves what the state doesn't mention
+    loaded.eval("other").unwrap();
+    assert_eq!(loaded.save_state().procs.len(), 2);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn saved_state_round_trips_through_json() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp
+        .eval(
+            "set score 12; set flags(debug) 1
+            proc add {a {b 1}} {expr {$a + $b}}",
+        )
+        .unwrap();
+    let state = interp.save_state();
+    let json = serde_json::to_string(&state).unwrap();
+    let parsed: tcl3::SavedState = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, state);
+
+    let mut loaded = Interp::new();
+    loaded.register_core_commands();
+    loaded.load_state(&parsed).unwrap();
+    assert_eq!(loaded.var("score"), Some("12"));
+    assert_eq!(loaded.var("flags(debug)"), Some("1"));
+    assert_eq!(loaded.eval_str("add $score").unwrap(), "13");
+}
+
+#[test]
+fn eval_hooks_see_every_command() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp
+        .register_closure("nap", |_, _| {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            Ok(Status::Ok)
+        })
+        .unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&events);
+    interp.set_eval_hook(move |event| {
+        let entry = match event {
+            EvalEvent::CommandEnter { argv } => format!("enter {}", argv[0]),
+            EvalEvent::CommandLeave {
+                argv,
+                status,
+                duration,
+                ..
+            } => {
+                if argv[0] == "nap" {
+                    assert!(*duration >= std::time::Duration::from_millis(2));
+                }
+                format!("leave {} {}", argv[0], status.is_ok())
+            }
+        };
+        log.lock().unwrap().push(entry);
+    });
+
+    interp
+        .eval("set i 0; while {< $i 3} {set i [+ $i 1]}")
+        .unwrap();
+    let count = |name: &str| {
+        events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|e| *e == &format!("enter {name}"))
+            .count()
+    };
+    assert_eq!(count("+"), 3);
+    assert_eq!(count("<"), 4);
+    assert_eq!(count("while"), 1);
+    events.lock().unwrap().clear();
+
+    interp.eval("nap").unwrap();
+    assert_eq!(*events.lock().unwrap(), ["enter nap", "leave nap true"]);
+    events.lock().unwrap().clear();
+
+    // Errors unwinding through commands still leave each one
+    interp.eval("proc f {} {error bad}; f").unwrap_err();
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "enter proc",
+            "leave proc true",
+            "enter f",
+            "enter error",
+            "leave error false",
+            "leave f false",
+        ]
+    );
+    events.lock().unwrap().clear();
+
+    interp.call(&["set", "x", "1"]).unwrap();
+    assert_eq!(events.lock().unwrap().len(), 2);
+
+    interp.clear_eval_hook();
+    interp.eval("set x 2").unwrap();
+    assert_eq!(events.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn panicking_commands_become_errors() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp
+        .register_closure("boom", |_, argv| panic!("bad input {}", argv[1]))
+        .unwrap();
+
+    let script = "proc f {x} {set local 1; boom $x}
+        set r [catch {f 42} msg]";
+    interp.eval(script).unwrap();
+    assert_eq!(interp.var("r"), Some("1"));
+    assert_eq!(
+        interp.var("msg"),
+        Some("command \"boom\" panicked: bad input 42")
+    );
+    assert_eq!(interp.level(), 0);
+
+    let e = interp.eval("boom again").unwrap_err();
+    assert_eq!(e.to_string(), "command \"boom\" panicked: bad input again");
+    assert_eq!(interp.eval_str("set after [+ 1 1]").unwrap(), "2");
+}
+
+#[test]
+fn errors_reach_the_embedder() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+
+    let e = interp.eval("set x 1\nno_such_command").unwrap_err();
+    assert_eq!(e.to_string(), "command not found: \"no_such_command\"");
+    assert_eq!(interp.error_line(), Some(2));
+}
+
+#[test]
+fn closures_keep_their_captured_state() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let captured = Arc::clone(&log);
+    interp
+        .register_closure("log", move |interp, argv| {
+            captured.lock().unwrap().push(argv[1].to_string());
+            interp.set_result(captured.lock().unwrap().len().to_string());
+            Ok(Status::Ok)
+        })
+        .unwrap();
+
+    interp.eval("log first; set n [log second]").unwrap();
+    assert_eq!(*log.lock().unwrap(), ["first", "second"]);
+    assert_eq!(interp.get_var("n").unwrap().as_str(), "2");
+
+    // Closure commands can't be registered twice, but rename and delete
+    // them like any other command
+    assert!(
+        interp
+            .register_closure("log", |_, _| Ok(Status::Ok))
+            .is_err()
+    );
+    interp.eval("rename log journal; journal third").unwrap();
+    assert!(interp.eval("log fourth").is_err());
+    interp.eval("rename journal {}").unwrap();
+    assert!(interp.eval("journal fifth").is_err());
+    assert_eq!(*log.lock().unwrap(), ["first", "second", "third"]);
+
+    // Deleting the command drops the closure and what it captured
+    assert_eq!(Arc::strong_count(&log), 1);
+}
+
+#[test]
+fn closures_can_be_redefined_as_procs() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp
+        .register_closure("greet", |interp, _| {
+            interp.set_result("from rust");
+            Ok(Status::Ok)
+        })
+        .unwrap();
+    assert_eq!(interp.eval_str("greet").unwrap(), "from rust");
+
+    interp.eval("proc greet {} {return {from tcl}}").unwrap();
+    assert_eq!(interp.eval_str("greet").unwrap(), "from tcl");
+}
+
+#[test]
+fn closures_cannot_reenter_themselves() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp
+        .register_closure("again", |interp, _| interp.eval("again"))
+        .unwrap();
+
+    let e = interp.eval("again").unwrap_err();
+    assert_eq!(e.to_string(), "can't invoke \"again\" while it is running");
+}
+
+fn cmd_count_add(
+    _interp: &mut Interp,
+    argv: &[TclValue],
+    count: &Arc<Mutex<i64>>,
+) -> Result<Status, TclError> {
+    *count.lock().unwrap() += argv[1].as_str().parse::<i64>().unwrap();
+    Ok(Status::Ok)
+}
+
+fn cmd_count_get(
+    interp: &mut Interp,
+    _argv: &[TclValue],
+    count: &Arc<Mutex<i64>>,
+) -> Result<Status, TclError> {
+    interp.set_result(count.lock().unwrap().to_string());
+    Ok(Status::Ok)
+}
+
+fn cmd_greeting(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    greeting: &String,
+) -> Result<Status, TclError> {
+    interp.set_result(format!("{greeting}, {}", argv[1]));
+    Ok(Status::Ok)
+}
+
+#[test]
+fn commands_get_their_typed_data() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+
+    let count = Arc::new(Mutex::new(0));
+    interp
+        .register_command_with_data("add", cmd_count_add, Arc::clone(&count))
+        .unwrap();
+    interp
+        .register_command_with_data("total", cmd_count_get, Arc::clone(&count))
+        .unwrap();
+    interp
+        .register_command_with_data("greet", cmd_greeting, "hello".to_string())
+        .unwrap();
+
+    interp.eval("add 2; add 3; greet world").unwrap();
+    assert_eq!(interp.take_result().as_str(), "hello, world");
+    assert_eq!(interp.eval_str("total").unwrap(), "5");
+    assert_eq!(*count.lock().unwrap(), 5);
+
+    assert_eq!(interp.command_data::<String>("greet").unwrap(), "hello");
+    assert!(interp.command_data::<String>("add").is_none());
+    assert_eq!(
+        *interp
+            .command_data::<Arc<Mutex<i64>>>("total")
+            .unwrap()
+            .lock()
+            .unwrap(),
+        5
+    );
+}
+
+#[test]
+fn tokenize_without_an_interpreter() {
+    let mut parser = Parser::new("puts $x");
+    let mut tokens = Vec::new();
+    loop {
+        let token = parser.next();
+        if token == Token::Eof {
+            break;
+        }
+        tokens.push(token);
+    }
+    assert_eq!(tokens, [Token::Esc, Token::Sep, Token::Var, Token::Eol]);
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn commands_can_be_unregistered() {
+    let mut interp = Interp::builder().build();
+    interp.unregister_command("puts").unwrap();
+    let e = interp.eval("puts hello").unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::CommandNotFound);
+
+    let e = interp.unregister_command("puts").unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::CommandNotFound);
+    assert_eq!(
+        e.to_string(),
+        "can't delete \"puts\": command doesn't exist"
+    );
+
+    interp.register_command("puts", cmd_double, None).unwrap();
+    assert_eq!(interp.eval_str("puts 4").unwrap(), "8");
+}
+
+#[test]
+fn delete_callbacks_run_once() {
+    let deleted = Arc::new(Mutex::new(Vec::new()));
+    let on_delete = |name: &'static str| {
+        let deleted = Arc::clone(&deleted);
+        move || deleted.lock().unwrap().push(name)
+    };
+
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    for name in ["a", "b", "c", "d"] {
+        interp.register_command(name, cmd_double, None).unwrap();
+        interp.set_delete_callback(name, on_delete(name)).unwrap();
+    }
+    assert!(interp.set_delete_callback("nope", || {}).is_err());
+
+    interp.unregister_command("a").unwrap();
+    // Renaming keeps the callback with the command
+    interp.eval("rename b renamed; rename renamed {}").unwrap();
+    interp.eval("proc c {} {}").unwrap();
+    assert_eq!(*deleted.lock().unwrap(), ["a", "b", "c"]);
+
+    interp.register_command("a", cmd_double, None).unwrap();
+    assert_eq!(interp.eval_str("a 21").unwrap(), "42");
+
+    drop(interp);
+    assert_eq!(*deleted.lock().unwrap(), ["a", "b", "c", "d"]);
+}
+
+#[test]
+fn command_names_are_listed_in_definition_order() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp.eval("proc zzz {} {}; proc aaa {} {}").unwrap();
+    let names: Vec<&str> = interp.command_names().collect();
+    assert!(names.contains(&"set"));
+    assert_eq!(names[names.len() - 2..], ["zzz", "aaa"]);
+    assert_eq!(interp.proc_names().collect::<Vec<_>>(), ["zzz", "aaa"]);
+    assert_eq!(interp.proc_definition("aaa"), Some(("", "")));
+    assert_eq!(interp.proc_definition("set"), None);
+}
+
+#[test]
+fn traced_commands_are_written_as_they_run() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    let err = tcl3::OutputBuffer::new();
+    interp.set_error_output(err.clone());
+    interp
+        .eval("proc double {x} {set y [* $x 2]; return $y}")
+        .unwrap();
+
+    interp.trace_eval = true;
+    interp
+        .eval("set r [double 4]; catch {error \"a\nb\"}")
+        .unwrap();
+    interp.trace_eval = false;
+    interp.eval("double 1").unwrap();
+
+    let trace = err.take();
+    let lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "1> double 4",
+            "  2> * 4 2",
+            "  2< ok 8",
+            "  2> set y 8",
+            "  2< ok 8",
+            "  2> return 8",
+            "  2< return 8",
+            "1< ok 8",
+            "1> set r 8",
+            "1< ok 8",
+            "1> catch {error \"a\\nb\"}",
+            "  2> error {a\\nb}",
+            "  2< error a\\nb",
+            "1< ok 1",
+        ]
+    );
+}
+
+#[test]
+fn stats_count_commands_and_depth() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    // set and while, the condition 11 times, and two commands per pass
+    interp
+        .eval("set i 0; while {< $i 10} {set i [+ $i 1]}")
+        .unwrap();
+    assert_eq!(
+        interp.stats().eval,
+        EvalStats {
+            commands: 1 + 1 + 11 + 2 * 10,
+            max_depth: 0
+        }
+    );
+
+    interp.reset_stats();
+    interp
+        .eval("proc down {n} {if {$n > 0} {down [- $n 1]}}; down 3")
+        .unwrap();
+    assert_eq!(interp.stats().eval.max_depth, 4);
+}
+
+#[test]
+fn stats_describe_frames_procs_and_variables() {
+    let mut interp = Interp::builder().build();
+    let before = interp.stats();
+    assert_eq!(before.depth, 0);
+    assert_eq!(before.frame_vars, vec![0]);
+    assert_eq!(before.procs, 0);
+
+    interp.eval("proc f {a b} {set c 1; snapshot}").unwrap();
+    let defined = interp.stats();
+    assert_eq!(defined.procs, 1);
+    assert_eq!(defined.commands, before.commands + 1);
+    assert_eq!(defined.proc_bytes, "a b".len() + "set c 1; snapshot".len());
+
+    interp
+        .eval(&format!("set big {}", "x".repeat(10_000)))
+        .unwrap();
+    assert!(interp.stats().var_bytes >= 10_000);
+
+    let inside = Arc::new(Mutex::new(None));
+    let seen = inside.clone();
+    interp
+        .register_closure("snapshot", move |interp, _| {
+            let names: Vec<Vec<String>> = interp
+                .frames()
+                .map(|f| {
+                    let mut names: Vec<String> = f.var_names().map(String::from).collect();
+                    names.sort();
+                    names
+                })
+                .collect();
+            *seen.lock().unwrap() = Some((interp.stats(), names));
+            Ok(Status::Ok)
+        })
+        .unwrap();
+    let evaluated = interp.stats().commands_evaluated;
+    interp.eval("f 1 2").unwrap();
+    let (stats, names) = inside.lock().unwrap().take().unwrap();
+    assert_eq!(stats.depth, 1);
+    assert_eq!(stats.frame_vars, vec![1, 3]);
+    assert_eq!(names[1], ["a", "b", "c"]);
+    assert_eq!(stats.commands_evaluated, evaluated + 3);
+
+    // Resetting the counts leaves the count since creation alone
+    interp.reset_stats();
+    assert_eq!(interp.stats().eval.commands, 0);
+    assert_eq!(interp.stats().commands_evaluated, evaluated + 3);
+    assert_eq!(interp.frames().count(), 1);
+}
+
+#[test]
+fn history_is_recorded_listed_and_redone() {
+    let mut interp = Interp::builder().build();
+    for script in ["set a 1", "proc f {} {\n  set ::x 1\n}", "set a [+ $a 1]"] {
+        interp.record_history(script);
+        interp.eval(script).unwrap();
+    }
+    assert_eq!(
+        interp.eval_str("history").unwrap(),
+        "     1  set a 1\n     2  proc f {} {\n\t  set ::x 1\n\t}\n     3  set a [+ $a 1]"
+    );
+
+    // Redo replaces its own entry, so redoing again repeats the same script
+    for a in ["3", "4"] {
+        interp.record_history("history redo");
+        assert_eq!(interp.eval_str("history redo").unwrap(), a);
+    }
+    assert_eq!(interp.get_var("a").unwrap(), "4");
+    assert_eq!(interp.eval_str("history redo 1; set a").unwrap(), "1");
+    assert_eq!(interp.eval_str("history redo -2; set a").unwrap(), "2");
+    let e = interp.eval("history redo 9").unwrap_err();
+    assert_eq!(e.to_string(), "event \"9\" hasn't occurred yet");
+
+    interp.eval("history keep 2").unwrap();
+    assert_eq!(
+        interp.eval_str("history").unwrap(),
+        "     4  set a [+ $a 1]\n     5  set a [+ $a 1]"
+    );
+    let e = interp.eval("history redo 1").unwrap_err();
+    assert_eq!(e.to_string(), "event \"1\" is too far in the past");
+
+    interp.eval("history clear").unwrap();
+    assert_eq!(interp.eval_str("history").unwrap(), "");
+    interp.record_history("set b 1");
+    assert_eq!(interp.eval_str("history").unwrap(), "     1  set b 1");
+}
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn interpreters_move_between_threads() {
+    assert_send::<Interp>();
+    assert_send::<TclValue>();
+
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    let calls = Arc::new(Mutex::new(0));
+    let counter = Arc::clone(&calls);
+    interp
+        .register_closure("tick", move |_, _| {
+            *counter.lock().unwrap() += 1;
+            Ok(Status::Ok)
+        })
+        .unwrap();
+    interp.eval("proc square {x} {tick; * $x $x}").unwrap();
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let worker = std::thread::spawn(move || {
+        tx.send(interp.eval_str("square 7")).unwrap();
+        interp
+    });
+    assert_eq!(rx.recv().unwrap().unwrap(), "49");
+
+    // And back again, still holding what the worker did
+    let mut interp = worker.join().unwrap();
+    assert_eq!(interp.eval_str("square 3").unwrap(), "9");
+    assert_eq!(*calls.lock().unwrap(), 2);
+
+    // Shared behind a mutex, as a multithreaded server would
+    let shared = Arc::new(Mutex::new(interp));
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let shared = Arc::clone(&shared);
+            std::thread::spawn(move || {
+                shared.lock().unwrap().eval("tick").unwrap();
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(*calls.lock().unwrap(), 6);
+}
+
+#[test]
+fn unknown_commands_suggest_close_names() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp.eval("proc greet {} {}").unwrap();
+
+    let e = interp.eval("sett x 1").unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::CommandNotFound);
+    assert_eq!(
+        e.to_string(),
+        "command not found: \"sett\"; did you mean \"set\"?"
+    );
+    // Commands the script defined are candidates too
+    let e = interp.eval("Greet").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "command not found: \"Greet\"; did you mean \"greet\"?"
+    );
+    // Nothing close, and too short to guess from
+    let e = interp.eval("frobnicate").unwrap_err();
+    assert_eq!(e.to_string(), "command not found: \"frobnicate\"");
+    let e = interp.eval("xy").unwrap_err();
+    assert_eq!(e.to_string(), "command not found: \"xy\"");
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn aliases_call_into_the_parent() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp.register_io_commands();
+    let output = tcl3::OutputBuffer::new();
+    interp.set_output(output.clone());
+
+    // A child without puts of its own prints through the parent's
+    interp
+        .eval("interp create c; interp eval c {rename puts {}}")
+        .unwrap();
+    assert!(interp.eval("interp eval c {puts hi}").is_err());
+    assert_eq!(
+        interp.eval_str("interp alias c puts {} puts").unwrap(),
+        "puts"
+    );
+    interp
+        .eval("interp eval c {puts {from the child}}")
+        .unwrap();
+    assert_eq!(output.take(), "from the child\n");
+
+    // Prefix words go before the caller's, and the target sees the
+    // parent's variables
+    interp
+        .eval(
+            "set log {}
+             proc record {level msg} {global log; set log \"$log$level:$msg \"; set log}
+             interp alias c warn {} record warning",
+        )
+        .unwrap();
+    assert_eq!(
+        interp
+            .eval_str("interp eval c {warn one; warn two}")
+            .unwrap(),
+        "warning:one warning:two "
+    );
+    assert_eq!(interp.var("log"), Some("warning:one warning:two "));
+    assert_eq!(
+        interp.eval_str("interp alias c warn").unwrap(),
+        "record warning"
+    );
+
+    // Errors come back into the child, where it can catch them
+    assert_eq!(
+        interp
+            .eval_str("interp eval c {catch {warn a b} msg; set msg}")
+            .unwrap(),
+        "wrong # args: should be \"record level msg\""
+    );
+
+    // A break in the parent can't end a loop in the child
+    interp.eval("interp alias c stop {} break").unwrap();
+    assert_eq!(
+        interp
+            .eval_str("interp eval c {foreach x {1 2} {catch stop msg}; set msg}")
+            .unwrap(),
+        "invoked \"break\" outside of a loop"
+    );
+
+    // Deleting the alias leaves the child without the command
+    interp.eval("interp alias c warn {}").unwrap();
+    let e = interp.eval("interp eval c {warn three}").unwrap_err();
+    assert_eq!(e.to_string(), "c: command not found: \"warn\"");
+    let e = interp.eval("interp alias c warn").unwrap_err();
+    assert_eq!(e.to_string(), "alias \"warn\" not found");
+}
+
+#[test]
+#[cfg(all(feature = "io", feature = "process"))]
+fn safe_children_only_reach_hidden_commands_through_the_parent() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp.register_io_commands();
+    interp.register_process_commands();
+    let output = tcl3::OutputBuffer::new();
+    interp.set_output(output.clone());
+    interp.eval("interp create -safe s").unwrap();
+
+    // Neither the command nor renaming or unknown can get at it
+    for script in [
+        "open /etc/passwd",
+        "exit 3",
+        "rename open opened",
+        "proc unknown {args} {open /etc/passwd}; open x",
+    ] {
+        let e = interp
+            .eval(&format!("interp eval s {{{script}}}"))
+            .unwrap_err();
+        assert_eq!(e.kind, TclErrorKind::CommandNotFound, "{script}: {e}");
+    }
+    assert_eq!(interp.eval_str("interp eval s {+ 1 2}").unwrap(), "3");
+
+    // The parent can run hidden commands on the child's behalf
+    let path = std::env::temp_dir().join(format!("tcl3-hidden-{}.txt", std::process::id()));
+    interp.set_var("path", path.to_str().unwrap()).unwrap();
+    let chan = interp
+        .eval_str("interp invokehidden s open $path w")
+        .unwrap();
+    interp
+        .eval(&format!(
+            "interp invokehidden s puts {chan} written; interp invokehidden s close {chan}"
+        ))
+        .unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "written\n");
+    std::fs::remove_file(&path).unwrap();
+
+    // Exposed under another name, a command is usable by that name only
+    interp.eval("interp expose s puts say").unwrap();
+    interp.eval("interp eval s {say hi}").unwrap();
+    assert_eq!(output.take(), "hi\n");
+    assert!(interp.eval("interp eval s {puts hi}").is_err());
+
+    // And hidden again
+    interp.eval("interp hide s say").unwrap();
+    assert!(interp.eval("interp eval s {say hi}").is_err());
+    let e = interp.eval("interp expose s nope").unwrap_err();
+    assert_eq!(e.to_string(), "unknown hidden command \"nope\"");
+}
+
+#[test]
+fn unknown_variables_suggest_close_names() {
+    let mut interp = Interp::new();
+    interp.register_core_commands();
+    interp.eval("set counter 1; set total 2").unwrap();
+
+    let e = interp.eval("set x $countr").unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::VariableNotFound);
+    assert_eq!(
+        e.to_string(),
+        "can't read \"countr\": no such variable; did you mean \"$counter\"?"
+    );
+    let e = interp.eval("set tot").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "can't read \"tot\": no such variable; did you mean \"$total\"?"
+    );
+
+    // Inside a proc, both its locals and the globals are candidates
+    interp
+        .eval("proc f {} {set count 0; set x $countt}")
+        .unwrap();
+    let e = interp.eval("f").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "can't read \"countt\": no such variable; did you mean \"$count\" or \"$counter\"?"
+    );
+
+    let e = interp.eval("set x $unrelated").unwrap_err();
+    assert_eq!(e.to_string(), "can't read \"unrelated\": no such variable");
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn channels_and_stdin_can_be_read_whole_or_in_chunks() {
+    let path = std::env::temp_dir().join(format!("tcl3-read-{}.txt", std::process::id()));
+    let contents = "first line\nsécond line\n";
+    std::fs::write(&path, contents).unwrap();
+    let mut interp = Interp::builder().build();
+    interp.set_var("path", path.to_str().unwrap()).unwrap();
+
+    let whole = interp
+        .eval_str("set f [open $path]; set all [read $f]; close $f; set all")
+        .unwrap();
+    assert_eq!(whole, contents);
+    assert_eq!(
+        interp
+            .eval_str("set f [open $path]; set r [read $f 5]|[read $f 5]; close $f; set r")
+            .unwrap(),
+        "first| line"
+    );
+    // Characters, not bytes, and a final newline dropped just once
+    assert_eq!(
+        interp
+            .eval_str("set f [open $path]; gets $f; set r [read $f 3]; close $f; set r")
+            .unwrap(),
+        "séc"
+    );
+    std::fs::write(&path, "text\n\n").unwrap();
+    assert_eq!(
+        interp
+            .eval_str("set f [open $path]; set r [read -nonewline $f]; close $f; set r")
+            .unwrap(),
+        "text\n"
+    );
+
+    let e = interp.eval("set f [open $path w]; read $f").unwrap_err();
+    assert!(e.to_string().ends_with("wasn't opened for reading"), "{e}");
+    interp.eval("close $f").unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    interp.set_input(std::io::Cursor::new("typed ahead\nrest\n"));
+    assert_eq!(interp.eval_str("read stdin 5").unwrap(), "typed");
+    assert_eq!(interp.eval_str("gets stdin").unwrap(), " ahead");
+    assert_eq!(interp.eval_str("read -nonewline stdin").unwrap(), "rest");
+    assert_eq!(interp.eval_str("eof stdin").unwrap(), "1");
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn latin1_text_round_trips_through_strings_and_channels() {
+    let mut interp = Interp::builder().build();
+    assert_eq!(
+        interp
+            .eval_str("encoding convertfrom iso8859-1 \"caf\\xe9\"")
+            .unwrap(),
+        "café"
+    );
+    assert_eq!(
+        interp
+            .eval_str("encoding convertto iso8859-1 [encoding convertfrom iso8859-1 \"caf\\xe9\"]")
+            .unwrap(),
+        "caf\u{e9}"
+    );
+    assert_eq!(
+        interp
+            .eval_str("string length [encoding convertto utf-8 café]")
+            .unwrap(),
+        "5"
+    );
+    assert_eq!(
+        interp.eval_str("encoding names").unwrap(),
+        "ascii iso8859-1 utf-8"
+    );
+
+    let path = std::env::temp_dir().join(format!("tcl3-latin1-{}.txt", std::process::id()));
+    std::fs::write(&path, b"caf\xe9\nna\xefve\n").unwrap();
+    interp.set_var("path", path.to_str().unwrap()).unwrap();
+    assert_eq!(
+        interp
+            .eval_str(
+                "set f [open $path r -encoding iso8859-1]
+                 set r [gets $f]|[read $f 3]
+                 close $f
+                 set r"
+            )
+            .unwrap(),
+        "café|naï"
+    );
+    interp
+        .eval("set f [open $path w -encoding iso8859-1]; puts $f été; close $f")
+        .unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"\xe9t\xe9\n");
+    std::fs::remove_file(&path).unwrap();
+
+    let e = interp.eval("open $path r -encoding ebcdic").unwrap_err();
+    assert_eq!(e.to_string(), "unknown encoding \"ebcdic\"");
+}
+
+#[test]
+#[cfg(feature = "coroutine")]
+fn coroutines_yield_values_and_go_away_when_done() {
+    let mut interp = Interp::builder().build();
+    interp
+        .eval("proc counter {} { set i 0; while 1 { yield $i; set i [+ $i 1] } }")
+        .unwrap();
+    assert_eq!(interp.eval_str("coroutine c counter").unwrap(), "0");
+    assert_eq!(interp.eval_str("c").unwrap(), "1");
+    assert_eq!(interp.eval_str("c").unwrap(), "2");
+
+    // Values passed to the command come back from yield
+    interp
+        .eval(
+            "proc summer {} {
+                set total 0
+                while 1 { set total [+ $total [yield $total]] }
+            }",
+        )
+        .unwrap();
+    assert_eq!(interp.eval_str("coroutine s summer").unwrap(), "0");
+    assert_eq!(interp.eval_str("s 5").unwrap(), "5");
+    assert_eq!(interp.eval_str("s 10").unwrap(), "15");
+
+    // Finishing returns the command's result and deletes the command
+    interp
+        .eval("proc twice {} { yield first; return second }")
+        .unwrap();
+    assert_eq!(interp.eval_str("coroutine t twice").unwrap(), "first");
+    assert_eq!(interp.eval_str("t").unwrap(), "second");
+    assert!(interp.get_command("t").is_none());
+    assert_eq!(interp.eval_str("coroutine u set x done").unwrap(), "done");
+    assert!(interp.get_command("u").is_none());
+
+    // A suspended coroutine can be renamed, or deleted to end it
+    interp.eval("rename c c2").unwrap();
+    assert_eq!(interp.eval_str("c2").unwrap(), "3");
+    interp.eval("rename c2 {}").unwrap();
+    assert!(interp.get_command("c2").is_none());
+    assert_eq!(interp.eval_str("set i outside").unwrap(), "outside");
+    // Even from inside catch and try, which don't stop it unwinding
+    interp
+        .eval(
+            "proc guarded {} { try { catch { yield in } } finally { set ::cleaned 1 } }
+            coroutine g guarded
+            rename g {}",
+        )
+        .unwrap();
+    assert!(interp.get_command("g").is_none());
+
+    let e = interp.eval("yield 1").unwrap_err();
+    assert_eq!(e.to_string(), "yield can only be called in a coroutine");
+    let e = interp.eval("coroutine s summer").unwrap_err();
+    assert_eq!(e.to_string(), "command \"s\" already exists");
+
+    // However high the nesting limit, the coroutine's thread gets a sane stack
+    interp
+        .eval("interp recursionlimit {} 4611686018427387904")
+        .unwrap();
+    assert_eq!(interp.eval_str("coroutine big yield").unwrap(), "");
+    interp.eval("big").unwrap();
+}
+
+#[test]
+#[cfg(feature = "coroutine")]
+fn coroutines_are_limited_and_left_out_of_safe_interps() {
+    let mut interp = Interp::builder().max_coroutines(Some(2)).build();
+    interp.eval("coroutine a yield; coroutine b yield").unwrap();
+    let e = interp.eval("catch { coroutine c yield }").unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::LimitExceeded);
+    assert_eq!(
+        e.to_string(),
+        "coroutine limit exceeded: only 2 coroutines allowed"
+    );
+    // Finished and deleted coroutines make room for more
+    interp.eval("a; rename b {}").unwrap();
+    interp.eval("coroutine c yield; coroutine d yield").unwrap();
+    assert_eq!(interp.max_coroutines(), Some(2));
+
+    // Each coroutine holds a thread, so untrusted scripts don't get them
+    let safe = tcl3::InterpBuilder::safe().build();
+    assert!(safe.get_command("coroutine").is_none());
+    assert!(safe.get_command("yield").is_none());
+    let e = interp
+        .eval("interp create -safe child; interp eval child { coroutine c yield }")
+        .unwrap_err();
+    assert_eq!(e.to_string(), "child: command not found: \"coroutine\"");
+}
+
+#[test]
+fn resource_limits_stop_scripts_that_grow_without_bound() {
+    let mut interp = Interp::builder()
+        .max_value_len(Some(1 << 20))
+        .max_vars_per_frame(Some(100))
+        .max_callframes(Some(50))
+        .build();
+
+    // Doubling a string stops at the limit, long before it gets big
+    let e = interp.eval("set x a; while 1 { set x $x$x }").unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::LimitExceeded);
+    assert_eq!(
+        e.to_string(),
+        "value length limit exceeded: 2097152 bytes, over the limit of 1048576"
+    );
+    assert_eq!(interp.eval_str("string length $x").unwrap(), "1048576");
+    let e = interp.eval("string range $x$x 0 end").unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::LimitExceeded);
+
+    // The limit errors get past catch
+    let e = interp.eval("catch { while 1 { set x $x$x } }").unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::LimitExceeded);
+
+    interp
+        .eval("proc fill {n} { while {$n > 0} { set v$n $n; set n [- $n 1] } }")
+        .unwrap();
+    interp.eval("fill 90").unwrap();
+    let e = interp.eval("fill 150").unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::LimitExceeded);
+    assert_eq!(
+        e.to_string(),
+        "variable limit exceeded: a frame can hold only 100 variables"
+    );
+
+    interp
+        .eval("proc down {n} { if {$n > 0} { down [- $n 1] } }")
+        .unwrap();
+    interp.eval("down 40").unwrap();
+    let e = interp.eval("down 100").unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::LimitExceeded);
+    assert_eq!(
+        e.to_string(),
+        "call frame limit exceeded: only 50 frames allowed"
+    );
+
+    // Ordinary scripts don't come near generous limits
+    assert_eq!(
+        interp
+            .eval_str("set s {}; foreach w {a b c} { set s $s$w }; set s")
+            .unwrap(),
+        "abc"
+    );
+    assert_eq!(interp.max_value_len(), Some(1 << 20));
+    assert_eq!(Interp::builder().build().max_callframes(), None);
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn fconfigure_sets_translation_and_reports_options() {
+    let mut interp = Interp::builder().build();
+    let path = std::env::temp_dir().join(format!("tcl3-fconfigure-{}.txt", std::process::id()));
+    interp.set_var("path", path.to_str().unwrap()).unwrap();
+
+    interp
+        .eval("set f [open $path w]; fconfigure $f -translation crlf; puts $f one; puts $f two")
+        .unwrap();
+    assert_eq!(
+        interp.eval_str("fconfigure $f").unwrap(),
+        "-buffering full -encoding utf-8 -translation crlf"
+    );
+    assert_eq!(
+        interp.eval_str("fconfigure $f -translation").unwrap(),
+        "crlf"
+    );
+    interp.eval("close $f").unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"one\r\ntwo\r\n");
+
+    // The report can be handed back to fconfigure as it is
+    interp
+        .eval("set f [open $path r]; fconfigure $f -buffering line -encoding iso8859-1")
+        .unwrap();
+    let report = interp.eval_str("fconfigure $f").unwrap();
+    assert_eq!(
+        report,
+        "-buffering line -encoding iso8859-1 -translation auto"
+    );
+    interp.eval("fconfigure $f -buffering none").unwrap();
+    interp.eval(&format!("fconfigure $f {report}")).unwrap();
+    assert_eq!(interp.eval_str("fconfigure $f").unwrap(), report);
+    assert_eq!(interp.eval_str("gets $f").unwrap(), "one");
+
+    interp.eval("fconfigure $f -translation binary").unwrap();
+    assert_eq!(interp.eval_str("read $f").unwrap(), "two\r\n");
+    interp.eval("close $f").unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        interp.eval_str("fconfigure stdout").unwrap(),
+        "-buffering line -encoding utf-8 -translation auto"
+    );
+    let e = interp.eval("fconfigure stdout -eofchar x").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "bad option \"-eofchar\": must be -buffering, -encoding, or -translation"
+    );
+    let e = interp
+        .eval("fconfigure stdout -buffering none")
+        .unwrap_err();
+    assert_eq!(e.to_string(), "can't change the options of \"stdout\"");
+}
+
+fn cmd_stack_push(
+    interp: &mut Interp,
+    argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let stack = interp.var("stack").unwrap_or_default().to_string();
+    let stack = format!("{stack} {}", argv[2]).trim_start().to_string();
+    interp.set_var("stack", stack)?;
+    Ok(Status::Ok)
+}
+
+fn cmd_stack_size(
+    interp: &mut Interp,
+    _argv: &[TclValue],
+    _privdata: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<Status, TclError> {
+    let size = interp.var("stack").unwrap_or_default().split(' ').count();
+    interp.set_result(size.to_string());
+    Ok(Status::Ok)
+}
+
+#[test]
+fn ensembles_dispatch_on_unambiguous_prefixes() {
+    let mut interp = Interp::builder().build();
+    assert_eq!(interp.eval_str("string len hello").unwrap(), "5");
+    assert_eq!(interp.eval_str("string r hello 1 2").unwrap(), "el");
+
+    let e = interp.eval("string frob x").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "unknown or ambiguous subcommand \"frob\": must be index, length, or range"
+    );
+    let e = interp.eval("string length").unwrap_err();
+    assert_eq!(e.kind, TclErrorKind::Arity);
+    assert_eq!(
+        e.to_string(),
+        "wrong # args: should be \"string length string\""
+    );
+
+    interp
+        .register_ensemble(
+            Ensemble::new("stack")
+                .subcommand("push", "value", cmd_stack_push)
+                .subcommand("pushall", "?value ...?", cmd_stack_push)
+                .subcommand("size", "", cmd_stack_size),
+        )
+        .unwrap();
+    interp
+        .eval("stack push a; stack pushall b; stack s")
+        .unwrap();
+    assert_eq!(interp.eval_str("set stack").unwrap(), "a b");
+    assert_eq!(interp.eval_str("stack si").unwrap(), "2");
+
+    // "pus" could be push or pushall, so both are named
+    let e = interp.eval("stack pus c").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "unknown or ambiguous subcommand \"pus\": must be push, pushall, or size"
+    );
+    assert_eq!(
+        interp.eval_str("info subcommands stack").unwrap(),
+        "push pushall size"
+    );
+    assert_eq!(
+        interp.eval_str("info sub string").unwrap(),
+        "index length range"
+    );
+    assert_eq!(
+        interp.eval_str("info subcommands clock").unwrap(),
+        "format microseconds milliseconds scan seconds"
+    );
+    let e = interp.eval("dict size").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "wrong # args: should be \"dict size dictionary\""
+    );
+    let e = interp.eval("info subcommands set").unwrap_err();
+    assert_eq!(e.to_string(), "\"set\" isn't an ensemble command");
+}
+
+#[test]
+fn setting_the_interrupt_flag_stops_evaluation() {
+    let mut interp = Interp::builder().build();
+    let flag = interp.interrupt_handle();
+    let setter = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        flag.store(true, std::sync::atomic::Ordering::SeqCst);
+    });
+    let e = interp.eval("catch { while 1 {} }").unwrap_err();
+    setter.join().unwrap();
+    assert_eq!(e.kind, TclErrorKind::Interrupted);
+    assert_eq!(e.to_string(), "evaluation interrupted");
+
+    // It stays set until cleared
+    assert_eq!(
+        interp.eval("set x 1").unwrap_err().kind,
+        TclErrorKind::Interrupted
+    );
+    interp
+        .interrupt_handle()
+        .store(false, std::sync::atomic::Ordering::SeqCst);
+    assert_eq!(interp.eval_str("set x 1").unwrap(), "1");
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn errors_name_the_file_they_happened_in() {
+    let dir = std::env::temp_dir().join(format!("tcl3-sources-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.tcl");
+    let lib = dir.join("lib.tcl");
+    std::fs::write(&config, "set a 1\n\nnosuch\n").unwrap();
+    std::fs::write(
+        &lib,
+        "# helpers\nproc fail {} {\n  set x 1\n  error boom\n}\n",
+    )
+    .unwrap();
+    let (config, lib) = (config.to_str().unwrap(), lib.to_str().unwrap());
+
+    let mut interp = Interp::builder().build();
+    interp.set_var("config", config).unwrap();
+    interp.set_var("lib", lib).unwrap();
+    interp.eval("source $config").unwrap_err();
+    assert_eq!(interp.error_file(), Some((config, 3)));
+    let trace = interp.error_trace();
+    assert!(
+        trace.contains(&format!("\"nosuch\" (file \"{config}\" line 3)")),
+        "{trace}"
+    );
+
+    // The proc's body is in lib.tcl, wherever it's called from
+    let main = "source $lib\nset y 2\nfail\n";
+    interp.eval_source("main.tcl", main).unwrap_err();
+    assert_eq!(interp.error_file(), Some((lib, 4)));
+    let trace = interp.error_trace();
+    assert!(
+        trace.contains(&format!("\"error boom\" (file \"{lib}\" line 4)")),
+        "{trace}"
+    );
+    assert!(
+        trace.contains("\"fail\" (file \"main.tcl\" line 3)"),
+        "{trace}"
+    );
+
+    // Sourcing unwinds, so later errors aren't put down to the file
+    assert_eq!(interp.script_source(), None);
+    interp.eval("\nnosuch").unwrap_err();
+    assert_eq!(interp.error_file(), None);
+    assert_eq!(interp.error_line(), Some(2));
+
+    assert_eq!(interp.eval_source("inline.tcl", "set z 3").unwrap(), "3");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn tcl_precision_sets_the_digits_floats_are_shown_with() {
+    let mut interp = Interp::builder().build();
+    assert_eq!(
+        interp.eval_str("expr {1.0 / 3}").unwrap(),
+        "0.3333333333333333"
+    );
+    assert_eq!(interp.eval_str("expr {4.0 / 2}").unwrap(), "2.0");
+
+    interp.eval("set tcl_precision 4").unwrap();
+    assert_eq!(interp.precision(), 4);
+    assert_eq!(interp.eval_str("expr {1.0 / 3}").unwrap(), "0.3333");
+    assert_eq!(interp.eval_str("/ 2.0 3").unwrap(), "0.6667");
+    assert_eq!(interp.eval_str("expr {4.0 / 2}").unwrap(), "2.0");
+    // Only the display is rounded; integers aren't affected
+    assert_eq!(interp.eval_str("expr {12345 * 10}").unwrap(), "123450");
+
+    let e = interp.eval("set tcl_precision 18").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "can't set \"tcl_precision\": improper value for precision"
+    );
+    assert_eq!(interp.precision(), 4);
+
+    interp.eval("unset tcl_precision").unwrap();
+    assert_eq!(
+        interp.eval_str("expr {1.0 / 3}").unwrap(),
+        "0.3333333333333333"
+    );
+}
+
+#[test]
+fn commands_that_leave_no_result_read_as_empty() {
+    let mut interp = Interp::builder().build();
+    interp.set_output(tcl3::OutputBuffer::new());
+    interp.eval("proc nothing {} {}").unwrap();
+
+    #[cfg(feature = "io")]
+    assert_eq!(interp.eval_str("set x [puts hi]").unwrap(), "");
+    assert_eq!(interp.eval_str("set x [nothing][nothing]").unwrap(), "");
+    for script in [
+        "if {} {set y 1}",
+        "if {[nothing]} {set y 1}",
+        "while {} {}",
+        "while {[nothing]} {}",
+    ] {
+        let e = interp.eval(script).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "expected boolean value but got \"\"",
+            "{script}"
+        );
+    }
+    assert_eq!(interp.var("y"), None);
+}
+
+#[test]
+fn wrong_argument_counts_leave_the_interpreter_as_it_was() {
+    let mut interp = Interp::builder().build();
+    interp
+        .eval("proc f {a b} { set inside 1 }\nset before 1")
+        .unwrap();
+
+    for (script, msg) in [
+        ("f 1", "wrong # args: should be \"f a b\""),
+        ("f 1 2 3", "wrong # args: should be \"f a b\""),
+        ("proc g {} { f 1 }\ng", "wrong # args: should be \"f a b\""),
+    ] {
+        let e = interp.eval(script).unwrap_err();
+        assert_eq!(e.kind, TclErrorKind::Arity, "{script}");
+        assert_eq!(e.to_string(), msg, "{script}");
+        assert_eq!(interp.level(), 0, "{script}");
+        assert_eq!(interp.var("before"), Some("1"));
+        assert_eq!(interp.var("inside"), None);
+        assert_eq!(interp.var("a"), None);
+    }
+    assert_eq!(interp.eval_str("f 1 2").unwrap(), "1");
+}
+
+#[test]
+#[cfg(all(feature = "io", feature = "process", unix))]
+fn pipelines_open_as_channels() {
+    let dir = std::env::temp_dir().join(format!("tcl3-pipes-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("sorted.txt");
+    let mut interp = Interp::builder().build();
+    interp.set_var("out", out.to_str().unwrap()).unwrap();
+
+    // Reading from the last command, through each stage in turn
+    interp
+        .eval("set f [open {|sh -c {printf 'b\\na\\n'} | sort} r]")
+        .unwrap();
+    let pids = interp.eval_str("pid $f").unwrap();
+    assert_eq!(pids.split(' ').count(), 2, "{pids}");
+    assert!(pids.split(' ').all(|pid| pid.parse::<u32>().is_ok()));
+    assert_eq!(interp.eval_str("gets $f").unwrap(), "a");
+    assert_eq!(interp.eval_str("read $f").unwrap(), "b\n");
+    interp.eval("close $f").unwrap();
+
+    // Writing to the first, with arguments quoted as list elements
+    interp
+        .eval(
+            "set f [open \"|sh -c {sort > \\\"\\$0\\\"} $out\" w]\nputs $f b\nputs $f a\nclose $f",
+        )
+        .unwrap();
+    assert_eq!(std::fs::read_to_string(&out).unwrap(), "a\nb\n");
+
+    // Failing commands make close fail, with their stderr
+    interp
+        .eval("set f [open {|sh -c {echo oops >&2; exit 3}} r]")
+        .unwrap();
+    let pid = interp.eval_str("pid $f").unwrap();
+    let e = interp.eval("close $f").unwrap_err();
+    assert_eq!(e.to_string(), "oops");
+    assert_eq!(
+        interp.eval_str("set errorCode").unwrap(),
+        format!("CHILDSTATUS {pid} 3")
+    );
+    assert!(interp.eval("pid $f").is_err());
+
+    let e = interp.eval("close [open {|sh -c {exit 2}}]").unwrap_err();
+    assert_eq!(e.to_string(), "child process exited abnormally");
+
+    let e = interp.eval("open {|no-such-command-here}").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "couldn't execute \"no-such-command-here\": no such file or directory"
+    );
+    let e = interp.eval("open {|sort |}").unwrap_err();
+    assert_eq!(e.to_string(), "illegal use of | in command");
+    assert_eq!(interp.eval_str("pid stdout").unwrap(), "");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A stream over memory the test can see into: reads come from `input`,
+/// writes go to `output`, and `closes` counts calls to close
+#[cfg(feature = "io")]
+#[derive(Clone, Default)]
+struct Duplex {
+    input: Arc<Mutex<std::collections::VecDeque<u8>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+    closes: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+#[cfg(feature = "io")]
+impl tcl3::Stream for Duplex {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        std::io::Read::read(&mut *self.input.lock().unwrap(), buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), TclError> {
+        self.closes
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn registered_channels_work_like_files() {
+    use std::sync::atomic::Ordering;
+
+    let mut interp = Interp::builder().build();
+    let console = Duplex::default();
+    console
+        .input
+        .lock()
+        .unwrap()
+        .extend(b"first line\nthe rest\n");
+    let name = interp.register_channel("console", Box::new(console.clone()));
+    assert!(name.starts_with("console"), "{name}");
+    interp.set_var("chan", name.as_str()).unwrap();
+
+    assert_eq!(interp.eval_str("gets $chan").unwrap(), "first line");
+    assert_eq!(interp.eval_str("read $chan").unwrap(), "the rest\n");
+    assert_eq!(interp.eval_str("eof $chan").unwrap(), "1");
+
+    // Output waits in the channel's buffer until it is flushed
+    interp.eval("puts $chan hello").unwrap();
+    assert_eq!(*console.output.lock().unwrap(), b"");
+    interp.eval("flush $chan").unwrap();
+    assert_eq!(*console.output.lock().unwrap(), b"hello\n");
+    interp.eval("fconfigure $chan -buffering none").unwrap();
+    interp.eval("puts -nonewline $chan bye").unwrap();
+    assert_eq!(*console.output.lock().unwrap(), b"hello\nbye");
+
+    interp.eval("close $chan").unwrap();
+    assert_eq!(console.closes.load(Ordering::SeqCst), 1);
+    assert!(interp.eval("gets $chan").is_err());
+
+    // Dropping the interpreter closes what is still open, once
+    let other = Duplex::default();
+    let name = interp.register_channel("console", Box::new(other.clone()));
+    interp.set_var("chan", name.as_str()).unwrap();
+    interp.eval("puts $chan pending").unwrap();
+    drop(interp);
+    assert_eq!(other.closes.load(Ordering::SeqCst), 1);
+    assert_eq!(*other.output.lock().unwrap(), b"pending\n");
+    assert_eq!(console.closes.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+#[cfg(feature = "io")]
+fn source_finds_files_next_to_the_one_sourcing_them() {
+    let dir = std::env::temp_dir().join(format!("tcl3-tree-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("b")).unwrap();
+    std::fs::write(
+        dir.join("a.tcl"),
+        "set seen(a) [info script]\nsource b/c.tcl\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("b/c.tcl"),
+        "set seen(c) [info script]\nsource d.tcl\nset seen(c-after) [info script]\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("b/d.tcl"),
+        "set seen(d) [info script]\nproc where {} { info script }\n",
+    )
+    .unwrap();
+
+    let mut interp = Interp::builder().build();
+    assert_eq!(interp.eval_str("info script").unwrap(), "");
+    let a = dir.join("a.tcl");
+    interp.set_var("a", a.to_str().unwrap()).unwrap();
+    interp.eval("source $a").unwrap();
+
+    let c = dir.join("b").join("c.tcl");
+    let d = dir.join("b").join("d.tcl");
+    assert_eq!(interp.var("seen(a)"), a.to_str());
+    assert_eq!(interp.var("seen(c)"), c.to_str());
+    assert_eq!(interp.var("seen(d)"), d.to_str());
+    assert_eq!(interp.var("seen(c-after)"), c.to_str());
+    // Back at the top, and in a proc from a file, whose body came from it
+    assert_eq!(interp.eval_str("info script").unwrap(), "");
+    assert_eq!(interp.eval_str("where").unwrap(), d.to_str().unwrap());
+
+    // Outside any file, relative paths are from the working directory
+    let e = interp.eval("source b/c.tcl").unwrap_err();
+    assert!(
+        e.to_string().starts_with("couldn't read file \"b/c.tcl\""),
+        "{e}"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,96 @@
+//! Replays the inputs in fuzz/regressions, which once made the
+//! interpreter panic, through the same checks as the fuzz target.
+
+use tcl3::{EvalLimits, InterpBuilder};
+
+#[test]
+fn regressions_do_not_panic() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/regressions");
+    let mut files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    assert!(!files.is_empty());
+
+    for file in files {
+        let script = std::fs::read_to_string(&file).unwrap();
+        // Each line alone as well, since most stop at their first error
+        for script in std::iter::once(script.as_str()).chain(script.lines()) {
+            let mut interp = InterpBuilder::safe()
+                .with_test_commands(true)
+                .max_value_len(Some(1 << 20))
+                .build();
+            let limits = EvalLimits {
+                max_nesting_depth: 200,
+                eval_limit: 10_000,
+            };
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                interp.eval_safe(script, limits)
+            }));
+            let name = file.display();
+            let res = res.unwrap_or_else(|_| panic!("{name}: eval_safe panicked on {script:?}"));
+            if let Err(e) = res {
+                assert!(!e.msg.contains("panicked"), "{name}: {script:?}: {e}");
+            }
+        }
+    }
+}
+
+#[test]
+fn eval_safe_applies_and_restores_limits() {
+    let mut interp = InterpBuilder::safe().build();
+    interp.set_max_nesting_depth(50);
+    let limits = EvalLimits {
+        max_nesting_depth: 1000,
+        eval_limit: 100,
+    };
+    let e = interp.eval_safe("while 1 {}", limits).unwrap_err();
+    assert_eq!(e.msg, "evaluation limit exceeded after 100 steps");
+    assert_eq!(interp.max_nesting_depth(), 50);
+    assert_eq!(interp.eval_limit(), None);
+
+    // Nor can a child interpreter escape them
+    let e = interp
+        .eval_safe(
+            "interp create c; catch {interp eval c {while 1 {}}}",
+            limits,
+        )
+        .unwrap_err();
+    assert_eq!(e.msg, "evaluation limit exceeded after 100 steps");
+
+    let e = interp
+        .eval_safe("proc f {} {f}; f", EvalLimits::default())
+        .unwrap_err();
+    assert_eq!(e.msg, "too many nested evaluations (infinite loop?)");
+    assert_eq!(interp.level(), 0);
+    assert_eq!(interp.eval_safe("+ 1 2", limits).unwrap(), "3");
+}
+
+#[test]
+fn deep_recursion_fails_without_overflowing() {
+    let deep = EvalLimits {
+        max_nesting_depth: 5000,
+        eval_limit: 10_000_000,
+    };
+    let scripts = [
+        "proc f {} {f}; f",
+        "proc f {} {catch {f} m; error $m}; f",
+        "proc f {} {try {f} on error {m} {error $m}}; f",
+        "proc f {} {if 1 {while 1 {f}}}; f",
+        "proc f {} {+ 1 [f]}; f",
+        "proc f {} {expr {[f] + 1}}; f",
+        "proc f {} {upvar 1 x x; f}; f",
+        // Each interpreter creates a child and runs the same in it
+        "set s {interp create c; interp eval c \"set s {$s}\"; interp eval c {if 1 $s}}; if 1 $s",
+    ];
+    for script in scripts {
+        let mut interp = InterpBuilder::safe().with_test_commands(true).build();
+        let e = interp.eval_safe(script, deep).unwrap_err();
+        assert!(
+            e.msg
+                .ends_with("too many nested evaluations (infinite loop?)"),
+            "{script}: {e}"
+        );
+    }
+}
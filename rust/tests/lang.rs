@@ -0,0 +1,70 @@
+//! Runs the Tcl test suites under tests/lang, which check the language
+//! with the `test` command.
+
+use tcl3::{Interp, OutputBuffer};
+
+#[test]
+fn language_suites_pass() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lang");
+    let mut files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "tcl"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty());
+
+    for file in files {
+        let mut interp = Interp::builder().with_test_commands(true).build();
+        let output = OutputBuffer::new();
+        interp.set_output(output.clone());
+        let script = std::fs::read_to_string(&file).unwrap();
+        let res = interp.eval(&script);
+
+        let counts = *interp.test_counts();
+        let name = file.display();
+        let report = output.take();
+        assert!(res.is_ok(), "{name}: {}\n{report}", res.unwrap_err());
+        assert_eq!(counts.failed, 0, "{name}\n{report}");
+        assert!(counts.passed > 0, "{name} has no tests");
+    }
+}
+
+#[test]
+fn failed_tests_are_reported() {
+    let mut interp = Interp::builder().with_test_commands(true).build();
+    let output = OutputBuffer::new();
+    interp.set_output(output.clone());
+    interp
+        .eval(
+            "test t-1 {passes} {set a 1} 1
+             test t-2 {wrong result} {set a 1} 2
+             test t-3 {wrong code} -body {error oops} -result oops
+             test t-4 {expected error} -body {error oops} -returnCodes error -result oops",
+        )
+        .unwrap();
+    assert_eq!(interp.test_counts().passed, 2);
+    assert_eq!(interp.test_counts().failed, 2);
+
+    let report = output.take();
+    assert_eq!(
+        report,
+        "\n==== t-2 wrong result FAILED\n\
+         ==== Contents of test case:\n\
+         set a 1\n\
+         ---- Result was:\n\
+         1\n\
+         ---- Result should have been (exact matching):\n\
+         2\n\
+         ==== t-2 FAILED\n\n\
+         \n==== t-3 wrong code FAILED\n\
+         ==== Contents of test case:\n\
+         error oops\n\
+         ---- Return code should have been one of: ok return\n\
+         ---- Return code was: error\n\
+         ==== t-3 FAILED\n\n"
+    );
+
+    assert_eq!(interp.eval_str("testreport suite").unwrap(), "2");
+    assert_eq!(output.take(), "suite:\tTotal\t4\tPassed\t2\tFailed\t2\n");
+}
@@ -0,0 +1,45 @@
+# clock format and scan
+
+test clock-1.1 {default format in GMT} {
+    clock format 1709211909 -gmt 1
+} {Thu Feb 29 13:05:09 UTC 2024}
+
+test clock-1.2 {directives} {
+    clock format 1709211909 -format {%a %e %b %Y, %I:%M %p (%j) %z} -gmt 1
+} {Thu 29 Feb 2024, 01:05 PM (060) +0000}
+
+test clock-1.3 {fixed time zones} {
+    clock format 0 -format {%F %T %z} -timezone -05:00
+} {1969-12-31 19:00:00 -0500}
+
+test clock-1.4 {unknown time zones} -body {
+    clock format 0 -timezone Nowhere/Special
+} -returnCodes error -result {time zone "Nowhere/Special" not found}
+
+test clock-2.1 {scan ISO 8601} {
+    clock scan 2024-02-29T13:05:09Z
+} 1709211909
+
+test clock-2.2 {scan with a format} {
+    clock scan {29/02/2024 13:05:09} -format {%d/%m/%Y %H:%M:%S} -gmt 1
+} 1709211909
+
+test clock-2.3 {scan takes the missing date from -base} {
+    clock scan 01:00 -format %H:%M -base 86400 -gmt 1
+} 90000
+
+test clock-2.4 {scan round-trips format} {
+    set t 951782400
+    set text [clock format $t -format {%Y-%m-%d %H:%M:%S} -timezone +0930]
+    clock scan $text -format {%Y-%m-%d %H:%M:%S} -timezone +0930
+} 951782400
+
+test clock-2.5 {scan rejects what doesn't match} -body {
+    clock scan 2023-02-29 -format %Y-%m-%d
+} -returnCodes error -result {input string "2023-02-29" does not match supplied format "%Y-%m-%d"}
+
+test clock-3.1 {seconds are counted from the epoch} {
+    > [clock seconds] 1700000000
+} 1
+
+testreport clock.tcl
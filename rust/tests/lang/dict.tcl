@@ -0,0 +1,111 @@
+# Dictionaries: flat and nested key paths, merging, incr and update
+
+test dict-1.1 {create, get, keys, values and size} {
+    set d [dict create a 1 b 2]
+    set r "[dict get $d b] [dict keys $d] [dict values $d] [dict size $d]"
+} {2 a b 1 2 2}
+
+test dict-1.2 {get without keys returns the dictionary} {
+    dict get {a 1 b 2}
+} {a 1 b 2}
+
+test dict-1.3 {later duplicate keys win but keep their place} {
+    dict create a 1 b 2 a 3
+} {a 3 b 2}
+
+test dict-1.4 {a value without a key} -body {
+    dict get {a 1 b} a
+} -returnCodes error -result {missing value to go with key}
+
+test dict-2.1 {three-level set creates intermediate dictionaries} {
+    set cfg {}
+    dict set cfg server http port 8080
+    dict set cfg server http host localhost
+    dict set cfg server name web
+    set r "$cfg | [dict get $cfg server http port]"
+} {server {http {port 8080 host localhost} name web} | 8080}
+
+test dict-2.2 {set into a variable that doesn't exist yet} {
+    catch {unset fresh}
+    dict set fresh a b 1
+} {a {b 1}}
+
+test dict-2.3 {get names the first missing key} -body {
+    dict get {a {b {c 1}}} a x c
+} -returnCodes error -result {key "x" not known in dictionary}
+
+test dict-2.4 {exists along a path} {
+    set d {a {b {c 1}}}
+    set r "[dict exists $d a b c] [dict exists $d a x c] [dict exists $d a b c d]"
+} {1 0 0}
+
+test dict-3.1 {merge: later dictionaries win} {
+    dict merge {a 1 b 2} {b 3 c 4} {c 5}
+} {a 1 b 3 c 5}
+
+test dict-3.2 {merge of nothing} {
+    dict merge
+} {}
+
+test dict-4.1 {incr by one and by an amount} {
+    set counts {x 1}
+    dict incr counts x
+    dict incr counts y 5
+    set counts
+} {x 2 y 5}
+
+test dict-4.2 {incr of a value that isn't an integer} -body {
+    set d {x abc}
+    dict incr d x
+} -returnCodes error -result {expected integer but got "abc"}
+
+test dict-4.3 {unset removes a key} {
+    set d {a 1 b 2}
+    dict unset d a
+} {b 2}
+
+test dict-5.1 {update binds locals and writes them back} {
+    set d {name old count 1}
+    set r [dict update d name n count c {
+        set n new
+        set c [+ $c 1]
+        set done yes
+    }]
+    set r "$r | $d"
+} {yes | name new count 2}
+
+test dict-5.2 {update writes back even when the body errors} {
+    set d {a 1 b 2}
+    set code [catch {
+        dict update d a x b y {
+            set x changed
+            error oops
+            set y never
+        }
+    } msg]
+    set r "$code $msg | $d"
+} {1 oops | a changed b 2}
+
+test dict-5.3 {unsetting a local removes its key, and missing keys start unset} {
+    set d {a 1 b 2}
+    dict update d a x c z {
+        unset x
+        set z [catch {set z}]
+    }
+    set d
+} {b 2 c 1}
+
+test dict-6.1 {thousands of keys, some repeated} {
+    set l {}
+    set i 0
+    while {$i < 3000} {
+        set l "$l k$i $i k[% $i 100] x"
+        set i [+ $i 1]
+    }
+    set d [dict merge $l]
+    dict unset d k10
+    set r "[dict size $d] [dict get $d k5] [dict get $d k2999]"
+    set r "$r [lindex [dict keys $d] 10] [lindex [dict keys $d] end]"
+} {2999 x 2999 k11 k2999}
+
+testreport dict.tcl
@@ -0,0 +1,62 @@
+# errorCode, and the options catch and try hand back
+
+# open is left out of builds without the io feature
+if {[info commands open] ne ""} {
+    test errorcode-1.1 {dispatching on the errorCode of a missing file} {
+        catch {open /no/such/dir/file} msg
+        set reason [lindex $errorCode 1]
+        if {$reason eq "ENOENT"} {
+            set r missing
+        } elseif {$reason eq "EACCES"} {
+            set r denied
+        } else {
+            set r other
+        }
+        set r "$r [lindex $errorCode 0] [lindex $errorCode 2]"
+    } {missing POSIX no such file or directory}
+}
+
+test errorcode-1.2 {division by zero} {
+    catch {/ 1 0}
+    set errorCode
+} {ARITH DIVZERO {divide by zero}}
+
+test errorcode-1.3 {plain errors have no code} {
+    catch {error oops}
+    set errorCode
+} NONE
+
+test errorcode-1.4 {error sets the code it's given} {
+    catch {error "bad input" {} {MYAPP PARSE 12}}
+    lindex $errorCode 1
+} PARSE
+
+test errorcode-2.1 {the options of a plain script error} {
+    proc fails {} {error "it broke"}
+    set code [catch {fails} msg opts]
+    set r "$code $msg | [dict get $opts -code] [dict get $opts -errorcode]"
+    set r "$r | [string range [dict get $opts -errorinfo] 0 7]"
+} {1 it broke | 1 NONE | it broke}
+
+test errorcode-2.2 {the options of a script that succeeds} {
+    catch {set x 1} msg opts
+    set opts
+} {-code 0 -level 0}
+
+test errorcode-2.3 {the options of break} {
+    catch {break} msg opts
+    dict get $opts -code
+} 3
+
+test errorcode-2.4 {try handlers get the same options} {
+    try {error denied {} {AUTH DENIED}} on error {msg opts} {
+        set r "$msg [dict get $opts -errorcode]"
+    }
+} {denied AUTH DENIED}
+
+test errorcode-3.1 {error with info starts the traceback from it} {
+    catch {error outer "inner trace"} msg opts
+    string range [dict get $opts -errorinfo] 0 10
+} {inner trace}
+
+testreport errorcode.tcl
@@ -0,0 +1,75 @@
+# foreach over one or several lists, several variables at a time
+
+test foreach-1.1 {one variable over one list} {
+    set r {}
+    foreach x {a b c} {set r "$r$x"}
+    set r
+} abc
+
+test foreach-1.2 {an empty list runs nothing} {
+    set r none
+    foreach x {} {set r ran}
+    set r
+} none
+
+test foreach-2.1 {pairs from a flat key/value list} {
+    set r {}
+    foreach {k v} {a 1 b 2 c 3} {set r "$r$k=$v "}
+    set r
+} {a=1 b=2 c=3 }
+
+test foreach-2.2 {a short last group is padded with empty strings} {
+    set r {}
+    foreach {a b c} {1 2 3 4} {set r "$r<$a|$b|$c>"}
+    set r
+} {<1|2|3><4||>}
+
+test foreach-3.1 {parallel lists of unequal length} {
+    set r {}
+    foreach a {1 2 3} b {x y} {set r "$r$a$b,"}
+    set r
+} {1x,2y,3,}
+
+test foreach-3.2 {parallel lists taking different numbers at a time} {
+    set r {}
+    foreach {k v} {a 1 b 2} n {first second third} {set r "$r$n:$k$v "}
+    set r
+} {first:a1 second:b2 third: }
+
+test foreach-4.1 {break inside the multi-list form} {
+    set r {}
+    foreach a {1 2 3 4} b {w x y z} {
+        if {$a == 3} break
+        set r "$r$a$b"
+    }
+    set r
+} {1w2x}
+
+test foreach-4.2 {continue skips to the next group} {
+    set r {}
+    foreach {k v} {a 1 b 2 c 3} {
+        if {$k eq "b"} continue
+        set r "$r$k"
+    }
+    set r
+} ac
+
+test foreach-4.3 {return from a proc inside foreach} {
+    proc first_even {l} {
+        foreach x $l {
+            if {[% $x 2] == 0} {return $x}
+        }
+        return none
+    }
+    set r "[first_even {1 3 4 6}] [first_even {1 3}]"
+} {4 none}
+
+test foreach-5.1 {a missing list} -body {
+    foreach a {1 2} b {x}
+} -returnCodes error -result {wrong # args: should be "foreach varList list ?varList list ...? command"}
+
+test foreach-5.2 {an empty varList} -body {
+    foreach {} {1 2} {}
+} -returnCodes error -result {wrong # args: should be "foreach varList list ?varList list ...? command"}
+
+testreport foreach.tcl
@@ -0,0 +1,43 @@
+# if
+
+test if-1.1 {true condition} {
+    if {1} {set r yes} else {set r no}
+} yes
+
+test if-1.2 {false condition} {
+    if {0} {set r yes} else {set r no}
+} no
+
+test if-1.3 {elseif chain} {
+    set x 2
+    if {$x == 1} {
+        set r one
+    } elseif {$x == 2} {
+        set r two
+    } else {
+        set r other
+    }
+} two
+
+test if-1.4 {no branch taken gives the empty string} {
+    if {0} {set r yes}
+} {}
+
+test if-2.1 {condition as a command} {
+    if {< 1 2} {set r less}
+} less
+
+test if-2.2 {errors in the condition propagate} -body {
+    if {$nosuch} {set r yes}
+} -returnCodes error -result {can't read "nosuch": no such variable}
+
+test if-2.3 {a bare word is a boolean, not a command} -body {
+    if maybe {set r yes}
+} -returnCodes error -result {expected boolean value but got "maybe"}
+
+test if-2.4 {even when a command has that name} -body {
+    proc maybe {} {return 1}
+    if {maybe} {set r yes}
+} -returnCodes error -result {expected boolean value but got "maybe"}
+
+testreport if.tcl
@@ -0,0 +1,78 @@
+# Commands taking indexes: integers, end, and either with an offset
+
+test index-1.1 {lindex with end-relative indexes} {
+    set l {a b c d}
+    set r [lindex $l end]
+    set r "$r [lindex $l end-1] [lindex $l 1+1] [lindex $l end-3]"
+} {d c c a}
+
+test index-1.2 {lindex out of range is empty} {
+    set r "[lindex {a b} 2][lindex {a b} -1][lindex {a b} end+1]"
+} {}
+
+test index-1.3 {lindex into nested lists} {
+    lindex {a {b {c d}}} 1 end 0
+} c
+
+test index-1.4 {lindex without an index returns the list} {
+    lindex {a b}
+} {a b}
+
+test index-1.5 {bad indexes} -body {
+    lindex {a b} end-x
+} -returnCodes error -result {bad index "end-x": must be integer?[+-]integer? or end?[+-]integer?}
+
+test index-2.1 {lrange clamps to the list} {
+    set r "[lrange {a b c d} -5 1] | [lrange {a b c d} end-1 end+9]"
+} {a b | c d}
+
+test index-2.2 {lrange of an empty range} {
+    lrange {a b c} 2 1
+} {}
+
+test index-3.1 {linsert before an index} {
+    linsert {a b c} 1 x y
+} {a x y b c}
+
+test index-3.2 {linsert at end appends} {
+    set r "[linsert {a b c} end x] | [linsert {a b c} end-1 x]"
+} {a b c x | a b x c}
+
+test index-3.3 {linsert clamps} {
+    set r "[linsert {a b} -3 x] | [linsert {a b} 9 y]"
+} {x a b | a b y}
+
+test index-4.1 {lset replaces an element} {
+    set l {a b c}
+    lset l end-1 {x y}
+    set l
+} {a {x y} c}
+
+test index-4.2 {lset into nested lists} {
+    set l {a {b c}}
+    lset l 1 0 z
+} {a {z c}}
+
+test index-4.3 {lset just past the end appends} {
+    set l {a b}
+    lset l end+1 c
+} {a b c}
+
+test index-4.4 {lset out of range} -body {
+    set l {a b}
+    lset l 3 c
+} -returnCodes error -result {list index out of range}
+
+test index-5.1 {string index} {
+    set r "[string index héllo 1][string index héllo end][string index abc 5]"
+} {éo}
+
+test index-5.2 {string range clamps} {
+    set r "[string range abcdef 1 end-1] [string range abcdef -2 1] [string range abc 2 1]|"
+} {bcde ab |}
+
+test index-5.3 {string length counts characters} {
+    string length héllo
+} 5
+
+testreport index.tcl
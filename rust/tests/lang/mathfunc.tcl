@@ -0,0 +1,60 @@
+# Math functions in expr
+
+test mathfunc-1.1 {float functions} {
+    set r "[expr {sqrt(16)}] [expr {pow(2, 10)}] [expr {floor(2.5)}] [expr {ceil(-2.5)}]"
+} {4.0 1024.0 2.0 -2.0}
+
+test mathfunc-1.2 {trigonometry} {
+    set r "[expr {sin(0)}] [expr {cos(0)}] [expr {round(atan2(1, 1) * 4 * 1000)}]"
+} {0.0 1.0 3142}
+
+test mathfunc-1.3 {exp and logs} {
+    set r "[expr {exp(0)}] [expr {log(1)}] [expr {log10(1000)}]"
+} {1.0 0.0 3.0}
+
+test mathfunc-2.1 {int truncates toward zero} {
+    set r "[expr {int(3.7)}] [expr {int(-3.7)}] [expr {int(5)}]"
+} {3 -3 5}
+
+test mathfunc-2.2 {round rounds half away from zero} {
+    set r "[expr {round(2.5)}] [expr {round(-2.5)}] [expr {round(2.4)}] [expr {round(7)}]"
+} {3 -3 2 7}
+
+test mathfunc-2.3 {double forces a float} {
+    set r "[expr {double(3)}] [expr {double(3) / 2}] [expr {3 / 2}]"
+} {3.0 1.5 1}
+
+test mathfunc-2.4 {abs, min and max keep their argument's type} {
+    set r "[expr {abs(-3)}] [expr {abs(-2.5)}] [expr {min(3, 2.5, 4)}] [expr {max(1, 7, 2)}]"
+} {3 2.5 2.5 7}
+
+test mathfunc-3.1 {nested calls} {
+    set x 2
+    expr {round(sqrt($x) * 100)}
+} 141
+
+test mathfunc-3.2 {calls mix with operators and whitespace} {
+    expr { 1 + max ( 2 , 3 * 2 ) }
+} 7
+
+test mathfunc-4.1 {square root of a negative} -body {
+    expr {sqrt(-1)}
+} -returnCodes error -result {domain error: argument not in valid range}
+
+test mathfunc-4.2 {log of zero} -body {
+    expr {log(0)}
+} -returnCodes error -result {domain error: argument not in valid range}
+
+test mathfunc-4.3 {argument counts} -body {
+    expr {pow(2)}
+} -returnCodes error -result {too few arguments for math function "pow"}
+
+test mathfunc-4.4 {unknown functions} -body {
+    expr {frob(2)}
+} -returnCodes error -result {syntax error in expression "frob(2)": unknown math function "frob"}
+
+test mathfunc-4.5 {integers too large} -body {
+    expr {int(1e300)}
+} -returnCodes error -result {integer value too large to represent}
+
+testreport mathfunc.tcl
@@ -0,0 +1,89 @@
+# proc and return
+
+proc double {x} {
+    * $x 2
+}
+
+test proc-1.1 {the result is the last command's} {
+    double 21
+} 42
+
+test proc-1.2 {return ends the proc} {
+    proc early {} {
+        return first
+        set never 1
+    }
+    early
+} first
+
+test proc-1.3 {default arguments} {
+    proc greet {{name world}} {
+        return "hello $name"
+    }
+    set r "[greet], [greet tcl]"
+} {hello world, hello tcl}
+
+test proc-1.4 {variadic arguments} {
+    proc rest {first args} {
+        return $args
+    }
+    rest a b {c d}
+} {b {c d}}
+
+test proc-2.1 {locals don't leak} -body {
+    proc local {} {
+        set inside 1
+    }
+    local
+    set inside
+} -returnCodes error -result {can't read "inside": no such variable}
+
+test proc-2.2 {global links to the top level} {
+    set counter 0
+    proc bump {} {
+        global counter
+        set counter [+ $counter 1]
+    }
+    bump
+    bump
+    set counter
+} 2
+
+test proc-2.3 {upvar links to the caller} {
+    proc setter {name value} {
+        upvar $name var
+        set var $value
+    }
+    setter target 7
+    set target
+} 7
+
+test proc-3.1 {recursion} {
+    proc fact {n} {
+        if {$n <= 1} {
+            return 1
+        }
+        * $n [fact [- $n 1]]
+    }
+    fact 10
+} 3628800
+
+test proc-3.2 {wrong number of arguments} -body {
+    double
+} -returnCodes error -result {wrong # args: should be "double x"}
+
+test proc-3.3 {too few arguments name every formal} -body {
+    proc pair {a b} {list $a $b}
+    pair 1
+} -returnCodes error -result {wrong # args: should be "pair a b"}
+
+test proc-3.4 {too many arguments} -body {
+    pair 1 2 3
+} -returnCodes error -result {wrong # args: should be "pair a b"}
+
+test proc-3.5 {defaults and args in the message} -body {
+    proc opts {a {b 2} args} {list $a $b $args}
+    opts
+} -returnCodes error -result {wrong # args: should be "opts a ?b? ?arg ...?"}
+
+testreport proc.tcl
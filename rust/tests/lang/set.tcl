@@ -0,0 +1,44 @@
+# set and unset
+
+test set-1.1 {set returns the new value} {
+    set a 5
+} 5
+
+test set-1.2 {set with one argument reads} {
+    set b hello
+    set b
+} hello
+
+test set-1.3 {values are strings} {
+    set c "two words"
+    set d $c
+} {two words}
+
+test set-1.4 {nested sets pass the value along} {
+    set y [set x 5]
+    set r "$x $y [set z [set y]]"
+} {5 5 5}
+
+test set-1.5 {set with one argument reads an array element} {
+    set arr2(k) v
+    set arr2(k)
+} v
+
+test set-2.1 {reading a missing variable} -body {
+    set nosuch
+} -returnCodes error -result {can't read "nosuch": no such variable}
+
+test set-2.2 {unset removes a variable} -setup {
+    set gone 1
+} -body {
+    unset gone
+    catch {set gone}
+} -result 1
+
+test set-3.1 {array elements} {
+    set arr(one) 1
+    set arr(two) 2
+    + $arr(one) $arr(two)
+} 3
+
+testreport set.tcl
@@ -0,0 +1,55 @@
+# interp steplimit, which bounds the steps a child's evaluations take
+
+test steplimit-1.1 {children start without a limit} -setup {
+    interp create child
+} -body {
+    interp steplimit child
+} -cleanup {
+    interp delete child
+} -result {}
+
+test steplimit-1.2 {setting a limit returns it} -setup {
+    interp create child
+} -body {
+    interp steplimit child 10000
+    interp steplimit child
+} -cleanup {
+    interp delete child
+} -result 10000
+
+test steplimit-2.1 {an endless loop runs out of steps} -setup {
+    interp create child
+    interp steplimit child 10000
+} -body {
+    interp eval child {while 1 {}}
+} -cleanup {
+    interp delete child
+} -returnCodes error -result {child: evaluation limit exceeded after 10000 steps}
+
+test steplimit-2.2 {catch inside the child can't hold off the limit} -setup {
+    interp create child
+    interp steplimit child 10000
+} -body {
+    interp eval child {while 1 {catch {error again}}}
+} -cleanup {
+    interp delete child
+} -returnCodes error -result {child: evaluation limit exceeded after 10000 steps}
+
+test steplimit-3.1 {scripts within the limit finish} -setup {
+    interp create child
+    interp steplimit child 10000
+} -body {
+    interp eval child {set i 0; while {$i < 100} {set i [+ $i 1]}; return $i}
+} -cleanup {
+    interp delete child
+} -result 100
+
+test steplimit-3.2 {an empty limit removes it} -setup {
+    interp create child
+    interp steplimit child 10
+    interp steplimit child {}
+} -body {
+    interp eval child {set i 0; while {$i < 10000} {set i [+ $i 1]}; return $i}
+} -cleanup {
+    interp delete child
+} -result 10000
@@ -0,0 +1,105 @@
+# trace add, remove and info execution
+
+proc double {x} { * $x 2 }
+
+test trace-1.1 {enter traces count calls} {
+    set calls 0
+    proc count {cmd op} { global calls; set calls [+ $calls 1] }
+    trace add execution double enter count
+    foreach i {1 2 3 4 5} { double $i }
+    trace remove execution double enter count
+    set calls
+} 5
+
+test trace-1.2 {enter traces get the command's words} {
+    proc remember {cmd op} { global seen; set seen [string length $cmd]:$op }
+    trace add execution double enter remember
+    double {2}
+    trace remove execution double enter remember
+    set seen
+} 8:enter
+
+test trace-2.1 {leave traces see the result} {
+    proc log {cmd code result op} { global seen; set seen "$cmd -> $code $result $op" }
+    trace add execution double leave log
+    double 21
+    trace remove execution double leave log
+    set seen
+} {double 21 -> 0 42 leave}
+
+test trace-2.2 {leave traces see errors, which carry on} {
+    proc fail {} { error boom }
+    trace add execution fail leave log
+    set r [catch fail m]
+    set r "$r $m / $seen"
+} {1 boom / fail -> 1 boom leave}
+
+test trace-2.3 {the command keeps its result} {
+    proc noisy {args} { return ignored }
+    trace add execution double {enter leave} noisy
+    set r [double 4]
+    trace remove execution double {enter leave} noisy
+    set r
+} 8
+
+test trace-3.1 {removing a trace stops it} {
+    set calls 0
+    trace add execution double enter count
+    double 1
+    trace remove execution double enter count
+    double 2
+    set r "$calls [trace info execution double]"
+} {1 }
+
+test trace-3.2 {trace info lists the traces} {
+    trace add execution double enter count
+    trace add execution double {leave enter} log
+    set info [trace info execution double]
+    trace remove execution double enter count
+    trace remove execution double {enter leave} log
+    set info
+} {{enter count} {{enter leave} log}}
+
+test trace-4.1 {callbacks can call the traced command} {
+    proc again {cmd op} { global inner; set inner [double 100] }
+    trace add execution double enter again
+    set r [double 1]
+    trace remove execution double enter again
+    set r "$r $inner"
+} {2 200}
+
+test trace-5.1 {traces go with a renamed command} {
+    proc triple {x} { * $x 3 }
+    set calls 0
+    trace add execution triple enter count
+    rename triple thrice
+    thrice 1
+    set r "$calls [trace info execution thrice]"
+} {1 {enter count}}
+
+test trace-5.2 {traces stay when a proc is redefined} {
+    proc thrice {x} { * $x 30 }
+    thrice 1
+    set calls
+} 2
+
+test trace-5.3 {traces go when a command is deleted} {
+    rename thrice {}
+    proc thrice {x} { * $x 3 }
+    thrice 1
+    set r "$calls [trace info execution thrice]"
+} {2 }
+
+test trace-6.1 {only existing commands} -body {
+    trace add execution nosuch enter count
+} -returnCodes error -result {unknown command "nosuch"}
+
+test trace-6.2 {only enter and leave} -body {
+    trace add execution double step count
+} -returnCodes error -result {bad operation "step": must be enter or leave}
+
+test trace-6.3 {wrong # args} -body {
+    trace info execution double extra
+} -returnCodes error -result {wrong # args: should be "trace info execution name"}
+
+testreport trace.tcl
@@ -0,0 +1,33 @@
+# while, break and continue
+
+test while-1.1 {counting} {
+    set i 0
+    while {$i < 10} {set i [+ $i 1]}
+    set i
+} 10
+
+test while-2.1 {break leaves the loop} {
+    set i 0
+    while {1} {
+        set i [+ $i 1]
+        if {$i == 5} break
+    }
+    set i
+} 5
+
+test while-2.2 {continue skips the rest of the body} {
+    set i 0
+    set sum 0
+    while {$i < 10} {
+        set i [+ $i 1]
+        if {[% $i 2] == 0} continue
+        set sum [+ $sum $i]
+    }
+    set sum
+} 25
+
+test while-3.1 {errors in the body propagate} -body {
+    while {1} {error "stop here"}
+} -returnCodes error -result {stop here}
+
+testreport while.tcl
@@ -24,12 +24,14 @@ def parse_args():
     return parser.parse_args()
 
 def exec_test(filepath):
+    # Options go before the script, since anything after it is passed to
+    # the script as $argv
     cmd = [args.impl, filepath]
     env = os.environ.copy()
     if 'picol' in filepath:
         env['PARSER_STDERR'] = '1'
     else:
-        cmd = cmd + ['--trace-parser']
+        cmd = [args.impl, '--trace-parser', filepath]
     result = subprocess.run(cmd, capture_output=True, text=True, env=env)
     return result.stdout.strip(), result.stderr.strip()
 
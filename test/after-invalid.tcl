@@ -0,0 +1 @@
+after soon
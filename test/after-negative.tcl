@@ -0,0 +1 @@
+after -5
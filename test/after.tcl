@@ -0,0 +1,7 @@
+after 10
+puts slept
+after 10 {puts done}
+proc late {} {
+  after 0 {return 5}
+}
+puts [late]
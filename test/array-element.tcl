@@ -0,0 +1,20 @@
+set prices(apple) 3
+set prices(pear) 5
+puts $prices(apple)
+puts "pears cost $prices(pear) each"
+set fruit pear
+puts $prices($fruit)
+puts [set prices(pear)]
+set prices(pear) 7
+puts $prices(pear)
+puts [expr {$prices(apple) + $prices($fruit)}]
+puts [catch {set prices(plum)} msg]
+puts $msg
+puts [catch {puts $prices} msg]
+puts $msg
+set scalar 1
+puts [catch {set scalar(x) 2} msg]
+puts $msg
+unset prices(apple)
+puts [catch {puts $prices(apple)} msg]
+puts $msg
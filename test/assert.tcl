@@ -0,0 +1,19 @@
+assert {== 1 1}
+assert {< 1 2} "one is less than two"
+assert_eq [+ 2 2] 4
+puts passed
+
+puts [catch {assert {> 1 2}} msg]
+puts $msg
+puts [catch {assert {> 1 2} "math is broken"} msg]
+puts $msg
+set four [+ 2 2]
+puts [catch {assert_eq $four 5} msg]
+puts $msg
+
+proc check {x} {
+  assert {> $x 0} "x must be positive"
+  puts unreachable
+}
+puts [catch {check -1} msg]
+puts $msg
@@ -0,0 +1,15 @@
+puts "a\tb"
+puts "line1\nline2"
+puts "back\\slash"
+puts "say \"hi\""
+puts \{
+puts \}
+puts a\ b
+puts \$notavar
+puts "\[not a command\]"
+puts "\x41\x4a \u00e9 \101\102"
+puts "\q"
+set x "quoted \"\$\" sign"
+puts $x
+puts {braced \n stays \{ literal}
+puts [set y \]]
@@ -0,0 +1,12 @@
+proc id {x} {return $x}
+puts [id {]}]
+puts [id "a]b"]
+puts [id [id [id nested]]]
+puts [id x[id y]z]
+puts "[id {a]}] and [id "q]"]"
+set s [id {[}]
+puts $s
+puts [id \]]
+puts [expr {[id 2] + [id {3}]}]
+puts [catch {id [id x} msg]
+puts $msg
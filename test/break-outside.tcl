@@ -0,0 +1,31 @@
+puts [catch {break}]
+proc f {} {
+  break
+}
+puts [catch {f} msg]
+puts $msg
+proc g {} {
+  continue
+}
+set i 0
+proc incr-i {} {
+  global i
+  set i [+ $i 1]
+}
+set loop {while {$i < 3} {incr-i; g}}
+puts [catch $loop msg]
+puts $msg
+puts $i
+proc h {} {
+  set n 0
+  while 1 {
+    set n [+ $n 1]
+    if {$n == 2} continue
+    if {$n > 3} break
+  }
+  return $n
+}
+puts [h]
+puts before
+break
+puts after
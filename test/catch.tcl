@@ -0,0 +1,9 @@
+puts [catch {error boom} msg]
+puts $msg
+puts [catch {+ 1 1} value]
+puts $value
+puts [catch {nosuchcommand}]
+puts [catch {break}]
+puts [catch {continue}]
+puts [catch {return 5} value]
+puts $value
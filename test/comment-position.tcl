@@ -0,0 +1,15 @@
+# comment at script start
+set x a#b
+puts $x
+puts #items
+set y 1 ;# comment after semicolon
+puts $y
+puts {# inside braces}
+proc p {} {
+    # comment in a body
+    return ok
+}
+puts [p]
+  # indented comment
+puts "#quoted"
+# comment at the end without a newline
\ No newline at end of file
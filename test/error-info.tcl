@@ -0,0 +1,15 @@
+proc a {} {
+  b
+}
+proc b {} {
+  c
+}
+proc c {} {
+  error "deep failure"
+}
+puts [catch {a} msg]
+puts $msg
+puts $errorInfo
+puts [catch {error second} msg]
+puts $errorInfo
+a
@@ -0,0 +1,4 @@
+set x 1
+puts $x
+putz hello
+puts unreachable
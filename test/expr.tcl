@@ -0,0 +1,25 @@
+puts [expr 1 + 2 * 3]
+puts [expr {(1 + 2) * 3}]
+puts [expr {2 ** 3 ** 2}]
+puts [expr {-2 + 5}]
+puts [expr {7 / 2.0}]
+puts [expr {0x10 + 0b11}]
+puts [expr {~5}]
+puts [expr {!0}]
+set x 4
+puts [expr {$x * $x}]
+set e {[+ 1 2] + $x}
+puts [expr $e]
+puts [expr {$x > 3 ? "big" : "small"}]
+puts [expr {"abc" eq "abc"}]
+puts [expr {"abc" < "abd"}]
+set e {1 || [error never]}
+puts [expr $e]
+set e {0 && [error never]}
+puts [expr $e]
+puts [catch {expr {1 +}} msg]
+puts $msg
+puts [catch {expr {"a" + 1}} msg]
+puts $msg
+puts [catch {expr {foo}} msg]
+puts $msg
@@ -0,0 +1,31 @@
+if true {
+  puts true-branch
+}
+if FALSE {
+  puts wrong
+} else {
+  puts false-branch
+}
+if yes {puts yes}
+if off {puts wrong} else {puts off}
+if 2.5 {puts float}
+if 0 {puts wrong} else {puts zero}
+while {false} {
+  puts wrong
+}
+while no {
+  puts wrong
+}
+proc truthy {} {
+  return on
+}
+if {[truthy]} {
+  puts script-result
+}
+proc undecided {} {
+  return maybe
+}
+puts [catch {if {[undecided]} {puts wrong}} msg]
+puts $msg
+puts [catch {if maybe {puts wrong}} msg]
+puts $msg
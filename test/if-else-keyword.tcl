@@ -0,0 +1,13 @@
+if 0 {puts wrong} {puts implicit-else}
+if 1 {puts then-branch} {puts wrong}
+if 0 {puts wrong} else {puts explicit-else}
+if 0 {puts wrong} elseif 0 {puts wrong} {puts implicit-after-elseif}
+puts [catch {if 1 {puts a} banana {puts b}} msg]
+puts $msg
+puts [catch {if 0 {puts a} else} msg]
+puts $msg
+if 1 then {puts then-keyword}
+if 0 then {puts wrong} elseif 1 then {puts then-after-elseif} else {puts wrong}
+if 0 then {puts wrong} {puts then-implicit-else}
+puts [catch {if 1 then} msg]
+puts $msg
@@ -0,0 +1,40 @@
+proc classify {n} {
+  if {< $n 0} {
+    puts negative
+  } elseif {== $n 0} {
+    puts zero
+  } elseif {< $n 10} {
+    puts small
+  } elseif {< $n 100} {
+    puts medium
+  } else {
+    puts large
+  }
+}
+classify -5
+classify 0
+classify 7
+classify 50
+classify 500
+
+set hits 0
+if 0 {
+  set hits [+ $hits 1]
+} elseif 1 {
+  set hits [+ $hits 10]
+} elseif 1 {
+  set hits [+ $hits 100]
+} else {
+  set hits [+ $hits 1000]
+}
+puts $hits
+
+if 0 {puts wrong} elseif 0 {puts wrong}
+puts no-branch
+
+puts [catch {if 0 {puts a} elseif} msg]
+puts $msg
+puts [catch {if 0 {puts a} elseif 1} msg]
+puts $msg
+puts [catch {if 0 {puts a} else {puts b} junk} msg]
+puts $msg
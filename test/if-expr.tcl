@@ -0,0 +1,24 @@
+set x 5
+if {$x > 3} {
+  puts greater
+}
+if {$x > 3 && $x < 10} {
+  puts between
+}
+if {$x == 1 || $x == 2} {puts wrong} elseif {$x % 2 == 1} {puts odd}
+set i 0
+set sum 0
+while {$i < 10} {
+  set sum [+ $sum $i]
+  set i [+ $i 1]
+}
+puts $sum
+if {== $x 5} {
+  puts script-condition
+}
+if {-7 / 2 == -4 && 7 / -2 == -4} {
+  puts floor-division
+}
+puts [expr {-7/2}]
+puts [expr {7/-2}]
+puts [expr {-7 % 2 + -7 / 2 * 2}]
@@ -0,0 +1,14 @@
+proc zeta {} {}
+proc alpha {} {}
+proc mid {} {}
+puts [info commands zeta]
+puts [info commands nosuch]
+set pattern {[azmo][lime]*}
+puts [info commands $pattern]
+rename alpha omega
+puts [info commands $pattern]
+rename zeta {}
+proc zeta {} {}
+puts [info commands $pattern]
+puts [info commands {*et?}]
+puts [info commands ?f]
@@ -0,0 +1,23 @@
+set i 0
+while {$i < 1000} {
+  set "v$i" $i
+  set i [+ $i 1]
+}
+puts [set v0]
+puts [set v500]
+puts [set v999]
+puts [info vars v99?]
+unset v995
+puts [info vars v99?]
+set zebra 1
+set apple 2
+set mango 3
+set pattern {[amz][a-p]*}
+puts [info vars $pattern]
+proc locals {} {
+  global apple
+  set b 1
+  set a 2
+  return [info vars]
+}
+puts [locals]
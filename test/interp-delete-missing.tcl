@@ -0,0 +1 @@
+interp delete nobody
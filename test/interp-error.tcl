@@ -0,0 +1,2 @@
+interp create child
+interp eval child {nosuchcommand}
@@ -0,0 +1,3 @@
+interp create child
+interp eval child {set x 1}
+puts $x
@@ -0,0 +1,16 @@
+puts [interp create child]
+puts [interp exists child]
+puts [interp exists other]
+interp eval child {set secret 42}
+puts [interp eval child {+ $secret 1}]
+interp eval child {
+  proc only_in_child {} {
+    return child-proc
+  }
+}
+puts [interp eval child only_in_child]
+set secret parent
+puts $secret
+interp delete child
+puts [interp exists child]
+puts [interp create]
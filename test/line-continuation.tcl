@@ -0,0 +1,13 @@
+proc show {a b c} {puts "$a $b $c"}
+show a \
+    b \
+    c
+puts "one \
+      two"
+set x {first \
+       second}
+puts $x
+# a comment \
+puts "this line is part of the comment"
+puts {kept \n as is}
+puts end\\
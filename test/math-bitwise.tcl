@@ -0,0 +1,15 @@
+puts [% 7 3]
+puts [% -7 3]
+puts [% 7 -3]
+puts [% -7 -3]
+puts [% 6 3]
+puts [& 12 10]
+puts [| 12 10]
+puts [^ 12 10]
+puts [<< 1 40]
+puts [>> 1024 3]
+puts [>> -16 2]
+puts [catch {<< 1 64} msg]
+puts $msg
+puts [catch {>> 1 -1} msg]
+puts $msg
@@ -0,0 +1,9 @@
+puts [/ 7 2]
+puts [/ -7 2]
+puts [/ 7 -2]
+puts [/ -7 -2]
+puts [/ -6 2]
+puts [/ 0 -3]
+foreach {a b} {7 2 -7 2 7 -2 -7 -2 -6 4 1 -5} {
+  puts [== $a [+ [* [/ $a $b] $b] [% $a $b]]]
+}
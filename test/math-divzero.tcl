@@ -0,0 +1,7 @@
+puts [catch {/ 1 0} msg]
+puts $msg
+puts [catch {% 5 0} msg]
+puts $msg
+puts [catch {/ 1.5 0} msg]
+puts $msg
+puts still-running
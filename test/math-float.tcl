@@ -0,0 +1,13 @@
+puts [+ 1.5 2]
+puts [/ 7 2]
+puts [/ 7.0 2]
+puts [< 1.5 2]
+puts [> 1.5 2]
+puts [== 2.0 2]
+puts [* 0.5 4]
+puts [- 0.1 0.3]
+puts [+ 1e3 1]
+puts [catch {% 7.5 2} msg]
+puts $msg
+puts [catch {+ 1.5 abc} msg]
+puts $msg
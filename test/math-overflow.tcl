@@ -0,0 +1,26 @@
+puts [+ 9223372036854775806 1]
+puts [catch {+ 9223372036854775807 1} msg]
+puts $msg
+puts [catch {+ -9223372036854775808 -1} msg]
+puts $msg
+puts [- -9223372036854775807 1]
+puts [catch {- -9223372036854775808 1} msg]
+puts $msg
+puts [catch {- 9223372036854775807 -1} msg]
+puts $msg
+puts [catch {- 0 -9223372036854775808} msg]
+puts $msg
+puts [* 4611686018427387903 2]
+puts [catch {* 9223372036854775807 2} msg]
+puts $msg
+puts [catch {* -9223372036854775808 -1} msg]
+puts $msg
+puts [/ -9223372036854775808 1]
+puts [catch {/ -9223372036854775808 -1} msg]
+puts $msg
+puts [% -9223372036854775808 -1]
+puts [<< 1 62]
+puts [catch {<< 1 63} msg]
+puts $msg
+puts [catch {<< -9223372036854775808 1} msg]
+puts $msg
@@ -0,0 +1,23 @@
+puts [** 2 10]
+puts [** 2 0]
+puts [** -3 3]
+puts [** 2 -1]
+puts [** 2.0 0.5]
+puts [catch {** 10 30} msg]
+puts $msg
+puts [abs -5]
+puts [abs 5]
+puts [abs -2.5]
+puts [catch {abs -9223372036854775808} msg]
+puts $msg
+puts [min 3 1 2]
+puts [max 3 1 2]
+puts [min 2 1.5]
+puts [max -1 -7]
+puts [catch {** 0 -1} msg]
+puts $msg
+puts [catch {** 0.0 -2.5} msg]
+puts $errorCode
+puts [catch {expr {0 ** -1}} msg]
+puts $msg
+puts [** 0 0]
@@ -0,0 +1,16 @@
+proc join3 {a b c} {
+    return "<$a $b $c"
+}
+puts [join3 [join3 1 [join3 2 3 4] 5] [join3 [join3 6 7 8] 9 10] 11]
+proc count {n} {
+    if {== $n 0} {
+        return 0
+    }
+    set rest [count [- $n 1]]
+    return [+ $rest $n]
+}
+puts [count 50]
+set script {join3 [join3 a b c] [nope] x}
+puts [catch $script msg]
+puts $msg
+puts [join3 x y z]
@@ -0,0 +1,16 @@
+proc check {script} {
+    if {[catch $script msg]} {
+        puts "error: $msg"
+    } else {
+        puts "ok: $msg"
+    }
+}
+check "puts \{abc"
+check "puts \{a \{b\}"
+check "puts \"abc"
+check "set x \[set y 1"
+check "set x \[set y \[set z 1\]"
+check "set x \[set y \{1\]"
+check "puts \${abc"
+check "puts \$a(b"
+check "set x 1"
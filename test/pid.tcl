@@ -0,0 +1,6 @@
+set p [pid]
+puts [regexp {^\d+$} $p]
+puts [catch {pid file1} msg]
+puts $msg
+set h [info hostname]
+puts [regexp {^.+$} $h]
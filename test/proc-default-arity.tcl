@@ -0,0 +1,4 @@
+proc greet {name {greeting Hello}} {
+  puts $name
+}
+greet
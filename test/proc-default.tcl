@@ -0,0 +1,12 @@
+proc greet {name {greeting Hello}} {
+  puts $greeting
+  puts $name
+}
+greet world
+greet world Howdy
+
+proc shout {{msg {hello world}}} {
+  puts $msg
+}
+shout
+shout bye
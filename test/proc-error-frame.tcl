@@ -0,0 +1,18 @@
+set x top
+proc fail {} {
+  set x local
+  error boom
+}
+puts [catch {fail} msg]
+puts $msg
+puts $x
+proc arity {a} {
+  set x arity
+}
+puts [catch {arity} msg]
+puts $x
+proc nested {} {
+  fail
+}
+puts [catch {nested} msg]
+puts $x
@@ -0,0 +1,4 @@
+proc broken {a {b} {c 1 2}} {
+  puts $a
+}
+puts unreachable
@@ -0,0 +1,3 @@
+proc broken "a {b" {
+  puts $a
+}
@@ -0,0 +1,9 @@
+proc f { a   b		c
+  {d 4} } {
+  puts $a
+  puts $b
+  puts $c
+  puts $d
+}
+f 1 2 3
+f 5 6 7 8
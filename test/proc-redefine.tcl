@@ -0,0 +1,25 @@
+proc f {} {
+  puts first
+}
+f
+proc f {} {
+  puts second
+}
+f
+
+rename puts builtin_puts
+proc puts {msg} {
+  builtin_puts wrapped
+  builtin_puts $msg
+}
+puts hello
+
+proc puts {msg} {
+  builtin_puts rewrapped
+  builtin_puts $msg
+}
+puts hello
+
+rename puts ""
+rename builtin_puts puts
+puts restored
@@ -0,0 +1,9 @@
+puts -nonewline "a"
+puts -nonewline stdout "b"
+puts "c"
+puts stdout "d"
+puts -nonewline
+set r [catch {puts nowhere text} msg]
+puts "$r: $msg"
+set r [catch {puts -nonewline stdout a b} msg]
+puts "$r: $msg"
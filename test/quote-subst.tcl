@@ -0,0 +1,14 @@
+proc double {x} {return [+ $x $x]}
+puts "[+ 1 2] at the start"
+puts "result: [+ 1 2] in the middle"
+puts "at the end: [+ 1 2]"
+puts "[+ 1 2]"
+puts "nested: [double [double 2]]"
+set a 5
+puts "$a[double $a] adjacent"
+puts "[double 1][double 2]"
+puts "spaces [double 1]   kept"
+set s "one word [double 3] with spaces"
+puts $s
+puts "quotes can span
+lines: [double 4]"
@@ -0,0 +1,23 @@
+proc f {} {
+  f
+}
+puts [catch {f} msg]
+puts $msg
+proc countdown {n} {
+  if {$n == 0} {
+    return done
+  }
+  return [countdown [- $n 1]]
+}
+puts [countdown 200]
+puts [interp recursionlimit {}]
+interp recursionlimit {} 50
+puts [catch {countdown 200} msg]
+puts $msg
+puts [countdown 10]
+interp create child
+puts [interp recursionlimit child 20]
+puts [catch {interp eval child {proc g {} {g}; g}} msg]
+puts $msg
+puts [catch {interp recursionlimit {} 0} msg]
+puts $msg
@@ -0,0 +1 @@
+regexp -bogus a b
@@ -0,0 +1 @@
+regexp {(} a
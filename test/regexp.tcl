@@ -0,0 +1,13 @@
+puts [regexp {(\w+)=(\w+)} "name=alice" all key value]
+puts $all
+puts $key
+puts $value
+puts [regexp {z} abc]
+puts [regexp -nocase {^HELLO} hello]
+puts [regexp -all {o} foo-boo]
+puts [regexp {a(x)?b} ab match sub]
+puts $match
+puts "sub=$sub"
+puts [regexp -- {-x} a-xb]
+puts [regexp -all -- {(\d)} a1b2c3 last digit]
+puts $digit
@@ -0,0 +1,11 @@
+puts [regsub {(\w+)=(\w+)} "foo=bar" {\2=\1}]
+puts [regsub {o} foo 0]
+puts [regsub -all {o} foo 0]
+puts [regsub -all {(\w+)=(\w+)} "a=1 b=2" {\1: \2} pairs]
+puts $pairs
+puts [regsub {bar} "foo=bar" {<&>}]
+puts [regsub {bar} "foo=bar" {\&\0}]
+puts [regsub {bar} "foo=bar" {\\}]
+puts [regsub -nocase {FOO} "foo=bar" baz]
+puts [regsub {zzz} "foo=bar" baz unchanged]
+puts $unchanged
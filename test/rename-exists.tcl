@@ -0,0 +1,3 @@
+proc a {} {}
+proc b {} {}
+rename a b
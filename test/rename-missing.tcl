@@ -0,0 +1 @@
+rename nope other
@@ -0,0 +1,16 @@
+set x [puts hi]
+puts "x=$x"
+set y [set z 5]
+puts $y
+puts [set z]
+proc noresult {} {
+  puts inside
+}
+set r [noresult]
+puts "r=$r"
+set w [while 0 {}]
+puts "w=$w"
+set e []
+puts "e=$e"
+set n [if 0 {set q 1}]
+puts "n=$n"
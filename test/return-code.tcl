@@ -0,0 +1,43 @@
+proc nothing {} {
+  return
+  puts unreachable
+}
+set r [nothing]
+puts "result:$r"
+proc fail {} {
+  return -code error "nope"
+}
+puts [catch {fail} msg]
+puts $msg
+proc stop {} {
+  return -code break
+}
+set i 0
+while 1 {
+  set i [+ $i 1]
+  if {$i == 3} {
+    stop
+  }
+}
+puts $i
+proc skip {} {
+  return -code continue
+}
+set n 0
+set i 0
+while {$i < 5} {
+  set i [+ $i 1]
+  if {$i % 2 == 0} {
+    skip
+  }
+  set n [+ $n 1]
+}
+puts $n
+proc ok {} {
+  return -code ok done
+}
+puts [ok]
+puts [catch {return -code bogus} msg]
+puts $msg
+puts [catch {return a b} msg]
+puts $msg
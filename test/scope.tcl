@@ -0,0 +1,37 @@
+set g top
+proc local {} {
+  set l inside
+  return $l
+}
+puts [local]
+puts [catch {puts $l} msg]
+puts $msg
+proc peek {} {
+  puts $g
+}
+puts [catch {peek} msg]
+puts $msg
+proc withglobal {} {
+  global g
+  return $g
+}
+puts [withglobal]
+proc inner {} {
+  set v inner
+  return $v
+}
+proc outer {} {
+  set v outer
+  set r [inner]
+  return "$v $r"
+}
+puts [outer]
+proc recurse {n} {
+  set mine $n
+  if {$n > 0} {
+    recurse [- $n 1]
+  }
+  puts $mine
+}
+recurse 2
+puts $g
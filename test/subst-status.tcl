@@ -0,0 +1,23 @@
+proc f {} {
+    return value
+    puts "not reached"
+}
+set x [f]
+puts "x = $x"
+
+set y [return 5]
+puts "y = $y"
+puts "script continues after [return early] substitution"
+
+set r [catch {while 1 {set z [break]}} msg]
+puts "$r: $msg"
+
+set r [catch {while 1 {set z [continue]}} msg]
+puts "$r: $msg"
+
+set n 0
+while {$n < 3} {
+    set n [+ $n 1]
+    if {$n == 2} { break }
+}
+puts "n = $n"
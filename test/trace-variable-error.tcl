@@ -0,0 +1,5 @@
+proc fail {name op} {
+  nosuchcommand
+}
+trace add variable x write fail
+set x 1
@@ -0,0 +1,36 @@
+proc logwrite {name op} {
+  upvar 1 $name value
+  puts $op
+  puts $value
+}
+trace add variable x write logwrite
+set x 1
+set x 2
+puts [trace info variable x]
+trace remove variable x write logwrite
+set x 3
+puts $x
+puts [trace info variable x]
+
+proc compute {name op} {
+  upvar 1 $name value
+  set value 42
+}
+trace add variable answer read compute
+puts $answer
+trace remove variable answer read compute
+
+proc inner {} {
+  global answer
+  set answer 7
+}
+inner
+puts $answer
+
+proc logunset {name op} {
+  puts $op
+  puts $name
+}
+set gone 1
+trace add variable gone unset logunset
+unset gone
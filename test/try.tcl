@@ -0,0 +1,48 @@
+try {
+  error oops
+} on error {msg opts} {
+  puts $msg
+  puts $opts
+} finally {
+  puts cleanup
+}
+
+try {
+  puts body
+} finally {
+  puts cleanup
+}
+
+try {
+  error fallthrough
+} on break {} - on error {msg} {
+  puts $msg
+}
+
+puts [try {+ 1 2} on ok {value} {+ $value 10}]
+
+proc early {} {
+  try {
+    return early-result
+  } finally {
+    puts finally-ran
+  }
+  puts unreachable
+}
+puts [early]
+
+set x 0
+while {< $x 5} {
+  set x [+ $x 1]
+  try {
+    break
+  } finally {
+    puts loop-finally
+  }
+}
+puts $x
+
+puts [catch {try {puts a} finally {error finally-failed}} msg]
+puts $msg
+puts [catch {try {error body-failed} finally {puts still-runs}} msg]
+puts $msg
@@ -0,0 +1 @@
+putz hello
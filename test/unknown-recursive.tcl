@@ -0,0 +1,4 @@
+proc unknown {args} {
+  alsomissing
+}
+putz hello
@@ -0,0 +1,11 @@
+proc unknown {name args} {
+  return $name
+}
+puts [42]
+puts [frobnicate a b]
+
+proc show {args} {
+  puts $args
+}
+show
+show a {b c} d
@@ -0,0 +1,3 @@
+set x 1
+unset x
+puts $x
@@ -0,0 +1,19 @@
+set 名前 "日本語"
+puts $名前
+puts "${名前}!"
+proc 🎉 {x} {return "🎉 $x 🎉"}
+puts [🎉 ü]
+puts [🎉 [🎉 中]]
+puts {braces 中文 {nested 😀}}
+puts "quotes é\ü and \é"
+puts \😀x
+puts [set x \😀]
+set a(ключ) значение
+puts $a(ключ)
+set key ключ
+puts "$a($key)"
+# комментарий 😀
+puts [expr {"é" eq "é"}]
+puts [regsub -all {.} "üé" {<&>}]
+puts [catch {error "fehler ü"} msg]
+puts $msg
@@ -0,0 +1,18 @@
+set x 007
+puts [+ $x 1]
+puts $x
+set f 1.50
+puts [* $f 2]
+puts $f
+puts [+ 1e3 0]
+set i 0
+while {< $i 1000} {
+    set i [+ $i 1]
+}
+puts $i
+proc show {args} {
+    puts $args
+}
+show a {b c} "d e" {}
+set l {x {y z}}
+show $l $l
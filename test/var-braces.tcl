@@ -0,0 +1,14 @@
+set x hello
+set a A
+set b B
+set {odd name} spaced
+puts ${x}
+puts "${x}abc"
+puts ${x}abc
+puts $a${b}c
+puts "$a${b}c"
+puts ${odd name}
+puts "<${odd name}>"
+set script "puts \${unterminated"
+puts [catch $script msg]
+puts $msg
@@ -0,0 +1,37 @@
+set a 1
+set b 2
+set x X
+puts "$a/$b"
+puts "$a$b"
+set y pre$x
+puts "$y post"
+puts $x.
+puts "x=$x"
+puts "$x: done"
+puts $a,$b
+puts "${x}y $x-y"
+puts "cost: $ 5"
+puts a$
+puts "$"
+set ::g global
+puts $::g
+set fruit pear
+set prices($fruit) 7
+puts $prices(pear)
+set "prices(a b)" spaced
+puts "$prices(a b)!"
+puts $g
+proc read-global {} {return $::g}
+puts [read-global]
+proc write-global {} {set ::made-here 1; set ::prices(plum) 3}
+write-global
+puts ${made-here}
+puts $prices(plum)
+proc link-global {} {global ::g; set g changed; upvar 0 ::fruit f; return $f}
+puts [link-global]
+puts $g
+proc drop-global {} {unset ::made-here}
+drop-global
+puts [catch {set made-here} msg]
+catch {error boom}
+puts [string range $::errorInfo 0 3]